@@ -0,0 +1,107 @@
+use crate::models::Note;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Width of an activity bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Day,
+    /// Weeks start on Monday
+    Week,
+}
+
+impl Granularity {
+    /// Periods returned when none are asked for: about a year either way.
+    pub fn default_periods(self) -> usize {
+        match self {
+            Granularity::Day => 365,
+            Granularity::Week => 52,
+        }
+    }
+
+    fn bucket_start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => {
+                date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+        }
+    }
+
+    fn step(self) -> Duration {
+        match self {
+            Granularity::Day => Duration::days(1),
+            Granularity::Week => Duration::weeks(1),
+        }
+    }
+}
+
+/// Notes created and edits made during one day or week (local time).
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityBucket {
+    pub start: NaiveDate,
+    pub created: usize,
+    pub edited: usize,
+}
+
+// An edit stamps the revision and `updated_at` a moment apart
+const SAME_EDIT_TOLERANCE_MS: i64 = 1000;
+
+/// When a note was edited: each saved revision, plus the last change if no
+/// revision records it (e.g. moving it to the archive).
+fn edit_times(note: &Note) -> Vec<DateTime<Utc>> {
+    let mut times: Vec<DateTime<Utc>> = note.revisions.iter().map(|r| r.saved_at).collect();
+    let recorded = |time: DateTime<Utc>| {
+        (time - note.updated_at).num_milliseconds().abs() < SAME_EDIT_TOLERANCE_MS
+    };
+    if !recorded(note.created_at) && !times.iter().any(|&time| recorded(time)) {
+        times.push(note.updated_at);
+    }
+    times
+}
+
+/// Counts per bucket for the `periods` buckets ending with the one holding
+/// `today`, oldest first. Empty buckets are included so the result can be
+/// drawn as a heatmap directly.
+pub fn activity(
+    notes: &[Note],
+    granularity: Granularity,
+    periods: usize,
+    today: NaiveDate,
+) -> Vec<ActivityBucket> {
+    if periods == 0 {
+        return Vec::new();
+    }
+    let last = granularity.bucket_start(today);
+    let first = last - granularity.step() * (periods.saturating_sub(1) as i32);
+    let mut buckets: Vec<ActivityBucket> = (0..periods)
+        .map(|i| ActivityBucket {
+            start: first + granularity.step() * i as i32,
+            created: 0,
+            edited: 0,
+        })
+        .collect();
+
+    let index = |time: DateTime<Utc>| {
+        let start = granularity.bucket_start(time.with_timezone(&Local).date_naive());
+        if start < first || start > last {
+            return None;
+        }
+        let offset = (start - first).num_days() / granularity.step().num_days();
+        Some(offset as usize)
+    };
+
+    for note in notes {
+        if let Some(i) = index(note.created_at) {
+            buckets[i].created += 1;
+        }
+        for time in edit_times(note) {
+            if let Some(i) = index(time) {
+                buckets[i].edited += 1;
+            }
+        }
+    }
+    buckets
+}
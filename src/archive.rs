@@ -0,0 +1,329 @@
+use crate::export::{self, ExportFormat};
+use crate::models::Note;
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Layout version of the manifest
+const FORMAT_VERSION: u32 = 1;
+
+/// What a zip archive export holds, at `manifest.json` in the archive.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub app_version: String,
+    pub exported_at: DateTime<Utc>,
+    pub name: String,
+    /// `md` or `json`: how the notes under `notes/` are written
+    pub notes_format: String,
+    pub notes: Vec<ManifestNote>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestNote {
+    pub id: String,
+    pub title: String,
+    /// Path of the note's file in the archive
+    pub path: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ManifestAttachment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestAttachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    /// Path of the file in the archive; `None` if its bytes were missing
+    /// from the attachments directory and couldn't be included
+    pub path: Option<String>,
+}
+
+/// A zip archive export planned from the notes: where everything goes.
+/// Building it reads nothing but the notes, so it can be made under the
+/// vault's lock and written out after letting go of it.
+pub struct Archive {
+    manifest: Manifest,
+    notes: Vec<Note>,
+    format: ExportFormat,
+    /// The attachment files to include: archive path and where they are
+    files: Vec<(String, PathBuf)>,
+}
+
+impl Archive {
+    /// Plans an archive of `notes` written as Markdown or JSON, with the
+    /// attachment files found in `attachments_dir`.
+    pub fn new(
+        notes: Vec<Note>,
+        format: ExportFormat,
+        name: &str,
+        attachments_dir: &Path,
+    ) -> Result<Self, String> {
+        if format == ExportFormat::Pdf {
+            return Err("Zip archives hold Markdown or JSON notes, not PDF".to_string());
+        }
+        let mut taken = HashSet::new();
+        let mut files = Vec::new();
+        let manifest_notes = notes
+            .iter()
+            .map(|note| {
+                let attachments = note
+                    .attachments
+                    .iter()
+                    .map(|attachment| {
+                        let source = attachments_dir.join(attachment.file_key());
+                        let path = source.is_file().then(|| {
+                            let path = format!(
+                                "attachments/{}/{}",
+                                attachment.id,
+                                safe_file_name(&attachment.filename)
+                            );
+                            files.push((path.clone(), source));
+                            path
+                        });
+                        ManifestAttachment {
+                            id: attachment.id.clone(),
+                            filename: attachment.filename.clone(),
+                            content_type: attachment.content_type.clone(),
+                            path,
+                        }
+                    })
+                    .collect();
+                ManifestNote {
+                    id: note.id.clone(),
+                    title: note.title.clone(),
+                    path: unique_path(note, format, &mut taken),
+                    attachments,
+                }
+            })
+            .collect();
+
+        Ok(Archive {
+            manifest: Manifest {
+                format: FORMAT_VERSION,
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+                exported_at: Utc::now(),
+                name: name.to_string(),
+                notes_format: format.extension().to_string(),
+                notes: manifest_notes,
+            },
+            notes,
+            format,
+            files,
+        })
+    }
+
+    pub fn note_count(&self) -> usize {
+        self.notes.len()
+    }
+
+    /// Attachment files that will be included.
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Writes the archive to `out` front to back, one entry at a time: the
+    /// manifest, the notes, then the attachment files.
+    pub fn write_to(&self, out: impl Write) -> io::Result<()> {
+        let mut zip = ZipStream::new(out, self.manifest.exported_at);
+        zip.add("manifest.json", &serde_json::to_vec_pretty(&self.manifest)?)?;
+        for (note, entry) in self.notes.iter().zip(&self.manifest.notes) {
+            let body = match self.format {
+                ExportFormat::Json => serde_json::to_vec_pretty(note)?,
+                _ => note_markdown(note, &entry.attachments).into_bytes(),
+            };
+            zip.add(&entry.path, &body)?;
+        }
+        for (path, source) in &self.files {
+            zip.add(path, &fs::read(source)?)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// File name for an archive called `name` (e.g. "Recipes" → `recipes.zip`).
+pub fn file_name(name: &str) -> String {
+    let file = export::export_file_name(name, ExportFormat::Markdown);
+    format!("{}.zip", file.trim_end_matches(".md"))
+}
+
+// The note as exported to Markdown, then links to its attachments in the archive
+fn note_markdown(note: &Note, attachments: &[ManifestAttachment]) -> String {
+    let mut markdown = export::notes_to_markdown(std::slice::from_ref(note));
+    let included: Vec<String> = attachments
+        .iter()
+        .filter_map(|attachment| {
+            let path = attachment.path.as_ref()?;
+            Some(format!("- [{}](../{})", attachment.filename, path))
+        })
+        .collect();
+    if !included.is_empty() {
+        markdown.push_str(&format!("\nAttachments:\n\n{}\n", included.join("\n")));
+    }
+    markdown
+}
+
+// `notes/<file name>`, numbered when notes share a title
+fn unique_path(note: &Note, format: ExportFormat, taken: &mut HashSet<String>) -> String {
+    let file = export::file_name(note, format.extension());
+    let (stem, extension) = file.rsplit_once('.').unwrap_or((&file, ""));
+    let mut path = format!("notes/{}", file);
+    let mut n = 1;
+    while !taken.insert(path.clone()) {
+        n += 1;
+        path = format!("notes/{}-{}.{}", stem, n, extension);
+    }
+    path
+}
+
+// An uploaded file's name without anything that would make it a path
+fn safe_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    match name.trim_start_matches('.') {
+        "" => "file".to_string(),
+        _ => name.to_string(),
+    }
+}
+
+// Zip archives without Zip64 extensions count in 32 and 16 bits
+const MAX_SIZE: u64 = u32::MAX as u64;
+const MAX_ENTRIES: usize = u16::MAX as usize;
+
+// An entry already written, for the central directory at the end
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes a zip archive front to back, so it can go where nothing can be
+/// rewritten afterwards, such as an HTTP response. The `zip` crate's writer
+/// goes back to fill in sizes, which needs a seekable file. Each entry is
+/// compressed in memory before it's written, and archives over 4 GiB
+/// aren't supported.
+pub struct ZipStream<W: Write> {
+    out: W,
+    entries: Vec<Entry>,
+    written: u64,
+    // Modification time of every entry, in MS-DOS form
+    time: u16,
+    date: u16,
+}
+
+impl<W: Write> ZipStream<W> {
+    pub fn new(out: W, modified: DateTime<Utc>) -> Self {
+        let local = modified.with_timezone(&Local);
+        let year = local.year().clamp(1980, 2107) as u16;
+        ZipStream {
+            out,
+            entries: Vec::new(),
+            written: 0,
+            time: ((local.hour() as u16) << 11)
+                | ((local.minute() as u16) << 5)
+                | (local.second() as u16 / 2),
+            date: ((year - 1980) << 9) | ((local.month() as u16) << 5) | local.day() as u16,
+        }
+    }
+
+    /// Adds a file, deflated unless that doesn't make it smaller.
+    pub fn add(&mut self, name: &str, bytes: &[u8]) -> io::Result<()> {
+        if self.entries.len() == MAX_ENTRIES {
+            return Err(too_large());
+        }
+        let mut crc = Crc::new();
+        crc.update(bytes);
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes)?;
+        let deflated = encoder.finish()?;
+        let (method, data) = if deflated.len() < bytes.len() {
+            (8, deflated.as_slice())
+        } else {
+            (0, bytes)
+        };
+
+        let entry = Entry {
+            name: name.to_string(),
+            method,
+            crc: crc.sum(),
+            compressed: size_field(data.len() as u64)?,
+            size: size_field(bytes.len() as u64)?,
+            offset: size_field(self.written)?,
+        };
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend(0x04034b50u32.to_le_bytes());
+        header.extend(20u16.to_le_bytes()); // version needed: 2.0
+        self.put_fields(&mut header, &entry);
+        header.extend(0u16.to_le_bytes()); // no extra field
+        header.extend(name.as_bytes());
+        self.write(&header)?;
+        self.write(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        let start = size_field(self.written)?;
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend(0x02014b50u32.to_le_bytes());
+            directory.extend(20u16.to_le_bytes()); // made by: 2.0
+            directory.extend(20u16.to_le_bytes()); // version needed: 2.0
+            self.put_fields(&mut directory, entry);
+            directory.extend([0; 12]); // no extra field or comment, disk 0, no attributes
+            directory.extend(entry.offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+        let count = self.entries.len() as u16;
+        let mut end = Vec::with_capacity(22);
+        end.extend(0x06054b50u32.to_le_bytes());
+        end.extend([0; 4]); // disk 0, directory on disk 0
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend(size_field(directory.len() as u64)?.to_le_bytes());
+        end.extend(start.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // no comment
+        self.write(&directory)?;
+        self.write(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    // Flags through name length, the same in local and central headers
+    fn put_fields(&self, header: &mut Vec<u8>, entry: &Entry) {
+        header.extend(0x0800u16.to_le_bytes()); // names are UTF-8
+        header.extend(entry.method.to_le_bytes());
+        header.extend(self.time.to_le_bytes());
+        header.extend(self.date.to_le_bytes());
+        header.extend(entry.crc.to_le_bytes());
+        header.extend(entry.compressed.to_le_bytes());
+        header.extend(entry.size.to_le_bytes());
+        header.extend((entry.name.len() as u16).to_le_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn size_field(value: u64) -> io::Result<u32> {
+    if value > MAX_SIZE {
+        return Err(too_large());
+    }
+    Ok(value as u32)
+}
+
+fn too_large() -> io::Error {
+    io::Error::other("Export is too large for a zip archive (over 4 GiB or 65535 files)")
+}
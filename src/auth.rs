@@ -0,0 +1,532 @@
+use crate::models::Note;
+use crate::notebooks::{MemberAccess, Notebook};
+use crate::reload::LiveConfig;
+use crate::tokens::{NoteToken, SharedNoteTokenStore, TokenAccess};
+use crate::users::{self, Role, SharedUserStore, User};
+use crate::web::ApiResponse;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use std::future::{ready, Ready};
+
+/// Who is making an API request.
+#[derive(Debug, Clone)]
+pub enum CurrentUser {
+    /// No accounts exist yet: single-user mode with full access.
+    Local,
+    User(User),
+    /// Holder of a token scoped to a single note.
+    NoteToken(NoteToken),
+}
+
+impl CurrentUser {
+    pub fn role(&self) -> Role {
+        match self {
+            CurrentUser::Local => Role::Admin,
+            CurrentUser::User(user) => user.role,
+            CurrentUser::NoteToken(token) => match token.access {
+                TokenAccess::Read => Role::Viewer,
+                TokenAccess::Write => Role::Editor,
+            },
+        }
+    }
+
+    /// Owner recorded on notes this user creates.
+    pub fn owner_id(&self) -> Option<String> {
+        match self {
+            CurrentUser::Local | CurrentUser::NoteToken(_) => None,
+            CurrentUser::User(user) => Some(user.id.clone()),
+        }
+    }
+
+    fn owns(&self, note: &Note) -> bool {
+        match self {
+            CurrentUser::Local => true,
+            CurrentUser::User(user) => note.owner.as_deref() == Some(user.id.as_str()),
+            CurrentUser::NoteToken(token) => token.note_id == note.id,
+        }
+    }
+
+    /// Admins see every note; editors and viewers their own and those
+    /// shared with them (directly or through a notebook), note tokens only
+    /// their note.
+    pub fn can_read(&self, note: &Note) -> bool {
+        if let CurrentUser::NoteToken(token) = self {
+            return token.note_id == note.id;
+        }
+        match self.role() {
+            Role::Admin => true,
+            Role::Editor | Role::Viewer => {
+                self.owns(note) || self.is_shared(note) || self.via_notebook(note).is_some()
+            }
+        }
+    }
+
+    // What the note's notebook lets the user do with it
+    fn via_notebook(&self, note: &Note) -> Option<MemberAccess> {
+        let CurrentUser::User(user) = self else {
+            return None;
+        };
+        note.notebook_access.as_ref()?.for_user(&user.id)
+    }
+
+    // Shared with the user, or offered to them to take over
+    fn is_shared(&self, note: &Note) -> bool {
+        let CurrentUser::User(user) = self else {
+            return false;
+        };
+        note.shared_with.contains(&user.id)
+            || note
+                .pending_transfer
+                .as_ref()
+                .is_some_and(|transfer| transfer.to == user.id)
+    }
+
+    pub fn can_write(&self, note: &Note) -> bool {
+        match self.role() {
+            Role::Admin => true,
+            Role::Editor => self.owns(note) || self.via_notebook(note) == Some(MemberAccess::Write),
+            Role::Viewer => false,
+        }
+    }
+
+    /// Handing the note over, sharing it and moving it between notebooks
+    /// are up to its owner (and admins), not everyone who may edit it.
+    pub fn can_manage(&self, note: &Note) -> bool {
+        match self {
+            CurrentUser::NoteToken(_) => false,
+            _ => self.role() == Role::Admin || self.owns(note),
+        }
+    }
+
+    /// Renaming a notebook and choosing its members is up to its owner
+    /// (and admins).
+    pub fn can_manage_notebook(&self, notebook: &Notebook) -> bool {
+        match self {
+            CurrentUser::Local => true,
+            CurrentUser::User(user) => {
+                user.role == Role::Admin || notebook.owner.as_deref() == Some(user.id.as_str())
+            }
+            CurrentUser::NoteToken(_) => false,
+        }
+    }
+
+    /// What the user may do with the notebook's notes, if anything; never
+    /// more than their role allows.
+    pub fn notebook_access(&self, notebook: &Notebook) -> Option<MemberAccess> {
+        let access = match self {
+            _ if self.can_manage_notebook(notebook) => MemberAccess::Write,
+            CurrentUser::User(user) => notebook.access().for_user(&user.id)?,
+            _ => return None,
+        };
+        match self.role() {
+            Role::Viewer => Some(MemberAccess::Read),
+            _ => Some(access),
+        }
+    }
+
+    /// Whether the notebook is listed for the user: they may use it or are
+    /// invited to it.
+    pub fn can_see_notebook(&self, notebook: &Notebook) -> bool {
+        self.notebook_access(notebook).is_some()
+            || matches!(self, CurrentUser::User(user) if notebook.invitation_for(&user.id).is_some())
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        // Routes outside the auth middleware behave like single-user mode
+        let user = req
+            .extensions()
+            .get::<CurrentUser>()
+            .cloned()
+            .unwrap_or(CurrentUser::Local);
+        ready(Ok(user))
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+// Embeds can't set headers, so note tokens may also come as `?access_token=`
+fn query_token(req: &ServiceRequest) -> Option<String> {
+    req.query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("access_token="))
+        .map(str::to_string)
+}
+
+/// Note tokens reach their note's endpoints (except token management) and
+/// attachments, whose handlers check the owning note.
+fn note_token_allows(token: &NoteToken, path: &str) -> bool {
+    if path.starts_with("/api/attachments/") {
+        return true;
+    }
+    let Some(rest) = path
+        .strip_prefix("/api/notes/")
+        .and_then(|rest| rest.strip_prefix(token.note_id.as_str()))
+    else {
+        return false;
+    };
+    rest.is_empty() || (rest.starts_with('/') && !rest.starts_with("/tokens"))
+}
+
+// Path below the prefix the API is mounted under
+fn api_path(req: &ServiceRequest) -> &str {
+    req.match_info().unprocessed()
+}
+
+// Accepting a notebook invitation, or leaving (removing oneself from) one
+fn is_membership_answer(path: &str) -> bool {
+    path.strip_prefix("/api/notebooks/")
+        .and_then(|rest| rest.split_once("/members/"))
+        .is_some_and(|(id, _)| !id.contains('/'))
+}
+
+// Saving or forgetting where the caller left off in a note
+fn is_reading_progress(path: &str) -> bool {
+    path.strip_prefix("/api/notes/")
+        .and_then(|rest| rest.strip_suffix("/progress"))
+        .is_some_and(|id| !id.contains('/'))
+}
+
+fn required_role(req: &ServiceRequest) -> Role {
+    if api_path(req).starts_with("/api/admin") {
+        Role::Admin
+    } else if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        Role::Viewer
+    } else if api_path(req).ends_with("/unlock") {
+        // Only reads the note; the passphrase is POSTed to keep it out of URLs
+        Role::Viewer
+    } else if is_reading_progress(api_path(req)) {
+        // Saving one's place in a note only takes reading it
+        Role::Viewer
+    } else if is_membership_answer(api_path(req)) {
+        // Viewers may be invited to notebooks too; the handlers check the rest
+        Role::Viewer
+    } else if matches!(
+        api_path(req),
+        "/api/search/history" | "/api/preferences" | "/api/ui/keymap"
+    ) {
+        // Clearing one's own search history or saving one's own
+        // preferences (shortcuts included) changes no notes
+        Role::Viewer
+    } else {
+        Role::Editor
+    }
+}
+
+/// Header carrying `server.admin_key` on `/api/admin` requests.
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+
+// Admin routes need the configured admin key on top of the admin role, and
+// stay closed while no key is configured
+fn check_admin_key(req: &ServiceRequest) -> Result<(), HttpResponse> {
+    let Some(expected) = req
+        .app_data::<web::Data<LiveConfig>>()
+        .and_then(|config| config.current().server.admin_key.clone())
+    else {
+        return Err(HttpResponse::ServiceUnavailable().json(ApiResponse::error(
+            "Admin endpoints are off: set server.admin_key in the config and send it as X-Admin-Key",
+        )));
+    };
+    let matches = req
+        .headers()
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        // Comparing hashes keeps the time taken independent of the key
+        .is_some_and(|given| users::hash_token(given) == users::hash_token(&expected));
+    if !matches {
+        return Err(
+            HttpResponse::Forbidden().json(ApiResponse::error("Missing or invalid admin key"))
+        );
+    }
+    Ok(())
+}
+
+fn reject<B>(
+    req: ServiceRequest,
+    response: HttpResponse,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Feeds that apps subscribe to by URL: protected like `/api`, but they
+/// can only pass a token as `?access_token=`.
+const FEED_PATHS: &[&str] = &["/calendar.ics"];
+
+/// Authenticates `/api` and feed requests by token and enforces role permissions.
+///
+/// Per-note ownership is checked by the handlers through [`CurrentUser`].
+pub async fn require_role<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let is_feed = FEED_PATHS.contains(&api_path(&req));
+    if !api_path(&req).starts_with("/api") && !is_feed {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+
+    let authenticated = match req.app_data::<web::Data<SharedUserStore>>() {
+        Some(users) => {
+            let users = users.lock().unwrap();
+            if users.is_enabled() {
+                let bearer =
+                    bearer_token(&req).or_else(|| if is_feed { query_token(&req) } else { None });
+                bearer
+                    .as_deref()
+                    .and_then(|token| users.find_by_token(token))
+                    .map(CurrentUser::User)
+                    .or_else(|| {
+                        let secret = bearer.or_else(|| query_token(&req))?;
+                        req.app_data::<web::Data<SharedNoteTokenStore>>()?
+                            .lock()
+                            .unwrap()
+                            .find_by_token(&secret)
+                            .map(CurrentUser::NoteToken)
+                    })
+            } else {
+                Some(CurrentUser::Local)
+            }
+        }
+        None => Some(CurrentUser::Local),
+    };
+
+    let Some(current) = authenticated else {
+        return reject(
+            req,
+            HttpResponse::Unauthorized().json(ApiResponse::error("Missing or invalid API token")),
+        );
+    };
+
+    if let CurrentUser::NoteToken(token) = &current {
+        if !note_token_allows(token, api_path(&req)) {
+            return reject(
+                req,
+                HttpResponse::Forbidden().json(ApiResponse::error(
+                    "This token only grants access to one note",
+                )),
+            );
+        }
+    }
+
+    if current.role() < required_role(&req) {
+        return reject(
+            req,
+            HttpResponse::Forbidden().json(ApiResponse::error("Insufficient permissions")),
+        );
+    }
+
+    if api_path(&req).starts_with("/api/admin") {
+        if let Err(response) = check_admin_key(&req) {
+            return reject(req, response);
+        }
+    }
+
+    req.extensions_mut().insert(current);
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Transfer;
+    use crate::notebooks::Visibility;
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
+
+    fn user(id: &str, role: Role) -> CurrentUser {
+        CurrentUser::User(User {
+            id: id.to_string(),
+            username: id.to_string(),
+            role,
+            created_at: Utc::now(),
+            token_hash: String::new(),
+            disabled_at: None,
+        })
+    }
+
+    fn token(note: &Note, access: TokenAccess) -> CurrentUser {
+        CurrentUser::NoteToken(NoteToken {
+            id: "t1".to_string(),
+            note_id: note.id.clone(),
+            vault: None,
+            access,
+            created_at: Utc::now(),
+            created_by: None,
+            token_hash: String::new(),
+        })
+    }
+
+    fn owned_by(owner: &str) -> Note {
+        let mut note = Note::new("Plan".to_string(), "draft".to_string(), Vec::new());
+        note.owner = Some(owner.to_string());
+        note
+    }
+
+    // Read, write and manage, in that order
+    fn access(user: &CurrentUser, note: &Note) -> (bool, bool, bool) {
+        (
+            user.can_read(note),
+            user.can_write(note),
+            user.can_manage(note),
+        )
+    }
+
+    #[test]
+    fn routes_need_the_role_of_what_they_change() {
+        let cases = [
+            (Method::GET, "/api/admin/stats", Role::Admin),
+            (Method::POST, "/api/admin/reindex", Role::Admin),
+            (Method::GET, "/api/notes", Role::Viewer),
+            (Method::HEAD, "/api/notes/n1", Role::Viewer),
+            (Method::POST, "/api/notes", Role::Editor),
+            (Method::PUT, "/api/notes/n1", Role::Editor),
+            (Method::POST, "/api/notes/n1/lock", Role::Editor),
+            (Method::PUT, "/api/notes/n1/location", Role::Editor),
+            (Method::POST, "/api/notes/n1/encrypt", Role::Editor),
+            (Method::POST, "/api/notes/n1/unlock", Role::Viewer),
+            (Method::PATCH, "/api/notes/n1/progress", Role::Viewer),
+            (Method::DELETE, "/api/notes/n1/progress", Role::Viewer),
+            (Method::POST, "/api/notes/n1/transfer", Role::Editor),
+            (Method::POST, "/api/notes/n1/tokens", Role::Editor),
+            (Method::POST, "/api/notebooks", Role::Editor),
+            (Method::POST, "/api/notebooks/b1/members", Role::Editor),
+            (
+                Method::POST,
+                "/api/notebooks/b1/members/accept",
+                Role::Viewer,
+            ),
+            (
+                Method::DELETE,
+                "/api/notebooks/b1/members/sam",
+                Role::Viewer,
+            ),
+            (Method::DELETE, "/api/search/history", Role::Viewer),
+            (Method::PUT, "/api/preferences", Role::Viewer),
+            (Method::PUT, "/api/tag-colors/work", Role::Editor),
+        ];
+        for (method, path, role) in cases {
+            let req = TestRequest::default()
+                .method(method.clone())
+                .uri(path)
+                .to_srv_request();
+            assert_eq!(required_role(&req), role, "{} {}", method, path);
+        }
+    }
+
+    #[test]
+    fn owners_and_admins_have_the_note_to_themselves() {
+        let note = owned_by("ana");
+        let cases = [
+            (CurrentUser::Local, (true, true, true)),
+            (user("root", Role::Admin), (true, true, true)),
+            (user("ana", Role::Editor), (true, true, true)),
+            (user("ben", Role::Editor), (false, false, false)),
+            (user("cam", Role::Viewer), (false, false, false)),
+        ];
+        for (user, expected) in cases {
+            assert_eq!(access(&user, &note), expected, "{:?}", user);
+        }
+    }
+
+    #[test]
+    fn sharing_and_transfers_only_let_others_read() {
+        let mut note = owned_by("ana");
+        note.shared_with = vec!["ben".to_string(), "cam".to_string()];
+        note.pending_transfer = Some(Transfer {
+            to: "dee".to_string(),
+            requested_by: Some("ana".to_string()),
+            requested_at: Utc::now(),
+        });
+        for id in ["ben", "dee"] {
+            assert_eq!(access(&user(id, Role::Editor), &note), (true, false, false));
+        }
+        assert_eq!(
+            access(&user("cam", Role::Viewer), &note),
+            (true, false, false)
+        );
+        assert_eq!(
+            access(&user("eli", Role::Editor), &note),
+            (false, false, false)
+        );
+    }
+
+    #[test]
+    fn notebook_members_get_their_access_within_their_role() {
+        let now = Utc::now();
+        let mut notebook =
+            Notebook::new("Team", Some("ana".to_string()), Visibility::Private, now).unwrap();
+        for (member, access) in [
+            ("ben", MemberAccess::Write),
+            ("cam", MemberAccess::Write),
+            ("dee", MemberAccess::Read),
+            ("eli", MemberAccess::Write),
+        ] {
+            notebook.invite(member, access, None, now).unwrap();
+        }
+        // eli hasn't accepted
+        for member in ["ben", "cam", "dee"] {
+            notebook.accept(member, now).unwrap();
+        }
+        let mut note = owned_by("ana");
+        note.notebook_access = Some(notebook.access());
+
+        let cases = [
+            (user("ben", Role::Editor), (true, true, false)),
+            (user("cam", Role::Viewer), (true, false, false)),
+            (user("dee", Role::Editor), (true, false, false)),
+            (user("eli", Role::Editor), (false, false, false)),
+        ];
+        for (user, expected) in cases {
+            assert_eq!(access(&user, &note), expected, "{:?}", user);
+        }
+        assert!(!user("ben", Role::Editor).can_manage_notebook(&notebook));
+        assert!(user("ana", Role::Editor).can_manage_notebook(&notebook));
+
+        notebook.visibility = Visibility::Team;
+        note.notebook_access = Some(notebook.access());
+        assert_eq!(
+            access(&user("eli", Role::Editor), &note),
+            (true, false, false)
+        );
+    }
+
+    #[test]
+    fn note_tokens_reach_only_their_note_and_never_manage_it() {
+        let note = owned_by("ana");
+        let other = owned_by("ana");
+        let read = token(&note, TokenAccess::Read);
+        let write = token(&note, TokenAccess::Write);
+        assert_eq!(access(&read, &note), (true, false, false));
+        assert_eq!(access(&write, &note), (true, true, false));
+        assert_eq!(access(&write, &other), (false, false, false));
+        assert_eq!(read.role(), Role::Viewer);
+        assert_eq!(write.role(), Role::Editor);
+
+        let CurrentUser::NoteToken(write) = write else {
+            unreachable!()
+        };
+        let own = format!("/api/notes/{}", note.id);
+        assert!(note_token_allows(&write, &own));
+        assert!(note_token_allows(&write, &format!("{}/progress", own)));
+        assert!(note_token_allows(&write, "/api/attachments/a1"));
+        assert!(!note_token_allows(&write, &format!("{}/tokens", own)));
+        assert!(!note_token_allows(
+            &write,
+            &format!("/api/notes/{}", other.id)
+        ));
+        assert!(!note_token_allows(&write, "/api/notes"));
+    }
+}
@@ -0,0 +1,360 @@
+use crate::models::Note;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[cfg(not(any(feature = "file-storage", feature = "sqlite-storage")))]
+compile_error!("enable at least one storage backend: `file-storage` or `sqlite-storage`");
+
+/// Where notes are persisted. The manager keeps every note in memory and
+/// hands the whole set to the backend on each save.
+pub trait StorageBackend: Send {
+    /// Every stored note, in order; a store that doesn't exist yet is empty.
+    /// Unreadable data is an `InvalidData` error, never an empty store.
+    fn load(&self) -> io::Result<Vec<Note>>;
+
+    fn save(&self, notes: &[Note]) -> io::Result<()>;
+
+    /// File holding the data; attachments and other data files live next to it.
+    fn path(&self) -> &Path;
+
+    /// When the store last changed on disk, to notice other processes' writes.
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.path())
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+}
+
+struct Registration {
+    scheme: &'static str,
+    open: fn(&str) -> io::Result<Box<dyn StorageBackend>>,
+}
+
+// Backends compiled into this build, selected by URI scheme
+const BACKENDS: &[Registration] = &[
+    #[cfg(feature = "file-storage")]
+    Registration {
+        scheme: "file",
+        open: |path| Ok(Box::new(file::FileBackend::new(path))),
+    },
+    #[cfg(feature = "sqlite-storage")]
+    Registration {
+        scheme: "sqlite",
+        open: |path| Ok(Box::new(sqlite::SqliteBackend::new(path)?)),
+    },
+];
+
+/// Splits `scheme://path`; a bare path means a JSON file.
+pub fn parse_uri(uri: &str) -> (&str, &str) {
+    uri.split_once("://").unwrap_or(("file", uri))
+}
+
+/// Opens the backend named by the URI's scheme, e.g. `file://data/notes.json`
+/// or `sqlite://data/notes.db`.
+pub fn open(uri: &str) -> io::Result<Box<dyn StorageBackend>> {
+    let (scheme, path) = parse_uri(uri);
+    match BACKENDS.iter().find(|backend| backend.scheme == scheme) {
+        Some(backend) => (backend.open)(path),
+        None => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "Storage scheme '{}://' isn't available in this build (compiled in: {})",
+                scheme,
+                BACKENDS
+                    .iter()
+                    .map(|backend| format!("{}://", backend.scheme))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )),
+    }
+}
+
+/// A fresh store under the temp directory, in the first backend this build
+/// has, and the directory to remove afterwards.
+#[cfg(test)]
+pub(crate) fn scratch_store() -> (std::path::PathBuf, String) {
+    let dir = std::env::temp_dir().join(format!("notes-test-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let uri = match BACKENDS[0].scheme {
+        "file" => format!("file://{}", dir.join("notes.json").display()),
+        scheme => format!("{}://{}", scheme, dir.join("notes.db").display()),
+    };
+    (dir, uri)
+}
+
+#[cfg(feature = "file-storage")]
+pub mod file {
+    use super::StorageBackend;
+    use crate::models::Note;
+    use crate::storage::SCHEMA_VERSION;
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, BufWriter};
+    use std::path::{Path, PathBuf};
+
+    // Upgrades from version N to N + 1 live at index N - 1. Add a step here
+    // whenever a change needs more than new `#[serde(default)]` fields.
+    const MIGRATIONS: &[fn(Value) -> io::Result<Value>] = &[migrate_v1_to_v2];
+
+    #[derive(Serialize)]
+    struct NotesFileRef<'a> {
+        schema_version: u64,
+        notes: &'a [Note],
+    }
+
+    #[derive(Deserialize)]
+    struct NotesFile {
+        notes: Vec<Note>,
+    }
+
+    fn invalid_data(message: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    /// v1 was a bare array of notes with local-time timestamps; v2 wraps the
+    /// notes in a versioned object and stores timestamps in UTC.
+    fn migrate_v1_to_v2(value: Value) -> io::Result<Value> {
+        let Value::Array(mut notes) = value else {
+            return Err(invalid_data("Expected a JSON array of notes".to_string()));
+        };
+
+        for note in notes.iter_mut() {
+            for field in ["created_at", "updated_at"] {
+                let Some(Value::String(timestamp)) = note.get_mut(field) else {
+                    continue;
+                };
+                let utc = DateTime::parse_from_rfc3339(timestamp)
+                    .map_err(|e| invalid_data(format!("Bad {} '{}': {}", field, timestamp, e)))?
+                    .with_timezone(&Utc);
+                *timestamp = utc.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+            }
+        }
+
+        Ok(serde_json::json!({ "schema_version": 2, "notes": notes }))
+    }
+
+    fn schema_version(value: &Value) -> io::Result<u64> {
+        match value {
+            Value::Array(_) => Ok(1),
+            Value::Object(map) => map
+                .get("schema_version")
+                .and_then(Value::as_u64)
+                .filter(|version| *version >= 1)
+                .ok_or_else(|| invalid_data("Missing or invalid schema_version".to_string())),
+            _ => Err(invalid_data("Unrecognized notes file format".to_string())),
+        }
+    }
+
+    /// Parses a notes file of any known version, returning the notes and the
+    /// version it was stored in.
+    fn parse_notes(raw: &str) -> io::Result<(Vec<Note>, u64)> {
+        let mut value: Value = serde_json::from_str(raw)
+            .map_err(|e| invalid_data(format!("Malformed JSON: {}", e)))?;
+        let version = schema_version(&value)?;
+
+        if version > SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "Notes file has schema version {} but this build only understands up to {}; \
+                 upgrade the app instead of risking data loss",
+                    version, SCHEMA_VERSION
+                ),
+            ));
+        }
+
+        for migration in &MIGRATIONS[(version - 1) as usize..] {
+            value = migration(value)?;
+        }
+
+        let file: NotesFile = serde_json::from_value(value)
+            .map_err(|e| invalid_data(format!("Invalid note: {}", e)))?;
+        Ok((file.notes, version))
+    }
+
+    fn write_notes_file(path: &Path, notes: &[Note]) -> io::Result<()> {
+        let parent = path.parent().unwrap_or(Path::new("."));
+
+        fs::create_dir_all(parent)?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(
+            writer,
+            &NotesFileRef {
+                schema_version: SCHEMA_VERSION,
+                notes,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// The versioned `notes.json` document.
+    pub struct FileBackend {
+        path: PathBuf,
+    }
+
+    impl FileBackend {
+        pub fn new(path: &str) -> Self {
+            FileBackend {
+                path: PathBuf::from(path),
+            }
+        }
+    }
+
+    impl StorageBackend for FileBackend {
+        /// Upgrades older file formats in place (the original is kept as
+        /// `<file>.v<N>.bak`); files from a newer build are `Unsupported`.
+        fn load(&self) -> io::Result<Vec<Note>> {
+            if !self.path.exists() {
+                return Ok(Vec::new());
+            }
+
+            let raw = fs::read_to_string(&self.path)?;
+            if raw.trim().is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let (notes, version) = parse_notes(&raw)?;
+            if version < SCHEMA_VERSION {
+                let backup = format!("{}.v{}.bak", self.path.display(), version);
+                fs::copy(&self.path, backup)?;
+                write_notes_file(&self.path, &notes)?;
+                println!(
+                    "✓ Upgraded {} from schema version {} to {}",
+                    self.path.display(),
+                    version,
+                    SCHEMA_VERSION
+                );
+            }
+            Ok(notes)
+        }
+
+        fn save(&self, notes: &[Note]) -> io::Result<()> {
+            write_notes_file(&self.path, notes)
+        }
+
+        fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+}
+
+#[cfg(feature = "sqlite-storage")]
+pub mod sqlite {
+    use super::StorageBackend;
+    use crate::models::Note;
+    use crate::storage::SCHEMA_VERSION;
+    use rusqlite::{params, Connection};
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    fn to_io(e: rusqlite::Error) -> io::Error {
+        io::Error::other(e)
+    }
+
+    /// One row per note holding its JSON, in list order. The schema version
+    /// lives in `PRAGMA user_version`.
+    pub struct SqliteBackend {
+        path: PathBuf,
+    }
+
+    impl SqliteBackend {
+        pub fn new(path: &str) -> io::Result<Self> {
+            let backend = SqliteBackend {
+                path: PathBuf::from(path),
+            };
+            let conn = backend.connect()?;
+            let version: u64 = conn
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .map_err(to_io)?;
+            if version > SCHEMA_VERSION {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "{} was written by a newer version (schema {}, this build supports {})",
+                        path, version, SCHEMA_VERSION
+                    ),
+                ));
+            }
+            Ok(backend)
+        }
+
+        fn connect(&self) -> io::Result<Connection> {
+            if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            let conn = Connection::open(&self.path).map_err(to_io)?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS notes (
+                    id TEXT PRIMARY KEY,
+                    position INTEGER NOT NULL,
+                    body TEXT NOT NULL
+                )",
+            )
+            .map_err(to_io)?;
+            Ok(conn)
+        }
+    }
+
+    impl StorageBackend for SqliteBackend {
+        fn load(&self) -> io::Result<Vec<Note>> {
+            let conn = self.connect()?;
+            let mut statement = conn
+                .prepare("SELECT id, body FROM notes ORDER BY position")
+                .map_err(to_io)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(to_io)?;
+
+            let mut notes = Vec::new();
+            for row in rows {
+                let (id, body) = row.map_err(to_io)?;
+                let note = serde_json::from_str(&body).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Note {} is malformed: {}", id, e),
+                    )
+                })?;
+                notes.push(note);
+            }
+            Ok(notes)
+        }
+
+        fn save(&self, notes: &[Note]) -> io::Result<()> {
+            let mut conn = self.connect()?;
+            let tx = conn.transaction().map_err(to_io)?;
+            tx.execute("DELETE FROM notes", []).map_err(to_io)?;
+            {
+                let mut insert = tx
+                    .prepare("INSERT INTO notes (id, position, body) VALUES (?1, ?2, ?3)")
+                    .map_err(to_io)?;
+                for (position, note) in notes.iter().enumerate() {
+                    let body = serde_json::to_string(note)?;
+                    insert
+                        .execute(params![note.id, position as i64, body])
+                        .map_err(to_io)?;
+                }
+            }
+            tx.pragma_update(None, "user_version", SCHEMA_VERSION)
+                .map_err(to_io)?;
+            tx.commit().map_err(to_io)
+        }
+
+        fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::config::BackupConfig;
+use crate::s3::S3Client;
+use crate::storage::SharedNotesManager;
+use crate::templates;
+use crate::vault_file;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// Schedules have minute resolution
+const TICK: Duration = Duration::from_secs(60);
+
+const STAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// One backup of a vault: a vault file named `<vault>-<UTC time>.nvault`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// Where it is: `local` or `s3://<bucket>/<prefix>`
+    pub target: String,
+    pub name: String,
+    pub taken_at: DateTime<Utc>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupReport {
+    pub name: String,
+    pub notes: usize,
+    pub files: usize,
+    pub bytes: u64,
+    /// Where the snapshot ended up
+    pub targets: Vec<String>,
+}
+
+/// A place snapshots are kept.
+pub trait SnapshotStore {
+    fn target(&self) -> String;
+    /// The vault's snapshots here, oldest first
+    fn list(&self, vault: &str) -> io::Result<Vec<Snapshot>>;
+    fn delete(&self, name: &str) -> io::Result<()>;
+}
+
+struct LocalStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore for LocalStore {
+    fn target(&self) -> String {
+        "local".to_string()
+    }
+
+    fn list(&self, vault: &str) -> io::Result<Vec<Snapshot>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(taken_at) = taken_at(vault, &name) {
+                snapshots.push(Snapshot {
+                    target: self.target(),
+                    name,
+                    taken_at,
+                    bytes: entry.metadata()?.len(),
+                });
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        Ok(snapshots)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.dir.join(name))
+    }
+}
+
+struct BucketStore {
+    client: S3Client,
+    prefix: String,
+}
+
+impl SnapshotStore for BucketStore {
+    fn target(&self) -> String {
+        format!("s3://{}/{}", self.client.bucket(), self.prefix)
+    }
+
+    fn list(&self, vault: &str) -> io::Result<Vec<Snapshot>> {
+        let mut snapshots: Vec<Snapshot> = self
+            .client
+            .list(&format!("{}{}-", self.prefix, vault))?
+            .into_iter()
+            .filter_map(|object| {
+                let name = object.key.strip_prefix(&self.prefix)?.to_string();
+                Some(Snapshot {
+                    target: self.target(),
+                    taken_at: taken_at(vault, &name)?,
+                    name,
+                    bytes: object.size,
+                })
+            })
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        Ok(snapshots)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.client.delete(&format!("{}{}", self.prefix, name))
+    }
+}
+
+fn snapshot_name(vault: &str, at: DateTime<Utc>) -> String {
+    format!(
+        "{}-{}.{}",
+        vault,
+        at.format(STAMP_FORMAT),
+        vault_file::EXTENSION
+    )
+}
+
+// When the snapshot `name` of `vault` was taken; `None` for other files
+// (including other vaults' snapshots whose name starts like this one's)
+fn taken_at(vault: &str, name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name
+        .strip_prefix(vault)?
+        .strip_prefix('-')?
+        .strip_suffix(vault_file::EXTENSION)?
+        .strip_suffix('.')?;
+    NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// The backup settings of one vault.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    vault: String,
+    config: BackupConfig,
+}
+
+impl Backups {
+    pub fn new(vault: &str, config: &BackupConfig) -> Self {
+        Self {
+            vault: vault.to_string(),
+            config: config.clone(),
+        }
+    }
+
+    pub fn vault(&self) -> &str {
+        &self.vault
+    }
+
+    fn password(&self) -> io::Result<String> {
+        self.config
+            .password
+            .clone()
+            .or_else(|| std::env::var("NOTES_VAULT_PASSWORD").ok())
+            .filter(|password| !password.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Backups need a password: set backup.password or NOTES_VAULT_PASSWORD",
+                )
+            })
+    }
+
+    fn bucket(&self) -> io::Result<Option<BucketStore>> {
+        let Some(s3) = &self.config.s3 else {
+            return Ok(None);
+        };
+        Ok(Some(BucketStore {
+            client: S3Client::new(s3)?,
+            prefix: s3.prefix.clone(),
+        }))
+    }
+
+    /// Every place this vault's snapshots may be: the local directory, and
+    /// the bucket if there is one.
+    pub fn stores(&self) -> io::Result<Vec<Box<dyn SnapshotStore>>> {
+        let mut stores: Vec<Box<dyn SnapshotStore>> = vec![Box::new(LocalStore {
+            dir: PathBuf::from(&self.config.dir),
+        })];
+        if let Some(bucket) = self.bucket()? {
+            stores.push(Box::new(bucket));
+        }
+        Ok(stores)
+    }
+
+    /// The vault's snapshots everywhere, oldest first.
+    pub fn list(&self) -> io::Result<Vec<Snapshot>> {
+        let mut snapshots = Vec::new();
+        for store in self.stores()? {
+            snapshots.extend(store.list(&self.vault)?);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.taken_at);
+        Ok(snapshots)
+    }
+
+    /// Writes a snapshot of the notes to the backup directory and uploads it,
+    /// with the notes unlocked again during the upload. If the upload fails
+    /// the local copy is kept, whatever `keep_local` says.
+    pub fn run(&self, manager: &SharedNotesManager) -> io::Result<BackupReport> {
+        let password = self.password()?;
+        let bucket = self.bucket()?;
+        let dir = Path::new(&self.config.dir);
+        fs::create_dir_all(dir)?;
+        let name = snapshot_name(&self.vault, Utc::now());
+        let path = dir.join(&name);
+        let exported = vault_file::export(&manager.lock().unwrap(), &self.vault, &path, &password)?;
+
+        let mut targets = vec!["local".to_string()];
+        if let Some(bucket) = bucket {
+            bucket
+                .client
+                .put(&format!("{}{}", bucket.prefix, name), &fs::read(&path)?)?;
+            targets.push(bucket.target());
+            if !self.config.keep_local {
+                fs::remove_file(&path)?;
+                targets.remove(0);
+            }
+        }
+        Ok(BackupReport {
+            name,
+            notes: exported.notes,
+            files: exported.files,
+            bytes: exported.bytes,
+            targets,
+        })
+    }
+}
+
+/// Takes a backup at every time of `backup.schedule`.
+pub fn spawn_backup_scheduler(manager: SharedNotesManager, backups: Backups) {
+    let Some(expression) = backups.config.schedule.clone() else {
+        return;
+    };
+    let schedule = match templates::parse_schedule(&expression) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            println!("⚠ Backups not scheduled: {}", e);
+            return;
+        }
+    };
+    if let Err(e) = backups.password().and_then(|_| backups.bucket()) {
+        println!("⚠ Backups not scheduled: {}", e);
+        return;
+    }
+
+    thread::spawn(move || {
+        let mut checked = Utc::now();
+        loop {
+            thread::sleep(TICK);
+            let now = Utc::now();
+            let due = schedule
+                .after(&checked.with_timezone(&Local))
+                .next()
+                .is_some_and(|time| time.with_timezone(&Utc) <= now);
+            checked = now;
+            if !due {
+                continue;
+            }
+            match backups.run(&manager) {
+                Ok(report) => println!(
+                    "💾 Backed up {} notes to {}",
+                    report.notes,
+                    report.targets.join(" and ")
+                ),
+                Err(e) => println!("⚠ Backup failed: {}", e),
+            }
+        }
+    });
+}
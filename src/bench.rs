@@ -0,0 +1,159 @@
+use crate::fixtures::Fixtures;
+use crate::storage::NotesManager;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// What `notes-app bench` measures with.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Notes in the store before the timed runs
+    pub notes: usize,
+    /// Times each operation runs
+    pub runs: usize,
+    pub seed: u64,
+    /// Storage scheme, `file` or `sqlite`
+    pub scheme: String,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            notes: 10_000,
+            runs: 20,
+            seed: 42,
+            scheme: "file".to_string(),
+        }
+    }
+}
+
+/// Latencies of one operation, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Timing {
+    pub operation: &'static str,
+    pub runs: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Timing {
+    fn new(operation: &'static str, mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let at = |fraction: f64| {
+            let index = ((samples.len() as f64 * fraction).ceil() as usize).saturating_sub(1);
+            samples.get(index).copied().map_or(0.0, ms)
+        };
+        let total: Duration = samples.iter().sum();
+        Self {
+            operation,
+            runs: samples.len(),
+            mean_ms: ms(total) / samples.len().max(1) as f64,
+            p50_ms: at(0.5),
+            p95_ms: at(0.95),
+            max_ms: samples.last().copied().map_or(0.0, ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub notes: usize,
+    pub seed: u64,
+    pub storage: String,
+    /// Size of the store once the fixtures are written
+    pub store_bytes: u64,
+    pub timings: Vec<Timing>,
+}
+
+fn time<T>(runs: usize, mut run: impl FnMut(usize) -> io::Result<T>) -> io::Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(runs);
+    for i in 0..runs {
+        let started = Instant::now();
+        run(i)?;
+        samples.push(started.elapsed());
+    }
+    Ok(samples)
+}
+
+// Size of every file in `dir`, blobs included
+fn dir_size(dir: &Path) -> u64 {
+    walk_files(dir)
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn walk_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .flat_map(|path| {
+            if path.is_dir() {
+                walk_files(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+/// Fills a throwaway store in the temp directory with `options.notes`
+/// generated notes, then times opening it, adding a note (each add is a
+/// save), listing, searching and saving. The store is deleted afterwards;
+/// the vault itself is never touched.
+pub fn run(options: &BenchOptions) -> io::Result<BenchReport> {
+    let dir = std::env::temp_dir().join(format!("notes-bench-{}", Uuid::new_v4().simple()));
+    fs::create_dir_all(&dir)?;
+    let result = run_in(&dir, options);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn run_in(dir: &Path, options: &BenchOptions) -> io::Result<BenchReport> {
+    let file = match options.scheme.as_str() {
+        "sqlite" => "notes.db",
+        _ => "notes.json",
+    };
+    let uri = format!("{}://{}", options.scheme, dir.join(file).display());
+    let mut fixtures = Fixtures::new(options.seed);
+
+    NotesManager::with_notes(&uri, fixtures.notes(options.notes))?;
+    let store_bytes = dir_size(dir);
+    let runs = options.runs.max(1);
+    let mut timings = Vec::new();
+
+    timings.push(Timing::new(
+        "load",
+        time(runs, |_| NotesManager::new(&uri))?,
+    ));
+    let mut mgr = NotesManager::new(&uri)?;
+    let extra = fixtures.notes(runs);
+    timings.push(Timing::new(
+        "add",
+        time(runs, |i| mgr.insert_note(extra[i].clone()))?,
+    ));
+    timings.push(Timing::new("list", time(runs, |_| Ok(mgr.list_notes()))?));
+    let queries: Vec<String> = (0..runs).map(|_| fixtures.query()).collect();
+    timings.push(Timing::new(
+        "search",
+        time(runs, |i| Ok(mgr.search_notes(&queries[i])))?,
+    ));
+    timings.push(Timing::new("save", time(runs, |_| mgr.save_notes())?));
+
+    Ok(BenchReport {
+        notes: options.notes,
+        seed: options.seed,
+        storage: options.scheme.clone(),
+        store_bytes,
+        timings,
+    })
+}
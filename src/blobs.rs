@@ -0,0 +1,131 @@
+use crate::models::Note;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Content longer than this many bytes is kept out of the notes store by default.
+pub const DEFAULT_INLINE_LIMIT: usize = 64 * 1024;
+
+/// Large note bodies stored as content-addressed files (`<sha256>.txt`), so
+/// the notes store stays small. Notes in memory always hold their full text.
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+fn blob_id(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf) -> Self {
+        BlobStore { dir }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.txt", id))
+    }
+
+    // Moves `content` into a blob if it's over `limit` bytes
+    fn stash(
+        &self,
+        content: &mut String,
+        blob: &mut Option<String>,
+        limit: usize,
+    ) -> io::Result<()> {
+        *blob = None;
+        if limit == 0 || content.len() <= limit {
+            return Ok(());
+        }
+        let id = blob_id(content);
+        let path = self.path(&id);
+        // Same id, same text: unchanged bodies aren't rewritten
+        if !path.exists() {
+            fs::create_dir_all(&self.dir)?;
+            fs::write(&path, content.as_bytes())?;
+        }
+        content.clear();
+        *blob = Some(id);
+        Ok(())
+    }
+
+    fn unstash(
+        &self,
+        note_id: &str,
+        content: &mut String,
+        blob: &mut Option<String>,
+    ) -> io::Result<()> {
+        if let Some(id) = blob.take() {
+            *content = fs::read_to_string(self.path(&id)).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Content of note {} ({}) is unreadable: {}", note_id, id, e),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Copies of `notes` ready to persist: content (including earlier
+    /// revisions) over `limit` bytes is written out and replaced by a
+    /// reference. A `limit` of 0 keeps everything inline.
+    pub fn externalize(&self, notes: &[Note], limit: usize) -> io::Result<Vec<Note>> {
+        let mut stored = notes.to_vec();
+        for note in &mut stored {
+            self.stash(&mut note.content, &mut note.content_blob, limit)?;
+            for revision in &mut note.revisions {
+                self.stash(&mut revision.content, &mut revision.content_blob, limit)?;
+            }
+        }
+        Ok(stored)
+    }
+
+    /// Puts externalized content back into loaded notes. A missing blob is
+    /// `InvalidData`, like any other damage to the store.
+    pub fn internalize(&self, notes: &mut [Note]) -> io::Result<()> {
+        for note in notes {
+            self.unstash(&note.id, &mut note.content, &mut note.content_blob)?;
+            for revision in &mut note.revisions {
+                self.unstash(&note.id, &mut revision.content, &mut revision.content_blob)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes blobs no longer referenced by `stored` (as returned by
+    /// [`BlobStore::externalize`]).
+    pub fn remove_unused(&self, stored: &[Note]) {
+        let used: HashSet<&str> = stored
+            .iter()
+            .flat_map(|note| {
+                std::iter::once(&note.content_blob)
+                    .chain(note.revisions.iter().map(|revision| &revision.content_blob))
+            })
+            .filter_map(|blob| blob.as_deref())
+            .collect();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let unused = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|id| !used.contains(id));
+            if unused && path.extension().is_some_and(|ext| ext == "txt") {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Directory for the blobs of the store at `storage_path`.
+pub fn dir_for(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join("blobs")
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A response body as it was sent, ready to be sent again.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+struct Entry {
+    response: CachedResponse,
+    last_used: u64,
+}
+
+/// Least-recently-used cache of rendered responses for endpoints that
+/// aggregate or render the whole store. Entries belong to one generation of
+/// the notes (see `NotesManager::generation`); any change to the notes
+/// empties the cache.
+pub struct ResponseCache {
+    /// Most entries kept; 0 turns caching off
+    capacity: usize,
+    generation: u64,
+    entries: HashMap<String, Entry>,
+    clock: u64,
+}
+
+pub type SharedResponseCache = Arc<Mutex<ResponseCache>>;
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        ResponseCache {
+            capacity,
+            generation: 0,
+            entries: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    // Entries from an older generation of the notes are stale
+    fn sync(&mut self, generation: u64) {
+        if generation != self.generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+    }
+
+    pub fn get(&mut self, key: &str, generation: u64) -> Option<CachedResponse> {
+        self.sync(generation);
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.response.clone())
+    }
+
+    pub fn insert(&mut self, key: String, generation: u64, response: CachedResponse) {
+        self.sync(generation);
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            Entry {
+                response,
+                last_used: self.clock,
+            },
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use crate::models::Note;
+use crate::outbound;
+use chrono::{DateTime, Utc};
+use scraper::{ElementRef, Html, Node, Selector};
+use std::io;
+use std::time::Duration;
+use url::Url;
+
+/// Tag put on every captured page.
+pub const CAPTURE_TAG: &str = "web-clip";
+
+// Stands in for indentation the converter means (nested list items), so
+// `tidy` can tell it from stray whitespace. Text never contains it: collapsed
+// whitespace becomes plain spaces
+const INDENT: char = '\u{a0}';
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Page chrome and non-content elements, never part of the article
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "button", "iframe", "svg", "canvas",
+];
+
+/// A web page reduced to its readable article.
+#[derive(Debug, Clone)]
+pub struct Captured {
+    pub url: String,
+    pub title: String,
+    /// The article converted to Markdown
+    pub markdown: String,
+}
+
+/// Parses `url`, accepting only http(s) pages.
+pub fn parse_url(url: &str) -> io::Result<Url> {
+    let parsed = Url::parse(url.trim())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid URL: {}", e)))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(parsed),
+        scheme => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Only http(s) pages can be captured, not {}://", scheme),
+        )),
+    }
+}
+
+/// Downloads the page at `url` and extracts its article. Pages on internal
+/// addresses, even when redirected to, aren't fetched. Network and HTTP
+/// errors are `Other`, non-HTML responses `InvalidData`.
+pub fn capture(url: &str) -> io::Result<Captured> {
+    let url = parse_url(url)?;
+    let response = outbound::public_agent()
+        .build()
+        .get(url.as_str())
+        .timeout(FETCH_TIMEOUT)
+        .set(
+            "User-Agent",
+            concat!("notes-app/", env!("CARGO_PKG_VERSION")),
+        )
+        .call()
+        .map_err(|e| io::Error::other(format!("Could not fetch {}: {}", url, e)))?;
+
+    let content_type = response.content_type().to_string();
+    if !content_type.contains("html") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not an HTML page ({})", url, content_type),
+        ));
+    }
+    // Redirects may have moved us; relative links resolve against the final page
+    let final_url = Url::parse(response.get_url()).unwrap_or(url);
+    let html = response.into_string()?;
+    Ok(extract(&html, &final_url))
+}
+
+impl Captured {
+    /// A new note holding the page, created at `now` and tagged `web-clip`
+    /// plus `tags`.
+    pub fn into_note(self, tags: &[String], now: DateTime<Utc>) -> Note {
+        let mut all_tags = vec![CAPTURE_TAG.to_string()];
+        for tag in tags.iter().map(|tag| tag.trim()) {
+            if !tag.is_empty() && !all_tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                all_tags.push(tag.to_string());
+            }
+        }
+        let mut note = Note::new_at(self.title, self.markdown, all_tags, now);
+        note.source_url = Some(self.url);
+        note
+    }
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid selector")
+}
+
+fn text_len(element: &ElementRef) -> usize {
+    element.text().map(|text| text.trim().len()).sum()
+}
+
+// Text held directly in an element's paragraphs: high for article bodies,
+// low for link lists and layout wrappers
+fn paragraph_score(element: &ElementRef) -> usize {
+    element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|child| child.value().name() == "p")
+        .map(|p| text_len(&p))
+        .sum()
+}
+
+/// Picks the element holding the article: `<article>` or `<main>` when the
+/// page has one, otherwise the block with the most paragraph text.
+fn main_content(document: &Html) -> ElementRef<'_> {
+    for css in ["article", "main", "[role=main]"] {
+        if let Some(element) = document
+            .select(&selector(css))
+            .max_by_key(text_len)
+            .filter(|element| text_len(element) > 0)
+        {
+            return element;
+        }
+    }
+    document
+        .select(&selector("div, section, td"))
+        .max_by_key(paragraph_score)
+        .filter(|element| paragraph_score(element) > 0)
+        .or_else(|| document.select(&selector("body")).next())
+        .unwrap_or_else(|| document.root_element())
+}
+
+fn page_title(document: &Html) -> Option<String> {
+    let meta = document
+        .select(&selector(r#"meta[property="og:title"]"#))
+        .find_map(|meta| meta.value().attr("content").map(str::to_string));
+    let text_of = |css: &str| {
+        document
+            .select(&selector(css))
+            .next()
+            .map(|element| element.text().collect::<String>())
+    };
+    meta.or_else(|| text_of("title"))
+        .or_else(|| text_of("h1"))
+        .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|title| !title.is_empty())
+}
+
+/// Extracts the readable article of a page as Markdown.
+pub fn extract(html: &str, url: &Url) -> Captured {
+    let document = Html::parse_document(html);
+    let converter = Converter { base: url };
+    let markdown = converter.children(main_content(&document), false);
+
+    Captured {
+        url: url.to_string(),
+        title: page_title(&document).unwrap_or_else(|| url.to_string()),
+        markdown: tidy(&markdown).replace(INDENT, " "),
+    }
+}
+
+// Collapses the blank lines left between blocks and strips stray spaces,
+// leaving code blocks untouched
+fn tidy(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut blank_lines = 0;
+    let mut in_fence = false;
+    for line in markdown.lines() {
+        let fence = line.trim_start_matches([' ', INDENT]).starts_with("```");
+        if !in_fence && line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        if in_fence && !fence {
+            out.push_str(line);
+        } else {
+            out.push_str(line.trim_matches(' '));
+            // A trailing double space is a line break
+            if line.ends_with("  ") {
+                out.push_str("  ");
+            }
+        }
+        in_fence ^= fence;
+        blank_lines = 0;
+    }
+    out
+}
+
+/// HTML to Markdown for the common article elements; anything else
+/// contributes just its text.
+struct Converter<'a> {
+    base: &'a Url,
+}
+
+impl Converter<'_> {
+    fn children(&self, element: ElementRef, in_pre: bool) -> String {
+        element
+            .children()
+            .map(|child| match child.value() {
+                Node::Text(text) if in_pre => text.to_string(),
+                Node::Text(text) => collapse_whitespace(text),
+                Node::Element(_) => ElementRef::wrap(child)
+                    .map(|child| self.element(child, in_pre))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    fn inline(&self, element: ElementRef) -> String {
+        self.children(element, false).trim().to_string()
+    }
+
+    fn absolute(&self, link: &str) -> String {
+        self.base
+            .join(link)
+            .map(|url| url.to_string())
+            .unwrap_or_else(|_| link.to_string())
+    }
+
+    fn element(&self, element: ElementRef, in_pre: bool) -> String {
+        let name = element.value().name();
+        if SKIPPED.contains(&name) {
+            return String::new();
+        }
+
+        let wrap = |marker: &str| {
+            let text = self.inline(element);
+            if text.is_empty() {
+                String::new()
+            } else {
+                format!("{}{}{}", marker, text, marker)
+            }
+        };
+
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                format!("\n\n{} {}\n\n", "#".repeat(level), self.inline(element))
+            }
+            "p" | "div" | "section" | "article" | "main" | "figure" | "table" | "tr" => {
+                format!("\n\n{}\n\n", self.children(element, in_pre))
+            }
+            "br" => "  \n".to_string(),
+            "hr" => "\n\n---\n\n".to_string(),
+            "strong" | "b" => wrap("**"),
+            "em" | "i" => wrap("*"),
+            "code" if in_pre => self.children(element, true),
+            "code" => wrap("`"),
+            "pre" => {
+                let code = self.children(element, true);
+                format!("\n\n```\n{}\n```\n\n", code.trim_matches('\n'))
+            }
+            "a" => {
+                let text = self.inline(element);
+                match element.value().attr("href") {
+                    Some(href) if !text.is_empty() && !href.starts_with('#') => {
+                        format!("[{}]({})", text, self.absolute(href))
+                    }
+                    _ => text,
+                }
+            }
+            "img" => match element.value().attr("src") {
+                Some(src) => format!(
+                    "![{}]({})",
+                    element.value().attr("alt").unwrap_or_default(),
+                    self.absolute(src)
+                ),
+                None => String::new(),
+            },
+            "ul" | "ol" => {
+                let items: Vec<String> = element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|child| child.value().name() == "li")
+                    .enumerate()
+                    .map(|(i, item)| {
+                        let marker = if name == "ol" {
+                            format!("{}. ", i + 1)
+                        } else {
+                            "- ".to_string()
+                        };
+                        let body = tidy(&self.children(item, false));
+                        let indent = INDENT.to_string().repeat(marker.len());
+                        format!("{}{}", marker, body.replace('\n', &format!("\n{}", indent)))
+                    })
+                    .collect();
+                format!("\n\n{}\n\n", items.join("\n"))
+            }
+            "blockquote" => {
+                let quoted = tidy(&self.children(element, false));
+                let lines: Vec<String> = quoted
+                    .lines()
+                    .map(|line| format!("> {}", line).trim_end().to_string())
+                    .collect();
+                format!("\n\n{}\n\n", lines.join("\n"))
+            }
+            _ => self.children(element, in_pre),
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                out.push(' ');
+            }
+            last_space = true;
+        } else {
+            out.push(c);
+            last_space = false;
+        }
+    }
+    out
+}
@@ -0,0 +1,74 @@
+use crate::models::Note;
+use crate::snooze;
+use crate::storage::NotesManager;
+use crate::tags;
+use std::collections::HashSet;
+
+/// Which notes a bulk cleanup removes; every condition given must hold,
+/// and at least one must be given.
+#[derive(Debug, Clone, Default)]
+pub struct CleanupFilter {
+    /// Tagged with this tag or one nested under it
+    pub tag: Option<String>,
+    /// Untouched for at least this long (`90d`, `12w`)
+    pub older_than: Option<String>,
+    /// Found by this search
+    pub query: Option<String>,
+}
+
+impl CleanupFilter {
+    pub fn is_empty(&self) -> bool {
+        [&self.tag, &self.older_than, &self.query]
+            .iter()
+            .all(|condition| condition.as_deref().is_none_or(|c| c.trim().is_empty()))
+    }
+}
+
+/// The notes `filter` picks among those `allowed`, oldest first. Notes
+/// already in the trash are only included with `include_trashed`.
+pub fn matching(
+    mgr: &NotesManager,
+    filter: &CleanupFilter,
+    include_trashed: bool,
+    allowed: &dyn Fn(&Note) -> bool,
+) -> Result<Vec<Note>, String> {
+    if filter.is_empty() {
+        return Err("Give a tag, an age (older_than) or a query to clean up by".to_string());
+    }
+    let cutoff = match filter.older_than.as_deref().map(str::trim) {
+        Some(age) if !age.is_empty() => {
+            let age = snooze::parse_duration(age)
+                .ok_or_else(|| format!("Invalid age: {} (expected e.g. 90d or 12w)", age))?;
+            Some(mgr.now() - age)
+        }
+        _ => None,
+    };
+    let tag = filter
+        .tag
+        .as_deref()
+        .filter(|tag| !tag.trim().is_empty())
+        .map(|tag| mgr.tag_rules().canonical(tag));
+    let found: Option<HashSet<String>> = filter
+        .query
+        .as_deref()
+        .filter(|query| !query.trim().is_empty())
+        .map(|query| {
+            mgr.search_notes(query)
+                .into_iter()
+                .map(|note| note.id)
+                .collect()
+        });
+
+    let mut notes: Vec<Note> = mgr
+        .notes
+        .iter()
+        .filter(|note| include_trashed || !note.is_trashed())
+        .filter(|note| tag.as_deref().is_none_or(|tag| tags::has_tag(note, tag)))
+        .filter(|note| cutoff.is_none_or(|cutoff| note.updated_at <= cutoff))
+        .filter(|note| found.as_ref().is_none_or(|found| found.contains(&note.id)))
+        .filter(|note| allowed(note))
+        .cloned()
+        .collect();
+    notes.sort_by_key(|note| note.updated_at);
+    Ok(notes)
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Duration, Utc};
+use std::sync::{Arc, Mutex};
+
+/// Where note timestamps come from. The notes manager asks its clock
+/// instead of the system, so time can be frozen or moved on, e.g. to see
+/// snoozed notes wake up or due dates pass.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The system's time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that stands still until it's set or advanced.
+// Not used by the binary; it's for tests and apps embedding the crate
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[allow(dead_code)]
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        FixedClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scratch_store;
+    use crate::storage::NotesManager;
+    use chrono::TimeZone;
+
+    #[test]
+    fn notes_are_stamped_by_the_managers_clock() {
+        let (dir, uri) = scratch_store();
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let mut mgr = NotesManager::new(&uri).unwrap();
+        mgr.set_clock(clock.clone());
+
+        let note = mgr
+            .add_note("Plan".to_string(), "draft".to_string(), Vec::new())
+            .unwrap();
+        assert_eq!(note.created_at, start);
+        assert_eq!(mgr.now(), start);
+
+        clock.advance(Duration::days(2));
+        let updated = mgr
+            .update_note(&note.id, None, Some("final".to_string()), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.created_at, start);
+        assert_eq!(updated.updated_at, start + Duration::days(2));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
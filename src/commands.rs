@@ -0,0 +1,1057 @@
+use crate::users::Role;
+use serde::Serialize;
+
+/// Where a parameter goes in the request.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamIn {
+    /// Replaces `{name}` in the path
+    Path,
+    Query,
+    /// A field of the JSON body
+    Body,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    String,
+    Number,
+    Boolean,
+    /// Array of strings
+    List,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Param {
+    pub name: &'static str,
+    #[serde(rename = "in")]
+    pub location: ParamIn,
+    #[serde(rename = "type")]
+    pub kind: ParamType,
+    pub required: bool,
+    pub description: &'static str,
+    /// The only values accepted, if there's a fixed set
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub values: &'static [&'static str],
+}
+
+/// Something a client can offer the user to do, e.g. as an entry of a
+/// command palette, and the request that does it.
+#[derive(Debug, Serialize)]
+pub struct Command {
+    /// Stable name, e.g. `note.create`
+    pub id: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// For grouping in a palette: `notes`, `search`, `tags`, `export`, `settings`, `admin`
+    pub category: &'static str,
+    /// Applies to one note, whose id the client fills in as `{id}`
+    pub needs_note: bool,
+    pub method: &'static str,
+    /// Below the mount point of the API
+    pub path: &'static str,
+    pub params: &'static [Param],
+    /// Least role that may run it
+    pub role: Role,
+}
+
+const fn param(
+    name: &'static str,
+    location: ParamIn,
+    kind: ParamType,
+    required: bool,
+    description: &'static str,
+) -> Param {
+    Param {
+        name,
+        location,
+        kind,
+        required,
+        description,
+        values: &[],
+    }
+}
+
+const NOTE_ID: Param = param(
+    "id",
+    ParamIn::Path,
+    ParamType::String,
+    true,
+    "Id of the note",
+);
+
+const NOTEBOOK_ID: Param = param(
+    "notebook",
+    ParamIn::Path,
+    ParamType::String,
+    true,
+    "Id of the notebook",
+);
+
+const STATES: &[&str] = &["active", "archived", "trashed", "snoozed", "all"];
+const SORTS: &[&str] = &["added", "updated", "created", "title", "last_viewed"];
+const PERIODS: &[&str] = &["today", "week", "month", "year"];
+const SEARCH_SCOPES: &[&str] = &["active", "trash", "revisions", "all"];
+const VISIBILITIES: &[&str] = &["private", "team"];
+const MEMBER_ACCESS: &[&str] = &["read", "write"];
+const EXPORT_FORMATS: &[&str] = &["markdown", "json", "pdf"];
+
+/// Every command, in the order a palette lists them.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        id: "note.create",
+        title: "New note",
+        description: "Create a note; without a title one is made from the content",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notes",
+        params: &[
+            param(
+                "title",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Title of the note",
+            ),
+            param(
+                "content",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Markdown text",
+            ),
+            param(
+                "tags",
+                ParamIn::Body,
+                ParamType::List,
+                true,
+                "Tags, nested with `/`",
+            ),
+            param(
+                "parent_id",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Note to file it under",
+            ),
+            param(
+                "allow_duplicate",
+                ParamIn::Query,
+                ParamType::Boolean,
+                false,
+                "Create it even if it's nearly the same as an existing note",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.from_url",
+        title: "Save web page",
+        description: "Create a note from the main article of a page",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notes/from-url",
+        params: &[
+            param(
+                "url",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Address of the page",
+            ),
+            param(
+                "tags",
+                ParamIn::Body,
+                ParamType::List,
+                false,
+                "Tags for the note",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.from_template",
+        title: "New note from template",
+        description: "Create a note from one of the configured templates",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/templates/{name}/notes",
+        params: &[param(
+            "name",
+            ParamIn::Path,
+            ParamType::String,
+            true,
+            "Template name, from GET /api/templates",
+        )],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.list",
+        title: "List notes",
+        description: "List notes, optionally by tag and state",
+        category: "notes",
+        needs_note: false,
+        method: "GET",
+        path: "/api/notes",
+        params: &[
+            param(
+                "tag",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Only notes with this tag or one below it",
+            ),
+            Param {
+                values: STATES,
+                ..param(
+                    "state",
+                    ParamIn::Query,
+                    ParamType::String,
+                    false,
+                    "Which notes to list",
+                )
+            },
+            Param {
+                values: SORTS,
+                ..param(
+                    "sort",
+                    ParamIn::Query,
+                    ParamType::String,
+                    false,
+                    "Order of the list",
+                )
+            },
+            Param {
+                values: PERIODS,
+                ..param(
+                    "created",
+                    ParamIn::Query,
+                    ParamType::String,
+                    false,
+                    "Only notes created since the start of this period",
+                )
+            },
+            param(
+                "untouched",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Only notes not edited since then, e.g. 6mo, 26w or 2026-01-31",
+            ),
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.on_this_day",
+        title: "On this day",
+        description: "Notes written on this day in earlier years",
+        category: "notes",
+        needs_note: false,
+        method: "GET",
+        path: "/api/notes/on-this-day",
+        params: &[param(
+            "date",
+            ParamIn::Query,
+            ParamType::String,
+            false,
+            "Look back from this day (YYYY-MM-DD) instead of today",
+        )],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.open",
+        title: "Open note",
+        description: "Fetch a note",
+        category: "notes",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}",
+        params: &[NOTE_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.update",
+        title: "Edit note",
+        description: "Change the title, content or tags of a note",
+        category: "notes",
+        needs_note: true,
+        method: "PUT",
+        path: "/api/notes/{id}",
+        params: &[
+            NOTE_ID,
+            param(
+                "title",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "New title",
+            ),
+            param(
+                "content",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "New content",
+            ),
+            param("tags", ParamIn::Body, ParamType::List, false, "New tags"),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.print",
+        title: "Print note",
+        description: "Open a page made for printing the note",
+        category: "notes",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}/print",
+        params: &[NOTE_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.related",
+        title: "Related notes",
+        description: "Find other notes on the same topic",
+        category: "notes",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}/related",
+        params: &[
+            NOTE_ID,
+            param(
+                "limit",
+                ParamIn::Query,
+                ParamType::Number,
+                false,
+                "At most this many (default 5)",
+            ),
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.comments",
+        title: "Show comments",
+        description: "List the comments left on a note",
+        category: "notes",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}/comments",
+        params: &[NOTE_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.comment",
+        title: "Comment on note",
+        description: "Leave a comment beneath a note",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/comments",
+        params: &[
+            NOTE_ID,
+            param(
+                "body",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Text of the comment",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.react",
+        title: "React to note",
+        description: "Mark a note with an emoji, like 👍 or ⭐",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/reactions",
+        params: &[
+            NOTE_ID,
+            param(
+                "emoji",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "A single emoji",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.link_check",
+        title: "Check links",
+        description: "Find wiki-links and web links in a note that lead nowhere",
+        category: "notes",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}/link-check",
+        params: &[NOTE_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.trash",
+        title: "Move to trash",
+        description: "Move a note to the trash, where it can be restored",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/trash",
+        params: &[NOTE_ID],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.restore",
+        title: "Restore from trash",
+        description: "Take a note out of the trash",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/restore",
+        params: &[NOTE_ID],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.archive",
+        title: "Archive note",
+        description: "Keep a note, but out of the way",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/archive",
+        params: &[NOTE_ID],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.unarchive",
+        title: "Unarchive note",
+        description: "Bring an archived note back",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/unarchive",
+        params: &[NOTE_ID],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.summarize",
+        title: "Summarize note",
+        description: "Have the configured language model write a summary into the note's metadata",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/summarize",
+        params: &[NOTE_ID],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.snooze",
+        title: "Snooze note",
+        description: "Hide a note until a given time",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/notes/{id}/snooze",
+        params: &[
+            NOTE_ID,
+            param(
+                "until",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "When the note shows up again: a duration (`3d`, `12h`) or a time",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.slug",
+        title: "Set note slug",
+        description: "Give a note a memorable address, /api/notes/by-slug/{slug}",
+        category: "notes",
+        needs_note: true,
+        method: "PUT",
+        path: "/api/notes/{id}/slug",
+        params: &[
+            NOTE_ID,
+            param(
+                "slug",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Lowercase letters, digits and dashes, unique in the vault",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.notebook",
+        title: "Move to notebook",
+        description: "Put a note into a notebook, sharing it with the notebook's members",
+        category: "notes",
+        needs_note: true,
+        method: "PUT",
+        path: "/api/notes/{id}/notebook",
+        params: &[
+            NOTE_ID,
+            param(
+                "notebook",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Id of the notebook",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "notebook.create",
+        title: "New notebook",
+        description: "Start a notebook to share notes with other users",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notebooks",
+        params: &[
+            param("name", ParamIn::Body, ParamType::String, true, "Name"),
+            Param {
+                values: VISIBILITIES,
+                ..param(
+                    "visibility",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "Whether every user may read its notes",
+                )
+            },
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "notebook.invite",
+        title: "Invite to notebook",
+        description: "Invite a user into a notebook; they join once they accept",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notebooks/{notebook}/members",
+        params: &[
+            NOTEBOOK_ID,
+            param(
+                "user",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Username or id of the user",
+            ),
+            Param {
+                values: MEMBER_ACCESS,
+                ..param(
+                    "access",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "What they may do with its notes",
+                )
+            },
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "notebook.accept",
+        title: "Join notebook",
+        description: "Accept an invitation into a notebook",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notebooks/{notebook}/members/accept",
+        params: &[NOTEBOOK_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "conflict.list",
+        title: "Show conflicts",
+        description: "List conflict copies with the notes they collided with",
+        category: "notes",
+        needs_note: false,
+        method: "GET",
+        path: "/api/conflicts",
+        params: &[],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "conflict.resolve",
+        title: "Resolve conflict",
+        description: "Keep one version, both, or a merge of a conflict copy and its note",
+        category: "notes",
+        needs_note: true,
+        method: "POST",
+        path: "/api/conflicts/{id}/resolve",
+        params: &[
+            param(
+                "id",
+                ParamIn::Path,
+                ParamType::String,
+                true,
+                "Id of the conflict copy",
+            ),
+            Param {
+                values: &["original", "copy", "both"],
+                ..param(
+                    "keep",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "Which version to keep",
+                )
+            },
+            param(
+                "content",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Merged content for the note, instead of keep",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "note.merge",
+        title: "Merge notes",
+        description: "Merge notes into the first one given",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notes/merge",
+        params: &[
+            param(
+                "ids",
+                ParamIn::Body,
+                ParamType::List,
+                true,
+                "Notes to merge, the one to keep first",
+            ),
+            Param {
+                values: &["concatenate", "keep-longest"],
+                ..param(
+                    "strategy",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "How the contents are combined",
+                )
+            },
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "search",
+        title: "Search",
+        description: "Search the notes",
+        category: "search",
+        needs_note: false,
+        method: "GET",
+        path: "/api/notes/search/{query}",
+        params: &[
+            param(
+                "query",
+                ParamIn::Path,
+                ParamType::String,
+                true,
+                "What to look for",
+            ),
+            param(
+                "tag",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Only notes with this tag",
+            ),
+            Param {
+                values: SEARCH_SCOPES,
+                ..param(
+                    "scope",
+                    ParamIn::Query,
+                    ParamType::String,
+                    false,
+                    "Where to look: notes, the trash, past versions or everywhere",
+                )
+            },
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "tags.bulk",
+        title: "Bulk edit tags",
+        description: "Add and remove tags on every note matching a search",
+        category: "tags",
+        needs_note: false,
+        method: "POST",
+        path: "/api/notes/bulk-tag",
+        params: &[
+            param(
+                "query",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Search selecting the notes",
+            ),
+            param("add", ParamIn::Body, ParamType::List, false, "Tags to add"),
+            param(
+                "remove",
+                ParamIn::Body,
+                ParamType::List,
+                false,
+                "Tags to remove",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "notes.replace",
+        title: "Find and replace",
+        description: "Replace text in every matching note, after a dry run",
+        category: "notes",
+        needs_note: false,
+        method: "POST",
+        path: "/api/replace",
+        params: &[
+            param(
+                "find",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "Text to find",
+            ),
+            param(
+                "replace",
+                ParamIn::Body,
+                ParamType::String,
+                true,
+                "What to put in its place",
+            ),
+            param(
+                "regex",
+                ParamIn::Body,
+                ParamType::Boolean,
+                false,
+                "Find is a regular expression; $1 in replace is its first group",
+            ),
+            param(
+                "ignore_case",
+                ParamIn::Body,
+                ParamType::Boolean,
+                false,
+                "Match upper and lower case alike",
+            ),
+            param(
+                "query",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Only notes matching this search",
+            ),
+            param(
+                "tag",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Only notes with this tag",
+            ),
+            param(
+                "confirm",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Token of the dry run to apply; without it nothing changes",
+            ),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "tags.rename",
+        title: "Rename tag",
+        description: "Rename a tag together with the tags below it",
+        category: "tags",
+        needs_note: false,
+        method: "PUT",
+        path: "/api/tags/{tag}",
+        params: &[
+            param(
+                "tag",
+                ParamIn::Path,
+                ParamType::String,
+                true,
+                "Tag to rename",
+            ),
+            param("name", ParamIn::Body, ParamType::String, true, "New name"),
+        ],
+        role: Role::Editor,
+    },
+    Command {
+        id: "export",
+        title: "Export notes",
+        description: "Download the notes matching a filter as one file",
+        category: "export",
+        needs_note: false,
+        method: "GET",
+        path: "/api/export",
+        params: &[
+            Param {
+                values: EXPORT_FORMATS,
+                ..param(
+                    "format",
+                    ParamIn::Query,
+                    ParamType::String,
+                    false,
+                    "File format",
+                )
+            },
+            param(
+                "tag",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Only notes with this tag",
+            ),
+            param(
+                "from",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Created on or after this date",
+            ),
+            param(
+                "to",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Created on or before this date",
+            ),
+            param(
+                "query",
+                ParamIn::Query,
+                ParamType::String,
+                false,
+                "Only notes matching this search",
+            ),
+            param(
+                "zip",
+                ParamIn::Query,
+                ParamType::Boolean,
+                false,
+                "A zip of Markdown or JSON notes with their attachments",
+            ),
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "note.pdf",
+        title: "Export note as PDF",
+        description: "Download a note as a PDF",
+        category: "export",
+        needs_note: true,
+        method: "GET",
+        path: "/api/notes/{id}/pdf",
+        params: &[NOTE_ID],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "preferences.update",
+        title: "Preferences",
+        description: "Change your preferences",
+        category: "settings",
+        needs_note: false,
+        method: "PUT",
+        path: "/api/preferences",
+        params: &[
+            Param {
+                values: SORTS,
+                ..param(
+                    "sort",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "Order of note lists",
+                )
+            },
+            param(
+                "page_size",
+                ParamIn::Body,
+                ParamType::Number,
+                false,
+                "Notes shown at a time",
+            ),
+            Param {
+                values: &["light", "dark", "system"],
+                ..param(
+                    "theme",
+                    ParamIn::Body,
+                    ParamType::String,
+                    false,
+                    "Colors of the web UI",
+                )
+            },
+            param(
+                "date_format",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "`strftime`-style",
+            ),
+            param(
+                "timezone",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "`local`, `UTC` or an offset like `+02:00`",
+            ),
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "keymap.update",
+        title: "Keyboard shortcuts",
+        description: "Change the web UI's keyboard shortcuts",
+        category: "settings",
+        needs_note: false,
+        method: "PUT",
+        path: "/api/ui/keymap",
+        params: &[
+            param(
+                "new_note",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Start a new note, e.g. `Alt+N`",
+            ),
+            param(
+                "search",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Jump to the search box, e.g. `/`",
+            ),
+            param(
+                "save",
+                ParamIn::Body,
+                ParamType::String,
+                false,
+                "Save the note being edited, e.g. `Ctrl+S`",
+            ),
+        ],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "admin.verify",
+        title: "Verify data",
+        description: "Check the notes and attachments for problems",
+        category: "admin",
+        needs_note: false,
+        method: "GET",
+        path: "/api/admin/verify",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.compact",
+        title: "Compact storage",
+        description: "Delete files no note refers to",
+        category: "admin",
+        needs_note: false,
+        method: "POST",
+        path: "/api/admin/compact",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.link_check",
+        title: "Check all links",
+        description: "Check every note's links now",
+        category: "admin",
+        needs_note: false,
+        method: "POST",
+        path: "/api/admin/link-check",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.backup",
+        title: "Back up now",
+        description: "Take a backup and upload it to the configured bucket",
+        category: "admin",
+        needs_note: false,
+        method: "POST",
+        path: "/api/admin/backup",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.backups",
+        title: "Show backups",
+        description: "List the backups, locally and in the bucket",
+        category: "admin",
+        needs_note: false,
+        method: "GET",
+        path: "/api/admin/backups",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.config",
+        title: "Show config",
+        description: "Compare the settings in effect with the config file",
+        category: "admin",
+        needs_note: false,
+        method: "GET",
+        path: "/api/admin/config",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.storage",
+        title: "Show storage usage",
+        description: "How close the vault and your notes are to their quotas",
+        category: "admin",
+        needs_note: false,
+        method: "GET",
+        path: "/api/stats/storage",
+        params: &[],
+        role: Role::Viewer,
+    },
+    Command {
+        id: "admin.reindex",
+        title: "Rebuild indexes",
+        description: "Work out tags, languages and outlines again",
+        category: "admin",
+        needs_note: false,
+        method: "POST",
+        path: "/api/admin/reindex",
+        params: &[],
+        role: Role::Admin,
+    },
+    Command {
+        id: "admin.policy",
+        title: "Apply retention policy",
+        description: "Run the configured retention rules now",
+        category: "admin",
+        needs_note: false,
+        method: "POST",
+        path: "/api/admin/policy/run",
+        params: &[],
+        role: Role::Admin,
+    },
+];
+
+/// The commands someone with `role` may run.
+pub fn available(role: Role) -> Vec<&'static Command> {
+    COMMANDS
+        .iter()
+        .filter(|command| command.role <= role)
+        .collect()
+}
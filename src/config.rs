@@ -0,0 +1,723 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+pub const DEFAULT_CONFIG_PATH: &str = "data/config.json";
+
+/// Name of the vault described by the top-level settings.
+pub const DEFAULT_VAULT: &str = "default";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub storage: StorageConfig,
+    pub search: SearchConfig,
+    pub server: ServerConfig,
+    pub transcription: TranscriptionConfig,
+    pub policy: PolicyConfig,
+    pub cli: CliConfig,
+    pub templates: Vec<TemplateConfig>,
+    pub webhooks: WebhooksConfig,
+    pub tags: TagsConfig,
+    pub titles: TitlesConfig,
+    pub links: LinksConfig,
+    pub backup: BackupConfig,
+    pub quota: QuotaConfig,
+    pub scripts: ScriptsConfig,
+    pub processors: ProcessorsConfig,
+    pub summary: SummaryConfig,
+    /// Other notebooks, each kept apart from the rest, by name. A vault's
+    /// section overrides any of the settings above for that vault
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vaults: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Where notes live: `file://data/notes.json` (or a bare path) or
+    /// `sqlite://data/notes.db`, if the build includes that backend
+    pub uri: String,
+    /// Note content longer than this many bytes is kept in `data/blobs/`
+    /// instead of the store itself; 0 keeps everything inline
+    pub inline_content_limit: usize,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            uri: "data/notes.json".to_string(),
+            inline_content_limit: crate::blobs::DEFAULT_INLINE_LIMIT,
+        }
+    }
+}
+
+/// Keeps tagging consistent: aliases are replaced by the tag they stand
+/// for, and implied tags are added, whenever a note is saved.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagsConfig {
+    /// e.g. `"js": "javascript"`
+    pub aliases: BTreeMap<String, String>,
+    /// e.g. `"rust": ["programming"]`
+    pub implies: BTreeMap<String, Vec<String>>,
+    /// Tag notes with the `#hashtags` written in their content
+    pub hashtags: bool,
+}
+
+/// Rhai scripts run when notes are created, edited, deleted or searched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptsConfig {
+    /// Directory holding the `.rhai` files; read when the vault is opened
+    pub dir: String,
+    /// Most steps one hook call may take before it's stopped; 0 is no limit
+    pub max_operations: u64,
+}
+
+impl Default for ScriptsConfig {
+    fn default() -> Self {
+        Self {
+            dir: "data/scripts".to_string(),
+            max_operations: 100_000,
+        }
+    }
+}
+
+/// What happens when a note is created without a title.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TitlesConfig {
+    /// Make one from the content instead of refusing the note
+    pub auto: bool,
+    /// Longest title made that way, in characters
+    pub max_length: usize,
+}
+
+impl Default for TitlesConfig {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            max_length: 60,
+        }
+    }
+}
+
+/// Checking that wiki-links lead to a note and web links still answer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinksConfig {
+    /// How long a web link gets to answer
+    pub timeout_secs: u64,
+    /// How often the server checks every note's links; 0 turns that off
+    pub check_interval_hours: u64,
+}
+
+impl Default for LinksConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 5,
+            check_interval_hours: 24,
+        }
+    }
+}
+
+/// Vault-file snapshots of the notes taken on a schedule, kept in a local
+/// directory and, when configured, uploaded to an S3-compatible bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Cron schedule (local time), e.g. "0 3 * * *"; no automatic backups when unset
+    pub schedule: Option<String>,
+    /// Directory the snapshots are written to
+    pub dir: String,
+    /// Keep the local snapshot after it was uploaded
+    pub keep_local: bool,
+    /// Password the snapshots are encrypted with; `NOTES_VAULT_PASSWORD` when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub s3: Option<S3Config>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            schedule: None,
+            dir: "data/backups".to_string(),
+            keep_local: true,
+            password: None,
+            s3: None,
+        }
+    }
+}
+
+/// Limits on how much a vault, and each user in it, may store. Notes in
+/// the trash count until they're deleted for good. Unset limits don't apply.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// The whole vault
+    #[serde(flatten)]
+    pub vault: QuotaLimits,
+    /// The notes each user owns, in multi-user mode
+    pub per_user: QuotaLimits,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_notes: Option<u64>,
+    /// Bytes of titles and content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// Bytes of attachments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attachment_bytes: Option<u64>,
+}
+
+/// An S3 bucket on AWS or an S3-compatible server (MinIO, Ceph, ...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct S3Config {
+    /// e.g. `https://s3.eu-central-1.amazonaws.com` or `http://minio.local:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Put in front of every snapshot's name, e.g. `notes/`
+    pub prefix: String,
+    /// Address the bucket as `endpoint/bucket` (what MinIO expects) rather
+    /// than `bucket.endpoint`
+    pub path_style: bool,
+    /// `AWS_ACCESS_KEY_ID` when empty
+    pub access_key_id: String,
+    /// `AWS_SECRET_ACCESS_KEY` when empty
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub secret_access_key: String,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".to_string(),
+            prefix: String::new(),
+            path_style: true,
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+        }
+    }
+}
+
+/// URLs that receive a JSON POST for every note event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhooksConfig {
+    pub targets: Vec<WebhookTarget>,
+    /// Retries after a failed delivery, waiting 1s, 2s, 4s, ... in between
+    pub max_retries: u32,
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            max_retries: 5,
+            timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookTarget {
+    pub url: String,
+    /// Event names to send, e.g. "note.created"; empty sends everything
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// External HTTP services that get notes as they're saved and may change or
+/// annotate them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessorsConfig {
+    /// Called in this order at their stage
+    pub pipeline: Vec<ProcessorConfig>,
+    pub timeout_secs: u64,
+}
+
+impl Default for ProcessorsConfig {
+    fn default() -> Self {
+        Self {
+            pipeline: Vec::new(),
+            timeout_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessorConfig {
+    pub name: String,
+    pub url: String,
+    pub stage: ProcessorStage,
+    /// Sent as a bearer token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Whether a pre-save processor that can't be reached stops the save;
+    /// otherwise it's skipped
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// When a processor is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessorStage {
+    /// Before a new or edited note is saved; may change its title, content,
+    /// tags and metadata, or refuse it
+    PreSave,
+    /// After it's saved, in the background; may add metadata
+    PostSave,
+}
+
+/// OpenAI-compatible chat completions API (OpenAI, a llama.cpp server,
+/// Ollama, ...) that writes note summaries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// Base URL of the API, e.g. `http://localhost:8080/v1`; summarizing is
+    /// off when unset
+    pub url: Option<String>,
+    /// Sent as a bearer token
+    pub api_key: Option<String>,
+    pub model: String,
+    /// Instructions sent ahead of the note
+    pub prompt: String,
+    /// Longer notes are cut off here before they're sent
+    pub max_input_chars: usize,
+    pub timeout_secs: u64,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            api_key: None,
+            model: "gpt-4o-mini".to_string(),
+            prompt: "Summarize the following note in a few sentences, in its own language. \
+                     Answer with the summary only."
+                .to_string(),
+            max_input_chars: 16_000,
+            timeout_secs: 60,
+        }
+    }
+}
+
+/// A note blueprint, optionally created automatically on a schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    pub name: String,
+    /// `{date}` is replaced with the (local) date the note is for
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Cron expression in local time, e.g. "0 9 * * Mon" for Mondays at 9am
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Create one note for runs missed while the app wasn't running
+    #[serde(default = "default_catch_up")]
+    pub catch_up: bool,
+}
+
+fn default_catch_up() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CliConfig {
+    /// Pick notes with an inline fuzzy finder instead of typing their number
+    /// (always off when the terminal can't support it)
+    pub fuzzy_picker: bool,
+    /// Command that edits note content, e.g. `vim` or `code --wait`;
+    /// `$VISUAL` / `$EDITOR` when unset
+    pub editor: Option<String>,
+    pub color: ColorChoice,
+    /// Ask for the note's title (or "yes") before deleting notes for good
+    /// or restoring them from the trash
+    pub confirm_destructive: bool,
+}
+
+impl Default for CliConfig {
+    fn default() -> Self {
+        Self {
+            fuzzy_picker: true,
+            editor: None,
+            color: ColorChoice::default(),
+            confirm_destructive: true,
+        }
+    }
+}
+
+/// Whether CLI output is colored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Colored on terminals, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn apply(self) {
+        match self {
+            ColorChoice::Auto => {}
+            ColorChoice::Always => colored::control::set_override(true),
+            ColorChoice::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// Retention rules applied periodically by the background policy task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    pub rules: Vec<PolicyRule>,
+    /// How often the rules run
+    pub interval_minutes: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            interval_minutes: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum PolicyRule {
+    /// Permanently delete notes that have been in the trash this long
+    PurgeTrash { after_days: i64 },
+    /// Archive notes not edited for this long
+    ArchiveUntouched { after_days: i64 },
+    /// Keep only the newest `max` revisions of each note
+    CapRevisions { max: usize },
+    /// Keep only the newest `keep` backup snapshots, locally and in the bucket
+    PruneBackups { keep: usize },
+}
+
+/// External speech-to-text program (e.g. whisper.cpp) run on audio attachments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranscriptionConfig {
+    /// Path to the binary; transcription is off when unset
+    pub command: Option<String>,
+    /// Arguments, with `{file}` replaced by the audio file path
+    pub args: Vec<String>,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            args: vec!["-nt".to_string(), "-f".to_string(), "{file}".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Base URL other devices use to reach the web server (e.g. your LAN
+    /// address), for short links and QR codes
+    pub public_url: Option<String>,
+    /// Port `notes-app web` listens on unless `--port` is given (8080 if unset)
+    pub port: Option<u16>,
+    pub cors: CorsConfig,
+    pub compression: CompressionConfig,
+    pub sanitizer: SanitizerConfig,
+    pub cache: CacheConfig,
+    /// When set, `/api/admin` requests must also send it in `X-Admin-Key`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+    pub problem_details: ProblemDetails,
+}
+
+/// When API errors are RFC 7807 `application/problem+json` documents
+/// instead of the usual `{"success": false, "message": ...}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProblemDetails {
+    /// For requests whose `Accept` header asks for them
+    #[default]
+    Negotiate,
+    Always,
+    Never,
+}
+
+/// In-memory cache of the stats, tag and rendered-HTML responses. It's
+/// emptied whenever a note changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Most responses kept (least recently used go first); 0 turns it off
+    pub capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { capacity: 256 }
+    }
+}
+
+/// What survives when note content is rendered to HTML. Notes may hold
+/// pasted markup; everything outside these lists is stripped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizerConfig {
+    /// Elements kept (their text is kept either way)
+    pub allowed_tags: Vec<String>,
+    /// Schemes links and images may point at
+    pub url_schemes: Vec<String>,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        Self {
+            allowed_tags: [
+                "a",
+                "b",
+                "blockquote",
+                "br",
+                "code",
+                "del",
+                "em",
+                "h1",
+                "h2",
+                "h3",
+                "h4",
+                "h5",
+                "h6",
+                "hr",
+                "i",
+                "img",
+                "li",
+                "ol",
+                "p",
+                "pre",
+                "s",
+                "strong",
+                "sub",
+                "sup",
+                "table",
+                "tbody",
+                "td",
+                "th",
+                "thead",
+                "tr",
+                "ul",
+            ]
+            .map(String::from)
+            .to_vec(),
+            url_schemes: ["http", "https", "mailto"].map(String::from).to_vec(),
+        }
+    }
+}
+
+/// gzip/brotli/zstd compression of responses, for clients that accept it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Smaller bodies are sent as they are; compressing them isn't worth it
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: 1024,
+        }
+    }
+}
+
+/// Which other web origins may call the API from a browser. The bundled UI is
+/// served from the same origin and needs no entry here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// e.g. "https://notes.example.com"; `"*"` allows any origin
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Seconds browsers may cache a preflight response
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: [
+                "Authorization",
+                "Content-Type",
+                "Accept",
+                "X-Lock-Holder",
+                "X-Vault",
+                "X-Admin-Key",
+                "X-Confirm-Delete",
+            ]
+            .map(String::from)
+            .to_vec(),
+            max_age: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Snowball stemmer language, e.g. "english", "german", "french"
+    pub language: String,
+    /// Reduce words to their stem so "running" matches "run"
+    pub stemming: bool,
+    /// Strip accents so "café" matches "cafe"
+    pub fold_diacritics: bool,
+    /// Recent queries remembered per user for suggestions; 0 keeps none
+    pub history_size: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            language: "english".to_string(),
+            stemming: true,
+            fold_diacritics: true,
+            history_size: 20,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to defaults when it doesn't exist.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let path = Path::new(path);
+
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        serde_json::from_reader(reader).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config file {}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// `default` followed by the configured vaults.
+    pub fn vault_names(&self) -> Vec<&str> {
+        std::iter::once(DEFAULT_VAULT)
+            .chain(self.vaults.keys().map(String::as_str))
+            .collect()
+    }
+
+    /// Settings for vault `name`: its section laid over the top-level
+    /// settings. A vault without a `storage.uri` of its own is stored in
+    /// `vaults/<name>/` next to the default notes file.
+    pub fn for_vault(&self, name: &str) -> io::Result<Config> {
+        if name == DEFAULT_VAULT {
+            return Ok(self.clone());
+        }
+        let section = self.vaults.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Unknown vault: {} (configured: {})",
+                    name,
+                    self.vault_names().join(", ")
+                ),
+            )
+        })?;
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid vault name: {} (use letters, digits, - and _)",
+                    name
+                ),
+            ));
+        }
+
+        let mut merged = serde_json::to_value(self)?;
+        overlay(&mut merged, section);
+        let mut config: Config = serde_json::from_value(merged).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid settings for vault {}: {}", name, e),
+            )
+        })?;
+        if section.pointer("/storage/uri").is_none() {
+            let (scheme, path) = crate::backend::parse_uri(&self.storage.uri);
+            let path = Path::new(path);
+            let file = path.file_name().unwrap_or("notes.json".as_ref());
+            let vault_path = path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join("vaults")
+                .join(name)
+                .join(file);
+            config.storage.uri = format!("{}://{}", scheme, vault_path.display());
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let path = Path::new(path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+// Lays `section` over `base`, object by object; other values replace what's there
+fn overlay(base: &mut Value, section: &Value) {
+    match (base, section) {
+        (Value::Object(base), Value::Object(section)) => {
+            for (key, value) in section {
+                // Vaults can't nest
+                if key == "vaults" {
+                    continue;
+                }
+                match base.get_mut(key) {
+                    Some(existing) => overlay(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, section) => *base = section.clone(),
+    }
+}
@@ -0,0 +1,115 @@
+use crate::models::Note;
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, AutomergeError, ObjId, ObjType, ReadDoc, ROOT};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Key of the text object holding the note's content
+const CONTENT_KEY: &str = "content";
+
+fn invalid(e: AutomergeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Note content as Automerge text documents (`<note id>.automerge`), so
+/// edits made offline on several devices merge character by character.
+/// Clients fetch a note's document, edit it locally and send back their
+/// changes; the merged text becomes the note's content.
+pub struct CrdtStore {
+    dir: PathBuf,
+}
+
+impl CrdtStore {
+    pub fn new(dir: PathBuf) -> Self {
+        CrdtStore { dir }
+    }
+
+    fn path(&self, note_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.automerge", note_id))
+    }
+
+    /// The note's document, created on first use. Edits that bypassed it
+    /// (plain PUTs, the CLI) are folded in as a change of its own, so the
+    /// document always reads as the note's current content.
+    pub fn document(&self, note: &Note) -> io::Result<AutoCommit> {
+        let mut doc = match fs::read(self.path(&note.id)) {
+            Ok(bytes) => AutoCommit::load(&bytes).map_err(invalid)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut doc = AutoCommit::new();
+                doc.put_object(ROOT, CONTENT_KEY, ObjType::Text)
+                    .map_err(invalid)?;
+                doc
+            }
+            Err(e) => return Err(e),
+        };
+        let text = content_object(&doc)?;
+        if doc.text(&text).map_err(invalid)? != note.content {
+            doc.update_text(&text, &note.content).map_err(invalid)?;
+        }
+        Ok(doc)
+    }
+
+    /// Applies `changes` (changes or a whole document saved by an Automerge
+    /// client) to the note's document and returns the merged document and
+    /// text. Nothing is stored here; call [`CrdtStore::save`] once the note
+    /// has the new content. Updates that don't descend from this note's
+    /// document would replace the text rather than merge with it, so they
+    /// are rejected as `InvalidInput`.
+    pub fn merge(&self, note: &Note, changes: &[u8]) -> io::Result<(AutoCommit, String)> {
+        let mut doc = self.document(note)?;
+        doc.load_incremental(changes).map_err(invalid)?;
+        if doc.get_all(ROOT, CONTENT_KEY).map_err(invalid)?.len() > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Update doesn't derive from this note's document; fetch it first",
+            ));
+        }
+        let text = doc.text(content_object(&doc)?).map_err(invalid)?;
+        Ok((doc, text))
+    }
+
+    pub fn save(&self, note_id: &str, doc: &mut AutoCommit) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(note_id), doc.save())
+    }
+
+    /// Deletes a note's document, e.g. once its content is encrypted.
+    pub fn remove(&self, note_id: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(note_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the documents of notes that no longer exist.
+    pub fn remove_unused(&self, exists: impl Fn(&str) -> bool) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let unused = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|id| !exists(id));
+            if unused && path.extension().is_some_and(|ext| ext == "automerge") {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn content_object(doc: &AutoCommit) -> io::Result<ObjId> {
+    match doc.get(ROOT, CONTENT_KEY).map_err(invalid)? {
+        Some((_, id)) if doc.object_type(&id).is_ok_and(|kind| kind == ObjType::Text) => Ok(id),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Document has no content text",
+        )),
+    }
+}
+
+/// Directory for the documents of the store at `storage_path`.
+pub fn dir_for(storage_path: &Path) -> PathBuf {
+    storage_path.parent().unwrap_or(Path::new(".")).join("crdt")
+}
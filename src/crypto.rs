@@ -0,0 +1,114 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Shown instead of the content of an encrypted note until it's unlocked.
+pub const LOCKED_PLACEHOLDER: &str = "🔒 This note is encrypted. Unlock it to read it.";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Content encrypted with AES-256-GCM under a key derived from the note's
+/// passphrase with Argon2id. All fields are base64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> io::Result<Key<Aes256Gcm>> {
+    let mut key = Key::<Aes256Gcm>::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| io::Error::other(format!("Key derivation failed: {}", e)))?;
+    Ok(key)
+}
+
+fn decode(field: &str) -> io::Result<Vec<u8>> {
+    STANDARD.decode(field).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Encrypted content is damaged: {}", e),
+        )
+    })
+}
+
+pub fn seal(plaintext: &str, passphrase: &str) -> io::Result<Sealed> {
+    let (salt, nonce, ciphertext) = encrypt(plaintext.as_bytes(), passphrase)?;
+    Ok(Sealed {
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+/// Decrypts `sealed`. A wrong passphrase is `PermissionDenied`.
+pub fn open(sealed: &Sealed, passphrase: &str) -> io::Result<String> {
+    let plaintext = decrypt(
+        &decode(&sealed.salt)?,
+        &decode(&sealed.nonce)?,
+        &decode(&sealed.ciphertext)?,
+        passphrase,
+    )?;
+    String::from_utf8(plaintext).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Encrypts binary data like [`seal`], as salt, nonce and ciphertext in a row.
+pub fn seal_bytes(plaintext: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    let (salt, nonce, ciphertext) = encrypt(plaintext, passphrase)?;
+    Ok([&salt[..], &nonce[..], &ciphertext].concat())
+}
+
+/// Decrypts the output of [`seal_bytes`]. A wrong passphrase is `PermissionDenied`.
+pub fn open_bytes(sealed: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Encrypted content is damaged: too short",
+        ));
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    decrypt(salt, nonce, ciphertext, passphrase)
+}
+
+fn encrypt(
+    plaintext: &[u8],
+    passphrase: &str,
+) -> io::Result<([u8; SALT_LEN], [u8; NONCE_LEN], Vec<u8>)> {
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Passphrase cannot be empty",
+        ));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| io::Error::other("Encryption failed"))?;
+    Ok((salt, nonce, ciphertext))
+}
+
+fn decrypt(salt: &[u8], nonce: &[u8], ciphertext: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    if nonce.len() != NONCE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Encrypted content is damaged: bad nonce",
+        ));
+    }
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "Wrong passphrase"))
+}
@@ -0,0 +1,166 @@
+use crate::activity::{self, ActivityBucket, Granularity};
+use crate::models::{Note, NoteState};
+use crate::storage::NotesManager;
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Days of activity the dashboard shows, today included.
+pub const ACTIVITY_DAYS: usize = 7;
+/// How far ahead due notes are listed; overdue ones always are.
+pub const DUE_DAYS: i64 = 7;
+// Entries per list
+const LIST_LIMIT: usize = 5;
+
+/// A note as one line of a dashboard list.
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteLine {
+    pub id: String,
+    pub title: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DueNote {
+    pub id: String,
+    pub title: String,
+    pub due_at: DateTime<Utc>,
+    pub overdue: bool,
+}
+
+/// A note with unchecked `- [ ]` items.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskNote {
+    pub id: String,
+    pub title: String,
+    pub open: usize,
+    /// Text of its first open task
+    pub next: String,
+}
+
+/// What's in the vault at a glance, for `GET /api/stats` and the CLI
+/// dashboard. Counts cover the notes outside the trash unless they say
+/// otherwise.
+#[derive(Debug, Clone, Serialize)]
+pub struct Dashboard {
+    pub total_notes: usize,
+    pub total_tags: usize,
+    /// Notes per state, the trash included
+    pub states: HashMap<NoteState, usize>,
+    /// Notes pinned in at least one tag
+    pub pinned: usize,
+    /// Notes created and edits made per day, oldest first
+    pub activity: Vec<ActivityBucket>,
+    /// Most recently edited notes
+    pub recent: Vec<NoteLine>,
+    /// Active notes due within `DUE_DAYS` days or overdue, soonest first
+    pub due: Vec<DueNote>,
+    /// Unchecked task items in all notes
+    pub open_tasks: usize,
+    /// Notes with the most open tasks
+    pub tasks: Vec<TaskNote>,
+    pub last_updated: DateTime<Utc>,
+}
+
+impl Dashboard {
+    /// Sums up the notes `visible` allows. Encrypted notes count, but their
+    /// tasks can't be read.
+    pub fn build(mgr: &NotesManager, visible: &dyn Fn(&Note) -> bool) -> Self {
+        let now = mgr.now();
+        let notes: Vec<Note> = mgr
+            .list_notes()
+            .into_iter()
+            .filter(|note| visible(note))
+            .collect();
+        let tags: HashSet<&String> = notes.iter().flat_map(|note| &note.tags).collect();
+
+        let mut recent: Vec<&Note> = notes.iter().collect();
+        recent.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+        let recent = recent
+            .into_iter()
+            .take(LIST_LIMIT)
+            .map(|note| NoteLine {
+                id: note.id.clone(),
+                title: note.title.clone(),
+                updated_at: note.updated_at,
+            })
+            .collect();
+
+        let horizon = now + Duration::days(DUE_DAYS);
+        let mut due: Vec<DueNote> = notes
+            .iter()
+            .filter(|note| note.state() == NoteState::Active)
+            .filter_map(|note| {
+                let due_at = note.due_at.filter(|due_at| *due_at <= horizon)?;
+                Some(DueNote {
+                    id: note.id.clone(),
+                    title: note.title.clone(),
+                    due_at,
+                    overdue: due_at < now,
+                })
+            })
+            .collect();
+        due.sort_by_key(|note| note.due_at);
+        due.truncate(LIST_LIMIT * 2);
+
+        let mut tasks: Vec<TaskNote> = notes
+            .iter()
+            .filter(|note| !note.is_encrypted())
+            .filter_map(|note| {
+                let open = open_tasks(&note.content);
+                Some(TaskNote {
+                    id: note.id.clone(),
+                    title: note.title.clone(),
+                    open: open.len(),
+                    next: open.first()?.to_string(),
+                })
+            })
+            .collect();
+        let open_tasks = tasks.iter().map(|note| note.open).sum();
+        tasks.sort_by_key(|note| std::cmp::Reverse(note.open));
+        tasks.truncate(LIST_LIMIT);
+
+        Self {
+            total_notes: notes.len(),
+            total_tags: tags.len(),
+            states: mgr.state_counts(visible),
+            pinned: notes
+                .iter()
+                .filter(|note| !note.pinned_in.is_empty())
+                .count(),
+            activity: activity::activity(
+                &notes,
+                Granularity::Day,
+                ACTIVITY_DAYS,
+                now.with_timezone(&Local).date_naive(),
+            ),
+            recent,
+            due,
+            open_tasks,
+            tasks,
+            last_updated: now,
+        }
+    }
+}
+
+/// The text of each unchecked Markdown task item (`- [ ] call Bob`).
+pub fn open_tasks(content: &str) -> Vec<&str> {
+    let mut in_code = false;
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            if line.starts_with("```") {
+                in_code = !in_code;
+            }
+            if in_code {
+                return None;
+            }
+            ["- [ ] ", "* [ ] ", "+ [ ] "]
+                .iter()
+                .find_map(|marker| line.strip_prefix(marker))
+                .map(str::trim)
+                .filter(|task| !task.is_empty())
+        })
+        .collect()
+}
@@ -0,0 +1,89 @@
+/// One line of a line-by-line comparison of two texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    /// Only in the old text
+    Removed(&'a str),
+    /// Only in the new text
+    Added(&'a str),
+}
+
+// Beyond this many line pairs the table gets too big; the texts are then
+// shown as replaced wholesale
+const MAX_CELLS: usize = 4_000_000;
+
+/// The lines of `old` and `new`, with those they share (their longest
+/// common subsequence) kept in place.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    if old.len().saturating_mul(new.len()) > MAX_CELLS {
+        return old
+            .into_iter()
+            .map(Line::Removed)
+            .chain(new.into_iter().map(Line::Added))
+            .collect();
+    }
+
+    // common[i][j]: length of the common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut result = Vec::new();
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            result.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            result.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    result.extend(old[i..].iter().copied().map(Line::Removed));
+    result.extend(new[j..].iter().copied().map(Line::Added));
+    result
+}
+
+/// Both texts in one, with each place they differ between Git-style
+/// `<<<<<<< old_label` / `=======` / `>>>>>>> new_label` markers, for
+/// merging by hand.
+pub fn with_markers(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut removed: Vec<&str> = Vec::new();
+    let mut added: Vec<&str> = Vec::new();
+    let flush = |out: &mut Vec<String>, removed: &mut Vec<&str>, added: &mut Vec<&str>| {
+        if removed.is_empty() && added.is_empty() {
+            return;
+        }
+        out.push(format!("<<<<<<< {}", old_label));
+        out.extend(removed.drain(..).map(str::to_string));
+        out.push("=======".to_string());
+        out.extend(added.drain(..).map(str::to_string));
+        out.push(format!(">>>>>>> {}", new_label));
+    };
+    for line in lines(old, new) {
+        match line {
+            Line::Same(text) => {
+                flush(&mut out, &mut removed, &mut added);
+                out.push(text.to_string());
+            }
+            Line::Removed(text) => removed.push(text),
+            Line::Added(text) => added.push(text),
+        }
+    }
+    flush(&mut out, &mut removed, &mut added);
+    out.join("\n")
+}
@@ -0,0 +1,81 @@
+use crate::models::Note;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Share of text two notes must have in common to count as the same note.
+pub const NEAR_DUPLICATE: f64 = 0.9;
+
+/// An existing note that new content is nearly the same as.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateHint {
+    pub id: String,
+    pub title: String,
+    /// How much of the text matches, 0 to 1
+    pub similarity: f64,
+}
+
+// Lowercased character trigrams of the text, with runs of whitespace as one space
+fn trigrams(text: &str) -> HashMap<[char; 3], usize> {
+    let chars: Vec<char> = text
+        .split_whitespace()
+        .flat_map(|word| word.chars().flat_map(char::to_lowercase).chain([' ']))
+        .collect();
+    let mut counts = HashMap::new();
+    for window in chars.windows(3) {
+        *counts.entry([window[0], window[1], window[2]]).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn total(grams: &HashMap<[char; 3], usize>) -> usize {
+    grams.values().sum()
+}
+
+// Dice coefficient of the two trigram bags
+fn similarity(a: &HashMap<[char; 3], usize>, b: &HashMap<[char; 3], usize>) -> f64 {
+    let shared: usize = a
+        .iter()
+        .filter_map(|(gram, count)| b.get(gram).map(|other| *count.min(other)))
+        .sum();
+    2.0 * shared as f64 / (total(a) + total(b)) as f64
+}
+
+/// The note among `notes` whose content is most like `content`, if at least
+/// [`NEAR_DUPLICATE`] of it matches. Encrypted notes are skipped, since
+/// their content can't be compared.
+pub fn find_near_duplicate<'a>(
+    notes: impl IntoIterator<Item = &'a Note>,
+    content: &str,
+) -> Option<DuplicateHint> {
+    let new = trigrams(content);
+    let new_total = total(&new);
+    if new_total == 0 {
+        return None;
+    }
+    // Texts whose lengths differ this much can't reach the threshold
+    let min_ratio = NEAR_DUPLICATE / (2.0 - NEAR_DUPLICATE);
+
+    notes
+        .into_iter()
+        .filter(|note| note.encrypted.is_none())
+        .filter_map(|note| {
+            let existing = trigrams(&note.content);
+            let existing_total = total(&existing);
+            let (shorter, longer) = if existing_total < new_total {
+                (existing_total, new_total)
+            } else {
+                (new_total, existing_total)
+            };
+            if (shorter as f64) < longer as f64 * min_ratio {
+                return None;
+            }
+            let score = similarity(&new, &existing);
+            (score >= NEAR_DUPLICATE).then_some((note, score))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(note, similarity)| DuplicateHint {
+            id: note.id.clone(),
+            title: note.title.clone(),
+            similarity,
+        })
+}
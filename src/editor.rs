@@ -0,0 +1,49 @@
+use crate::config::CliConfig;
+use std::fs;
+use std::io;
+use std::process::Command;
+use uuid::Uuid;
+
+/// The editor command to use: `cli.editor`, then `$VISUAL`, then `$EDITOR`.
+pub fn editor_command(config: &CliConfig) -> Option<String> {
+    config
+        .editor
+        .clone()
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .map(|command| command.trim().to_string())
+        .filter(|command| !command.is_empty())
+}
+
+/// Opens `initial` in `command` (e.g. `vim` or `code --wait`) and returns
+/// the text once the editor exits, or `None` if it wasn't changed. The
+/// temporary file is a `.md` so editors pick Markdown highlighting.
+pub fn edit(command: &str, initial: &str) -> io::Result<Option<String>> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Editor command is empty"))?;
+
+    let path = std::env::temp_dir().join(format!("note-{}.md", Uuid::new_v4().simple()));
+    fs::write(&path, initial)?;
+    let status = Command::new(program).args(parts).arg(&path).status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    let status = status.map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Couldn't start editor `{}`: {}", program, e),
+        )
+    })?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "Editor `{}` exited with {}",
+            program, status
+        )));
+    }
+
+    // Editors usually add a final newline; it isn't part of the note
+    let edited = edited?.trim_end_matches(['\r', '\n']).to_string();
+    Ok((edited != initial.trim_end_matches(['\r', '\n'])).then_some(edited))
+}
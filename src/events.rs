@@ -0,0 +1,149 @@
+use crate::models::Note;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Subscribers that fall further behind than this miss events (and are told so)
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EventKind {
+    #[serde(rename = "note.created")]
+    Created,
+    #[serde(rename = "note.updated")]
+    Updated,
+    #[serde(rename = "note.deleted")]
+    Deleted,
+    /// Taken back out of the trash
+    #[serde(rename = "note.restored")]
+    Restored,
+    #[serde(rename = "note.trashed")]
+    Trashed,
+    #[serde(rename = "note.archived")]
+    Archived,
+    #[serde(rename = "note.unarchived")]
+    Unarchived,
+    /// Handed over to another user, who now owns it
+    #[serde(rename = "note.transferred")]
+    Transferred,
+    /// A comment left on the note, the newest one in its `comments`
+    #[serde(rename = "note.commented")]
+    Commented,
+    /// An emoji put on the note or taken off it
+    #[serde(rename = "note.reacted")]
+    Reacted,
+    /// A tag renamed on several notes, after their `note.updated` events
+    #[serde(rename = "tag.renamed")]
+    TagRenamed,
+    /// Tags added or removed in bulk, after the `note.updated` events
+    #[serde(rename = "notes.retagged")]
+    Retagged,
+    /// Several notes moved to the trash at once, after their `note.trashed` events
+    #[serde(rename = "notes.trashed")]
+    BulkTrashed,
+    /// Several notes deleted at once, after their `note.deleted` events
+    #[serde(rename = "notes.deleted")]
+    BulkDeleted,
+}
+
+impl EventKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Created => "note.created",
+            EventKind::Updated => "note.updated",
+            EventKind::Deleted => "note.deleted",
+            EventKind::Restored => "note.restored",
+            EventKind::Trashed => "note.trashed",
+            EventKind::Archived => "note.archived",
+            EventKind::Unarchived => "note.unarchived",
+            EventKind::Transferred => "note.transferred",
+            EventKind::Commented => "note.commented",
+            EventKind::Reacted => "note.reacted",
+            EventKind::TagRenamed => "tag.renamed",
+            EventKind::Retagged => "notes.retagged",
+            EventKind::BulkTrashed => "notes.trashed",
+            EventKind::BulkDeleted => "notes.deleted",
+        }
+    }
+}
+
+/// A change to the notes store, as delivered to webhooks and other listeners.
+/// Events about one note carry it; bulk events carry the ids of the notes
+/// they changed and a summary instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteEvent {
+    pub event: EventKind,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub note_id: String,
+    /// The note after the change (its last state for deletions)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<Note>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub note_ids: Vec<String>,
+    /// What a bulk change did, e.g. "Renamed tag 'js' to 'javascript'"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    // The notes of a bulk event, to tell who may see which
+    #[serde(skip)]
+    notes: Vec<Note>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl NoteEvent {
+    pub fn new(event: EventKind, note: &Note, timestamp: DateTime<Utc>) -> Self {
+        NoteEvent {
+            event,
+            note_id: note.id.clone(),
+            note: Some(note.clone()),
+            note_ids: Vec::new(),
+            summary: None,
+            notes: Vec::new(),
+            timestamp,
+        }
+    }
+
+    pub fn bulk(
+        event: EventKind,
+        notes: &[Note],
+        summary: String,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        NoteEvent {
+            event,
+            note_id: String::new(),
+            note: None,
+            note_ids: notes.iter().map(|note| note.id.clone()).collect(),
+            summary: Some(summary),
+            notes: notes.to_vec(),
+            timestamp,
+        }
+    }
+
+    /// The event as someone who can only read some notes gets it: bulk
+    /// events list only those notes, and nothing is left of events about
+    /// notes they can't read.
+    pub fn visible_to(&self, can_read: impl Fn(&Note) -> bool) -> Option<NoteEvent> {
+        if let Some(note) = &self.note {
+            return can_read(note).then(|| self.clone());
+        }
+        let notes: Vec<Note> = self
+            .notes
+            .iter()
+            .filter(|note| can_read(note))
+            .cloned()
+            .collect();
+        if notes.is_empty() {
+            return None;
+        }
+        let mut event = self.clone();
+        event.note_ids = notes.iter().map(|note| note.id.clone()).collect();
+        event.notes = notes;
+        Some(event)
+    }
+}
+
+pub type EventSender = broadcast::Sender<NoteEvent>;
+
+pub fn channel() -> EventSender {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
@@ -0,0 +1,462 @@
+use crate::models::Note;
+use crate::placeholders;
+use crate::snooze;
+use crate::storage::NotesManager;
+use crate::tags;
+use chrono::Local;
+use printpdf::{
+    BuiltinFont, Error, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point,
+};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use std::collections::HashSet;
+use std::io;
+
+// A4 portrait, all measurements in millimetres
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+
+const BODY_SIZE: f32 = 11.0;
+const META_SIZE: f32 = 9.0;
+const TITLE_SIZE: f32 = 20.0;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+// Points to millimetres
+const PT: f32 = 0.3528;
+
+/// Formats a set of notes can be exported to, as one file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One document, each note under its title
+    #[default]
+    Markdown,
+    Json,
+    /// Each note on its own page
+    Pdf,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            "pdf" => Ok(ExportFormat::Pdf),
+            other => Err(format!(
+                "Unknown export format: {} (expected markdown, json or pdf)",
+                other
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// Which notes an export includes; every condition given must hold. With
+/// none, every note outside the trash is exported.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Tagged with this tag or one nested under it
+    pub tag: Option<String>,
+    /// Created on or after this time (`2026-01-01`, RFC 3339, ...)
+    pub from: Option<String>,
+    /// Created before this time
+    pub to: Option<String>,
+    /// Found by this search, e.g. `tag:recipes lang:de`
+    pub query: Option<String>,
+}
+
+/// The notes outside the trash `filter` picks among those `allowed`,
+/// oldest first.
+pub fn select(
+    mgr: &NotesManager,
+    filter: &ExportFilter,
+    allowed: &dyn Fn(&Note) -> bool,
+) -> Result<Vec<Note>, String> {
+    let given = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    };
+    let time = |value: &Option<String>| {
+        given(value)
+            .map(|value| snooze::parse_when(&value, mgr.now()))
+            .transpose()
+    };
+    let from = time(&filter.from)?;
+    let to = time(&filter.to)?;
+    let tag = given(&filter.tag).map(|tag| mgr.tag_rules().canonical(&tag));
+    let found: Option<HashSet<String>> = given(&filter.query).map(|query| {
+        mgr.search_notes(&query)
+            .into_iter()
+            .map(|note| note.id)
+            .collect()
+    });
+
+    let mut notes: Vec<Note> = mgr
+        .notes
+        .iter()
+        .filter(|note| !note.is_trashed())
+        .filter(|note| tag.as_deref().is_none_or(|tag| tags::has_tag(note, tag)))
+        .filter(|note| from.is_none_or(|from| note.created_at >= from))
+        .filter(|note| to.is_none_or(|to| note.created_at < to))
+        .filter(|note| found.as_ref().is_none_or(|found| found.contains(&note.id)))
+        .filter(|note| allowed(note))
+        .cloned()
+        .collect();
+    notes.sort_by_key(|note| note.created_at);
+    Ok(notes)
+}
+
+/// `notes` as one file in `format`; `name` titles the PDF document.
+pub fn export_notes(notes: &[Note], format: ExportFormat, name: &str) -> io::Result<Vec<u8>> {
+    match format {
+        ExportFormat::Markdown => Ok(notes_to_markdown(notes).into_bytes()),
+        ExportFormat::Json => Ok(serde_json::to_vec_pretty(notes)?),
+        ExportFormat::Pdf => notes_to_pdf(notes, name).map_err(|e| io::Error::other(e.to_string())),
+    }
+}
+
+/// File name for an export called `name` (e.g. "Recipes" → `recipes.md`).
+pub fn export_file_name(name: &str, format: ExportFormat) -> String {
+    let stem = slug(name);
+    let stem = if stem.is_empty() { "notes" } else { &stem };
+    format!("{}.{}", stem, format.extension())
+}
+
+/// The notes as one Markdown document, their placeholders filled in.
+pub fn notes_to_markdown(notes: &[Note]) -> String {
+    notes
+        .iter()
+        .map(|note| {
+            let note = placeholders::resolve(note);
+            let mut section = format!("# {}\n\n", note.title);
+            if !note.tags.is_empty() {
+                let tags: Vec<String> = note.tags.iter().map(|tag| format!("#{}", tag)).collect();
+                section.push_str(&format!("Tags: {}  \n", tags.join(" ")));
+            }
+            section.push_str(&format!(
+                "Created: {} · Updated: {}\n\n{}\n",
+                note.created_at.with_timezone(&Local).format(DATE_FORMAT),
+                note.updated_at.with_timezone(&Local).format(DATE_FORMAT),
+                note.content.trim_end()
+            ));
+            section
+        })
+        .collect::<Vec<_>>()
+        .join("\n---\n\n")
+}
+
+/// Renders a note (header with title, tags and timestamps, then the
+/// Markdown content with its placeholders filled in) as a PDF document.
+///
+/// Uses the built-in PDF fonts, so characters outside Windows-1252 are dropped.
+pub fn note_to_pdf(note: &Note) -> Result<Vec<u8>, Error> {
+    let mut pdf = PdfWriter::new(&note.title)?;
+    write_note(&mut pdf, note);
+    pdf.finish()
+}
+
+/// Renders `notes` into one PDF document, each starting on a new page.
+pub fn notes_to_pdf(notes: &[Note], title: &str) -> Result<Vec<u8>, Error> {
+    let mut pdf = PdfWriter::new(title)?;
+    for (i, note) in notes.iter().enumerate() {
+        if i > 0 {
+            pdf.new_page();
+        }
+        write_note(&mut pdf, note);
+    }
+    pdf.finish()
+}
+
+fn write_note(pdf: &mut PdfWriter, note: &Note) {
+    let note = &placeholders::resolve(note);
+    pdf.write_wrapped(&note.title, TITLE_SIZE, FontStyle::Bold, 0.0);
+    if !note.tags.is_empty() {
+        let tags = note
+            .tags
+            .iter()
+            .map(|tag| format!("#{}", tag))
+            .collect::<Vec<_>>()
+            .join(" ");
+        pdf.write_wrapped(&tags, META_SIZE, FontStyle::Regular, 0.0);
+    }
+    pdf.write_wrapped(
+        &format!(
+            "Created: {}   Updated: {}",
+            note.created_at.with_timezone(&Local).format(DATE_FORMAT),
+            note.updated_at.with_timezone(&Local).format(DATE_FORMAT)
+        ),
+        META_SIZE,
+        FontStyle::Regular,
+        0.0,
+    );
+    pdf.rule();
+
+    render_markdown(pdf, &note.content);
+}
+
+#[derive(Clone, Copy)]
+enum FontStyle {
+    Regular,
+    Bold,
+    Mono,
+}
+
+struct PdfWriter {
+    doc: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    mono: IndirectFontRef,
+    // Baseline of the next line, measured from the bottom of the page
+    y: f32,
+}
+
+impl PdfWriter {
+    fn new(title: &str) -> Result<Self, Error> {
+        let (doc, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let mono = doc.add_builtin_font(BuiltinFont::Courier)?;
+        let layer = doc.get_page(page).get_layer(layer);
+
+        Ok(PdfWriter {
+            doc,
+            layer,
+            regular,
+            bold,
+            mono,
+            y: PAGE_HEIGHT - MARGIN,
+        })
+    }
+
+    fn font(&self, style: FontStyle) -> &IndirectFontRef {
+        match style {
+            FontStyle::Regular => &self.regular,
+            FontStyle::Bold => &self.bold,
+            FontStyle::Mono => &self.mono,
+        }
+    }
+
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn write_line(&mut self, text: &str, size: f32, style: FontStyle, indent: f32) {
+        let line_height = size * PT * 1.4;
+        self.ensure_space(line_height);
+        self.y -= line_height;
+        self.layer.use_text(
+            text,
+            size,
+            Mm(MARGIN + indent),
+            Mm(self.y),
+            self.font(style),
+        );
+    }
+
+    fn write_wrapped(&mut self, text: &str, size: f32, style: FontStyle, indent: f32) {
+        // Rough average glyph width: half the font size (Courier is exactly 0.6)
+        let glyph_width = match style {
+            FontStyle::Mono => size * PT * 0.6,
+            _ => size * PT * 0.5,
+        };
+        let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN - indent) / glyph_width).max(1.0) as usize;
+
+        for line in wrap(text, max_chars) {
+            self.write_line(&line, size, style, indent);
+        }
+    }
+
+    fn space(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn rule(&mut self) {
+        self.space(2.0);
+        self.ensure_space(4.0);
+        let line = Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        };
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(line);
+        self.space(4.0);
+    }
+
+    fn finish(self) -> Result<Vec<u8>, Error> {
+        self.doc.save_to_bytes()
+    }
+}
+
+fn render_markdown(pdf: &mut PdfWriter, content: &str) {
+    let mut text = String::new();
+    let mut size = BODY_SIZE;
+    let mut style = FontStyle::Regular;
+    let mut indent = 0.0;
+    let mut list_depth: usize = 0;
+
+    for event in Parser::new(content) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                size = heading_size(level);
+                style = FontStyle::Bold;
+                pdf.space(2.0);
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                style = FontStyle::Mono;
+                size = META_SIZE;
+            }
+            Event::Start(Tag::BlockQuote(_)) => indent += 6.0,
+            Event::Start(Tag::List(_)) => {
+                // Flush the parent item's text before its nested list
+                if !text.trim().is_empty() {
+                    let item_indent = list_depth.saturating_sub(1) as f32 * 6.0;
+                    pdf.write_wrapped(text.trim(), size, style, indent + item_indent);
+                    text.clear();
+                }
+                list_depth += 1;
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str("\u{2022} ");
+            }
+            Event::End(TagEnd::BlockQuote(_)) => indent -= 6.0,
+            Event::End(TagEnd::List(_)) => {
+                list_depth = list_depth.saturating_sub(1);
+                pdf.space(1.5);
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                for line in text.trim_end_matches('\n').lines() {
+                    pdf.write_wrapped(line, size, style, indent + 4.0);
+                }
+                text.clear();
+                style = FontStyle::Regular;
+                size = BODY_SIZE;
+                pdf.space(1.5);
+            }
+            Event::End(TagEnd::Heading(_) | TagEnd::Paragraph | TagEnd::Item) => {
+                if !text.trim().is_empty() {
+                    let item_indent = list_depth.saturating_sub(1) as f32 * 6.0;
+                    pdf.write_wrapped(text.trim(), size, style, indent + item_indent);
+                }
+                text.clear();
+                style = FontStyle::Regular;
+                size = BODY_SIZE;
+                if list_depth == 0 {
+                    pdf.space(1.5);
+                }
+            }
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => {
+                pdf.write_wrapped(text.trim(), size, style, indent);
+                text.clear();
+            }
+            Event::Rule => pdf.rule(),
+            Event::TaskListMarker(done) => text.push_str(if done { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    if !text.trim().is_empty() {
+        pdf.write_wrapped(text.trim(), size, style, indent);
+    }
+}
+
+fn heading_size(level: HeadingLevel) -> f32 {
+    match level {
+        HeadingLevel::H1 => 16.0,
+        HeadingLevel::H2 => 14.0,
+        HeadingLevel::H3 => 12.5,
+        _ => BODY_SIZE,
+    }
+}
+
+/// Greedy word wrap; words longer than a line are split.
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > max_chars {
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            lines.push(word.drain(..max_chars).collect());
+        }
+        let word: String = word.into_iter().collect();
+
+        let current_len = current.chars().count();
+        if current_len > 0 && current_len + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// File name for an exported note, derived from its title.
+pub fn file_name(note: &Note, extension: &str) -> String {
+    let stem = slug(&note.title);
+    if stem.is_empty() {
+        format!("{}.{}", note.id, extension)
+    } else {
+        format!("{}.{}", stem, extension)
+    }
+}
+
+// Lowercase alphanumeric words joined by dashes
+fn slug(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
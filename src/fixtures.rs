@@ -0,0 +1,175 @@
+use crate::models::Note;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use uuid::Uuid;
+
+// Vocabulary the generated text is drawn from; common enough words that
+// searches for them find something
+const WORDS: &[&str] = &[
+    "meeting", "project", "release", "budget", "design", "review", "customer", "server", "deploy",
+    "garden", "recipe", "travel", "invoice", "schedule", "draft", "research", "library", "coffee",
+    "weekend", "backup", "report", "feedback", "roadmap", "bug", "idea", "plan", "call", "email",
+    "book", "music", "health", "running", "kitchen", "market", "paper", "rust", "python",
+    "database", "index", "network", "storage", "latency", "cache", "query", "summer", "winter",
+    "family", "friend", "school", "office", "the", "and", "with", "for", "about", "after",
+    "before", "into", "over", "under", "quick", "slow", "new", "old",
+];
+
+const TAGS: &[&str] = &[
+    "work",
+    "work/projects",
+    "work/projects/alpha",
+    "work/meetings",
+    "personal",
+    "personal/health",
+    "ideas",
+    "reading",
+    "recipes",
+    "travel",
+    "journal",
+    "dev/rust",
+    "dev/ops",
+];
+
+/// Small deterministic random number generator (SplitMix64): the same seed
+/// always gives the same numbers, on every platform.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; `n` must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Makes synthetic notes for benchmarks and tests: titles, Markdown content
+/// with headings, lists and tasks, nested tags and creation times spread
+/// over the three years before 2025. The same seed gives the same notes,
+/// ids included.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    rng: Rng,
+    start: DateTime<Utc>,
+}
+
+impl Fixtures {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            start: Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap(),
+        }
+    }
+
+    /// The generator behind the notes, for picking among them the same way.
+    #[allow(dead_code)] // for tests and apps embedding the crate
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// `count` words joined with spaces.
+    pub fn words(&mut self, count: usize) -> String {
+        (0..count)
+            .map(|_| *self.rng.pick(WORDS))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// A note of a few paragraphs, created (and last edited) some time
+    /// after the start of 2022.
+    pub fn note(&mut self) -> Note {
+        let length = 2 + self.rng.below(5);
+        let title = capitalize(&self.words(length));
+        let mut content = Vec::new();
+        for _ in 0..1 + self.rng.below(4) {
+            match self.rng.below(4) {
+                0 => content.push(format!("## {}", capitalize(&self.words(3)))),
+                1 => {
+                    for _ in 0..1 + self.rng.below(4) {
+                        let done = if self.rng.below(3) == 0 { "x" } else { " " };
+                        content.push(format!("- [{}] {}", done, self.words(4)));
+                    }
+                }
+                _ => {
+                    let length = 20 + self.rng.below(60);
+                    content.push(format!("{}.", capitalize(&self.words(length))));
+                }
+            }
+            content.push(String::new());
+        }
+        let mut tags: Vec<String> = (0..self.rng.below(4))
+            .map(|_| self.rng.pick(TAGS).to_string())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        let created = self.start + Duration::minutes(self.rng.below(3 * 365 * 24 * 60) as i64);
+        let mut note = Note::new_at(
+            title,
+            content.join("\n").trim_end().to_string(),
+            tags,
+            created,
+        );
+        note.id = Uuid::from_u128(
+            u128::from(self.rng.next_u64()) << 64 | u128::from(self.rng.next_u64()),
+        )
+        .to_string();
+        note.updated_at = created + Duration::minutes(self.rng.below(90 * 24 * 60) as i64);
+        note
+    }
+
+    pub fn notes(&mut self, count: usize) -> Vec<Note> {
+        (0..count).map(|_| self.note()).collect()
+    }
+
+    /// A one- or two-word search query made of words the notes use.
+    pub fn query(&mut self) -> String {
+        let length = 1 + self.rng.below(2);
+        self.words(length)
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_the_same_notes() {
+        let first = Fixtures::new(42).notes(50);
+        let second = Fixtures::new(42).notes(50);
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.title, b.title);
+            assert_eq!(a.content, b.content);
+            assert_eq!(a.tags, b.tags);
+            assert_eq!(a.created_at, b.created_at);
+            assert_eq!(a.updated_at, b.updated_at);
+        }
+
+        let other = Fixtures::new(43).notes(50);
+        assert!(first.iter().zip(&other).any(|(a, b)| a.id != b.id));
+    }
+}
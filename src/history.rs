@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use dialoguer::{Completion, History};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// File next to the notes that holds the vault's search history.
+const HISTORY_FILE: &str = "search_history.json";
+
+pub fn path_for(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(HISTORY_FILE)
+}
+
+/// History key of the CLI and of single-user web mode.
+pub const LOCAL_USER: &str = "local";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchEntry {
+    pub query: String,
+    pub searched_at: DateTime<Utc>,
+}
+
+/// Recent search queries, kept per user.
+pub struct SearchHistory {
+    // Newest first
+    by_user: BTreeMap<String, Vec<SearchEntry>>,
+    storage_path: String,
+}
+
+pub type SharedSearchHistory = Arc<Mutex<SearchHistory>>;
+
+impl SearchHistory {
+    pub fn new(storage_path: &str) -> io::Result<Self> {
+        let path = Path::new(storage_path);
+        let by_user = if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            serde_json::from_reader(reader)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(SearchHistory {
+            by_user,
+            storage_path: storage_path.to_string(),
+        })
+    }
+
+    fn save_history(&self) -> io::Result<()> {
+        let path = Path::new(&self.storage_path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.by_user)?;
+        Ok(())
+    }
+
+    /// The user's past queries, newest first.
+    pub fn recent(&self, user: &str) -> &[SearchEntry] {
+        self.by_user.get(user).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Puts `query`, searched at `now`, at the front of the user's history (a
+    /// repeat moves up instead of being added twice) and keeps the newest
+    /// `limit`; 0 keeps none.
+    pub fn record(
+        &mut self,
+        user: &str,
+        query: &str,
+        limit: usize,
+        now: DateTime<Utc>,
+    ) -> io::Result<()> {
+        let query = query.trim();
+        if query.is_empty() || limit == 0 {
+            return Ok(());
+        }
+        let entries = self.by_user.entry(user.to_string()).or_default();
+        entries.retain(|entry| !entry.query.eq_ignore_ascii_case(query));
+        entries.insert(
+            0,
+            SearchEntry {
+                query: query.to_string(),
+                searched_at: now,
+            },
+        );
+        entries.truncate(limit);
+        self.save_history()
+    }
+
+    pub fn clear(&mut self, user: &str) -> io::Result<()> {
+        if self.by_user.remove(user).is_some() {
+            self.save_history()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    History,
+    Tag,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: SuggestionKind,
+}
+
+/// Type-ahead completions for `prefix`: past queries starting with it,
+/// newest first, then tags starting with it (at any level of a nested tag).
+/// An empty prefix suggests only the most recent queries.
+pub fn suggest(
+    history: &[SearchEntry],
+    tags: &BTreeSet<String>,
+    prefix: &str,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let prefix = prefix.trim().to_lowercase();
+    let starts = |text: &str| text.to_lowercase().starts_with(&prefix);
+
+    let queries = history
+        .iter()
+        .filter(|entry| starts(&entry.query))
+        .map(|entry| Suggestion {
+            text: entry.query.clone(),
+            kind: SuggestionKind::History,
+        });
+    let tags = tags
+        .iter()
+        .filter(|_| !prefix.is_empty())
+        .filter(|tag| starts(tag) || tag.split('/').skip(1).any(starts))
+        .map(|tag| Suggestion {
+            text: tag.clone(),
+            kind: SuggestionKind::Tag,
+        });
+
+    let mut seen = BTreeSet::new();
+    queries
+        .chain(tags)
+        .filter(|suggestion| seen.insert(suggestion.text.to_lowercase()))
+        .take(limit)
+        .collect()
+}
+
+/// Lets ↑/↓ at the CLI search prompt walk through past queries.
+pub struct RecentQueries(pub Vec<SearchEntry>);
+
+impl History<String> for RecentQueries {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.0.get(pos).map(|entry| entry.query.clone())
+    }
+
+    // Queries are recorded once the search has run
+    fn write(&mut self, _: &String) {}
+}
+
+/// Tab completion at the CLI search prompt, from [`suggest`].
+pub struct QueryCompletion {
+    pub recent: Vec<SearchEntry>,
+    pub tags: BTreeSet<String>,
+}
+
+impl Completion for QueryCompletion {
+    fn get(&self, input: &str) -> Option<String> {
+        if input.trim().is_empty() {
+            return None;
+        }
+        suggest(&self.recent, &self.tags, input, 1)
+            .into_iter()
+            .next()
+            .map(|suggestion| suggestion.text)
+    }
+}
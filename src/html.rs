@@ -0,0 +1,152 @@
+use crate::config::SanitizerConfig;
+use crate::models::{Comment, Note};
+use crate::outline::{self, Heading};
+use crate::placeholders;
+use crate::preferences::Preferences;
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{html, Parser};
+use std::collections::HashSet;
+
+/// Strips everything from `html` that the allowlist doesn't name: scripts,
+/// event handlers, `javascript:` links, unknown elements and attributes.
+pub fn sanitize(html: &str, config: &SanitizerConfig) -> String {
+    // Script and style bodies are always dropped, never allowed through
+    let tags: HashSet<&str> = config
+        .allowed_tags
+        .iter()
+        .map(String::as_str)
+        .filter(|tag| !matches!(*tag, "script" | "style"))
+        .collect();
+    let schemes: HashSet<&str> = config.url_schemes.iter().map(String::as_str).collect();
+    ammonia::Builder::default()
+        .tags(tags)
+        .url_schemes(schemes)
+        // Heading anchors, for links to a section
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .clean(html)
+        .to_string()
+}
+
+/// Note content (Markdown, possibly with inline HTML) as sanitized HTML,
+/// headings carrying their outline slug as `id`.
+pub fn render_content(content: &str, config: &SanitizerConfig) -> String {
+    let events =
+        outline::with_anchors(Parser::new_ext(content, outline::MARKDOWN_OPTIONS).collect());
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events.into_iter());
+    sanitize(&rendered, config)
+}
+
+// Links to the sections of a note long enough to have several
+fn table_of_contents(headings: &[Heading]) -> String {
+    if headings.len() < 2 {
+        return String::new();
+    }
+    let top = headings
+        .iter()
+        .map(|heading| heading.level)
+        .min()
+        .unwrap_or(1);
+    let items: String = headings
+        .iter()
+        .map(|heading| {
+            format!(
+                "<li style=\"margin-left: {}em\"><a href=\"#{}\">{}</a></li>\n",
+                heading.level - top,
+                ammonia::clean_text(&heading.slug),
+                ammonia::clean_text(&heading.text)
+            )
+        })
+        .collect();
+    format!("<nav>\n<ul>\n{}</ul>\n</nav>\n", items)
+}
+
+// Comments beneath the note, as plain text
+fn comments_section(comments: &[Comment]) -> String {
+    if comments.is_empty() {
+        return String::new();
+    }
+    let items: String = comments
+        .iter()
+        .map(|comment| {
+            format!(
+                "<li><p><strong>{}</strong> <time datetime=\"{}\">{}</time></p>\n<p style=\"white-space: pre-wrap\">{}</p></li>\n",
+                ammonia::clean_text(comment.author_name.as_deref().unwrap_or("You")),
+                comment.created_at.to_rfc3339(),
+                comment.created_at.format("%Y-%m-%d %H:%M UTC"),
+                ammonia::clean_text(&comment.body)
+            )
+        })
+        .collect();
+    format!(
+        "<section>\n<h2>Comments</h2>\n<ul>\n{}</ul>\n</section>\n",
+        items
+    )
+}
+
+/// A standalone page showing the note, e.g. for someone opening a shared
+/// link, with its placeholders filled in and `comments` beneath it.
+pub fn render_page(note: &Note, comments: &[Comment], config: &SanitizerConfig) -> String {
+    let note = placeholders::resolve(note);
+    let title = ammonia::clean_text(&note.title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n{}{}{}</body>\n</html>\n",
+        table_of_contents(&outline::headings(&note.content)),
+        render_content(&note.content, config),
+        comments_section(comments),
+    )
+}
+
+// Black on white, no page breaks right after a heading or inside a block,
+// and the address after each web link, which paper can't follow
+const PRINT_CSS: &str = "@page { margin: 2cm; }
+body { font-family: Georgia, 'Times New Roman', serif; font-size: 12pt; line-height: 1.5; color: #000; background: #fff; max-width: 42em; margin: 0 auto; }
+h1, h2, h3, h4, h5, h6 { break-after: avoid; page-break-after: avoid; }
+pre, blockquote, table, figure, img { break-inside: avoid; page-break-inside: avoid; }
+p, li { orphans: 3; widows: 3; }
+pre { white-space: pre-wrap; border: 1px solid #ccc; padding: 0.5em; }
+img { max-width: 100%; }
+a { color: inherit; }
+@media print { a[href^=\"http\"]::after { content: \" (\" attr(href) \")\"; font-size: 90%; } }
+footer { margin-top: 2em; padding-top: 0.5em; border-top: 1px solid #999; font-size: 9pt; color: #444; }
+footer span { margin-right: 1.5em; }";
+
+/// The note as a page to print: just the title and content, page breaks
+/// kept out of headings and blocks, and its details in a footer, with times
+/// shown the way `preferences` says.
+pub fn render_print_page(
+    note: &Note,
+    config: &SanitizerConfig,
+    preferences: &Preferences,
+    printed_at: DateTime<Utc>,
+) -> String {
+    let note = placeholders::resolve(note);
+    let title = ammonia::clean_text(&note.title);
+    let mut details = vec![
+        format!("Created {}", preferences.format_time(&note.created_at)),
+        format!("Updated {}", preferences.format_time(&note.updated_at)),
+    ];
+    if let Some(due_at) = &note.due_at {
+        details.push(format!("Due {}", preferences.format_time(due_at)));
+    }
+    if !note.tags.is_empty() {
+        details.push(format!("Tags: {}", note.tags.join(", ")));
+    }
+    details.push(format!("Note {}", note.id));
+    details.push(format!("Printed {}", preferences.format_time(&printed_at)));
+    let footer: String = details
+        .iter()
+        .map(|detail| format!("<span>{}</span>", ammonia::clean_text(detail)))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n{}<footer>{}</footer>\n</body>\n</html>\n",
+        PRINT_CSS,
+        render_content(&note.content, config),
+        footer,
+    )
+}
@@ -0,0 +1,104 @@
+use crate::models::Note;
+use chrono::{DateTime, Utc};
+
+// RFC 5545 lines are at most 75 octets; longer ones continue after CRLF + space
+const MAX_LINE_OCTETS: usize = 75;
+
+// Longest note excerpt put in an event's description
+const DESCRIPTION_CHARS: usize = 500;
+
+/// Renders the notes with a due date, and the snoozed ones (a reminder for
+/// when they come back), as an iCalendar feed. Each event links to its
+/// note under `base_url`.
+pub fn calendar(notes: &[Note], name: &str, base_url: &str, now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//notes-app//Notes//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for note in notes.iter().filter(|note| !note.is_trashed()) {
+        if let Some(due) = note.due_at {
+            event(&mut lines, note, "due", &note.title, due, base_url, now);
+        }
+        if let Some(until) = note.snoozed_until.filter(|_| note.is_snoozed(now)) {
+            let summary = format!("⏰ {}", note.title);
+            event(&mut lines, note, "reminder", &summary, until, base_url, now);
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+fn event(
+    lines: &mut Vec<String>,
+    note: &Note,
+    kind: &str,
+    summary: &str,
+    start: DateTime<Utc>,
+    base_url: &str,
+    now: DateTime<Utc>,
+) {
+    let description: String = note.content.chars().take(DESCRIPTION_CHARS).collect();
+    lines.extend([
+        "BEGIN:VEVENT".to_string(),
+        // Stable, so calendars update the event instead of duplicating it
+        format!("UID:{}-{}@notes-app", note.id, kind),
+        format!("DTSTAMP:{}", timestamp(now)),
+        format!("LAST-MODIFIED:{}", timestamp(note.updated_at)),
+        format!("DTSTART:{}", timestamp(start)),
+        "DURATION:PT15M".to_string(),
+        format!("SUMMARY:{}", escape(summary)),
+        format!("DESCRIPTION:{}", escape(&description)),
+        format!("URL:{}/#note-{}", base_url, note.id),
+    ]);
+    if !note.tags.is_empty() {
+        let tags: Vec<String> = note.tags.iter().map(|tag| escape(tag)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    lines.extend([
+        "BEGIN:VALARM".to_string(),
+        "ACTION:DISPLAY".to_string(),
+        format!("DESCRIPTION:{}", escape(summary)),
+        "TRIGGER:PT0M".to_string(),
+        "END:VALARM".to_string(),
+        "END:VEVENT".to_string(),
+    ]);
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+// TEXT values escape backslashes, separators and newlines
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+// Splits long lines without cutting a UTF-8 character in half
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // The leading space counts towards the continuation line
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+/// What the web UI's shortcuts can do.
+pub const ACTIONS: &[&str] = &["new_note", "search", "save"];
+
+// Modifiers in the order shortcuts are written
+const MODIFIERS: &[&str] = &["Ctrl", "Alt", "Shift", "Meta"];
+
+// Keys with names rather than a character, as browsers report them
+const NAMED_KEYS: &[&str] = &[
+    "Enter",
+    "Escape",
+    "Tab",
+    "Space",
+    "Backspace",
+    "Delete",
+    "Insert",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "ArrowUp",
+    "ArrowDown",
+    "ArrowLeft",
+    "ArrowRight",
+];
+
+/// The web UI's keyboard shortcuts, written like `Ctrl+S` or `/`. An empty
+/// shortcut leaves the action without one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keymap {
+    /// Clear the form and start a new note
+    pub new_note: String,
+    /// Jump to the search box
+    pub search: String,
+    /// Save the note being edited
+    pub save: String,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            new_note: "Alt+N".to_string(),
+            search: "/".to_string(),
+            save: "Ctrl+S".to_string(),
+        }
+    }
+}
+
+impl Keymap {
+    fn bindings(&self) -> [(&'static str, &String); 3] {
+        [
+            ("new_note", &self.new_note),
+            ("search", &self.search),
+            ("save", &self.save),
+        ]
+    }
+
+    fn binding_mut(&mut self, action: &str) -> Option<&mut String> {
+        match action {
+            "new_note" => Some(&mut self.new_note),
+            "search" => Some(&mut self.search),
+            "save" => Some(&mut self.save),
+            _ => None,
+        }
+    }
+
+    /// Checks every shortcut and writes it the standard way (`ctrl+shift+k`
+    /// becomes `Ctrl+Shift+K`). No two actions may share one.
+    pub fn normalize(&mut self) -> Result<(), String> {
+        for action in ACTIONS {
+            let binding = self
+                .binding_mut(action)
+                .expect("every action has a binding");
+            if !binding.is_empty() {
+                *binding = normalize_shortcut(binding)
+                    .map_err(|e| format!("Invalid shortcut for {}: {}", action, e))?;
+            }
+        }
+        let bindings = self.bindings();
+        for (i, (action, shortcut)) in bindings.iter().enumerate() {
+            let taken = bindings[..i]
+                .iter()
+                .find(|(_, other)| !shortcut.is_empty() && other == shortcut);
+            if let Some((other, _)) = taken {
+                return Err(format!(
+                    "{} is bound to both {} and {}",
+                    shortcut, other, action
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds `action` to `shortcut`, as the CLI sets them.
+    pub fn set(&mut self, action: &str, shortcut: &str) -> Result<(), String> {
+        let binding = self.binding_mut(action).ok_or_else(|| {
+            format!(
+                "Unknown shortcut action: {} (expected {})",
+                action,
+                ACTIONS.join(", ")
+            )
+        })?;
+        *binding = shortcut.trim().to_string();
+        self.normalize()
+    }
+}
+
+fn normalize_shortcut(shortcut: &str) -> Result<String, String> {
+    // `+` on its own (or after a modifier, `Ctrl++`) is the plus key
+    let (modifiers, key) = match shortcut.trim() {
+        "+" => ("", "+"),
+        trimmed if trimmed.ends_with("++") => (&trimmed[..trimmed.len() - 2], "+"),
+        trimmed => match trimmed.rsplit_once('+') {
+            Some((modifiers, key)) => (modifiers, key),
+            None => ("", trimmed),
+        },
+    };
+
+    let mut held = Vec::new();
+    for modifier in modifiers.split('+').filter(|part| !part.is_empty()) {
+        let name = match modifier.trim().to_lowercase().as_str() {
+            "ctrl" | "control" => "Ctrl",
+            "alt" | "option" => "Alt",
+            "shift" => "Shift",
+            "meta" | "cmd" | "command" | "super" => "Meta",
+            other => return Err(format!("unknown modifier '{}'", other)),
+        };
+        if !held.contains(&name) {
+            held.push(name);
+        }
+    }
+    held.sort_by_key(|name| MODIFIERS.iter().position(|m| m == name));
+
+    let key = key.trim();
+    let key = if key.chars().count() == 1 {
+        key.to_uppercase()
+    } else if let Some(named) = NAMED_KEYS
+        .iter()
+        .find(|named| named.eq_ignore_ascii_case(key))
+    {
+        named.to_string()
+    } else if is_function_key(key) {
+        key.to_uppercase()
+    } else {
+        return Err(format!("unknown key '{}'", key));
+    };
+    held.push(&key);
+    Ok(held.join("+"))
+}
+
+fn is_function_key(key: &str) -> bool {
+    key.strip_prefix(['F', 'f'])
+        .and_then(|number| number.parse::<u8>().ok())
+        .is_some_and(|number| (1..=12).contains(&number))
+}
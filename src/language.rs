@@ -0,0 +1,113 @@
+use whatlang::Lang;
+
+// Below this many letters a guess is mostly noise
+const MIN_LETTERS: usize = 20;
+
+// The start of a long note says as much as all of it
+const SAMPLE_CHARS: usize = 2000;
+
+/// ISO 639-1 code (e.g. "de") of the language `text` is written in, or
+/// `None` when it's too short or too mixed to tell reliably.
+pub fn detect(text: &str) -> Option<String> {
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_LETTERS {
+        return None;
+    }
+    let sample: String = text.chars().take(SAMPLE_CHARS).collect();
+    let info = whatlang::detect(&sample)?;
+    info.is_reliable()
+        .then(|| iso_639_1(info.lang()).to_string())
+}
+
+/// The code a language filter such as `lang:german`, `lang:deu` or `lang:de`
+/// stands for, as stored on notes.
+pub fn normalize(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    Lang::all()
+        .iter()
+        .find(|lang| lang.code() == language || lang.eng_name().eq_ignore_ascii_case(&language))
+        .map(|lang| iso_639_1(*lang).to_string())
+        .unwrap_or(language)
+}
+
+/// English name of a stored language code, e.g. "German" for "de".
+pub fn name(code: &str) -> Option<&'static str> {
+    Lang::all()
+        .iter()
+        .find(|lang| iso_639_1(**lang) == code)
+        .map(|lang| lang.eng_name())
+}
+
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        // The Snowball stemmer knows Bokmål as plain Norwegian
+        Lang::Nob => "no",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+    }
+}
@@ -1,3 +1,71 @@
+pub mod activity;
+pub mod archive;
+#[cfg(feature = "web")]
+pub mod auth;
+pub mod backend;
+pub mod backup;
+pub mod bench;
+pub mod blobs;
+pub mod cache;
+pub mod capture;
+pub mod cleanup;
+pub mod clock;
+pub mod commands;
+pub mod config;
+#[cfg(feature = "web")]
+pub mod crdt;
+pub mod crypto;
+pub mod dashboard;
+pub mod diff;
+pub mod duplicates;
+pub mod editor;
+pub mod events;
+pub mod export;
+pub mod fixtures;
+pub mod history;
+pub mod html;
+pub mod ics;
+pub mod keymap;
+pub mod language;
+pub mod links;
+pub mod locks;
+pub mod maintenance;
+pub mod merge;
 pub mod models;
+pub mod notebooks;
+pub mod notion;
+pub mod outbound;
+pub mod outline;
+pub mod output;
+pub mod picker;
+pub mod placeholders;
+pub mod policy;
+pub mod preferences;
+pub mod processors;
+pub mod progress;
+pub mod qr;
+pub mod quota;
+pub mod reload;
+pub mod replace;
+pub mod s3;
+pub mod scripts;
+pub mod search;
+pub mod setup;
+pub mod snooze;
 pub mod storage;
+pub mod suggest;
+pub mod summarize;
+pub mod tags;
+pub mod templates;
+pub mod thumbnail;
+pub mod timeline;
+pub mod titles;
+pub mod tokens;
+pub mod transcribe;
+pub mod users;
+pub mod vault_file;
+pub mod views;
+pub mod watcher;
+#[cfg(feature = "web")]
 pub mod web;
+pub mod webhooks;
@@ -0,0 +1,345 @@
+use crate::config::LinksConfig;
+use crate::models::Note;
+use crate::outbound;
+use crate::storage::SharedNotesManager;
+use chrono::{DateTime, Duration as Age, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Where the background check leaves its last report, next to the data file.
+pub const REPORT_FILE: &str = "link_check.json";
+
+// How often the background task looks whether a check is due
+const TICK: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Link {
+    /// `[[Title]]`, `[[Title|label]]` or `[[Title#heading]]`: the title (or id) of another note
+    Wiki(String),
+    /// An http(s) URL, as a Markdown link, `<autolink>` or bare
+    Url(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    Wiki,
+    Url,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BrokenLink {
+    pub kind: LinkKind,
+    pub target: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteLinkReport {
+    pub note_id: String,
+    pub title: String,
+    /// Links found in the note
+    pub checked: usize,
+    pub broken: Vec<BrokenLink>,
+}
+
+/// The result of checking every note.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkCheckReport {
+    pub checked_at: DateTime<Utc>,
+    pub notes_checked: usize,
+    pub links_checked: usize,
+    /// Only the notes with broken links
+    pub notes: Vec<NoteLinkReport>,
+}
+
+/// The links in `content`, each once, in order of appearance. Fenced code
+/// blocks and inline code are skipped.
+pub fn extract(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut in_fence = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for (i, part) in line.split('`').enumerate() {
+            // Odd parts are inside inline code
+            if i % 2 == 0 {
+                extract_from(part, &mut links);
+            }
+        }
+    }
+    let mut seen = Vec::new();
+    links.retain(|link| {
+        let fresh = !seen.contains(link);
+        if fresh {
+            seen.push(link.clone());
+        }
+        fresh
+    });
+    links
+}
+
+fn extract_from(text: &str, links: &mut Vec<Link>) {
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let inner = &rest[start + 2..start + 2 + len];
+        let target = inner.split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() {
+            links.push(Link::Wiki(target.to_string()));
+        }
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    let mut rest = text;
+    while let Some(start) = find_url_start(rest) {
+        let url = url_at(&rest[start..]);
+        if url.contains("://") && url.len() > url.find("://").unwrap_or(0) + 3 {
+            links.push(Link::Url(url.to_string()));
+        }
+        rest = &rest[start + url.len().max(1)..];
+    }
+}
+
+fn find_url_start(text: &str) -> Option<usize> {
+    match (text.find("http://"), text.find("https://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// The URL at the start of `text`: up to whitespace or a closing bracket/quote,
+// without trailing punctuation or a `)` that closes the surrounding Markdown
+fn url_at(text: &str) -> &str {
+    let end = text
+        .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '\'' | ']'))
+        .unwrap_or(text.len());
+    let mut url = &text[..end];
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        let unbalanced =
+            trimmed.ends_with(')') && trimmed.matches('(').count() < trimmed.matches(')').count();
+        let trimmed = if unbalanced {
+            &trimmed[..trimmed.len() - 1]
+        } else {
+            trimmed
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Whether a wiki-link target names one of `notes`, by title (ignoring case)
+/// or id.
+fn names_note<'a>(target: &str, notes: impl IntoIterator<Item = &'a Note>) -> bool {
+    let target = target.to_lowercase();
+    notes
+        .into_iter()
+        .any(|note| note.title.to_lowercase() == target || note.id == target)
+}
+
+/// A note's links with its wiki-links already looked up; the URLs still
+/// need the network, which [`Pending::finish`] does without any lock held.
+pub struct Pending {
+    note_id: String,
+    title: String,
+    checked: usize,
+    broken: Vec<BrokenLink>,
+    urls: Vec<String>,
+}
+
+/// Extracts `note`'s links and checks its wiki-links against `notes`.
+/// Encrypted notes have no readable links.
+pub fn prepare<'a>(note: &Note, notes: impl IntoIterator<Item = &'a Note> + Clone) -> Pending {
+    let links = if note.is_encrypted() {
+        Vec::new()
+    } else {
+        extract(&note.content)
+    };
+    let mut pending = Pending {
+        note_id: note.id.clone(),
+        title: note.title.clone(),
+        checked: links.len(),
+        broken: Vec::new(),
+        urls: Vec::new(),
+    };
+    for link in links {
+        match link {
+            Link::Wiki(target) if !names_note(&target, notes.clone()) => {
+                pending.broken.push(BrokenLink {
+                    kind: LinkKind::Wiki,
+                    target,
+                    reason: "No note with this title".to_string(),
+                })
+            }
+            Link::Wiki(_) => {}
+            Link::Url(url) => pending.urls.push(url),
+        }
+    }
+    pending
+}
+
+impl Pending {
+    /// Checks the URLs; `seen` remembers results across notes so each URL
+    /// is requested once per run.
+    pub fn finish(
+        mut self,
+        checker: &Checker,
+        seen: &mut HashMap<String, Option<String>>,
+    ) -> NoteLinkReport {
+        for url in self.urls {
+            let failure = seen
+                .entry(url.clone())
+                .or_insert_with(|| checker.check(&url).err());
+            if let Some(reason) = failure {
+                self.broken.push(BrokenLink {
+                    kind: LinkKind::Url,
+                    target: url,
+                    reason: reason.clone(),
+                });
+            }
+        }
+        NoteLinkReport {
+            note_id: self.note_id,
+            title: self.title,
+            checked: self.checked,
+            broken: self.broken,
+        }
+    }
+}
+
+/// Requests external links with a timeout.
+pub struct Checker {
+    agent: ureq::Agent,
+}
+
+impl Checker {
+    pub fn new(config: &LinksConfig) -> Self {
+        Self {
+            // Notes can name any URL; internal hosts aren't probed
+            agent: outbound::public_agent()
+                .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+                .user_agent(concat!("notes-app/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+
+    /// A HEAD request, followed by a GET if the server answers HEAD with an
+    /// error, as servers that only route GET do. The error says why the link
+    /// counts as broken.
+    pub fn check(&self, url: &str) -> Result<(), String> {
+        match self.agent.head(url).call() {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(..)) => {
+                self.agent.get(url).call().map(|_| ()).map_err(describe)
+            }
+            Err(e) => Err(describe(e)),
+        }
+    }
+}
+
+fn describe(error: ureq::Error) -> String {
+    if outbound::is_refused(&error) {
+        // The same for every internal host, open or not
+        return "internal address, not checked".to_string();
+    }
+    match error {
+        ureq::Error::Status(code, response) => {
+            format!("HTTP {} {}", code, response.status_text())
+        }
+        ureq::Error::Transport(transport) => transport.to_string(),
+    }
+}
+
+/// Checks every note that isn't in the trash.
+pub fn check_all(manager: &SharedNotesManager, config: &LinksConfig) -> LinkCheckReport {
+    let pending: Vec<Pending> = {
+        let mgr = manager.lock().unwrap();
+        let notes: Vec<&Note> = mgr.notes.iter().filter(|note| !note.is_trashed()).collect();
+        notes
+            .iter()
+            .map(|note| prepare(note, notes.iter().copied()))
+            .collect()
+    };
+
+    let checker = Checker::new(config);
+    let mut seen = HashMap::new();
+    let notes_checked = pending.len();
+    let reports: Vec<NoteLinkReport> = pending
+        .into_iter()
+        .map(|pending| pending.finish(&checker, &mut seen))
+        .collect();
+    LinkCheckReport {
+        checked_at: manager.lock().unwrap().now(),
+        notes_checked,
+        links_checked: reports.iter().map(|report| report.checked).sum(),
+        notes: reports
+            .into_iter()
+            .filter(|report| !report.broken.is_empty())
+            .collect(),
+    }
+}
+
+pub fn report_path(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(REPORT_FILE)
+}
+
+/// The last saved report, if a check has run.
+pub fn load_report(path: &Path) -> io::Result<Option<LinkCheckReport>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+}
+
+pub fn save_report(path: &Path, report: &LinkCheckReport) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(report)?)
+}
+
+/// Checks all links every `check_interval_hours`, saving the report for
+/// `GET /api/link-check`. Runs are counted from the saved report, so
+/// restarts don't trigger extra rounds of requests.
+pub fn spawn_link_checker(manager: SharedNotesManager, config: LinksConfig) {
+    if config.check_interval_hours == 0 {
+        return;
+    }
+    let path = report_path(manager.lock().unwrap().storage_path());
+    let interval = Age::hours(config.check_interval_hours as i64);
+
+    thread::spawn(move || loop {
+        let last = load_report(&path)
+            .ok()
+            .flatten()
+            .map(|report| report.checked_at);
+        let now = manager.lock().unwrap().now();
+        if last.is_none_or(|at| now - at >= interval) {
+            let report = check_all(&manager, &config);
+            let broken: usize = report.notes.iter().map(|note| note.broken.len()).sum();
+            match save_report(&path, &report) {
+                Ok(()) if broken > 0 => {
+                    println!("🔗 Link check found {} broken link(s)", broken)
+                }
+                Ok(()) => {}
+                Err(e) => println!("⚠ Link check report not saved: {}", e),
+            }
+        }
+        thread::sleep(TICK);
+    });
+}
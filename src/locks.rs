@@ -0,0 +1,112 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Lease length when the client doesn't ask for one.
+pub const DEFAULT_LEASE_SECS: u64 = 60;
+/// Longest lease a client can take (or renew) at once.
+pub const MAX_LEASE_SECS: u64 = 300;
+
+/// The right to edit a note for a short while. Leases are only held in
+/// memory: a restarted server has no locks.
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub note_id: String,
+    /// Secret identifying the client holding the lease; it sends it back to
+    /// edit, renew or release
+    pub holder: String,
+    /// User who took the lease; `None` in single-user mode
+    pub user: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Lease {
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at > now
+    }
+}
+
+/// Edit leases by note id. Expired leases are dropped as they're looked at.
+#[derive(Default)]
+pub struct EditLocks {
+    leases: HashMap<String, Lease>,
+}
+
+pub type SharedEditLocks = Arc<Mutex<EditLocks>>;
+
+fn lease_duration(seconds: u64) -> Duration {
+    Duration::seconds(seconds.clamp(1, MAX_LEASE_SECS) as i64)
+}
+
+impl EditLocks {
+    /// The active lease on a note, if any.
+    pub fn active(&mut self, note_id: &str, now: DateTime<Utc>) -> Option<&Lease> {
+        if self
+            .leases
+            .get(note_id)
+            .is_some_and(|lease| !lease.is_active(now))
+        {
+            self.leases.remove(note_id);
+        }
+        self.leases.get(note_id)
+    }
+
+    /// Takes the lease on a note, or renews it when `holder` already has
+    /// it. Fails with the blocking lease while someone else holds one.
+    pub fn acquire(
+        &mut self,
+        note_id: &str,
+        holder: Option<&str>,
+        user: Option<String>,
+        seconds: u64,
+        now: DateTime<Utc>,
+    ) -> Result<Lease, Lease> {
+        let holder = match self.active(note_id, now) {
+            Some(lease) if Some(lease.holder.as_str()) == holder => lease.holder.clone(),
+            Some(lease) => return Err(lease.clone()),
+            // Holders are issued here, never picked by clients
+            None => format!("lk_{}", Uuid::new_v4().simple()),
+        };
+
+        let lease = Lease {
+            note_id: note_id.to_string(),
+            holder,
+            user,
+            expires_at: now + lease_duration(seconds),
+        };
+        self.leases.insert(note_id.to_string(), lease.clone());
+        Ok(lease)
+    }
+
+    /// Ends `holder`'s lease early. `Ok(false)` if the note wasn't locked;
+    /// fails with the lease if someone else holds it.
+    pub fn release(
+        &mut self,
+        note_id: &str,
+        holder: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Lease> {
+        match self.active(note_id, now) {
+            None => Ok(false),
+            Some(lease) if Some(lease.holder.as_str()) == holder => {
+                self.leases.remove(note_id);
+                Ok(true)
+            }
+            Some(lease) => Err(lease.clone()),
+        }
+    }
+
+    /// The lease keeping `holder` from editing a note, if another client
+    /// holds one.
+    pub fn blocking(
+        &mut self,
+        note_id: &str,
+        holder: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Option<Lease> {
+        self.active(note_id, now)
+            .filter(|lease| Some(lease.holder.as_str()) != holder)
+            .cloned()
+    }
+}
@@ -1,12 +1,116 @@
+// Much of the library is only reachable through the API in CLI-only builds
+#![cfg_attr(not(feature = "web"), allow(dead_code))]
+
+mod activity;
+mod archive;
+#[cfg(feature = "web")]
+mod auth;
+mod backend;
+mod backup;
+mod bench;
+mod blobs;
+#[cfg(feature = "web")]
+mod cache;
+mod capture;
+mod cleanup;
+mod clock;
+#[cfg(feature = "web")]
+mod commands;
+mod config;
+#[cfg(feature = "web")]
+mod crdt;
+mod crypto;
+mod dashboard;
+mod diff;
+mod duplicates;
+mod editor;
+mod events;
+mod export;
+mod fixtures;
+mod history;
+mod html;
+mod ics;
+mod keymap;
+mod language;
+mod links;
+#[cfg(feature = "web")]
+mod locks;
+mod maintenance;
+mod merge;
 mod models;
+mod notebooks;
+mod notion;
+mod outbound;
+mod outline;
+mod output;
+mod picker;
+mod placeholders;
+mod policy;
+mod preferences;
+mod processors;
+mod progress;
+mod qr;
+mod quota;
+mod reload;
+mod replace;
+mod s3;
+mod scripts;
+mod search;
+mod setup;
+mod snooze;
 mod storage;
+mod suggest;
+mod summarize;
+mod tags;
+mod templates;
+mod thumbnail;
+mod timeline;
+mod titles;
+#[cfg(feature = "web")]
+mod tokens;
+mod transcribe;
+#[cfg(feature = "web")]
+mod users;
+mod vault_file;
+mod views;
+mod watcher;
+#[cfg(feature = "web")]
 mod web;
+mod webhooks;
 
-use chrono::Local;
+use archive::Archive;
+use backup::Backups;
+use bench::BenchOptions;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use cleanup::CleanupFilter;
 use colored::*;
+use config::Config;
+use dashboard::Dashboard;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Input;
+use export::{ExportFilter, ExportFormat};
+use history::{QueryCompletion, RecentQueries, SearchHistory};
+use merge::MergeStrategy;
+use models::{Comment, GeoPoint, Note, NoteState, ReadingProgress, Resolution, StateFilter};
+use output::{local_time, Column, OutputFormat, QuicklistFormat};
+use preferences::{NoteSort, PreferenceStore, Preferences};
+use progress::ProgressStore;
+use reload::LiveConfig;
+use replace::Replace;
+use scripts::Hooks;
+use search::{SearchHit, SearchScope, SearchSort};
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Write};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use storage::{NotesManager, SharedNotesManager};
+use timeline::Period;
+#[cfg(feature = "web")]
+use tokens::NoteTokenStore;
+#[cfg(feature = "web")]
+use users::UserStore;
+#[cfg(feature = "web")]
+use web::{ServerOptions, WebServerBuilder};
 
 fn get_input(prompt: &str) -> String {
     print!("{}", prompt);
@@ -29,74 +133,182 @@ fn display_header(title: &str) {
     println!("{}", "═".repeat(60).bright_blue());
 }
 
-fn cli_mode(manager: SharedNotesManager) -> io::Result<()> {
+fn cli_mode(manager: SharedNotesManager, config: &Config) -> io::Result<()> {
     println!("{}", "✨ Rust Notes App ✨".bright_magenta().bold());
     println!("{}", "─".repeat(40).bright_black());
+    show_dashboard(&manager);
 
     loop {
         println!("\n{}", "Available commands:".bright_cyan().bold());
         println!("  {} - Add new note", "1".bright_green());
-        println!("  {} - List all notes", "2".bright_yellow());
+        println!("  {} - List notes", "2".bright_yellow());
         println!("  {} - View note details", "3".bright_blue());
         println!("  {} - Search notes", "4".bright_magenta());
         println!("  {} - Update note", "5".bright_cyan());
         println!("  {} - Delete note", "6".bright_red());
-        println!("  {} - Start web server", "7".bright_green().bold());
-        println!("  {} - Exit", "8".bright_red().bold());
+        println!("  {} - Export note to PDF", "7".bright_blue());
+        println!("  {} - Share note (QR code)", "8".bright_magenta());
+        println!("  {} - Bulk edit tags", "9".bright_yellow());
+        println!("  {} - Merge notes", "10".bright_cyan());
+        println!("  {} - Trash (restore / empty)", "11".bright_red());
+        println!("  {} - Clean up", "12".bright_yellow());
+        println!("  {} - Recently viewed", "13".bright_blue());
+        println!("  {} - Dashboard", "14".bright_magenta());
+        println!("  {} - Start web server", "15".bright_green().bold());
+        println!("  {} - Exit", "16".bright_red().bold());
 
         let choice = get_input(&format!("\n{} ", "Enter your choice:".bright_white()));
 
         match choice.as_str() {
-            "1" => add_note(&manager),
+            "1" => add_note(&manager, config),
             "2" => list_notes(&manager),
-            "3" => view_note(&manager),
-            "4" => search_notes(&manager),
-            "5" => update_note(&manager),
-            "6" => delete_note(&manager),
-            "7" => {
+            "3" => view_note(&manager, config),
+            "4" => search_notes(&manager, config),
+            "5" => update_note(&manager, config),
+            "6" => delete_note(&manager, config),
+            "7" => export_note_pdf(&manager, config),
+            "8" => share_note(&manager, config),
+            "9" => bulk_tag(&manager),
+            "10" => merge_notes(&manager),
+            "11" => manage_trash(&manager, config),
+            "12" => clean_up(&manager, config),
+            "13" => recently_viewed(&manager),
+            "14" => show_dashboard(&manager),
+            "15" => {
                 println!("{}", "🌐 Starting web server...".bright_green().bold());
                 return Ok(());
             }
-            "8" => {
+            "16" => {
                 println!("{}", "👋 Goodbye!".bright_magenta().bold());
                 std::process::exit(0);
             }
             _ => {
                 println!(
                     "{}",
-                    "❌ Invalid choice! Please enter a number between 1 and 8.".bright_red()
+                    "❌ Invalid choice! Please enter a number between 1 and 16.".bright_red()
                 );
             }
         }
     }
 }
 
-fn add_note(manager: &SharedNotesManager) {
+// Content written in the configured editor, starting from `current`:
+// `Some(None)` if it came back unchanged, `None` when there's no editor (or
+// it failed) and the content has to be typed in instead
+fn content_from_editor(config: &Config, current: &str) -> Option<Option<String>> {
+    let command = editor::editor_command(&config.cli)?;
+    println!(
+        "{} {}",
+        "📝 Opening".bright_cyan(),
+        format!("{} (save and close it to continue)...", command).bright_white()
+    );
+    match editor::edit(&command, current) {
+        Ok(content) => Some(content),
+        Err(e) => {
+            println!(
+                "{} {}",
+                "⚠ Editor failed, type the content instead:".bright_yellow(),
+                e
+            );
+            None
+        }
+    }
+}
+
+fn add_note(manager: &SharedNotesManager, config: &Config) {
     display_header("ADD NEW NOTE");
-    let title = get_input(&format!("{} ", "Title:".bright_green()));
-    if title.is_empty() {
+    let prompt = if config.titles.auto {
+        "Title (Enter to take it from the content):"
+    } else {
+        "Title:"
+    };
+    let title = get_input(&format!("{} ", prompt.bright_green()));
+    if title.is_empty() && !config.titles.auto {
         println!("{}", "⚠ Title cannot be empty!".bright_red());
         return;
     }
 
-    println!(
-        "{}",
-        "Content (type 'END' on a new line to finish):".bright_yellow()
-    );
-    let mut content_lines = Vec::new();
+    let content = match content_from_editor(config, "") {
+        Some(content) => content.unwrap_or_default(),
+        None => {
+            println!(
+                "{}",
+                "Content (type 'END' on a new line to finish):".bright_yellow()
+            );
+            let mut content_lines = Vec::new();
 
-    loop {
-        let line = get_input("");
-        if line == "END" {
-            break;
+            loop {
+                let line = get_input("");
+                if line == "END" {
+                    break;
+                }
+                content_lines.push(line);
+            }
+            content_lines.join("\n")
+        }
+    };
+    let title = match titles::resolve(&title, &content, &config.titles) {
+        Ok(made) if title.is_empty() => {
+            println!("{} {}", "📝 Title:".bright_blue(), made.bright_cyan());
+            made
+        }
+        Ok(title) => title,
+        Err(e) => {
+            println!("{} {}", "⚠".bright_red(), e.bright_red());
+            return;
+        }
+    };
+    let duplicate = {
+        let mgr = manager.lock().unwrap();
+        duplicates::find_near_duplicate(&mgr.list_notes(), &content)
+    };
+    if let Some(hint) = duplicate {
+        let open = get_input(&format!(
+            "{} ",
+            format!(
+                "⚠ This is nearly the same as '{}' ({:.0}% alike). Open that note instead? (y/n)",
+                hint.title,
+                hint.similarity * 100.0
+            )
+            .bright_yellow()
+        ))
+        .to_lowercase();
+        if open == "y" || open == "yes" {
+            let mgr = manager.lock().unwrap();
+            if let Some(note) = mgr.get_note(&hint.id) {
+                print_note(&note, local_progress(&mgr, &note.id).as_ref());
+            }
+            return;
         }
-        content_lines.push(line);
     }
+    let suggested: Vec<String> = {
+        let mgr = manager.lock().unwrap();
+        suggest::suggest_tags(
+            &mgr.list_notes(),
+            None,
+            &format!("{}\n{}", title, content),
+            &[],
+            5,
+        )
+        .into_iter()
+        .map(|suggestion| suggestion.tag)
+        .collect()
+    };
 
-    let content = content_lines.join("\n");
-    let tags_input = get_input("Enter tags (comma-separated, press Enter to skip): ");
+    let tags_input = if suggested.is_empty() {
+        get_input("Enter tags (comma-separated, press Enter to skip): ")
+    } else {
+        println!(
+            "{} {}",
+            "💡 Suggested tags:".bright_yellow(),
+            suggested.join(", ").bright_magenta()
+        );
+        get_input("Enter tags (comma-separated, '+' to use the suggestions, Enter to skip): ")
+    };
     let tags: Vec<String> = if tags_input.is_empty() {
         Vec::new()
+    } else if tags_input == "+" {
+        suggested
     } else {
         tags_input
             .split(',')
@@ -105,14 +317,31 @@ fn add_note(manager: &SharedNotesManager) {
             .collect()
     };
 
+    let location = match read_location("Location (lat,lon[,label], press Enter to skip): ") {
+        Ok(location) => location,
+        Err(e) => {
+            println!("{} {}", "⚠".bright_red(), e.bright_red());
+            return;
+        }
+    };
+
     let mut mgr = manager.lock().unwrap();
-    match mgr.add_note(title, content, tags) {
+    let added = mgr
+        .add_note(title, content, tags)
+        .and_then(|note| match location {
+            Some(location) => mgr
+                .set_location(&note.id, Some(location))
+                .map(|updated| updated.unwrap_or(note)),
+            None => Ok(note),
+        });
+    match added {
         Ok(note) => {
             println!(
                 "{} {}",
                 "✅ Note added successfully! ID:".bright_green(),
                 note.id.bright_cyan()
             );
+            print_quota_warnings(&mgr);
         }
         Err(e) => {
             println!("{} {}", "❌ Error:".bright_red(), e);
@@ -120,229 +349,2442 @@ fn add_note(manager: &SharedNotesManager) {
     }
 }
 
+// Quotas at 80% or more, so they don't fill up unnoticed
+fn print_quota_warnings(mgr: &NotesManager) {
+    for report in mgr.storage_reports(None) {
+        for warning in report.warnings {
+            println!("{} {}", "⚠".bright_yellow(), warning.bright_yellow());
+        }
+    }
+}
+
+/// Asks for a `lat,lon[,label]` location; an empty answer means none.
+fn read_location(prompt: &str) -> Result<Option<GeoPoint>, String> {
+    let input = get_input(prompt);
+    if input.is_empty() {
+        Ok(None)
+    } else {
+        input.parse().map(Some)
+    }
+}
+
 fn list_notes(manager: &SharedNotesManager) {
     display_header("ALL NOTES");
+    let input = get_input(&format!(
+        "{} ",
+        "Show (active/archived/trashed/snoozed/all, Enter for everything outside the trash):"
+            .bright_white()
+    ));
+    let filter = match input.as_str() {
+        "" => None,
+        other => match other.parse::<StateFilter>() {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                println!("{} {}", "❌".bright_red(), e.bright_red());
+                return;
+            }
+        },
+    };
+
     let mgr = manager.lock().unwrap();
-    let notes = mgr.list_notes();
+    let counts = mgr.state_counts(&|_| true);
+    let count = |state| counts.get(&state).copied().unwrap_or_default();
+    println!(
+        "{} {} active, {} archived, {} in trash",
+        "📊".bright_blue(),
+        count(NoteState::Active).to_string().bright_cyan(),
+        count(NoteState::Archived).to_string().bright_cyan(),
+        count(NoteState::Trashed).to_string().bright_cyan()
+    );
 
-    if notes.is_empty() {
-        println!("{}", "📭 No notes found.".bright_yellow());
-    } else {
+    let preferences = local_preferences();
+    match filter {
+        None => print_threads(&mgr.list_notes(), mgr.now(), &preferences),
+        Some(filter) => print_in_state(&mgr, filter, preferences.sort),
+    }
+}
+
+/// The CLI's preferences, the same ones single-user web mode edits; the
+/// defaults if they can't be read.
+fn local_preferences() -> Preferences {
+    PreferenceStore::new(preferences::DEFAULT_PREFERENCES_PATH)
+        .map(|store| store.get(history::LOCAL_USER))
+        .unwrap_or_else(|e| {
+            eprintln!("{} Preferences unavailable: {}", "⚠".bright_yellow(), e);
+            Preferences::default()
+        })
+}
+
+fn print_threads(notes: &[Note], now: DateTime<Utc>, preferences: &Preferences) {
+    // Snoozed notes keep their numbers but aren't shown
+    let mut printed: Vec<bool> = notes.iter().map(|note| note.is_snoozed(now)).collect();
+    let snoozed = printed.iter().filter(|snoozed| **snoozed).count();
+    if snoozed > 0 {
         println!(
             "{} {}",
-            "📝 Total notes:".bright_blue(),
-            notes.len().to_string().bright_cyan()
+            format!("💤 {} snoozed", snoozed).bright_black(),
+            "(list 'snoozed' to see them)".dimmed()
         );
-        for (i, note) in notes.iter().enumerate() {
-            let truncated_content = if note.content.len() > 50 {
-                format!("{}...", &note.content[..47])
-            } else {
-                note.content.clone()
-            };
-
-            println!(
-                "{} {} {}",
-                format!("[{:2}]", i + 1).bright_white().bold(),
-                note.title.bold().green(),
-                format!("({})", truncated_content).dimmed()
-            );
+    }
+    if notes.len() == snoozed {
+        println!("{}", "📭 No notes found.".bright_yellow());
+        return;
+    }
+    println!(
+        "{} {}",
+        "📝 Total notes:".bright_blue(),
+        (notes.len() - snoozed).to_string().bright_cyan()
+    );
 
-            if !note.tags.is_empty() {
-                println!(
-                    "     {}",
-                    note.tags
-                        .iter()
-                        .map(|t| format!("[{}]", t).bright_magenta().to_string())
-                        .collect::<Vec<String>>()
-                        .join(" ")
-                );
-            }
+    // Sub-notes are shown under their parent; numbers stay the list position
+    // whatever order the threads are shown in
+    let listed: HashSet<&str> = notes.iter().map(|note| note.id.as_str()).collect();
+    let mut roots: Vec<usize> = (0..notes.len())
+        .filter(|&i| {
+            notes[i]
+                .parent_id
+                .as_deref()
+                .is_none_or(|parent| !listed.contains(parent))
+        })
+        .collect();
+    roots.sort_by(|&a, &b| preferences.sort.compare(&notes[a], &notes[b]));
+    let mut shown = 0;
+    for i in roots {
+        if printed[i] {
+            continue;
+        }
+        if shown > 0 && shown % preferences.page_size == 0 && !show_more() {
+            return;
+        }
+        print_thread(notes, i, 0, &mut printed);
+        shown += 1;
+    }
+    // Anything left over sits in a parent cycle made outside the app
+    for i in 0..notes.len() {
+        if !printed[i] {
+            print_thread(notes, i, 0, &mut printed);
         }
     }
 }
 
-fn view_note(manager: &SharedNotesManager) {
-    display_header("VIEW NOTE");
-    let index_input = get_input(&format!("{} ", "Enter note number to view:".bright_white()));
-    if let Ok(index) = index_input.parse::<usize>() {
-        let mgr = manager.lock().unwrap();
-        if index > 0 && index <= mgr.notes_count() {
-            if let Some(note) = mgr.get_note_by_index(index - 1) {
-                println!("{}", "─".repeat(60).bright_black());
-                println!("{}: {}", "ID".bright_cyan().bold(), note.id);
-                println!("{}: {}", "Title".bright_green().bold(), note.title);
-                println!("{}:\n{}", "Content".bright_white(), note.content);
-                if !note.tags.is_empty() {
-                    println!(
-                        "{}: {}",
-                        "Tags".bright_yellow().bold(),
-                        note.tags
-                            .iter()
-                            .map(|tag| format!("#{}", tag).bright_magenta().to_string())
-                            .collect::<Vec<String>>()
-                            .join(" ")
-                    );
-                }
-                println!("{}: {}", "Created".bright_blue(), note.created_at);
-                println!("{}: {}", "Updated".bright_blue(), note.updated_at);
-                println!("{}", "─".repeat(60).bright_black());
-            }
-        } else {
-            println!("{}", "❌ Invalid note number!".bright_red());
-        }
-    } else {
-        println!("{}", "❌ Please enter a valid number!".bright_red());
+// Asked between pages of a long list; without a terminal everything is shown
+fn show_more() -> bool {
+    if !setup::is_interactive() {
+        return true;
     }
+    let answer = get_input(&format!(
+        "{} ",
+        "── Enter for more, q to stop:".bright_black()
+    ));
+    !answer.eq_ignore_ascii_case("q")
 }
 
-fn search_notes(manager: &SharedNotesManager) {
-    display_header("SEARCH NOTES");
-    let query = get_input(&format!("{} ", "Enter search query:".bright_white()));
-    if !query.is_empty() {
-        let mgr = manager.lock().unwrap();
-        let results = mgr.search_notes(&query);
-        if results.is_empty() {
-            println!(
-                "{} '{}'",
-                "🔍 No notes found matching".bright_yellow(),
-                query.bright_white()
-            );
-        } else {
-            println!(
-                "{} {} {}",
-                "🔍 Found".bright_green(),
-                results.len().to_string().bright_cyan(),
-                "notes:".bright_green()
-            );
-            for (i, note) in results.iter().enumerate() {
-                println!(
-                    "{} {} {}",
-                    format!("[{:2}]", i + 1).bright_white().bold(),
-                    note.title.bold().green(),
-                    format!("({} chars)", note.content.len()).dimmed()
-                );
-            }
+// Flat list of the notes in one state. Numbers are the ones other commands
+// take; trashed notes have none
+fn print_in_state(mgr: &NotesManager, filter: StateFilter, sort: NoteSort) {
+    let mut notes = mgr.notes_in_state(filter);
+    sort.apply(&mut notes);
+    if notes.is_empty() {
+        println!("{}", "📭 No notes found.".bright_yellow());
+        return;
+    }
+    let numbers: HashMap<String, usize> = mgr
+        .list_notes()
+        .into_iter()
+        .enumerate()
+        .map(|(i, note)| (note.id, i + 1))
+        .collect();
+
+    let now = mgr.now();
+    for note in &notes {
+        let number = match numbers.get(&note.id) {
+            Some(number) => format!("[{:2}]", number),
+            None => "[ -]".to_string(),
+        };
+        let mut badge = match note.state() {
+            NoteState::Active => String::new(),
+            NoteState::Archived => " 📦 archived".bright_black().to_string(),
+            NoteState::Trashed => " 🗑 trashed".bright_black().to_string(),
+        };
+        if let Some(until) = note.snoozed_until.filter(|_| note.is_snoozed(now)) {
+            let until = until
+                .with_timezone(&Local)
+                .format(" 💤 until %Y-%m-%d %H:%M");
+            badge.push_str(&until.to_string().bright_black().to_string());
         }
+        println!(
+            "{} {}{}",
+            number.bright_white().bold(),
+            note.title.bold().green(),
+            badge
+        );
     }
 }
 
-fn update_note(manager: &SharedNotesManager) {
-    display_header("UPDATE NOTE");
-    let index_input = get_input(&format!(
-        "{} ",
-        "Enter note number to update:".bright_white()
-    ));
-    if let Ok(index) = index_input.parse::<usize>() {
-        let mut mgr = manager.lock().unwrap();
-        if index > 0 && index <= mgr.notes_count() {
-            println!(
-                "{}",
-                "ℹ Leave field blank to keep current value.".bright_blue()
-            );
+fn print_thread(notes: &[Note], index: usize, depth: usize, printed: &mut [bool]) {
+    if printed[index] {
+        return;
+    }
+    printed[index] = true;
 
-            let current_note = &mgr.notes[index - 1];
-            let new_title = get_input(&format!(
-                "{} [{}]: ",
-                "Title".bright_green(),
-                current_note.title
-            ));
-            let title = if new_title.is_empty() {
-                None
-            } else {
-                Some(new_title)
-            };
+    let note = &notes[index];
+    let indent = "    ".repeat(depth);
+    let branch = if depth > 0 { "↳ " } else { "" };
+    let truncated_content = if note.content.chars().count() > 50 {
+        format!("{}...", note.content.chars().take(47).collect::<String>())
+    } else {
+        note.content.clone()
+    };
 
-            println!(
-                "{}",
-                "Content (type 'END' on new line to finish, 'KEEP' to keep current):"
-                    .bright_yellow()
-            );
-            println!("{}", "Current content:".bright_blue());
-            println!("{}", current_note.content);
+    println!(
+        "{}{}{} {} {}",
+        indent,
+        branch.bright_black(),
+        format!("[{:2}]", index + 1).bright_white().bold(),
+        note.title.bold().green(),
+        format!("({})", truncated_content).dimmed()
+    );
 
-            let mut new_content_lines = Vec::new();
-            loop {
-                let line = get_input("");
-                if line == "END" {
-                    break;
-                } else if line == "KEEP" {
-                    new_content_lines.clear();
-                    break;
-                }
-                new_content_lines.push(line);
-            }
+    if !note.tags.is_empty() {
+        println!(
+            "{}     {}",
+            indent,
+            note.tags
+                .iter()
+                .map(|t| colored_tag(note, t, format!("[{}]", t)))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+    }
 
-            let content = if !new_content_lines.is_empty() {
-                Some(new_content_lines.join("\n"))
-            } else {
-                None
-            };
+    for (child, _) in notes
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| child.parent_id.as_deref() == Some(note.id.as_str()))
+    {
+        print_thread(notes, child, depth + 1, printed);
+    }
+}
 
-            let new_tags_input = get_input(&format!(
-                "{} [{}]: ",
-                "Tags".bright_magenta(),
-                current_note.tags.join(", ")
-            ));
-            let tags = if new_tags_input.is_empty() {
-                None
-            } else {
-                Some(
-                    new_tags_input
-                        .split(',')
-                        .map(|s| s.trim().to_string())
-                        .filter(|s| !s.is_empty())
-                        .collect(),
-                )
-            };
+// Marks the line where reading left off, so long notes can be picked up there
+fn with_reading_marker(content: &str, progress: Option<&ReadingProgress>) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let at = progress
+        .map(|progress| (progress.percent / 100.0 * lines.len() as f64).round() as usize)
+        .filter(|at| (1..lines.len()).contains(at));
+    let Some(at) = at else {
+        return content.to_string();
+    };
+    let marker = "── 📖 continue here ──".bright_yellow().to_string();
+    let mut marked = lines[..at].to_vec();
+    marked.push(&marker);
+    marked.extend(&lines[at..]);
+    marked.join("\n")
+}
 
-            let id = mgr.notes[index - 1].id.clone();
-            match mgr.update_note(&id, title, content, tags) {
-                Ok(Some(_)) => println!("{}", "✅ Note updated successfully!".bright_green()),
-                Ok(None) => println!("{}", "❌ Note not found!".bright_red()),
-                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+// Long notes can be read from one of their sections on: where it starts
+// in the content, or `None` to show all of it
+fn choose_section(note: &Note) -> Option<usize> {
+    if note.outline.len() < 2 {
+        return None;
+    }
+    println!("{}", "📑 Sections:".bright_cyan());
+    let top = note.outline.iter().map(|heading| heading.level).min()?;
+    for (i, heading) in note.outline.iter().enumerate() {
+        println!(
+            "  {}{} {}",
+            "  ".repeat((heading.level - top) as usize),
+            format!("[{}]", i + 1).bright_white(),
+            heading.text
+        );
+    }
+    let choice = get_input(&format!(
+        "{} ",
+        "Read from section (Enter for the whole note):".bright_white()
+    ));
+    let heading = note
+        .outline
+        .get(choice.parse::<usize>().ok()?.checked_sub(1)?)?;
+    outline::section_start(&note.content, &heading.slug)
+}
+
+// `text` for one of the note's tags, in the tag's color if it has one
+fn colored_tag(note: &Note, tag: &str, text: String) -> String {
+    match note.tag_colors.get(tag).and_then(|color| tags::rgb(color)) {
+        Some((r, g, b)) => text.truecolor(r, g, b).to_string(),
+        None => text.bright_magenta().to_string(),
+    }
+}
+
+// Where the local user left off reading the note
+fn local_progress(mgr: &NotesManager, id: &str) -> Option<ReadingProgress> {
+    ProgressStore::new(progress::path_for(mgr.storage_path()))
+        .ok()?
+        .get(history::LOCAL_USER, id)
+        .cloned()
+}
+
+// Everything about the note, for reading it in the terminal
+fn print_note(note: &Note, progress: Option<&ReadingProgress>) {
+    println!("{}", "─".repeat(60).bright_black());
+    println!("{}: {}", "ID".bright_cyan().bold(), note.id);
+    if let Some(slug) = &note.slug {
+        println!("{}: {}", "Slug".bright_cyan().bold(), slug);
+    }
+    println!("{}: {}", "Title".bright_green().bold(), note.title);
+    let content = match choose_section(note) {
+        Some(start) => note.content[start..].to_string(),
+        None => with_reading_marker(&note.content, progress),
+    };
+    println!("{}:\n{}", "Content".bright_white(), content);
+    if !note.tags.is_empty() {
+        println!(
+            "{}: {}",
+            "Tags".bright_yellow().bold(),
+            note.tags
+                .iter()
+                .map(|tag| colored_tag(note, tag, format!("#{}", tag)))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+    }
+    let reactions = note.reaction_counts();
+    if !reactions.is_empty() {
+        let counts: Vec<String> = reactions
+            .iter()
+            .map(|r| format!("{} {}", r.emoji, r.count))
+            .collect();
+        println!(
+            "{}: {}",
+            "Reactions".bright_magenta().bold(),
+            counts.join("  ")
+        );
+    }
+    if let Some(location) = &note.location {
+        println!(
+            "{} {}",
+            "📍".bright_cyan(),
+            match &location.label {
+                Some(label) => format!("{} ({}, {})", label, location.lat, location.lon),
+                None => format!("{}, {}", location.lat, location.lon),
             }
-        } else {
-            println!("{}", "❌ Invalid note number!".bright_red());
+        );
+    }
+    if let Some(due) = &note.due_at {
+        println!("{} {}", "📅 Due:".bright_cyan(), local_time(due));
+    }
+    if let Some(url) = &note.source_url {
+        println!("{} {}", "🔗".bright_cyan(), url.underline());
+    }
+    if let Some(code) = &note.language {
+        let name = language::name(code).unwrap_or(code);
+        println!("{} {}", "🌐 Language:".bright_cyan(), name);
+    }
+    for attachment in &note.attachments {
+        println!(
+            "{} {} {}",
+            "📎".bright_yellow(),
+            attachment.filename.bright_white(),
+            format!("({:?}, {} bytes)", attachment.kind, attachment.size).dimmed()
+        );
+        if let Some(transcript) = &attachment.transcript {
+            println!("   {} {}", "Transcript:".bright_cyan(), transcript);
         }
     }
+    println!(
+        "{}: {}",
+        "Created".bright_blue(),
+        local_time(&note.created_at)
+    );
+    println!(
+        "{}: {}",
+        "Updated".bright_blue(),
+        local_time(&note.updated_at)
+    );
+    if let Some(progress) = progress {
+        println!(
+            "{} {}",
+            format!("📖 {:.0}% read", progress.percent).bright_yellow(),
+            format!("(as of {})", local_time(&progress.updated_at)).dimmed()
+        );
+    }
+    if let Some(last) = &note.last_viewed_at {
+        println!(
+            "{} {}",
+            format!("👁 Viewed {} times", note.view_count).bright_blue(),
+            format!("(last {})", local_time(last)).dimmed()
+        );
+    }
+    println!("{}", "─".repeat(60).bright_black());
 }
 
-fn delete_note(manager: &SharedNotesManager) {
-    display_header("DELETE NOTE");
-    let index_input = get_input(&format!(
-        "{} ",
-        "Enter note number to delete:".bright_white()
-    ));
-    if let Ok(index) = index_input.parse::<usize>() {
+fn view_note(manager: &SharedNotesManager, config: &Config) {
+    display_header("VIEW NOTE");
+    if let Some(index) = picker::choose_note(manager, &config.cli, "view") {
         let mut mgr = manager.lock().unwrap();
         if index > 0 && index <= mgr.notes_count() {
-            match mgr.delete_note_by_index(index - 1) {
-                Ok(_) => println!("{}", "✅ Note deleted successfully!".bright_green()),
-                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            if let Some(id) = mgr.get_note_by_index(index - 1).map(|note| note.id.clone()) {
+                show_note(&mut mgr, &id);
             }
         } else {
             println!("{}", "❌ Invalid note number!".bright_red());
         }
+    } else {
+        println!("{}", "❌ Please enter a valid number!".bright_red());
     }
 }
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    let storage_path = "data/notes.json";
-    let manager = Arc::new(Mutex::new(NotesManager::new(storage_path)?));
+// Prints the note with its comments and related notes, counting the view
+fn show_note(mgr: &mut NotesManager, id: &str) {
+    let note = mgr.record_view(id);
+    if let Err(e) = mgr.flush_views() {
+        println!("{} {}", "⚠ Couldn't record the view:".bright_yellow(), e);
+    }
+    if let Some(note) = note {
+        print_note(&note, local_progress(mgr, id).as_ref());
+        print_comments(&note.comments);
+        print_related(&suggest::related_notes(&mgr.list_notes(), &note, 5));
+    }
+}
 
-    // Check command line arguments
-    let args: Vec<String> = std::env::args().collect();
+// Notes listed under "Recently viewed"
+/// Counts, the week's activity, recent and due notes and open tasks, as
+/// `/api/stats` reports them.
+fn show_dashboard(manager: &SharedNotesManager) {
+    let dashboard = Dashboard::build(&manager.lock().unwrap(), &|_| true);
+    display_header("DASHBOARD");
+    let count = |state| dashboard.states.get(&state).copied().unwrap_or(0);
+    println!(
+        "{} {}   {} {}   {} {}   {} {}   {} {}",
+        "📝 Active".bright_green(),
+        count(NoteState::Active),
+        "🗄 Archived".bright_blue(),
+        count(NoteState::Archived),
+        "🗑 Trashed".bright_red(),
+        count(NoteState::Trashed),
+        "📌 Pinned".bright_yellow(),
+        dashboard.pinned,
+        "🏷 Tags".bright_cyan(),
+        dashboard.total_tags
+    );
 
-    if args.len() > 1 && args[1] == "web" {
+    let week: Vec<String> = dashboard
+        .activity
+        .iter()
+        .map(|day| {
+            format!(
+                "{} +{}/~{}",
+                day.start.format("%a"),
+                day.created,
+                day.edited
+            )
+        })
+        .collect();
+    println!(
+        "\n{} {}",
+        "📈 This week (created/edited):".bright_cyan().bold(),
+        week.join("  ").dimmed()
+    );
+
+    if !dashboard.recent.is_empty() {
+        println!("\n{}", "🕒 Recently edited".bright_cyan().bold());
+        for note in &dashboard.recent {
+            println!(
+                "  {} {}",
+                note.title.green(),
+                format!("({})", local_time(&note.updated_at)).dimmed()
+            );
+        }
+    }
+
+    println!("\n{}", "📅 Due".bright_cyan().bold());
+    if dashboard.due.is_empty() {
+        println!("  {}", "Nothing due this week.".dimmed());
+    }
+    for note in &dashboard.due {
+        let when = local_time(&note.due_at);
+        if note.overdue {
+            println!(
+                "  {} {}",
+                note.title.bright_red(),
+                format!("(overdue since {})", when).red()
+            );
+        } else {
+            println!(
+                "  {} {}",
+                note.title.green(),
+                format!("({})", when).dimmed()
+            );
+        }
+    }
+
+    println!(
+        "\n{} {}",
+        "☑ Open tasks:".bright_cyan().bold(),
+        dashboard.open_tasks
+    );
+    for note in &dashboard.tasks {
+        println!(
+            "  {} {} {}",
+            format!("{:>3}", note.open).bright_white().bold(),
+            note.title.green(),
+            format!("– next: {}", note.next).dimmed()
+        );
+    }
+}
+
+const RECENTLY_VIEWED: usize = 10;
+
+// The notes last opened, most recent first, to pick one up again
+fn recently_viewed(manager: &SharedNotesManager) {
+    display_header("RECENTLY VIEWED");
+    let mut mgr = manager.lock().unwrap();
+    let listed = mgr.list_notes();
+    let mut viewed: Vec<(usize, &Note)> = listed
+        .iter()
+        .enumerate()
+        .filter(|(_, note)| note.last_viewed_at.is_some())
+        .collect();
+    if viewed.is_empty() {
+        println!("{}", "📭 No notes viewed yet.".bright_yellow());
+        return;
+    }
+    viewed.sort_by(|(_, a), (_, b)| NoteSort::LastViewed.compare(a, b));
+    viewed.truncate(RECENTLY_VIEWED);
+    for (index, note) in &viewed {
+        let last = note.last_viewed_at.as_ref().map(local_time);
+        println!(
+            "{} {} {}",
+            format!("[{:2}]", index + 1).bright_white().bold(),
+            note.title.bold().green(),
+            format!(
+                "(viewed {}, {} {})",
+                last.unwrap_or_default(),
+                note.view_count,
+                if note.view_count == 1 {
+                    "view"
+                } else {
+                    "views"
+                }
+            )
+            .dimmed()
+        );
+    }
+
+    let input = get_input(&format!(
+        "\n{} ",
+        "Note number to open (Enter to go back):".bright_white()
+    ));
+    if input.is_empty() {
+        return;
+    }
+    match input
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| viewed.iter().find(|(index, _)| index + 1 == number))
+    {
+        Some((_, note)) => {
+            let id = note.id.clone();
+            show_note(&mut mgr, &id);
+        }
+        None => println!("{}", "❌ Invalid note number!".bright_red()),
+    }
+}
+
+fn print_comments(comments: &[Comment]) {
+    if comments.is_empty() {
+        return;
+    }
+    println!("{}", "💬 Comments:".bright_cyan().bold());
+    for comment in comments {
+        println!(
+            "   {} {}",
+            comment
+                .author_name
+                .as_deref()
+                .unwrap_or("You")
+                .bright_white(),
+            local_time(&comment.created_at).dimmed()
+        );
+        for line in comment.body.lines() {
+            println!("     {}", line);
+        }
+    }
+}
+
+fn print_related(related: &[suggest::RelatedNote]) {
+    if related.is_empty() {
+        return;
+    }
+    println!("{}", "🧭 Related:".bright_cyan().bold());
+    for note in related {
+        let tags = if note.shared_tags.is_empty() {
+            String::new()
+        } else {
+            format!(" #{}", note.shared_tags.join(" #"))
+        };
+        println!(
+            "   • {} {}{}",
+            note.title.bright_white(),
+            format!("({:.0}% alike)", note.score * 100.0).dimmed(),
+            tags.bright_magenta()
+        );
+    }
+}
+
+fn parse_tags(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn merge_notes(manager: &SharedNotesManager) {
+    display_header("MERGE NOTES");
+    let numbers = get_input(&format!(
+        "{} ",
+        "Note numbers to merge (e.g. 1 4 7; the first one is kept):".bright_white()
+    ));
+    let notes: Option<Vec<Note>> = {
+        let mgr = manager.lock().unwrap();
+        numbers
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|number| !number.is_empty())
+            .map(|number| {
+                number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| number.checked_sub(1))
+                    .and_then(|i| mgr.get_note_by_index(i).cloned())
+            })
+            .collect()
+    };
+    let Some(notes) = notes.filter(|notes| notes.len() >= 2) else {
+        println!(
+            "{}",
+            "❌ Give at least two valid note numbers!".bright_red()
+        );
+        return;
+    };
+    for note in &notes {
+        println!(
+            "   • {} {}",
+            note.title.bright_white(),
+            format!("({} chars)", note.content.chars().count()).dimmed()
+        );
+    }
+
+    let strategy = match get_input(&format!(
+        "{} ",
+        "Strategy (concatenate/keep-longest) [concatenate]:".bright_white()
+    ))
+    .parse::<MergeStrategy>()
+    {
+        Ok(strategy) => strategy,
+        Err(e) => {
+            println!("{} {}", "❌".bright_red(), e);
+            return;
+        }
+    };
+    let confirm = get_input(&format!(
+        "{} ",
+        format!(
+            "Merge {} notes into '{}'? The others are removed (y/n)",
+            notes.len(),
+            notes[0].title
+        )
+        .bright_yellow()
+    ))
+    .to_lowercase();
+    if confirm != "y" && confirm != "yes" {
+        println!("{}", "Cancelled.".bright_yellow());
+        return;
+    }
+
+    let ids: Vec<String> = notes.into_iter().map(|note| note.id).collect();
+    match manager.lock().unwrap().merge_notes(&ids, strategy) {
+        Ok(Some(note)) => println!(
+            "{} {}",
+            "✅ Merged into".bright_green(),
+            note.title.bright_white().bold()
+        ),
+        Ok(None) => println!("{}", "❌ Note not found!".bright_red()),
+        Err(e) => println!("{} {}", "❌ Failed to merge notes:".bright_red(), e),
+    }
+}
+
+fn bulk_tag(manager: &SharedNotesManager) {
+    display_header("BULK EDIT TAGS");
+    let query = get_input(&format!("{} ", "Apply to notes matching:".bright_white()));
+    if query.is_empty() {
+        return;
+    }
+
+    let matches = manager.lock().unwrap().search_notes(&query);
+    if matches.is_empty() {
+        println!(
+            "{} '{}'",
+            "🔍 No notes found matching".bright_yellow(),
+            query.bright_white()
+        );
+        return;
+    }
+    println!(
+        "{} {} {}",
+        "🔍 Found".bright_green(),
+        matches.len().to_string().bright_cyan(),
+        "notes:".bright_green()
+    );
+    for note in &matches {
+        println!(
+            "   • {} {}",
+            note.title.bright_white(),
+            format!("{:?}", note.tags).dimmed()
+        );
+    }
+
+    let add = parse_tags(&get_input(
+        "Tags to add (comma-separated, Enter for none): ",
+    ));
+    let remove = parse_tags(&get_input(
+        "Tags to remove (comma-separated, Enter for none): ",
+    ));
+    if add.is_empty() && remove.is_empty() {
+        println!("{}", "ℹ Nothing to change.".bright_blue());
+        return;
+    }
+
+    let confirm = get_input(&format!(
+        "{} ",
+        format!("Update tags on {} notes? (y/n)", matches.len()).bright_yellow()
+    ))
+    .to_lowercase();
+    if confirm != "y" && confirm != "yes" {
+        println!("{}", "Cancelled.".bright_yellow());
+        return;
+    }
+
+    match manager
+        .lock()
+        .unwrap()
+        .bulk_tag(&query, &add, &remove, &|_| true)
+    {
+        Ok((_, modified)) => println!(
+            "{} {} {}",
+            "✅ Updated".bright_green(),
+            modified.to_string().bright_cyan(),
+            "notes".bright_green()
+        ),
+        Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+    }
+}
+
+// Recent searches listed above the search prompt
+const RECENT_SEARCHES_SHOWN: usize = 5;
+
+// With a terminal, ↑/↓ recall recent searches and Tab completes them and tag names
+fn read_search_query(manager: &SharedNotesManager, history: Option<&SearchHistory>) -> String {
+    let recent = history
+        .map(|history| history.recent(history::LOCAL_USER).to_vec())
+        .unwrap_or_default();
+    if !recent.is_empty() {
+        let shown: Vec<&str> = recent
+            .iter()
+            .take(RECENT_SEARCHES_SHOWN)
+            .map(|entry| entry.query.as_str())
+            .collect();
+        println!(
+            "{} {}",
+            "🕘 Recent:".bright_blue(),
+            shown.join(" · ").dimmed()
+        );
+    }
+    if !setup::is_interactive() {
+        return get_input(&format!("{} ", "Enter search query:".bright_white()));
+    }
+
+    let completion = QueryCompletion {
+        recent: recent.clone(),
+        tags: manager
+            .lock()
+            .unwrap()
+            .notes
+            .iter()
+            .flat_map(|note| note.tags.iter().cloned())
+            .collect(),
+    };
+    let mut recent = RecentQueries(recent);
+    Input::<String>::with_theme(&ColorfulTheme::default())
+        .with_prompt("Enter search query (↑/↓ recent, Tab completes)")
+        .allow_empty(true)
+        .history_with(&mut recent)
+        .completion_with(&completion)
+        .interact_text()
+        .map(|query| query.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn search_notes(manager: &SharedNotesManager, config: &Config) {
+    display_header("SEARCH NOTES");
+    let path = history::path_for(manager.lock().unwrap().storage_path());
+    let mut history = SearchHistory::new(&path.to_string_lossy())
+        .inspect_err(|e| println!("{} Search history unavailable: {}", "⚠".bright_yellow(), e))
+        .ok();
+    let query = read_search_query(manager, history.as_ref());
+    if let Some(history) = &mut history {
+        let now = manager.lock().unwrap().now();
+        let recorded = history.record(history::LOCAL_USER, &query, config.search.history_size, now);
+        if let Err(e) = recorded {
+            println!(
+                "{} Couldn't save search history: {}",
+                "⚠".bright_yellow(),
+                e
+            );
+        }
+    }
+    if !query.is_empty() {
+        let sort = get_input(&format!(
+            "{} ",
+            "Sort by (relevance/updated/created/title) [relevance]:".bright_white()
+        ))
+        .parse::<SearchSort>()
+        .unwrap_or_else(|e| {
+            println!("{} {}", "⚠".bright_yellow(), e);
+            SearchSort::default()
+        });
+
+        let mgr = manager.lock().unwrap();
+        let mut results: Vec<SearchHit> = mgr
+            .search_notes(&query)
+            .into_iter()
+            .map(|note| mgr.highlight_note(note, &query))
+            .collect();
+        search::sort_hits(&mut results, sort);
+        if results.is_empty() {
+            println!(
+                "{} '{}'",
+                "🔍 No notes found matching".bright_yellow(),
+                query.bright_white()
+            );
+        } else {
+            println!(
+                "{} {} {}",
+                "🔍 Found".bright_green(),
+                results.len().to_string().bright_cyan(),
+                "notes:".bright_green()
+            );
+            for (i, hit) in results.into_iter().enumerate() {
+                let spans_in = |field: &str| {
+                    hit.highlights
+                        .iter()
+                        .find(|h| h.field == field)
+                        .map(|h| h.positions.clone())
+                        .unwrap_or_default()
+                };
+                let mark = |s: &str| s.bright_yellow().bold().underline().to_string();
+                let plain = |s: &str| s.to_string();
+
+                println!(
+                    "{} {} {}",
+                    format!("[{:2}]", i + 1).bright_white().bold(),
+                    search::snippet(&hit.note.title, &spans_in("title"), None, mark, |s| s
+                        .bold()
+                        .green()
+                        .to_string()),
+                    format!("({} chars)", hit.note.content.len()).dimmed()
+                );
+
+                let content_spans = spans_in("content");
+                if !content_spans.is_empty() {
+                    println!(
+                        "     {}",
+                        search::snippet(
+                            &hit.note.content,
+                            &content_spans,
+                            Some(search::SNIPPET_CONTEXT),
+                            mark,
+                            plain
+                        )
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn update_note(manager: &SharedNotesManager, config: &Config) {
+    display_header("UPDATE NOTE");
+    if let Some(index) = picker::choose_note(manager, &config.cli, "update") {
+        let mut mgr = manager.lock().unwrap();
+        if index > 0 && index <= mgr.notes_count() {
+            println!(
+                "{}",
+                "ℹ Leave field blank to keep current value.".bright_blue()
+            );
+
+            let Some(current_note) = mgr.get_note_by_index(index - 1).cloned() else {
+                return;
+            };
+            let new_title = get_input(&format!(
+                "{} [{}]: ",
+                "Title".bright_green(),
+                current_note.title
+            ));
+            let title = if new_title.is_empty() {
+                None
+            } else {
+                Some(new_title)
+            };
+
+            let content = match content_from_editor(config, &current_note.content) {
+                Some(None) => {
+                    println!("{}", "ℹ Content unchanged.".bright_blue());
+                    None
+                }
+                Some(content) => content,
+                None => {
+                    println!(
+                        "{}",
+                        "Content (type 'END' on new line to finish, 'KEEP' to keep current):"
+                            .bright_yellow()
+                    );
+                    println!("{}", "Current content:".bright_blue());
+                    println!("{}", current_note.content);
+
+                    let mut new_content_lines = Vec::new();
+                    loop {
+                        let line = get_input("");
+                        if line == "END" {
+                            break;
+                        } else if line == "KEEP" {
+                            new_content_lines.clear();
+                            break;
+                        }
+                        new_content_lines.push(line);
+                    }
+
+                    if !new_content_lines.is_empty() {
+                        Some(new_content_lines.join("\n"))
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            let new_tags_input = get_input(&format!(
+                "{} [{}]: ",
+                "Tags".bright_magenta(),
+                current_note.tags.join(", ")
+            ));
+            let tags = if new_tags_input.is_empty() {
+                None
+            } else {
+                Some(
+                    new_tags_input
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty())
+                        .collect(),
+                )
+            };
+
+            let current_location = current_note
+                .location
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default();
+            let location = match get_input(&format!(
+                "{} [{}] ('-' to remove): ",
+                "Location".bright_cyan(),
+                current_location
+            ))
+            .as_str()
+            {
+                "" => None,
+                "-" => Some(None),
+                input => match input.parse::<GeoPoint>() {
+                    Ok(location) => Some(Some(location)),
+                    Err(e) => {
+                        println!("{} {}", "⚠".bright_red(), e.bright_red());
+                        return;
+                    }
+                },
+            };
+
+            let updated = mgr
+                .update_note(&current_note.id, title, content, tags)
+                .and_then(|note| match (note, location) {
+                    (Some(note), Some(location)) => mgr
+                        .set_location(&note.id, location)
+                        .map(|updated| updated.or(Some(note))),
+                    (note, _) => Ok(note),
+                });
+            match updated {
+                Ok(Some(_)) => println!("{}", "✅ Note updated successfully!".bright_green()),
+                Ok(None) => println!("{}", "❌ Note not found!".bright_red()),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        } else {
+            println!("{}", "❌ Invalid note number!".bright_red());
+        }
+    }
+}
+
+fn delete_note(manager: &SharedNotesManager, config: &Config) {
+    display_header("DELETE NOTE");
+    if let Some(index) = picker::choose_note(manager, &config.cli, "delete") {
+        let mut mgr = manager.lock().unwrap();
+        if index > 0 && index <= mgr.notes_count() {
+            let title = mgr
+                .get_note_by_index(index - 1)
+                .map(|note| note.title.clone())
+                .unwrap_or_default();
+            let action = format!("Delete '{}' for good? This can't be undone.", title);
+            if !confirmed(config, &action, &title) {
+                return;
+            }
+            match mgr.delete_note_by_index(index - 1) {
+                Ok(_) => println!("{}", "✅ Note deleted successfully!".bright_green()),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        } else {
+            println!("{}", "❌ Invalid note number!".bright_red());
+        }
+    }
+}
+
+// With `confirm_destructive` on, the user has to type `phrase` (a note's
+// title, or "yes") before an action that can't be undone
+fn confirmed(config: &Config, action: &str, phrase: &str) -> bool {
+    if !config.cli.confirm_destructive {
+        return true;
+    }
+    let phrase = if phrase.trim().is_empty() {
+        "yes"
+    } else {
+        phrase.trim()
+    };
+    println!("{} {}", "⚠".bright_yellow(), action.bright_yellow());
+    let typed = get_input(&format!(
+        "{} '{}' {} ",
+        "Type".bright_white(),
+        phrase.bold(),
+        "to confirm:".bright_white()
+    ));
+    if typed.trim() == phrase {
+        true
+    } else {
+        println!("{}", "❌ Cancelled.".bright_red());
+        false
+    }
+}
+
+fn manage_trash(manager: &SharedNotesManager, config: &Config) {
+    display_header("TRASH");
+    let mut mgr = manager.lock().unwrap();
+    let trashed = mgr.notes_in_state(StateFilter::Trashed);
+    if trashed.is_empty() {
+        println!("{}", "🗑 The trash is empty.".bright_yellow());
+        return;
+    }
+    for (i, note) in trashed.iter().enumerate() {
+        let when = note
+            .trashed_at
+            .map(|at| {
+                at.with_timezone(&Local)
+                    .format(" (%Y-%m-%d %H:%M)")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "{} {}{}",
+            format!("[{:2}]", i + 1).bright_white().bold(),
+            note.title.bold().green(),
+            when.bright_black()
+        );
+    }
+
+    let input = get_input(&format!(
+        "\n{} ",
+        "'r N' to restore, 'd N' to delete for good, 'empty' to empty the trash (Enter to go back):"
+            .bright_cyan()
+    ));
+    let (action, number) = input.split_once(' ').unwrap_or((input.as_str(), ""));
+    let note = number
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| trashed.get(i));
+
+    match (action, note) {
+        ("", _) => {}
+        ("empty", _) => {
+            let action = format!("Delete the {} notes in the trash for good?", trashed.len());
+            if !confirmed(config, &action, "yes") {
+                return;
+            }
+            match mgr.empty_trash() {
+                Ok(count) => println!(
+                    "{}",
+                    format!("✅ Deleted {} notes from the trash.", count).bright_green()
+                ),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        }
+        ("r", Some(note)) => {
+            let action = format!("Restore '{}' from the trash?", note.title);
+            if !confirmed(config, &action, &note.title) {
+                return;
+            }
+            match mgr.set_trashed(&note.id, false) {
+                Ok(_) => println!("{}", "✅ Note restored!".bright_green()),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        }
+        ("d", Some(note)) => {
+            let action = format!("Delete '{}' for good? This can't be undone.", note.title);
+            if !confirmed(config, &action, &note.title) {
+                return;
+            }
+            match mgr.delete_note(&note.id) {
+                Ok(_) => println!("{}", "✅ Note deleted for good.".bright_green()),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        }
+        _ => println!("{}", "❌ Invalid choice!".bright_red()),
+    }
+}
+
+fn clean_up(manager: &SharedNotesManager, config: &Config) {
+    display_header("CLEAN UP");
+    println!(
+        "{}",
+        "Notes matching everything you give are removed (Enter to skip one).".bright_black()
+    );
+    let answer = |prompt: &str| Some(get_input(prompt)).filter(|answer| !answer.is_empty());
+    let filter = CleanupFilter {
+        tag: answer(&format!("{} ", "🏷 Tag:".bright_cyan())),
+        older_than: answer(&format!(
+            "{} ",
+            "⏳ Untouched for at least (e.g. 90d, 12w):".bright_cyan()
+        )),
+        query: answer(&format!("{} ", "🔍 Search query:".bright_cyan())),
+    };
+
+    let mut mgr = manager.lock().unwrap();
+    let notes = match cleanup::matching(&mgr, &filter, false, &|_| true) {
+        Ok(notes) => notes,
+        Err(e) => {
+            println!("{} {}", "❌ Error:".bright_red(), e);
+            return;
+        }
+    };
+    if notes.is_empty() {
+        println!("{}", "📭 No notes match.".bright_yellow());
+        return;
+    }
+    println!(
+        "\n{}",
+        format!("{} notes match:", notes.len())
+            .bright_white()
+            .bold()
+    );
+    for note in &notes {
+        println!(
+            "  {} {} {}",
+            "•".bright_black(),
+            note.title.bold().green(),
+            format!(
+                "({}, updated {})",
+                note.short_id(),
+                note.updated_at.with_timezone(&Local).format("%Y-%m-%d")
+            )
+            .bright_black()
+        );
+    }
+
+    let ids: Vec<String> = notes.iter().map(|note| note.id.clone()).collect();
+    let choice = get_input(&format!(
+        "\n{} ",
+        "'t' to move them to the trash, 'd' to delete them for good (Enter to cancel):"
+            .bright_cyan()
+    ));
+    match choice.as_str() {
+        "" => {}
+        "t" => match mgr.trash_notes(&ids) {
+            Ok(count) => println!(
+                "{}",
+                format!("✅ Moved {} notes to the trash.", count).bright_green()
+            ),
+            Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+        },
+        "d" => {
+            let action = format!("Delete these {} notes for good?", ids.len());
+            if !confirmed(config, &action, "yes") {
+                return;
+            }
+            match mgr.delete_notes(&ids) {
+                Ok(count) => println!("{}", format!("✅ Deleted {} notes.", count).bright_green()),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        }
+        _ => println!("{}", "❌ Invalid choice!".bright_red()),
+    }
+}
+
+// Made like any title from content, or a timestamp if there's no text
+fn clip_title(content: &str, max_chars: usize, now: DateTime<Utc>) -> String {
+    titles::from_content(content, max_chars).unwrap_or_else(|| {
+        format!(
+            "Clipped {}",
+            now.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        )
+    })
+}
+
+/// `notes-app clip`: save the clipboard text as a new note and print its id.
+fn clip_note(manager: &SharedNotesManager, config: &Config) -> io::Result<()> {
+    let content = arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| io::Error::other(format!("Could not read clipboard: {}", e)))?;
+
+    if content.trim().is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Clipboard is empty",
+        ));
+    }
+
+    let mut mgr = manager.lock().unwrap();
+    let title = clip_title(&content, config.titles.max_length, mgr.now());
+    let note = mgr.add_note(title, content, vec!["clipped".to_string()])?;
+    println!("{}", note.id);
+    Ok(())
+}
+
+/// `notes-app capture <url> [tag...]`: save a web page's article as a new
+/// note and print its id.
+fn capture_note(manager: &SharedNotesManager, args: &[String]) -> io::Result<()> {
+    let Some(url) = args.get(2) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Usage: notes-app capture <url> [tag...]",
+        ));
+    };
+
+    let captured = capture::capture(url)?;
+    let mut mgr = manager.lock().unwrap();
+    let note = captured.into_note(&args[3..], mgr.now());
+    let note = mgr.insert_note(note)?;
+    println!("{}", note.id);
+    Ok(())
+}
+
+/// `notes-app summarize <number|id>` summarizes one note; without a note,
+/// every note of at least `--min-length` characters (`--tag` narrows them)
+/// that has no summary yet, or all of them again with `--redo`. Notes are
+/// summarized one at a time without holding the vault meanwhile.
+fn summarize_notes(
+    manager: &SharedNotesManager,
+    config: &Config,
+    args: &[String],
+) -> io::Result<()> {
+    let notes = {
+        let mgr = manager.lock().unwrap();
+        match args.get(2).filter(|arg| !arg.starts_with("--")) {
+            Some(key) => vec![find_note(&mgr, key)?],
+            None => {
+                let min_length = match flag_value(args, "--min-length") {
+                    Some(length) => length
+                        .parse()
+                        .map_err(|_| invalid_input(format!("Invalid length: {}", length)))?,
+                    None => summarize::DEFAULT_MIN_LENGTH,
+                };
+                let tag = flag_value(args, "--tag").map(|tag| mgr.tag_rules().canonical(tag));
+                let redo = args.iter().any(|arg| arg == "--redo");
+                mgr.awake_notes()
+                    .into_iter()
+                    .filter(|note| !note.is_encrypted() && note.content.len() >= min_length)
+                    .filter(|note| tag.as_deref().is_none_or(|tag| tags::has_tag(note, tag)))
+                    .filter(|note| redo || summarize::summary_of(note).is_none())
+                    .collect()
+            }
+        }
+    };
+    if notes.is_empty() {
+        println!("{}", "Nothing to summarize.".bright_yellow());
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for note in &notes {
+        println!(
+            "{} {}",
+            "📝 Summarizing".bright_blue(),
+            note.title.bright_cyan()
+        );
+        let summary = match summarize::summarize(&config.summary, note) {
+            Ok(summary) => summary,
+            // Not configured: no point asking for the others
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Err(e),
+            Err(e) => {
+                println!("{} {}", "✗".bright_red(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        summarize::save_summary(&mut manager.lock().unwrap(), &note.id, summary.clone())?;
+        println!("{}\n", summary);
+    }
+    println!(
+        "{} {} of {} notes",
+        "✅ Summarized".bright_green(),
+        notes.len() - failed,
+        notes.len()
+    );
+    Ok(())
+}
+
+fn print_diff(old: &str, new: &str) {
+    for line in diff::lines(old, new) {
+        match line {
+            diff::Line::Same(text) => println!("  {}", text),
+            diff::Line::Removed(text) => println!("{}", format!("- {}", text).red()),
+            diff::Line::Added(text) => println!("{}", format!("+ {}", text).green()),
+        }
+    }
+}
+
+/// `notes-app conflicts`: go through the conflict copies one by one, showing
+/// how each differs from its note and picking or merging the versions.
+fn resolve_conflicts(manager: &SharedNotesManager, config: &Config) -> io::Result<()> {
+    display_header("CONFLICTS");
+    let conflicts = manager.lock().unwrap().conflicts();
+    if conflicts.is_empty() {
+        println!("{}", "✅ No conflicts.".bright_green());
+        return Ok(());
+    }
+
+    for conflict in conflicts {
+        let copy = &conflict.copy;
+        println!(
+            "
+{}",
+            "─".repeat(60).bright_black()
+        );
+        let Some(original) = &conflict.original else {
+            println!(
+                "{} {}",
+                copy.title.bold().yellow(),
+                "(its note no longer exists)".bright_black()
+            );
+            let answer = get_input(&format!("{} ", "Keep it as a note? (y/n)".bright_cyan()));
+            if answer == "y" || answer == "yes" {
+                report_resolution(manager, &copy.id, Resolution::KeepBoth);
+            }
+            continue;
+        };
+
+        println!("{} {}", "- original:".red(), original.title.bold().green());
+        println!("{} {}", "+ copy:    ".green(), copy.title.bold().yellow());
+        let copy_tags: Vec<&str> = copy
+            .tags
+            .iter()
+            .map(String::as_str)
+            .filter(|tag| *tag != storage::CONFLICT_TAG)
+            .collect();
+        if original.tags != copy_tags {
+            println!(
+                "{} {}  {} {}",
+                "Tags:".bright_blue(),
+                original.tags.join(", ").red(),
+                "→".bright_black(),
+                copy_tags.join(", ").green()
+            );
+        }
+        println!();
+        print_diff(&original.content, &copy.content);
+
+        let choice = get_input(&format!(
+            "
+{} ",
+            "Keep [o]riginal, [c]opy, [b]oth, [m]erge in the editor, or [s]kip:".bright_cyan()
+        ));
+        let resolution = match choice.to_lowercase().as_str() {
+            "o" | "original" => Resolution::KeepOriginal,
+            "c" | "copy" => Resolution::KeepCopy,
+            "b" | "both" => Resolution::KeepBoth,
+            "m" | "merge" => {
+                let draft =
+                    diff::with_markers(&original.content, &copy.content, "original", "copy");
+                match content_from_editor(config, &draft) {
+                    Some(Some(merged)) => Resolution::Merged(merged),
+                    Some(None) => {
+                        println!("{}", "⏭ Left unmerged; skipped.".bright_black());
+                        continue;
+                    }
+                    None => {
+                        println!(
+                            "{}",
+                            "⚠ Merging needs an editor (set cli.editor or $EDITOR); skipped."
+                                .bright_yellow()
+                        );
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                println!("{}", "⏭ Skipped.".bright_black());
+                continue;
+            }
+        };
+        report_resolution(manager, &copy.id, resolution);
+    }
+    Ok(())
+}
+
+fn report_resolution(manager: &SharedNotesManager, copy_id: &str, resolution: Resolution) {
+    match manager
+        .lock()
+        .unwrap()
+        .resolve_conflict(copy_id, resolution)
+    {
+        Ok(Some(note)) => println!(
+            "{} {}",
+            "✅ Resolved:".bright_green(),
+            note.title.bold().green()
+        ),
+        Ok(None) => println!("{}", "❌ That copy is gone already.".bright_red()),
+        Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+    }
+}
+
+// `notes-app admin verify|compact|reindex|backup|backups`: store maintenance
+fn run_admin_command(
+    manager: &SharedNotesManager,
+    vault: &str,
+    config: &Config,
+    args: &[String],
+) -> io::Result<()> {
+    let backups = Backups::new(vault, &config.backup);
+    match args.get(2).map(String::as_str) {
+        Some("backup") => {
+            let report = backups.run(manager)?;
+            println!(
+                "{} {} notes and {} attachment(s) as {} ({} bytes) to {}",
+                "💾 Backed up".bright_green(),
+                report.notes,
+                report.files,
+                report.name.bright_cyan(),
+                report.bytes,
+                report.targets.join(" and ")
+            );
+            return Ok(());
+        }
+        Some("backups") => {
+            let snapshots = backups.list()?;
+            if snapshots.is_empty() {
+                println!("{}", "💾 No backups yet.".bright_yellow());
+            }
+            for snapshot in snapshots {
+                println!(
+                    "{}  {}  {} bytes  {}",
+                    snapshot
+                        .taken_at
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M"),
+                    snapshot.name.bright_cyan(),
+                    snapshot.bytes,
+                    snapshot.target.dimmed()
+                );
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut mgr = manager.lock().unwrap();
+    match args.get(2).map(String::as_str) {
+        Some("verify") => {
+            let report = maintenance::verify(&mgr, mgr.now());
+            println!(
+                "{}",
+                format!(
+                    "🔎 Checked {} notes and {} attachments",
+                    report.notes, report.attachments
+                )
+                .bright_blue()
+            );
+            if report.is_ok() {
+                println!("{}", "✅ No problems found".bright_green());
+                return Ok(());
+            }
+            for problem in &report.problems {
+                let note = problem
+                    .note_id
+                    .as_deref()
+                    .map(|id| format!(" [{}]", &id[..id.len().min(8)]))
+                    .unwrap_or_default();
+                println!(
+                    "{} {}{}",
+                    "⚠".bright_yellow(),
+                    problem.detail,
+                    note.dimmed()
+                );
+            }
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} problem(s) found", report.problems.len()),
+            ))
+        }
+        Some("compact") => {
+            let report = maintenance::compact(&mut mgr)?;
+            println!(
+                "{} {} file(s) removed, {} bytes freed",
+                "🧹 Store compacted:".bright_green(),
+                report.removed_files,
+                report.freed_bytes
+            );
+            Ok(())
+        }
+        Some("storage") => {
+            for report in mgr.storage_reports(None) {
+                let meters = [
+                    ("Notes", &report.notes, report.trash.notes),
+                    ("Note bytes", &report.bytes, report.trash.bytes),
+                    (
+                        "Attachment bytes",
+                        &report.attachment_bytes,
+                        report.trash.attachment_bytes,
+                    ),
+                ];
+                for (name, meter, trashed) in meters {
+                    let limit = match (meter.limit, meter.percent) {
+                        (Some(limit), Some(percent)) => format!(" of {} ({:.0}%)", limit, percent),
+                        _ => " (no limit)".to_string(),
+                    };
+                    println!(
+                        "{:<18} {}{}, {} in the trash",
+                        name.bright_blue(),
+                        meter.used,
+                        limit,
+                        trashed
+                    );
+                }
+                for warning in &report.warnings {
+                    println!("{} {}", "⚠".bright_yellow(), warning.bright_yellow());
+                }
+            }
+            Ok(())
+        }
+        Some("reindex") => {
+            let report = maintenance::reindex(&mut mgr)?;
+            println!(
+                "{} {} notes, {} retagged, {} with a new language, {} attachment(s) rehashed",
+                "🔁 Store reindexed:".bright_green(),
+                report.notes,
+                report.retagged,
+                report.languages,
+                report.rehashed
+            );
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Usage: notes-app admin verify|compact|reindex|storage|backup|backups",
+        )),
+    }
+}
+
+/// `notes-app bench [--notes N] [--runs N] [--seed N] [--storage file|sqlite]`:
+/// times the store on generated notes in a throwaway directory.
+fn run_bench(args: &[String]) -> io::Result<()> {
+    let number = |flag: &str| -> io::Result<Option<u64>> {
+        flag_value(args, flag)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| invalid_input(format!("Invalid {}: {}", flag, value)))
+            })
+            .transpose()
+    };
+    let defaults = BenchOptions::default();
+    let options = BenchOptions {
+        notes: number("--notes")?.map_or(defaults.notes, |n| n as usize),
+        runs: number("--runs")?.map_or(defaults.runs, |n| n as usize),
+        seed: number("--seed")?.unwrap_or(defaults.seed),
+        scheme: flag_value(args, "--storage").map_or(defaults.scheme, str::to_string),
+    };
+    let format = match flag_value(args, "--output") {
+        Some(format) => format.parse::<OutputFormat>().map_err(invalid_input)?,
+        None => OutputFormat::default(),
+    };
+    if format == OutputFormat::Table {
+        println!(
+            "{}",
+            format!(
+                "⏱ Generating {} notes and timing the store...",
+                options.notes
+            )
+            .bright_blue()
+        );
+    }
+    let report = bench::run(&options)?;
+    println!("{}", output::render_bench(&report, format)?);
+    Ok(())
+}
+
+/// `notes-app user add|list|disable|enable|reset-password`: manages the
+/// accounts of multi-user mode in `data/users.json` directly, e.g. to set up
+/// the first admin on a headless server. A running server only reads the file
+/// at startup.
+#[cfg(feature = "web")]
+fn run_user_command(args: &[String]) -> io::Result<()> {
+    const USAGE: &str = "Usage: notes-app user add <username> [--role viewer|editor|admin] | list [--output json|yaml|table] | disable <user> | enable <user> | reset-password <user>";
+    let mut store = UserStore::new(users::DEFAULT_USERS_PATH)?;
+    let not_found = |key: &str| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No user with id or username '{}'", key),
+        )
+    };
+    let key = || {
+        args.get(3)
+            .filter(|arg| !arg.starts_with("--"))
+            .ok_or_else(|| invalid_input(USAGE.to_string()))
+    };
+
+    match args.get(2).map(String::as_str) {
+        Some("add") => {
+            let username = key()?.trim();
+            // The first account is always an admin (see `UserStore::add_user`)
+            let role = match flag_value(args, "--role") {
+                Some(role) => role.parse::<users::Role>().map_err(invalid_input)?,
+                None => users::Role::Editor,
+            };
+            let first = !store.is_enabled();
+            let (user, token) = store.add_user(username, role)?;
+            println!(
+                "{} {} ({})",
+                "✅ Created user".bright_green(),
+                user.username.bright_cyan(),
+                format!("{:?}", user.role).to_lowercase()
+            );
+            println!(
+                "{} {}",
+                "🔑 Token (shown once, keep it safe):".bright_yellow(),
+                token.bright_white().bold()
+            );
+            if first {
+                println!(
+                    "{}",
+                    "🔒 Multi-user mode is on: API requests now need a token".bright_yellow()
+                );
+            }
+        }
+        Some("list") => {
+            let format = match flag_value(args, "--output") {
+                Some(format) => format.parse::<OutputFormat>().map_err(invalid_input)?,
+                None => OutputFormat::default(),
+            };
+            let users = store.list_users();
+            if users.is_empty() && format == OutputFormat::Table {
+                println!(
+                    "{}",
+                    "👥 No users yet (single-user mode). Add one with `notes-app user add <username>`."
+                        .bright_yellow()
+                );
+                return Ok(());
+            }
+            println!("{}", output::render_users(&users, format)?);
+        }
+        Some(command @ ("disable" | "enable")) => {
+            let key = key()?;
+            let user = store
+                .set_disabled(key, command == "disable")?
+                .ok_or_else(|| not_found(key))?;
+            let status = if user.is_disabled() {
+                "🚫 Disabled".bright_yellow()
+            } else {
+                "✅ Enabled".bright_green()
+            };
+            println!("{} {}", status, user.username.bright_cyan());
+        }
+        Some("reset-password" | "reset-token") => {
+            let key = key()?;
+            let (user, token) = store.reset_token(key)?.ok_or_else(|| not_found(key))?;
+            println!(
+                "{} {}; the old token no longer works",
+                "🔁 New token for".bright_green(),
+                user.username.bright_cyan()
+            );
+            println!(
+                "{} {}",
+                "🔑 Token (shown once, keep it safe):".bright_yellow(),
+                token.bright_white().bold()
+            );
+        }
+        _ => return Err(invalid_input(USAGE.to_string())),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "web"))]
+fn run_user_command(_: &[String]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "This build has no multi-user mode (rebuild with the `web` feature)",
+    ))
+}
+
+/// `notes-app export --format markdown|json|pdf [--query <query>] [--tag <tag>]
+/// [--from <date>] [--to <date>] [--name <name>] [--zip] [--out <file>]`: the
+/// matching notes as one file, or with `--zip` as a zip of Markdown or JSON
+/// notes with their attachments, written to stdout without `--out`.
+fn run_export_command(manager: &SharedNotesManager, args: &[String]) -> io::Result<()> {
+    let format = match flag_value(args, "--format") {
+        Some(format) => format.parse::<ExportFormat>().map_err(invalid_input)?,
+        None => ExportFormat::default(),
+    };
+    let filter = ExportFilter {
+        tag: flag_value(args, "--tag").map(str::to_string),
+        from: flag_value(args, "--from").map(str::to_string),
+        to: flag_value(args, "--to").map(str::to_string),
+        query: flag_value(args, "--query").map(str::to_string),
+    };
+    let name = flag_value(args, "--name").unwrap_or("Notes");
+
+    let mgr = manager.lock().unwrap();
+    let notes = export::select(&mgr, &filter, &|_| true).map_err(invalid_input)?;
+    if args.iter().any(|arg| arg == "--zip") {
+        let archive =
+            Archive::new(notes, format, name, &mgr.attachments_dir()).map_err(invalid_input)?;
+        drop(mgr);
+        match flag_value(args, "--out") {
+            Some(path) => {
+                archive.write_to(io::BufWriter::new(std::fs::File::create(path)?))?;
+                println!(
+                    "{} {} notes and {} attachment(s) to {}",
+                    "📦 Exported".bright_green(),
+                    archive.note_count(),
+                    archive.file_count(),
+                    path.bright_cyan()
+                );
+            }
+            None => {
+                archive.write_to(io::stdout().lock())?;
+            }
+        }
+        return Ok(());
+    }
+    let body = export::export_notes(&notes, format, name)?;
+    match flag_value(args, "--out") {
+        Some(path) => {
+            std::fs::write(path, body)?;
+            println!(
+                "{} {} notes to {}",
+                "📦 Exported".bright_green(),
+                notes.len(),
+                path.bright_cyan()
+            );
+        }
+        None => io::stdout().write_all(&body)?,
+    }
+    Ok(())
+}
+
+/// `notes-app export|import --vault <file> [--password <password>]`: the
+/// whole vault as one encrypted file. Without `--password`, it's taken from
+/// `NOTES_VAULT_PASSWORD` or asked for.
+fn run_vault_file_command(
+    manager: &SharedNotesManager,
+    vault: &str,
+    args: &[String],
+) -> io::Result<()> {
+    let export = args[1] == "export";
+    let Some(path) = flag_value(args, "--vault") else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Usage: notes-app {} --vault <file>.{} [--password <password>]",
+                args[1],
+                vault_file::EXTENSION
+            ),
+        ));
+    };
+    let path = Path::new(path);
+    let password = match flag_value(args, "--password") {
+        Some(password) => password.to_string(),
+        None => match std::env::var("NOTES_VAULT_PASSWORD") {
+            Ok(password) => password,
+            Err(_) => {
+                let mut prompt = dialoguer::Password::new().with_prompt("Vault file password");
+                if export {
+                    prompt = prompt.with_confirmation("Repeat password", "Passwords don't match");
+                }
+                prompt.interact().map_err(|dialoguer::Error::IO(e)| e)?
+            }
+        },
+    };
+
+    let mut mgr = manager.lock().unwrap();
+    if export {
+        let report = vault_file::export(&mgr, vault, path, &password)?;
+        println!(
+            "{} {} notes and {} attachment(s) in {} ({} bytes)",
+            "📦 Exported".bright_green(),
+            report.notes,
+            report.files,
+            path.display().to_string().bright_cyan(),
+            report.bytes
+        );
+    } else {
+        let report = vault_file::import(&mut mgr, path, &password)?;
+        println!(
+            "{} {} added, {} replaced, {} unchanged, {} attachment(s) written",
+            format!(
+                "📥 Imported vault '{}' from {}:",
+                report.vault,
+                report
+                    .exported_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+            )
+            .bright_green(),
+            report.added,
+            report.replaced,
+            report.unchanged,
+            report.files
+        );
+    }
+    Ok(())
+}
+
+/// `notes-app import --notion <export.zip>`: the pages and databases of a
+/// Notion "Markdown & CSV" export.
+fn run_notion_import(manager: &SharedNotesManager, path: &str) -> io::Result<()> {
+    let zip = std::fs::read(path)?;
+    let report = notion::import(&mut manager.lock().unwrap(), &zip, None)?;
+    println!(
+        "{} {} pages and {} database rows ({} notebook(s)), {} links made wiki-links",
+        format!("📥 Imported from {}:", path).bright_green(),
+        report.pages,
+        report.rows,
+        report.notebooks,
+        report.links
+    );
+    Ok(())
+}
+
+// Value following `flag` on the command line, e.g. `--port 9000`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+#[cfg(feature = "web")]
+fn server_options(args: &[String], config: &Config) -> io::Result<ServerOptions> {
+    let mut options = ServerOptions::default();
+    if let Some(port) = config.server.port {
+        options.port = port;
+    }
+    if let Some(bind) = flag_value(args, "--bind") {
+        options.bind = bind.to_string();
+    }
+    if let Some(port) = flag_value(args, "--port") {
+        options.port = port.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid port: {}", port),
+            )
+        })?;
+    }
+    options.auto_port = args.iter().any(|arg| arg == "--auto-port");
+    options.insecure_cors = args.iter().any(|arg| arg == "--insecure-cors");
+    Ok(options)
+}
+
+/// Serves the web interface with the `--bind`/`--port` options in `args`.
+/// `current` answers requests without `X-Vault`; the other vaults in `base`
+/// are opened alongside it.
+#[cfg(feature = "web")]
+async fn start_web(
+    current: (String, SharedNotesManager),
+    live: &LiveConfig,
+    args: &[String],
+) -> io::Result<()> {
+    let base = live.base();
+    let config = live.current();
+    let options = server_options(args, &config)?;
+    let users = Arc::new(Mutex::new(UserStore::new(users::DEFAULT_USERS_PATH)?));
+    let note_tokens = Arc::new(Mutex::new(NoteTokenStore::new(
+        tokens::DEFAULT_TOKENS_PATH,
+    )?));
+
+    // Link checks send requests to the linked sites, so only a server runs them
+    links::spawn_link_checker(current.1.clone(), config.links.clone());
+    let mut vaults = vec![current];
+    let mut watchers = Vec::new();
+    for name in base.vault_names() {
+        if vaults.iter().any(|(open, _)| open == name) {
+            continue;
+        }
+        let vault_config = base.for_vault(name)?;
+        let manager = open_vault(&vault_config)?;
+        watchers.push(start_background_tasks(&manager, name, &vault_config, live));
+        links::spawn_link_checker(manager.clone(), vault_config.links.clone());
+        vaults.push((name.to_string(), manager));
+    }
+    // Safe settings apply while serving; the rest are listed at /api/admin/config
+    match watcher::watch_config_file(live.clone(), vaults.clone()) {
+        Ok(watcher) => watchers.push(Some(watcher)),
+        Err(e) => println!("{} {}", "⚠ Config reloading disabled:".bright_yellow(), e),
+    }
+    let served = WebServerBuilder::new(vaults.clone(), users, note_tokens, (*config).clone())
+        .live_config(live.clone())
+        .options(options)
+        .prefix(flag_value(args, "--prefix").unwrap_or(""))
+        .run()
+        .await;
+    // Views counted since the last flush
+    for (name, manager) in &vaults {
+        if let Err(e) = manager.lock().unwrap().flush_views() {
+            println!("⚠ Views of {} not saved: {}", name, e);
+        }
+    }
+    served
+}
+
+#[cfg(not(feature = "web"))]
+async fn start_web(
+    _: (String, SharedNotesManager),
+    _: &LiveConfig,
+    _: &[String],
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "This build has no web interface (rebuild with the `web` feature)",
+    ))
+}
+
+fn export_note_pdf(manager: &SharedNotesManager, config: &Config) {
+    display_header("EXPORT NOTE TO PDF");
+    if let Some(index) = picker::choose_note(manager, &config.cli, "export") {
+        let mgr = manager.lock().unwrap();
+        if let Some(note) = index.checked_sub(1).and_then(|i| mgr.get_note_by_index(i)) {
+            let default_path = export::file_name(note, "pdf");
+            let path = get_input(&format!(
+                "{} [{}]: ",
+                "Output file".bright_green(),
+                default_path
+            ));
+            let path = if path.is_empty() { default_path } else { path };
+
+            match export::note_to_pdf(note)
+                .map_err(|e| io::Error::other(e.to_string()))
+                .and_then(|pdf| std::fs::write(&path, pdf))
+            {
+                Ok(_) => println!(
+                    "{} {}",
+                    "✅ Note exported to".bright_green(),
+                    path.bright_cyan()
+                ),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+        } else {
+            println!("{}", "❌ Invalid note number!".bright_red());
+        }
+    } else {
+        println!("{}", "❌ Please enter a valid number!".bright_red());
+    }
+}
+
+fn share_note(manager: &SharedNotesManager, config: &Config) {
+    display_header("SHARE NOTE");
+    if let Some(index) = picker::choose_note(manager, &config.cli, "share") {
+        let mgr = manager.lock().unwrap();
+        if let Some(note) = index.checked_sub(1).and_then(|i| mgr.get_note_by_index(i)) {
+            let base_url = config.server.public_url.clone().unwrap_or_else(|| {
+                format!("http://localhost:{}", config.server.port.unwrap_or(8080))
+            });
+            let url = format!("{}/n/{}", base_url.trim_end_matches('/'), note.short_id());
+
+            println!("{} {}", "🔗 Link:".bright_cyan(), url.bright_white());
+            match qr::qr_terminal(&url) {
+                Ok(code) => println!("{}", code),
+                Err(e) => println!("{} {}", "❌ Error:".bright_red(), e),
+            }
+            if config.server.public_url.is_none() {
+                println!(
+                    "{}",
+                    "ℹ Set server.public_url in data/config.json to your LAN address to scan from a phone."
+                        .bright_blue()
+                );
+            }
+        } else {
+            println!("{}", "❌ Invalid note number!".bright_red());
+        }
+    } else {
+        println!("{}", "❌ Please enter a valid number!".bright_red());
+    }
+}
+
+/// Opens the notes store. A damaged JSON file is moved aside after salvaging
+/// what it can, and starting over with nothing requires the user's confirmation.
+// Removes `--vault <name>` from the arguments, so commands keep their positions.
+// After `export`/`import`, `--vault` names the vault file instead
+fn take_vault_flag(mut args: Vec<String>) -> (Option<String>, Vec<String>) {
+    let is_file = |i: usize| {
+        args.iter()
+            .take(i)
+            .skip(1)
+            .any(|arg| arg == "export" || arg == "import")
+    };
+    match args.iter().position(|arg| arg == "--vault") {
+        Some(i) if i + 1 < args.len() && !is_file(i) => {
+            let vault = args.remove(i + 1);
+            args.remove(i);
+            (Some(vault), args)
+        }
+        _ => (None, args),
+    }
+}
+
+fn choose_vault(config: &Config) -> io::Result<String> {
+    let names = config.vault_names();
+    let choice = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt("Open which vault?")
+        .items(&names)
+        .default(0)
+        .interact()
+        .map_err(|dialoguer::Error::IO(e)| e)?;
+    Ok(names[choice].to_string())
+}
+
+fn open_vault(config: &Config) -> io::Result<SharedNotesManager> {
+    let mut notes_manager = open_notes(&config.storage.uri)?;
+    notes_manager.set_search_config(&config.search);
+    notes_manager.set_tag_config(&config.tags);
+    notes_manager.set_inline_limit(config.storage.inline_content_limit);
+    notes_manager.set_quota(&config.quota);
+    notes_manager.set_processors(&config.processors);
+    notes_manager.set_hooks(Hooks::load(&config.scripts)?);
+    Ok(Arc::new(Mutex::new(notes_manager)))
+}
+
+// Retention policies, scheduled templates and backups, webhooks and
+// post-save processors for one vault. Policies, webhooks and processors
+// follow `live`; the rest keep `config`. The returned watcher keeps the
+// notes fresh while it's alive
+fn start_background_tasks(
+    manager: &SharedNotesManager,
+    vault: &str,
+    config: &Config,
+    live: &LiveConfig,
+) -> Option<notify::RecommendedWatcher> {
+    let backups = Backups::new(vault, &config.backup);
+    policy::spawn_policy_task(manager.clone(), live.clone(), backups.clone());
+    backup::spawn_backup_scheduler(manager.clone(), backups);
+    templates::spawn_template_scheduler(manager.clone(), config.templates.clone());
+    views::spawn_view_flusher(manager.clone());
+    webhooks::spawn_webhooks(manager, live.clone(), vault);
+    processors::spawn_processors(manager, live.clone(), vault);
+
+    // Keep serving fresh data when notes.json is edited by another process
+    match watcher::watch_notes_file(manager.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            println!("{} {}", "⚠ File watching disabled:".bright_yellow(), e);
+            None
+        }
+    }
+}
+
+fn open_notes(uri: &str) -> io::Result<NotesManager> {
+    let error = match NotesManager::new(uri) {
+        Ok(manager) => return Ok(manager),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => e,
+        Err(e) => return Err(e),
+    };
+    let (scheme, path) = backend::parse_uri(uri);
+    if scheme != "file" {
+        return Err(error);
+    }
+
+    println!(
+        "{} {}: {}",
+        "❌ Could not read".bright_red().bold(),
+        path,
+        error
+    );
+    let recovered = storage::recover_notes_file(path)?;
+    let question = if recovered.is_empty() {
+        "No notes could be recovered. Start with an empty notebook? (y/n)".to_string()
+    } else {
+        format!(
+            "Recovered {} notes. Continue with just these? (y/n)",
+            recovered.len()
+        )
+    };
+    println!("{}", question.bright_yellow().bold());
+
+    let response = get_input("> ").to_lowercase();
+    if response != "y" && response != "yes" {
+        println!("{}", "Left the file untouched.".bright_yellow());
+        return Err(error);
+    }
+
+    let quarantined = storage::quarantine_file(path)?;
+    println!(
+        "{} {}",
+        "📦 Damaged file kept as".bright_green(),
+        quarantined
+    );
+    NotesManager::with_notes(uri, recovered)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Non-interactive `list`, `view` and `search` commands for scripts, printing
+/// with `--output json|yaml|table`. Returns false for any other command.
+// Full id first, then list number, short id and merged-away id
+fn find_note(mgr: &NotesManager, key: &str) -> io::Result<Note> {
+    mgr.get_note(key)
+        .or_else(|| mgr.get_note_by_slug(key))
+        .or_else(|| {
+            key.parse::<usize>()
+                .ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|i| mgr.get_note_by_index(i).cloned())
+        })
+        .or_else(|| mgr.get_note_by_short_id(key))
+        .or_else(|| mgr.merged_into(key).cloned())
+        .ok_or_else(|| invalid_input(format!("No note matches '{}'", key)))
+}
+
+fn run_script_command(args: &[String], manager: &SharedNotesManager) -> io::Result<bool> {
+    // `quicklist` has formats of its own
+    let format = || match flag_value(args, "--output") {
+        Some(format) => format.parse::<OutputFormat>().map_err(invalid_input),
+        None => Ok(OutputFormat::default()),
+    };
+    let mut mgr = manager.lock().unwrap();
+
+    let rendered = match args.get(1).map(String::as_str) {
+        Some("list") => {
+            let listed = match flag_value(args, "--state") {
+                Some(state) => mgr.notes_in_state(state.parse().map_err(invalid_input)?),
+                None => mgr.awake_notes(),
+            };
+            let tag = flag_value(args, "--tag").map(|tag| mgr.tag_rules().canonical(tag));
+            let mut notes: Vec<Note> = listed
+                .into_iter()
+                .filter(|note| tag.as_deref().is_none_or(|tag| tags::has_tag(note, tag)))
+                .collect();
+            let sort = match flag_value(args, "--sort") {
+                Some(sort) => sort.parse::<NoteSort>().map_err(invalid_input)?,
+                None => local_preferences().sort,
+            };
+            sort.apply(&mut notes);
+            // `--sort-by <column>` orders by a column instead, `--desc` turns it around
+            if let Some(column) = flag_value(args, "--sort-by") {
+                let column = column.parse::<Column>().map_err(invalid_input)?;
+                notes.sort_by(|a, b| column.compare(a, b));
+            }
+            if args.iter().any(|arg| arg == "--desc") {
+                notes.reverse();
+            }
+            if let Some(tag) = &tag {
+                notes.sort_by_key(|note| !note.is_pinned_in(tag));
+            }
+            let columns = match flag_value(args, "--columns") {
+                Some(columns) => Column::parse_list(columns).map_err(invalid_input)?,
+                None => output::DEFAULT_COLUMNS.to_vec(),
+            };
+            output::render_notes(&notes, format()?, &columns)?
+        }
+        Some(command @ ("pin" | "unpin")) => {
+            let (Some(key), Some(tag)) = (args.get(2), args.get(3)) else {
+                return Err(invalid_input(format!(
+                    "Usage: {} <number|id> <tag>",
+                    command
+                )));
+            };
+            let id = find_note(&mgr, key)?.id;
+            let pinned = command == "pin";
+            let note = mgr
+                .set_pinned_in(&id, tag, pinned)?
+                .ok_or_else(|| invalid_input(format!("No note matches '{}'", key)))?;
+            if pinned {
+                format!("📌 Pinned '{}' in {}", note.title, tag)
+            } else {
+                format!("📍 Unpinned '{}' from {}", note.title, tag)
+            }
+        }
+        // `slug <number|id> <slug>`, or `--clear` to remove it
+        Some("slug") => {
+            let (Some(key), Some(slug)) = (args.get(2), args.get(3)) else {
+                return Err(invalid_input(
+                    "Usage: slug <number|id> <slug|--clear>".to_string(),
+                ));
+            };
+            let id = find_note(&mgr, key)?.id;
+            let slug = Some(slug.as_str()).filter(|slug| *slug != "--clear");
+            let note = mgr
+                .set_slug(&id, slug)?
+                .ok_or_else(|| invalid_input(format!("No note matches '{}'", key)))?;
+            match &note.slug {
+                Some(slug) => format!("🔖 '{}' is now at /api/notes/by-slug/{}", note.title, slug),
+                None => format!("🔖 Removed the slug of '{}'", note.title),
+            }
+        }
+        // `preferences [set <key> <value>]`, shared with the web UI
+        Some("preferences") => {
+            let mut preferences = local_preferences();
+            if args.get(2).map(String::as_str) == Some("set") {
+                let (Some(key), Some(value)) = (args.get(3), args.get(4)) else {
+                    return Err(invalid_input(
+                        "Usage: preferences set <key> <value>".to_string(),
+                    ));
+                };
+                preferences.set(key, value).map_err(invalid_input)?;
+                PreferenceStore::new(preferences::DEFAULT_PREFERENCES_PATH)?
+                    .set(history::LOCAL_USER, preferences.clone())?;
+            }
+            output::render_preferences(&preferences, format()?)?
+        }
+        Some("view") => {
+            let key = args
+                .get(2)
+                .ok_or_else(|| invalid_input("Usage: view <number|id>".to_string()))?;
+            let id = find_note(&mgr, key)?.id;
+            let note = mgr
+                .record_view(&id)
+                .ok_or_else(|| invalid_input(format!("No note matches '{}'", key)))?;
+            mgr.flush_views()?;
+            let format = format()?;
+            let rendered = output::render_note(&note, format)?;
+            // Scripts reading JSON or YAML get the note alone, as before
+            if format == OutputFormat::Table {
+                let related = suggest::related_notes(&mgr.list_notes(), &note, 5);
+                format!("{}{}", rendered, output::render_related(&related))
+            } else {
+                rendered
+            }
+        }
+        // `replace <find> <replacement>`: a dry run unless confirmed with the
+        // token the dry run printed
+        Some("replace") => {
+            let (Some(find), Some(replacement)) = (args.get(2), args.get(3)) else {
+                return Err(invalid_input(
+                    "Usage: replace <find> <replacement> [--regex] [--ignore-case] [--tag <tag>] \
+                     [--query <query>] [--confirm <token>]"
+                        .to_string(),
+                ));
+            };
+            let request = Replace {
+                find: find.clone(),
+                replace: replacement.clone(),
+                regex: args.iter().any(|arg| arg == "--regex"),
+                ignore_case: args.iter().any(|arg| arg == "--ignore-case"),
+                query: flag_value(args, "--query").map(str::to_string),
+                tag: flag_value(args, "--tag").map(str::to_string),
+            };
+            let plan =
+                replace::plan(&mgr, &request, &|_| true, &|_| false).map_err(invalid_input)?;
+            let format = format()?;
+            let preview = output::render_replace_preview(&plan.preview, format)?;
+            match flag_value(args, "--confirm") {
+                // JSON and YAML carry the token themselves
+                None if plan.preview.notes.is_empty() || format != OutputFormat::Table => preview,
+                None => format!(
+                    "{}\nDry run: nothing changed. Add --confirm {} to apply",
+                    preview, plan.preview.token
+                ),
+                Some(token) if token != plan.preview.token => {
+                    println!("{}", preview);
+                    return Err(invalid_input(format!(
+                        "The notes changed since that dry run; check the preview above and \
+                         confirm with {}",
+                        plan.preview.token
+                    )));
+                }
+                Some(_) => {
+                    let changed = plan.apply(&mut mgr)?;
+                    format!("{}\n✅ Replaced in {} notes", preview, changed.len())
+                }
+            }
+        }
+        // For launchers: recently edited first, optionally narrowed by a search
+        // Journaling review: notes created lately, left alone for long, or
+        // written on this day in earlier years
+        Some(command @ ("today" | "week")) => {
+            let since = command
+                .parse::<Period>()
+                .map_err(invalid_input)?
+                .start(mgr.now());
+            let mut notes: Vec<Note> = mgr
+                .awake_notes()
+                .into_iter()
+                .filter(|note| note.created_at >= since)
+                .collect();
+            notes.sort_by_key(|note| note.created_at);
+            output::render_notes(&notes, format()?, output::DEFAULT_COLUMNS)?
+        }
+        Some("untouched") => {
+            let span = args
+                .get(2)
+                .filter(|arg| !arg.starts_with("--"))
+                .map_or(timeline::DEFAULT_UNTOUCHED, String::as_str);
+            let cutoff = timeline::parse_cutoff(span, mgr.now()).map_err(invalid_input)?;
+            let mut notes: Vec<Note> = mgr
+                .awake_notes()
+                .into_iter()
+                .filter(|note| note.updated_at < cutoff)
+                .collect();
+            notes.sort_by_key(|note| note.updated_at);
+            output::render_notes(&notes, format()?, output::DEFAULT_COLUMNS)?
+        }
+        Some("on-this-day") => {
+            let today = match flag_value(args, "--date") {
+                Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                    invalid_input(format!("Invalid date: {} (expected YYYY-MM-DD)", date))
+                })?,
+                None => mgr.now().with_timezone(&Local).date_naive(),
+            };
+            let found = timeline::on_this_day(mgr.awake_notes(), today);
+            output::render_anniversaries(&found, format()?)?
+        }
+        Some("quicklist") => {
+            let format = match flag_value(args, "--output") {
+                Some(format) => format.parse::<QuicklistFormat>().map_err(invalid_input)?,
+                None => QuicklistFormat::default(),
+            };
+            let mut notes = match args.get(2).filter(|arg| !arg.starts_with("--")) {
+                Some(query) => mgr.search_notes(query),
+                None => mgr.awake_notes(),
+            };
+            notes.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+            output::render_quicklist(&notes, format)?
+        }
+        Some("open") => {
+            let key = args
+                .get(2)
+                .ok_or_else(|| invalid_input("Usage: open <id>".to_string()))?;
+            find_note(&mgr, key)?.content
+        }
+        Some("search") => {
+            let query = args
+                .get(2)
+                .ok_or_else(|| invalid_input("Usage: search <query>".to_string()))?;
+            let sort = match flag_value(args, "--sort") {
+                Some(sort) => sort.parse::<SearchSort>().map_err(invalid_input)?,
+                None => SearchSort::default(),
+            };
+            let scope = match flag_value(args, "--scope") {
+                Some(scope) => scope.parse::<SearchScope>().map_err(invalid_input)?,
+                None => SearchScope::default(),
+            };
+            let mut hits = mgr.search_scoped(query, scope);
+            search::sort_hits(&mut hits, sort);
+            output::render_hits(&hits, format()?)?
+        }
+        _ => return Ok(false),
+    };
+
+    // Output piped into `head` and the like may close early; that's not an error
+    match writeln!(io::stdout(), "{}", rendered) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+        _ => Ok(true),
+    }
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let (vault, args) = take_vault_flag(args);
+    let base = if args.len() > 1 && args[1] == "setup" {
+        let current = Config::load(config::DEFAULT_CONFIG_PATH)?;
+        setup::run_wizard(config::DEFAULT_CONFIG_PATH, current)?;
+        return Ok(());
+    } else if args.len() == 1
+        && setup::is_first_run(config::DEFAULT_CONFIG_PATH)
+        && setup::is_interactive()
+    {
+        setup::run_wizard(config::DEFAULT_CONFIG_PATH, Config::default())?
+    } else {
+        Config::load(config::DEFAULT_CONFIG_PATH)?
+    };
+    base.cli.color.apply();
+    output::use_preferences(local_preferences());
+
+    let vault = match vault {
+        Some(vault) => vault,
+        None if args.len() == 1 && !base.vaults.is_empty() && setup::is_interactive() => {
+            choose_vault(&base)?
+        }
+        None => config::DEFAULT_VAULT.to_string(),
+    };
+    if args.len() > 1 && args[1] == "user" {
+        return run_user_command(&args);
+    }
+    if args.len() > 1 && args[1] == "bench" {
+        return run_bench(&args);
+    }
+    let config = base.for_vault(&vault)?;
+    let live = LiveConfig::new(config::DEFAULT_CONFIG_PATH, base.clone(), &vault)?;
+    let manager = open_vault(&config)?;
+
+    if run_script_command(&args, &manager)? {
+        return Ok(());
+    }
+
+    if vault != config::DEFAULT_VAULT {
+        println!(
+            "{} {}",
+            "🗄 Vault:".bright_blue(),
+            vault.bright_cyan().bold()
+        );
+    }
+    let _watcher = start_background_tasks(&manager, &vault, &config, &live);
+
+    if args.len() > 1 && args[1] == "clip" {
+        clip_note(&manager, &config)?;
+    } else if args.len() > 1 && args[1] == "capture" {
+        capture_note(&manager, &args)?;
+    } else if args.len() > 1 && args[1] == "summarize" {
+        summarize_notes(&manager, &config, &args)?;
+    } else if args.len() > 1 && args[1] == "conflicts" {
+        resolve_conflicts(&manager, &config)?;
+    } else if args.len() > 1 && args[1] == "admin" {
+        run_admin_command(&manager, &vault, &config, &args)?;
+    } else if args.len() > 1
+        && args[1] == "export"
+        && (flag_value(&args, "--format").is_some() || args.iter().any(|arg| arg == "--zip"))
+    {
+        run_export_command(&manager, &args)?;
+    } else if args.len() > 1 && args[1] == "import" && flag_value(&args, "--notion").is_some() {
+        run_notion_import(&manager, flag_value(&args, "--notion").unwrap_or_default())?;
+    } else if args.len() > 1 && (args[1] == "export" || args[1] == "import") {
+        run_vault_file_command(&manager, &vault, &args)?;
+    } else if args.len() > 1 && args[1] == "web" {
         println!(
             "{}",
             "🌐 Starting Rust Notes Web Server...".bright_green().bold()
         );
-        web::start_web_server(manager).await;
+        start_web((vault.clone(), manager.clone()), &live, &args).await?;
     } else {
         // CLI mode
-        cli_mode(manager.clone())?;
+        cli_mode(manager.clone(), &config)?;
+        if !cfg!(feature = "web") {
+            return Ok(());
+        }
 
         // After CLI mode, ask if user wants to start web server
         println!("\n{}", "─".repeat(60).bright_blue());
@@ -356,7 +2798,7 @@ async fn main() -> io::Result<()> {
 
         if response == "y" || response == "yes" {
             println!("{}", "🌐 Starting web server...".bright_green().bold());
-            web::start_web_server(manager).await;
+            start_web((vault.clone(), manager.clone()), &live, &args).await?;
         }
     }
 
@@ -0,0 +1,300 @@
+use crate::blobs;
+use crate::models::content_hash;
+use crate::storage::NotesManager;
+use crate::thumbnail::ThumbSize;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Clocks drift; only times further ahead than this count as "in the future"
+const CLOCK_SKEW_MINUTES: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    DuplicateId,
+    BadTimestamp,
+    MissingParent,
+    MissingAttachment,
+    OrphanedFile,
+}
+
+/// Something `verify` found wrong with the store.
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note_id: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub notes: usize,
+    pub attachments: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactReport {
+    /// Unreferenced attachment files, previews and blobs deleted
+    pub removed_files: usize,
+    pub freed_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexReport {
+    pub notes: usize,
+    /// Notes whose tags changed under the current aliases and implications
+    pub retagged: usize,
+    /// Attachments from before content hashing moved to their hash
+    pub rehashed: usize,
+    /// Notes whose detected language changed
+    pub languages: usize,
+}
+
+/// Checks the notes for duplicate ids, impossible timestamps, dangling
+/// parents and attachments whose files are missing, and the attachments
+/// directory for files no note refers to. Changes nothing.
+pub fn verify(mgr: &NotesManager, now: DateTime<Utc>) -> VerifyReport {
+    let mut problems = Vec::new();
+    let mut problem = |kind, note_id: Option<&str>, detail: String| {
+        problems.push(Problem {
+            kind,
+            note_id: note_id.map(str::to_string),
+            detail,
+        })
+    };
+    let future = now + Duration::minutes(CLOCK_SKEW_MINUTES);
+
+    let mut note_ids = HashSet::new();
+    let mut attachment_ids = HashSet::new();
+    for note in &mgr.notes {
+        let id = Some(note.id.as_str());
+        if !note_ids.insert(note.id.as_str()) {
+            problem(
+                ProblemKind::DuplicateId,
+                id,
+                format!("Note id {} is used more than once", note.id),
+            );
+        }
+        if note.created_at > note.updated_at {
+            problem(
+                ProblemKind::BadTimestamp,
+                id,
+                format!(
+                    "Created ({}) after it was last updated ({})",
+                    note.created_at.to_rfc3339(),
+                    note.updated_at.to_rfc3339()
+                ),
+            );
+        }
+        let stamps = [
+            ("Created", Some(note.created_at)),
+            ("Updated", Some(note.updated_at)),
+            ("Archived", note.archived_at),
+            ("Trashed", note.trashed_at),
+        ];
+        for (label, time) in stamps {
+            if let Some(time) = time.filter(|time| *time > future) {
+                problem(
+                    ProblemKind::BadTimestamp,
+                    id,
+                    format!("{} in the future ({})", label, time.to_rfc3339()),
+                );
+            }
+        }
+
+        for attachment in &note.attachments {
+            if !attachment_ids.insert(attachment.id.as_str()) {
+                problem(
+                    ProblemKind::DuplicateId,
+                    id,
+                    format!("Attachment id {} is used more than once", attachment.id),
+                );
+            }
+            if !mgr.attachment_path(attachment.file_key()).is_file() {
+                problem(
+                    ProblemKind::MissingAttachment,
+                    id,
+                    format!(
+                        "File for attachment {} ({}) is missing",
+                        attachment.id, attachment.filename
+                    ),
+                );
+            }
+        }
+    }
+
+    for note in &mgr.notes {
+        if let Some(parent) = note
+            .parent_id
+            .as_deref()
+            .filter(|parent| !note_ids.contains(parent))
+        {
+            problem(
+                ProblemKind::MissingParent,
+                Some(&note.id),
+                format!("Parent note {} doesn't exist", parent),
+            );
+        }
+    }
+
+    for path in orphaned_files(mgr) {
+        problem(
+            ProblemKind::OrphanedFile,
+            None,
+            format!("{} isn't used by any note", path.display()),
+        );
+    }
+
+    VerifyReport {
+        notes: mgr.notes.len(),
+        attachments: attachment_ids.len(),
+        problems,
+    }
+}
+
+/// Deletes attachment files and previews no note refers to, then rewrites
+/// the store, which drops unused content blobs.
+pub fn compact(mgr: &mut NotesManager) -> io::Result<CompactReport> {
+    let mut report = CompactReport {
+        removed_files: 0,
+        freed_bytes: 0,
+    };
+    for path in orphaned_files(mgr) {
+        let size = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        fs::remove_file(&path)?;
+        report.removed_files += 1;
+        report.freed_bytes += size;
+    }
+
+    let blob_dir = blobs::dir_for(mgr.storage_path());
+    let (blob_count, blob_bytes) = dir_usage(&blob_dir);
+    mgr.save_notes()?;
+    let (count_after, bytes_after) = dir_usage(&blob_dir);
+    report.removed_files += blob_count.saturating_sub(count_after);
+    report.freed_bytes += blob_bytes.saturating_sub(bytes_after);
+    Ok(report)
+}
+
+/// Rebuilds what's derived from the notes: tags are run through the current
+/// aliases and implications, languages and outlines are worked out again, and attachments
+/// stored before content hashing are moved to their hash (sharing files with
+/// identical ones). Always saves, so cached responses are rebuilt too.
+pub fn reindex(mgr: &mut NotesManager) -> io::Result<ReindexReport> {
+    let rules = mgr.tag_rules().clone();
+    let colors = mgr.tag_colors().clone();
+    let mut retagged = 0;
+    let mut languages = 0;
+    for note in &mut mgr.notes {
+        let tags = rules.apply(note.tags.clone());
+        if tags != note.tags {
+            note.tags = tags;
+            retagged += 1;
+        }
+        note.tag_colors = colors.for_tags(&note.tags);
+        let language = note.language.take();
+        note.analyze();
+        if note.language != language {
+            languages += 1;
+        }
+    }
+
+    let mut rehashed = 0;
+    for n in 0..mgr.notes.len() {
+        for a in 0..mgr.notes[n].attachments.len() {
+            let attachment = &mgr.notes[n].attachments[a];
+            if attachment.sha256.is_some() {
+                continue;
+            }
+            let old_path = mgr.attachment_path(&attachment.id);
+            // A missing file stays missing; `verify` reports it
+            let Ok(bytes) = fs::read(&old_path) else {
+                continue;
+            };
+            let hash = content_hash(&bytes);
+            let new_path = mgr.attachment_path(&hash);
+            if new_path.exists() {
+                fs::remove_file(&old_path)?;
+            } else {
+                fs::rename(&old_path, &new_path)?;
+            }
+            for size in ThumbSize::ALL {
+                let _ = fs::remove_file(mgr.thumbnail_path(&attachment.id, size));
+            }
+            mgr.notes[n].attachments[a].sha256 = Some(hash);
+            rehashed += 1;
+        }
+    }
+
+    mgr.save_notes()?;
+    Ok(ReindexReport {
+        notes: mgr.notes.len(),
+        retagged,
+        rehashed,
+        languages,
+    })
+}
+
+// Files in the attachments directory (and its previews) whose key no
+// attachment uses
+fn orphaned_files(mgr: &NotesManager) -> Vec<PathBuf> {
+    let used: HashSet<&str> = mgr
+        .notes
+        .iter()
+        .flat_map(|note| &note.attachments)
+        .map(|attachment| attachment.file_key())
+        .collect();
+    let dir = mgr.attachments_dir();
+
+    let mut orphans: Vec<PathBuf> = files_in(&dir)
+        .into_iter()
+        .filter(|path| file_name(path).is_some_and(|name| !used.contains(name)))
+        .collect();
+    orphans.extend(files_in(&dir.join("thumbs")).into_iter().filter(|path| {
+        file_name(path).is_some_and(|name| {
+            let key = ThumbSize::ALL
+                .iter()
+                .find_map(|size| name.strip_suffix(&format!("-{}.png", size.name())));
+            key.is_none_or(|key| !used.contains(key))
+        })
+    }));
+    orphans.sort();
+    orphans
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+// Number of files and their total size
+fn dir_usage(dir: &Path) -> (usize, u64) {
+    let files = files_in(dir);
+    let bytes = files
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum();
+    (files.len(), bytes)
+}
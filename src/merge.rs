@@ -0,0 +1,111 @@
+use crate::models::{Note, Revision};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// How the content of merged notes is combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Every note's content in turn, each under its title
+    #[default]
+    Concatenate,
+    /// Only the longest content, with its title
+    KeepLongest,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "concatenate" | "concat" => Ok(MergeStrategy::Concatenate),
+            "keep-longest" | "longest" => Ok(MergeStrategy::KeepLongest),
+            other => Err(format!(
+                "Unknown merge strategy: {} (expected concatenate or keep-longest)",
+                other
+            )),
+        }
+    }
+}
+
+/// Combines `notes` into the first one, which keeps its id. Content follows
+/// `strategy`; tags and attachments are unioned; every note's history, and
+/// its version from before the merge, are kept as revisions; the other ids
+/// are remembered in `merged_from` so links to them still resolve.
+pub fn merge(notes: Vec<Note>, strategy: MergeStrategy, now: DateTime<Utc>) -> Note {
+    let (title, content) = match strategy {
+        MergeStrategy::Concatenate => (
+            notes[0].title.clone(),
+            notes
+                .iter()
+                .map(|note| format!("## {}\n\n{}", note.title, note.content.trim_end()))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        ),
+        MergeStrategy::KeepLongest => {
+            // The first of equally long notes wins
+            let longest = notes.iter().fold(&notes[0], |longest, note| {
+                if note.content.chars().count() > longest.content.chars().count() {
+                    note
+                } else {
+                    longest
+                }
+            });
+            (longest.title.clone(), longest.content.clone())
+        }
+    };
+
+    let mut revisions: Vec<Revision> = notes
+        .iter()
+        .flat_map(|note| {
+            note.revisions
+                .iter()
+                .cloned()
+                .chain(std::iter::once(Revision {
+                    title: note.title.clone(),
+                    content: note.content.clone(),
+                    content_blob: None,
+                    tags: note.tags.clone(),
+                    saved_at: now,
+                }))
+        })
+        .collect();
+    revisions.sort_by_key(|revision| revision.saved_at);
+
+    let mut notes = notes.into_iter();
+    let mut merged = notes.next().expect("merging needs at least one note");
+    for note in notes {
+        for tag in note.tags {
+            if !merged.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                merged.tags.push(tag);
+            }
+        }
+        merged.attachments.extend(note.attachments);
+        merged.created_at = merged.created_at.min(note.created_at);
+        merged.location = merged.location.or(note.location);
+        merged.source_url = merged.source_url.or(note.source_url);
+        merged.due_at = merged.due_at.or(note.due_at);
+        for (key, value) in note.metadata {
+            merged.metadata.entry(key).or_insert(value);
+        }
+        for pin in note.pinned_in {
+            if !merged.is_pinned_in(&pin) {
+                merged.pinned_in.push(pin);
+            }
+        }
+        for reader in note.shared_with {
+            if !merged.shared_with.contains(&reader) && merged.owner.as_ref() != Some(&reader) {
+                merged.shared_with.push(reader);
+            }
+        }
+        merged.merged_from.push(note.id);
+        merged.merged_from.extend(note.merged_from);
+    }
+    merged.title = title;
+    merged.content = content;
+    merged.revisions = revisions;
+    merged.analyze();
+    merged.updated_at = now;
+    merged
+}
@@ -1,36 +1,668 @@
-use chrono::Local;
+use crate::crypto::Sealed;
+use crate::language;
+use crate::notebooks::NotebookAccess;
+use crate::outline::{self, Heading};
+use crate::tags;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
 use uuid::Uuid;
 
+/// Longest slug a note can have.
+pub const MAX_SLUG_LENGTH: usize = 64;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Note {
     pub id: String,
+    /// Name chosen for the note's URL (`/api/notes/by-slug/{slug}`), unique
+    /// in the vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    pub title: String,
+    pub content: String,
+    /// Only in the stored form of notes whose content is too large to keep
+    /// inline: the blob holding it (see `blobs.rs`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_blob: Option<String>,
+    /// Set on encrypted notes, whose `content` is then only a placeholder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<Sealed>,
+    /// Stored and served as UTC; older files with local offsets are converted on load
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    /// Colors of those tags that have one, kept up to date as the tags or
+    /// their colors change
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tag_colors: BTreeMap<String, String>,
+    /// Id of the user who created the note, or was handed it since;
+    /// `None` in single-user mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Ids of other users who may read (not edit) the note
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shared_with: Vec<String>,
+    /// A handover to another user, waiting for them to accept it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_transfer: Option<Transfer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// Remarks left on the note, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<Comment>,
+    /// Emoji put on the note, at most one of each per user
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>,
+    /// Note this one is a reply to or sub-note of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Id of the notebook the note is in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notebook: Option<String>,
+    /// Who that notebook lets at the note, kept up to date as its members
+    /// change; not stored
+    #[serde(skip)]
+    pub notebook_access: Option<NotebookAccess>,
+    /// Set while the note is archived: kept and searchable, but out of the way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<DateTime<Utc>>,
+    /// Set while the note is in the trash; trashed notes are hidden from lists and search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trashed_at: Option<DateTime<Utc>>,
+    /// Hidden from the default list and search until this time passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// When whatever the note is about is due; listed in the calendar feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<DateTime<Utc>>,
+    /// Earlier versions of the note, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<Revision>,
+    /// Where the note was written or what place it's about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<GeoPoint>,
+    /// Page the note was captured from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// Language the note is written in (ISO 639-1, e.g. "de"), detected
+    /// whenever its text changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Section headings of the content, kept up to date as it changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outline: Vec<Heading>,
+    /// Ids of notes merged into this one, which still lead here
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub merged_from: Vec<String>,
+    /// On a conflict copy: the note whose version this one collided with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict_of: Option<String>,
+    /// Free-form fields such as `project`, which the content can show with
+    /// `{{metadata.project}}` wherever it's rendered
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Value>,
+    /// Tags under which the note is listed first, e.g. a project's overview
+    /// pinned in `alpha`; the unfiltered list is unaffected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_in: Vec<String>,
+    /// How often the note has been opened, through the API or the CLI
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub view_count: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+/// A note offered to another user, who becomes its owner by accepting.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Transfer {
+    /// Id of the user the note is offered to
+    pub to: String,
+    /// Id of the user who offered it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_by: Option<String>,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Longest comment accepted, in characters.
+pub const MAX_COMMENT_LENGTH: usize = 10_000;
+
+/// A remark left on a note and shown beneath it. Comments aren't part of the
+/// note's text: adding or removing one isn't an edit.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Comment {
+    pub id: String,
+    /// Id of the user who wrote it; `None` in single-user mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Their username at the time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Comment {
+    /// A comment saying `body`, trimmed; empty and overlong ones are refused.
+    pub fn new(
+        author: Option<String>,
+        author_name: Option<String>,
+        body: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Self, String> {
+        let body = body.trim();
+        if body.is_empty() {
+            return Err("Comment cannot be empty".to_string());
+        }
+        if body.chars().count() > MAX_COMMENT_LENGTH {
+            return Err(format!(
+                "Comment is longer than {} characters",
+                MAX_COMMENT_LENGTH
+            ));
+        }
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            author,
+            author_name,
+            body: body.to_string(),
+            created_at: now,
+        })
+    }
+}
+
+/// An emoji put on a note, e.g. 👍 for useful or ⭐ for important. Like
+/// comments, reactions don't count as edits.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Reaction {
+    pub emoji: String,
+    /// Id of the user who reacted; `None` in single-user mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Reaction {
+    /// `emoji` trimmed, if it looks like one: a few characters, none of them
+    /// ASCII or whitespace (skin tones and ZWJ sequences take several).
+    pub fn parse_emoji(emoji: &str) -> Result<String, String> {
+        let emoji = emoji.trim();
+        let valid = (1..=16).contains(&emoji.chars().count())
+            && emoji.chars().all(|c| !c.is_ascii() && !c.is_whitespace());
+        if valid {
+            Ok(emoji.to_string())
+        } else {
+            Err(format!("'{}' is not an emoji", emoji))
+        }
+    }
+}
+
+/// How many reacted to a note with one emoji.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: usize,
+}
+
+/// Where reading a long note left off.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ReadingProgress {
+    /// Share of the note read so far, 0 to 100
+    pub percent: f64,
+    /// Position chosen by the client, e.g. the id of the last heading passed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ReadingProgress {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.percent.is_finite() || !(0.0..=100.0).contains(&self.percent) {
+            return Err(format!(
+                "Progress must be between 0 and 100 percent, got {}",
+                self.percent
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A place on Earth in decimal degrees, with an optional human-readable name.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+// Mean Earth radius used by the haversine formula
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+impl GeoPoint {
+    /// Checks the coordinates are finite and within latitude/longitude range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.lat.is_finite() || !(-90.0..=90.0).contains(&self.lat) {
+            return Err(format!(
+                "Latitude must be between -90 and 90, got {}",
+                self.lat
+            ));
+        }
+        if !self.lon.is_finite() || !(-180.0..=180.0).contains(&self.lon) {
+            return Err(format!(
+                "Longitude must be between -180 and 180, got {}",
+                self.lon
+            ));
+        }
+        Ok(())
+    }
+
+    /// Great-circle distance to `other` in kilometres (haversine).
+    pub fn distance_km(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+}
+
+impl std::fmt::Display for GeoPoint {
+    /// `lat,lon[,label]`, the same form `from_str` accepts.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.lat, self.lon)?;
+        if let Some(label) = &self.label {
+            write!(f, ",{}", label)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for GeoPoint {
+    type Err = String;
+
+    /// Parses `lat,lon` with an optional `,label` after it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ',');
+        let mut coordinate = |name: &str| {
+            parts
+                .next()
+                .map(str::trim)
+                .filter(|part| !part.is_empty())
+                .ok_or_else(|| format!("Missing {} (expected lat,lon)", name))?
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid {} (expected a number)", name))
+        };
+        let lat = coordinate("latitude")?;
+        let lon = coordinate("longitude")?;
+        let label = parts
+            .next()
+            .map(|label| label.trim().to_string())
+            .filter(|label| !label.is_empty());
+        let point = GeoPoint { lat, lon, label };
+        point.validate()?;
+        Ok(point)
+    }
+}
+
+/// A note with its sub-notes, recursively.
+#[derive(Debug, Serialize, Clone)]
+pub struct ThreadNode {
+    #[serde(flatten)]
+    pub note: Note,
+    pub children: Vec<ThreadNode>,
+}
+
+/// A conflict copy with the note it collided with, if that still exists.
+#[derive(Debug, Serialize, Clone)]
+pub struct Conflict {
+    pub copy: Note,
+    pub original: Option<Note>,
+}
+
+/// How a conflict is settled; the copy is gone afterwards, except with `KeepBoth`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The note stays as it is
+    KeepOriginal,
+    /// The note takes the copy's title, content and tags
+    KeepCopy,
+    /// The copy becomes an ordinary note of its own
+    KeepBoth,
+    /// The note gets this content, e.g. the two versions merged by hand
+    Merged(String),
+}
+
+/// Snapshot of a note taken before an edit changed its text or tags.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Revision {
     pub title: String,
     pub content: String,
-    pub created_at: String,
-    pub updated_at: String,
+    /// Stored form only, like [`Note::content_blob`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_blob: Option<String>,
     pub tags: Vec<String>,
+    /// When this version was current until
+    pub saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentKind {
+    File,
+    Image,
+    Audio,
+}
+
+impl AttachmentKind {
+    pub fn from_content_type(content_type: &str) -> Self {
+        if content_type.starts_with("audio/") {
+            AttachmentKind::Audio
+        } else if content_type.starts_with("image/") {
+            AttachmentKind::Image
+        } else {
+            AttachmentKind::File
+        }
+    }
+}
+
+/// Where a note is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteState {
+    Active,
+    Archived,
+    /// Trashed wins over archived: a trashed note is only in the trash
+    Trashed,
+}
+
+/// Which notes a listing shows: one state, or all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateFilter {
+    Active,
+    Archived,
+    Trashed,
+    /// Notes outside the trash that are still snoozed
+    Snoozed,
+    All,
+}
+
+impl StateFilter {
+    /// Whether `note` is in this state at `now`.
+    pub fn matches(self, note: &Note, now: DateTime<Utc>) -> bool {
+        match self {
+            StateFilter::Active => note.state() == NoteState::Active,
+            StateFilter::Archived => note.state() == NoteState::Archived,
+            StateFilter::Trashed => note.state() == NoteState::Trashed,
+            StateFilter::Snoozed => !note.is_trashed() && note.is_snoozed(now),
+            StateFilter::All => true,
+        }
+    }
+}
+
+impl std::str::FromStr for StateFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "active" => Ok(StateFilter::Active),
+            "archived" => Ok(StateFilter::Archived),
+            "trashed" | "trash" => Ok(StateFilter::Trashed),
+            "snoozed" => Ok(StateFilter::Snoozed),
+            "all" => Ok(StateFilter::All),
+            other => Err(format!(
+                "Unknown state: {} (expected active, archived, trashed, snoozed or all)",
+                other
+            )),
+        }
+    }
+}
+
+/// File attached to a note; the bytes live in `data/attachments/`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: String,
+    pub filename: String,
+    pub content_type: String,
+    pub kind: AttachmentKind,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+    /// Speech-to-text of audio attachments, included in search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript: Option<String>,
+    /// Hash of the bytes. Attachments with the same content share one file,
+    /// `data/attachments/{sha256}`; older ones without it use their id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Hex SHA-256 of attachment bytes, the name they're stored under.
+pub fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+impl Attachment {
+    pub fn new(filename: String, content_type: String, bytes: &[u8]) -> Self {
+        Attachment {
+            id: Uuid::new_v4().to_string(),
+            kind: AttachmentKind::from_content_type(&content_type),
+            filename,
+            content_type,
+            size: bytes.len() as u64,
+            created_at: Utc::now(),
+            transcript: None,
+            sha256: Some(content_hash(bytes)),
+        }
+    }
+
+    /// Name of the file holding the bytes (and its previews).
+    pub fn file_key(&self) -> &str {
+        self.sha256.as_deref().unwrap_or(&self.id)
+    }
 }
 
 impl Note {
+    // The binary always goes through `new_at` with the manager's clock
+    #[allow(dead_code)]
     pub fn new(title: String, content: String, tags: Vec<String>) -> Self {
-        let now = Local::now().to_rfc3339();
-        Note {
+        Self::new_at(title, content, tags, Utc::now())
+    }
+
+    /// A new note created at `now` (see [`Clock`](crate::clock::Clock)).
+    pub fn new_at(title: String, content: String, tags: Vec<String>, now: DateTime<Utc>) -> Self {
+        let mut note = Note {
             id: Uuid::new_v4().to_string(),
+            slug: None,
             title,
             content,
-            created_at: now.clone(),
+            content_blob: None,
+            encrypted: None,
+            created_at: now,
             updated_at: now,
             tags,
+            tag_colors: BTreeMap::new(),
+            owner: None,
+            shared_with: Vec::new(),
+            pending_transfer: None,
+            attachments: Vec::new(),
+            comments: Vec::new(),
+            reactions: Vec::new(),
+            parent_id: None,
+            notebook: None,
+            notebook_access: None,
+            archived_at: None,
+            trashed_at: None,
+            snoozed_until: None,
+            due_at: None,
+            merged_from: Vec::new(),
+            conflict_of: None,
+            view_count: 0,
+            last_viewed_at: None,
+            revisions: Vec::new(),
+            location: None,
+            source_url: None,
+            language: None,
+            outline: Vec::new(),
+            metadata: BTreeMap::new(),
+            pinned_in: Vec::new(),
+        };
+        note.analyze();
+        note
+    }
+
+    /// Works out `language` and `outline` again from the title and content.
+    /// Encrypted notes get neither, since their content is only a placeholder.
+    pub fn analyze(&mut self) {
+        if self.is_encrypted() {
+            self.language = None;
+            self.outline = Vec::new();
+        } else {
+            self.language = language::detect(&format!("{}\n{}", self.title, self.content));
+            self.outline = outline::headings(&self.content);
+        }
+    }
+
+    /// Whether the note is pinned in exactly `tag` (ignoring case).
+    pub fn is_pinned_in(&self, tag: &str) -> bool {
+        let tag = tag.trim_matches(tags::SEPARATOR);
+        self.pinned_in
+            .iter()
+            .any(|pin| pin.eq_ignore_ascii_case(tag))
+    }
+
+    /// `slug` trimmed and lowercased, if it can name a note: up to
+    /// [`MAX_SLUG_LENGTH`] letters, digits and dashes, with a letter among
+    /// them and dashes only between words.
+    pub fn parse_slug(slug: &str) -> Result<String, String> {
+        let slug = slug.trim().to_lowercase();
+        let valid = (1..=MAX_SLUG_LENGTH).contains(&slug.len())
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && slug.chars().any(|c| c.is_ascii_lowercase())
+            && !slug.starts_with('-')
+            && !slug.ends_with('-')
+            && !slug.contains("--");
+        if valid {
+            Ok(slug)
+        } else {
+            Err(format!(
+                "'{}' can't be a slug: use lowercase letters, digits and single dashes, at most {} \
+                 characters",
+                slug, MAX_SLUG_LENGTH
+            ))
+        }
+    }
+
+    /// First 8 hex digits of the id, used in `/n/{short_id}` links.
+    pub fn short_id(&self) -> String {
+        self.id.chars().filter(|c| *c != '-').take(8).collect()
+    }
+
+    /// Hash of everything the note holds but its view counts, which don't
+    /// make it a different note: equal fingerprints, same note.
+    pub fn fingerprint(&self) -> u64 {
+        let mut fields = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = fields.as_object_mut() {
+            fields.remove("view_count");
+            fields.remove("last_viewed_at");
+        }
+        let mut hasher = DefaultHasher::new();
+        fields.to_string().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn state(&self) -> NoteState {
+        if self.is_trashed() {
+            NoteState::Trashed
+        } else if self.is_archived() {
+            NoteState::Archived
+        } else {
+            NoteState::Active
         }
     }
 
+    /// Words in the content; none while it's encrypted.
+    pub fn word_count(&self) -> usize {
+        if self.is_encrypted() {
+            return 0;
+        }
+        self.content.split_whitespace().count()
+    }
+
+    /// Counts of each emoji on the note, in the order they were first used.
+    pub fn reaction_counts(&self) -> Vec<ReactionCount> {
+        let mut counts: Vec<ReactionCount> = Vec::new();
+        for reaction in &self.reactions {
+            match counts.iter_mut().find(|c| c.emoji == reaction.emoji) {
+                Some(count) => count.count += 1,
+                None => counts.push(ReactionCount {
+                    emoji: reaction.emoji.clone(),
+                    count: 1,
+                }),
+            }
+        }
+        counts
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.trashed_at.is_some()
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    pub fn is_snoozed(&self, now: DateTime<Utc>) -> bool {
+        self.snoozed_until.is_some_and(|until| until > now)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted.is_some()
+    }
+
+    // The binary always goes through `update_at` with the manager's clock
+    #[allow(dead_code)]
     pub fn update(
         &mut self,
         title: Option<String>,
         content: Option<String>,
         tags: Option<Vec<String>>,
     ) {
+        self.update_at(title, content, tags, Utc::now())
+    }
+
+    /// [`update`](Self::update) as of `now`.
+    pub fn update_at(
+        &mut self,
+        title: Option<String>,
+        content: Option<String>,
+        tags: Option<Vec<String>>,
+        now: DateTime<Utc>,
+    ) {
+        let changes_text = title.as_ref().is_some_and(|t| *t != self.title)
+            || content.as_ref().is_some_and(|c| *c != self.content)
+            || tags.as_ref().is_some_and(|t| *t != self.tags);
+        if changes_text {
+            self.revisions.push(Revision {
+                title: self.title.clone(),
+                content: self.content.clone(),
+                content_blob: None,
+                tags: self.tags.clone(),
+                saved_at: now,
+            });
+        }
+
         if let Some(t) = title {
             self.title = t;
         }
@@ -39,7 +671,16 @@ impl Note {
         }
         if let Some(tags) = tags {
             self.tags = tags;
+            // A pin means nothing once the note no longer shows up under the tag
+            let pinned_in = std::mem::take(&mut self.pinned_in);
+            self.pinned_in = pinned_in
+                .into_iter()
+                .filter(|pin| tags::has_tag(self, pin))
+                .collect();
+        }
+        if changes_text {
+            self.analyze();
         }
-        self.updated_at = Local::now().to_rfc3339();
+        self.updated_at = now;
     }
 }
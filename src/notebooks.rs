@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Longest notebook name accepted.
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// Who besides its members can see a notebook's notes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Only the owner and the members who accepted
+    #[default]
+    Private,
+    /// Every account holder may read its notes
+    Team,
+}
+
+/// What a member may do with the notebook's notes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberAccess {
+    #[default]
+    Read,
+    Write,
+}
+
+/// A user invited into a notebook, who joins by accepting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    /// Id of the user
+    pub user: String,
+    pub access: MemberAccess,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invited_by: Option<String>,
+    pub invited_at: DateTime<Utc>,
+    /// Unset while the invitation waits for an answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub joined_at: Option<DateTime<Utc>>,
+}
+
+/// A collection of notes shared with the same people. Notes keep their
+/// owners; the notebook only lets others in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notebook {
+    pub id: String,
+    pub name: String,
+    /// Id of the user who created it; `None` in single-user mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<Member>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Who a notebook lets at its notes, kept on each of them so permission
+/// checks only need the note.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NotebookAccess {
+    team: bool,
+    /// The owner and the members who joined
+    users: BTreeMap<String, MemberAccess>,
+}
+
+impl NotebookAccess {
+    pub fn for_user(&self, user: &str) -> Option<MemberAccess> {
+        match self.users.get(user) {
+            Some(access) => Some(*access),
+            None => self.team.then_some(MemberAccess::Read),
+        }
+    }
+}
+
+fn valid_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Notebook name can't be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Notebook name is longer than {} characters",
+            MAX_NAME_LENGTH
+        ));
+    }
+    Ok(name.to_string())
+}
+
+impl Notebook {
+    pub fn new(
+        name: &str,
+        owner: Option<String>,
+        visibility: Visibility,
+        now: DateTime<Utc>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            name: valid_name(name)?,
+            owner,
+            visibility,
+            members: Vec::new(),
+            created_at: now,
+        })
+    }
+
+    pub fn rename(&mut self, name: &str) -> Result<(), String> {
+        self.name = valid_name(name)?;
+        Ok(())
+    }
+
+    pub fn access(&self) -> NotebookAccess {
+        let mut users: BTreeMap<String, MemberAccess> = self
+            .members
+            .iter()
+            .filter(|member| member.joined_at.is_some())
+            .map(|member| (member.user.clone(), member.access))
+            .collect();
+        if let Some(owner) = &self.owner {
+            users.insert(owner.clone(), MemberAccess::Write);
+        }
+        NotebookAccess {
+            team: self.visibility == Visibility::Team,
+            users,
+        }
+    }
+
+    /// The invitation waiting for `user` to accept it, if any.
+    pub fn invitation_for(&self, user: &str) -> Option<&Member> {
+        self.members
+            .iter()
+            .find(|member| member.user == user && member.joined_at.is_none())
+    }
+
+    /// Invites `user`, or changes the access of a member or invitation.
+    pub fn invite(
+        &mut self,
+        user: &str,
+        access: MemberAccess,
+        invited_by: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if self.owner.as_deref() == Some(user) {
+            return Err("The owner of a notebook can't be invited to it".to_string());
+        }
+        match self.members.iter_mut().find(|member| member.user == user) {
+            Some(member) => member.access = access,
+            None => self.members.push(Member {
+                user: user.to_string(),
+                access,
+                invited_by,
+                invited_at: now,
+                joined_at: None,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Joins `user` by accepting their invitation.
+    pub fn accept(&mut self, user: &str, now: DateTime<Utc>) -> Result<(), String> {
+        let member = self
+            .members
+            .iter_mut()
+            .find(|member| member.user == user && member.joined_at.is_none())
+            .ok_or_else(|| "No invitation to this notebook is waiting for you".to_string())?;
+        member.joined_at = Some(now);
+        Ok(())
+    }
+
+    /// Takes `user` out of the members, or their invitation back. Returns
+    /// whether they were there.
+    pub fn remove(&mut self, user: &str) -> bool {
+        let before = self.members.len();
+        self.members.retain(|member| member.user != user);
+        self.members.len() != before
+    }
+}
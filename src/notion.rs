@@ -0,0 +1,377 @@
+use crate::models::Note;
+use crate::notebooks::{self, Visibility};
+use crate::storage::NotesManager;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Cursor, Read};
+use std::sync::LazyLock;
+use uuid::Uuid;
+use zip::ZipArchive;
+
+// A Markdown link or image: `[label](target)`, `![alt](target)`
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(!?)\[([^\]\n]*)\]\(([^)\s]+)\)").unwrap());
+
+/// What a Notion import brought in.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NotionReport {
+    pub pages: usize,
+    /// Database rows, each a note in its database's notebook
+    pub rows: usize,
+    /// One per database
+    pub notebooks: usize,
+    /// Links between pages turned into wiki-links
+    pub links: usize,
+}
+
+// A note to create, with the export path it came from
+struct Imported {
+    path: Option<String>,
+    note: Note,
+}
+
+/// Adds the pages and databases of a Notion export ("Markdown & CSV", as a
+/// zip) to `mgr`. Sub-pages get their page as parent; each database becomes
+/// a notebook whose rows are notes, with the columns as metadata (a `Tags`
+/// column as tags). Links between exported pages become wiki-links.
+pub fn import(
+    mgr: &mut NotesManager,
+    zip: &[u8],
+    owner: Option<String>,
+) -> io::Result<NotionReport> {
+    let mut files = BTreeMap::new();
+    read_export(zip, &mut files)?;
+    if !files
+        .keys()
+        .any(|path| path.ends_with(".md") || path.ends_with(".csv"))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No Markdown or CSV files in the export; export from Notion as \"Markdown & CSV\"",
+        ));
+    }
+
+    let now = mgr.now();
+    let mut report = NotionReport::default();
+    let mut imported: Vec<Imported> = Vec::new();
+    // Every page's note id up front, so sub-pages can find their parent
+    // whether it's a page or a database row
+    let ids: HashMap<&str, String> = files
+        .keys()
+        .filter(|path| path.ends_with(".md"))
+        .map(|path| (path.as_str(), Uuid::new_v4().to_string()))
+        .collect();
+
+    // Pages outside databases
+    for (path, bytes) in files.iter().filter(|(path, _)| path.ends_with(".md")) {
+        let dir = parent_dir(path);
+        if database_csv(&files, dir).is_some() {
+            continue;
+        }
+        let text = String::from_utf8_lossy(bytes);
+        let (title, content) = split_title(&text, strip_id(file_stem(path)));
+        let mut note = Note::new_at(title, content, Vec::new(), now);
+        note.id = ids[path.as_str()].clone();
+        note.owner = owner.clone();
+        note.parent_id = ids.get(format!("{}.md", dir).as_str()).cloned();
+        imported.push(Imported {
+            path: Some(path.clone()),
+            note,
+        });
+        report.pages += 1;
+    }
+
+    // Databases: a CSV of the rows and a folder with each row's page
+    let databases: Vec<&String> = files
+        .keys()
+        .filter(|path| path.ends_with(".csv"))
+        .filter(|path| {
+            let folder = database_folder(path);
+            database_csv(&files, folder) == Some(path.as_str())
+        })
+        .collect();
+    let mut rows_used = HashSet::new();
+    for csv_path in databases {
+        let folder = database_folder(csv_path);
+        let mut name: String = strip_id(file_stem(folder))
+            .chars()
+            .take(notebooks::MAX_NAME_LENGTH)
+            .collect();
+        if name.trim().is_empty() {
+            name = "Notion database".to_string();
+        }
+        let notebook = mgr.create_notebook(&name, owner.clone(), Visibility::Private)?;
+        report.notebooks += 1;
+        let parent_id = ids
+            .get(format!("{}.md", parent_dir(csv_path)).as_str())
+            .cloned();
+
+        let mut reader = csv::Reader::from_reader(files[csv_path].as_slice());
+        let headers: Vec<String> = reader
+            .headers()
+            .map_err(csv_error)?
+            .iter()
+            // Notion writes a byte order mark
+            .map(|header| header.trim_start_matches('\u{feff}').to_string())
+            .collect();
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            let title = record.get(0).unwrap_or_default().trim().to_string();
+            let page = files
+                .keys()
+                .filter(|path| path.ends_with(".md") && parent_dir(path) == folder)
+                .find(|path| !rows_used.contains(*path) && strip_id(file_stem(path)) == title)
+                .cloned();
+            let content = match &page {
+                Some(path) => {
+                    rows_used.insert(path.clone());
+                    let text = String::from_utf8_lossy(&files[path]);
+                    strip_properties(&split_title(&text, &title).1, &headers)
+                }
+                None => String::new(),
+            };
+
+            let mut tags = Vec::new();
+            let mut metadata = BTreeMap::new();
+            for (header, value) in headers.iter().zip(record.iter()).skip(1) {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                if header.eq_ignore_ascii_case("tags") {
+                    tags.extend(
+                        value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|tag| !tag.is_empty())
+                            .map(str::to_string),
+                    );
+                } else {
+                    metadata.insert(header.clone(), Value::String(value.to_string()));
+                }
+            }
+
+            let mut note = Note::new_at(title, content, tags, now);
+            note.owner = owner.clone();
+            note.parent_id = parent_id.clone();
+            note.notebook = Some(notebook.id.clone());
+            note.metadata = metadata;
+            if let Some(path) = &page {
+                note.id = ids[path.as_str()].clone();
+            }
+            imported.push(Imported { path: page, note });
+            report.rows += 1;
+        }
+    }
+
+    // Titles shared by several notes can't name one, so those links use the id
+    let mut title_counts: HashMap<String, usize> = HashMap::new();
+    for title in mgr
+        .notes
+        .iter()
+        .map(|note| &note.title)
+        .chain(imported.iter().map(|imported| &imported.note.title))
+    {
+        *title_counts.entry(title.to_lowercase()).or_default() += 1;
+    }
+    let targets: HashMap<&str, String> = imported
+        .iter()
+        .filter_map(|imported| Some((imported.path.as_deref()?, &imported.note)))
+        .map(|(path, note)| {
+            let unique = title_counts.get(&note.title.to_lowercase()) == Some(&1);
+            let name = if unique && !note.title.contains(['|', '#', '[', ']']) {
+                note.title.clone()
+            } else {
+                note.id.clone()
+            };
+            (path, name)
+        })
+        .collect();
+    let mut contents = Vec::new();
+    for imported in &imported {
+        let Some(path) = &imported.path else {
+            contents.push(None);
+            continue;
+        };
+        let (content, links) = rewrite_links(&imported.note.content, parent_dir(path), &targets);
+        report.links += links;
+        contents.push(Some(content));
+    }
+
+    mgr.batch(|mgr| {
+        for (imported, content) in imported.into_iter().zip(contents) {
+            let mut note = imported.note;
+            if let Some(content) = content {
+                note.content = content;
+            }
+            mgr.insert_note(note)?;
+        }
+        Ok(())
+    })?;
+    Ok(report)
+}
+
+// Markdown and CSV files of the export by their path inside it. Large
+// exports come as a zip of zips, one per part
+fn read_export(zip: &[u8], files: &mut BTreeMap<String, Vec<u8>>) -> io::Result<()> {
+    let mut archive = ZipArchive::new(Cursor::new(zip)).map_err(zip_error)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(zip_error)?;
+        let name = entry.name().replace('\\', "/");
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension);
+        if entry.is_dir() || !matches!(extension, Some("md" | "csv" | "zip")) {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        if extension == Some("zip") {
+            read_export(&bytes, files)?;
+        } else {
+            files.insert(name, bytes);
+        }
+    }
+    Ok(())
+}
+
+// "Meeting notes 0123…cdef" → "Meeting notes": Notion ends file names with
+// the page id
+fn strip_id(name: &str) -> &str {
+    match name.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => title,
+        _ => name,
+    }
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit_once('/').map_or(path, |(_, name)| name);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+// The folder holding a database's row pages: its CSV's path without the
+// extension or the `_all` of the variant with every row
+fn database_folder(csv_path: &str) -> &str {
+    let folder = csv_path.strip_suffix(".csv").unwrap_or(csv_path);
+    folder.strip_suffix("_all").unwrap_or(folder)
+}
+
+// The CSV of the database whose rows are in `folder`, preferring the one
+// with every row over the one with a view's
+fn database_csv<'a>(files: &'a BTreeMap<String, Vec<u8>>, folder: &str) -> Option<&'a str> {
+    [format!("{}_all.csv", folder), format!("{}.csv", folder)]
+        .into_iter()
+        .find_map(|path| files.get_key_value(&path).map(|(path, _)| path.as_str()))
+}
+
+// The page's `# Title` heading, or `fallback` without one, and the rest
+fn split_title(text: &str, fallback: &str) -> (String, String) {
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    match text.strip_prefix("# ") {
+        Some(rest) => {
+            let (title, body) = rest.split_once('\n').unwrap_or((rest, ""));
+            (title.trim().to_string(), body.trim().to_string())
+        }
+        None => (fallback.to_string(), text.trim().to_string()),
+    }
+}
+
+// A row's page starts with its properties, one `Name: value` line each;
+// they're in the metadata already
+fn strip_properties(body: &str, headers: &[String]) -> String {
+    let mut lines = body.lines().peekable();
+    while let Some(line) = lines.peek() {
+        let is_property = line
+            .split_once(':')
+            .is_some_and(|(name, _)| headers.iter().any(|header| header == name.trim()));
+        if !is_property {
+            break;
+        }
+        lines.next();
+    }
+    lines.collect::<Vec<_>>().join("\n").trim().to_string()
+}
+
+// Replaces links to exported pages with wiki-links to what they became.
+// Returns the new content and how many links changed
+fn rewrite_links(content: &str, dir: &str, targets: &HashMap<&str, String>) -> (String, usize) {
+    let mut links = 0;
+    let rewritten = MARKDOWN_LINK.replace_all(content, |caps: &Captures| {
+        let (label, target) = (&caps[2], &caps[3]);
+        let name = (caps[1].is_empty() && !target.contains(':'))
+            .then(|| resolve(dir, target.split('#').next().unwrap_or_default()))
+            .and_then(|path| targets.get(path.as_str()));
+        match name {
+            Some(name) => {
+                links += 1;
+                if label.is_empty() || label == name {
+                    format!("[[{}]]", name)
+                } else {
+                    format!("[[{}|{}]]", name, label)
+                }
+            }
+            None => caps[0].to_string(),
+        }
+    });
+    (rewritten.into_owned(), links)
+}
+
+// Path of a link `target` (percent-encoded, relative) from a page in `dir`
+fn resolve(dir: &str, target: &str) -> String {
+    let mut parts: Vec<String> = dir
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect();
+    for part in percent_decode(target).split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part.to_string()),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn zip_error(e: zip::result::ZipError) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Not a readable Notion export: {}", e),
+    )
+}
+
+fn csv_error(e: csv::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unreadable Notion database: {}", e),
+    )
+}
@@ -0,0 +1,74 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// Whether `ip` is only reachable from inside: loopback, private and
+/// link-local ranges (cloud metadata at 169.254.169.254 included), carrier
+/// NAT, unspecified, broadcast and multicast addresses.
+pub fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_internal_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal_v4(ip),
+            None => is_internal_v6(ip),
+        },
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // 0.0.0.0/8 and carrier-grade NAT, 100.64.0.0/10
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// Resolves `host:port` like the system does, refusing hosts with an
+/// internal address so users can't make the server reach into its own
+/// network.
+pub fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if let Some(addr) = addrs.iter().find(|addr| is_internal(addr.ip())) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} resolves to the internal address {}", netloc, addr.ip()),
+        ));
+    }
+    Ok(addrs)
+}
+
+/// An agent for fetching URLs users name. Every connection goes through
+/// [`resolve_public`], redirects included, and is checked as it's made, so
+/// a host can't switch to an internal address after a first lookup.
+pub fn public_agent() -> ureq::AgentBuilder {
+    ureq::AgentBuilder::new().resolver(resolve_public)
+}
+
+/// Whether the request failed because [`resolve_public`] refused its host.
+pub fn is_refused(error: &ureq::Error) -> bool {
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        if error
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+        {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
@@ -0,0 +1,128 @@
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Markdown extensions notes are rendered with.
+pub const MARKDOWN_OPTIONS: Options = Options::ENABLE_TABLES.union(Options::ENABLE_STRIKETHROUGH);
+
+/// A section heading of a note.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    /// 1 for `#`, up to 6
+    pub level: u8,
+    pub text: String,
+    /// Anchor derived from the text alone, so it survives edits elsewhere
+    /// in the note: `#setup-notes`, or `#setup-notes-1` for a second one
+    pub slug: String,
+}
+
+/// The headings of Markdown `content`, in order.
+pub fn headings(content: &str) -> Vec<Heading> {
+    let events: Vec<Event> = Parser::new_ext(content, MARKDOWN_OPTIONS).collect();
+    let mut slugs = Slugs::default();
+    sections(&events)
+        .into_iter()
+        .map(|(start, end)| {
+            let text = heading_text(&events[start + 1..end]);
+            let level = match &events[start] {
+                Event::Start(Tag::Heading { level, .. }) => *level as u8,
+                _ => unreachable!("sections start at headings"),
+            };
+            let slug = slugs.next(&text);
+            Heading { level, text, slug }
+        })
+        .collect()
+}
+
+/// `events` with each heading given its slug as `id`, matching
+/// [`headings`]. Headings that set their own id (`# Title {#id}`) keep it.
+pub fn with_anchors(mut events: Vec<Event>) -> Vec<Event> {
+    let mut slugs = Slugs::default();
+    for (start, end) in sections(&events) {
+        let slug = slugs.next(&heading_text(&events[start + 1..end]));
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[start] {
+            id.get_or_insert(CowStr::from(slug));
+        }
+    }
+    events
+}
+
+/// Where in `content` the heading with `slug` starts, e.g. to show a note
+/// from that section on.
+pub fn section_start(content: &str, slug: &str) -> Option<usize> {
+    let (events, offsets): (Vec<Event>, Vec<usize>) = Parser::new_ext(content, MARKDOWN_OPTIONS)
+        .into_offset_iter()
+        .map(|(event, range)| (event, range.start))
+        .unzip();
+    let mut slugs = Slugs::default();
+    sections(&events)
+        .into_iter()
+        .find(|(start, end)| slugs.next(&heading_text(&events[start + 1..*end])) == slug)
+        .map(|(start, _)| offsets[start])
+}
+
+// Start and end positions of every heading in `events`
+fn sections(events: &[Event]) -> Vec<(usize, usize)> {
+    let mut sections = Vec::new();
+    let mut start = None;
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Heading { .. }) => start = Some(i),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(start) = start.take() {
+                    sections.push((start, i));
+                }
+            }
+            _ => {}
+        }
+    }
+    sections
+}
+
+fn heading_text(events: &[Event]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Hands out slugs, numbering repeats the way GitHub does
+#[derive(Default)]
+struct Slugs {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugs {
+    fn next(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let count = self.seen.entry(base.clone()).or_insert(0);
+        let slug = match *count {
+            0 => base,
+            n => format!("{}-{}", base, n),
+        };
+        *count += 1;
+        slug
+    }
+}
+
+fn slugify(text: &str) -> String {
+    let slug: String = text
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect();
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug
+    }
+}
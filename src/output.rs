@@ -0,0 +1,572 @@
+use crate::bench::BenchReport;
+use crate::models::Note;
+use crate::preferences::Preferences;
+use crate::replace::ReplacePreview;
+use crate::search::SearchHit;
+use crate::suggest::RelatedNote;
+use crate::timeline::Anniversary;
+use chrono::{DateTime, Utc};
+use dialoguer::console::Term;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::io;
+use std::sync::OnceLock;
+
+/// How the scripting commands (`list`, `view`, `search`) print their results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Yaml,
+    #[default]
+    Table,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "table" => Ok(OutputFormat::Table),
+            other => Err(format!(
+                "Unknown output format: {} (expected json, yaml or table)",
+                other
+            )),
+        }
+    }
+}
+
+/// How `quicklist` prints notes for launchers (Alfred, rofi, dmenu, ...).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuicklistFormat {
+    /// `id<TAB>title<TAB>tags`, one note per line
+    #[default]
+    Tsv,
+    /// Alfred Script Filter JSON
+    Alfred,
+}
+
+impl std::str::FromStr for QuicklistFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tsv" | "text" => Ok(QuicklistFormat::Tsv),
+            "alfred" => Ok(QuicklistFormat::Alfred),
+            other => Err(format!(
+                "Unknown quicklist format: {} (expected tsv or alfred)",
+                other
+            )),
+        }
+    }
+}
+
+// How timestamps are shown; the defaults until the CLI reads the user's
+static DISPLAY: OnceLock<Preferences> = OnceLock::new();
+
+/// Shows times the way `preferences` say from now on. Only the first call counts.
+pub fn use_preferences(preferences: Preferences) {
+    let _ = DISPLAY.set(preferences);
+}
+
+// Timestamps are stored in UTC and only shown in the preferred zone
+pub fn local_time(time: &DateTime<Utc>) -> String {
+    DISPLAY.get_or_init(Preferences::default).format_time(time)
+}
+
+/// Serializes `value` as JSON or YAML. Tables are built by the callers.
+fn render_data<T: Serialize>(value: &T, format: OutputFormat) -> io::Result<String> {
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value).map_err(io::Error::other),
+        OutputFormat::Yaml => serde_yaml::to_string(value).map_err(io::Error::other),
+        OutputFormat::Table => unreachable!("tables are rendered by the caller"),
+    }
+}
+
+const MAX_CELL_CHARS: usize = 40;
+// Columns aren't narrowed below this (or their header) to fit the terminal
+const MIN_CELL_CHARS: usize = 6;
+
+fn clip_cell(text: &str, max: usize) -> String {
+    let text = text.replace(['\n', '\t'], " ");
+    if text.chars().count() > max {
+        let clipped: String = text.chars().take(max.saturating_sub(1)).collect();
+        format!("{}…", clipped)
+    } else {
+        text
+    }
+}
+
+// Columns of the terminal, when that's where the output goes
+fn terminal_width() -> Option<usize> {
+    Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| usize::from(columns))
+}
+
+// Narrows the widest columns until a line fits in `max` characters
+fn fit_widths(widths: &mut [usize], headers: &[&str], max: usize) {
+    let gaps = 2 * widths.len().saturating_sub(1);
+    while widths.iter().sum::<usize>() + gaps > max {
+        let widest = widths
+            .iter()
+            .enumerate()
+            .filter(|(i, width)| **width > MIN_CELL_CHARS.max(headers[*i].chars().count()))
+            .max_by_key(|(_, width)| **width)
+            .map(|(i, _)| i);
+        match widest {
+            Some(i) => widths[i] -= 1,
+            None => break,
+        }
+    }
+}
+
+/// Plain aligned columns, one line per row, no colors so it pipes cleanly.
+/// On a terminal the widest columns are cut short to fit its width.
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| clip_cell(cell, MAX_CELL_CHARS))
+                .collect()
+        })
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    if let Some(max) = terminal_width() {
+        fit_widths(&mut widths, headers, max);
+    }
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| clip_cell(cell, *width))
+                .collect()
+        })
+        .collect();
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let mut out = vec![line(headers.to_vec())];
+    let separators: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+    out.push(line(separators.iter().map(String::as_str).collect()));
+    for row in &rows {
+        out.push(line(row.iter().map(String::as_str).collect()));
+    }
+    out.join("\n")
+}
+
+/// A column of the note table `list` prints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Title,
+    Tags,
+    Updated,
+    /// Words in the content
+    Words,
+}
+
+/// The columns shown unless others are asked for.
+pub const DEFAULT_COLUMNS: &[Column] = &[Column::Id, Column::Title, Column::Tags, Column::Updated];
+
+impl std::str::FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "id" => Ok(Column::Id),
+            "title" => Ok(Column::Title),
+            "tags" => Ok(Column::Tags),
+            "updated" | "updated_at" => Ok(Column::Updated),
+            "words" => Ok(Column::Words),
+            other => Err(format!(
+                "Unknown column: {} (expected id, title, tags, updated or words)",
+                other
+            )),
+        }
+    }
+}
+
+impl Column {
+    /// Columns named in a comma-separated list, like `id,title,words`.
+    pub fn parse_list(list: &str) -> Result<Vec<Column>, String> {
+        let mut columns = Vec::new();
+        for name in list.split(',').filter(|name| !name.trim().is_empty()) {
+            let column: Column = name.parse()?;
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        if columns.is_empty() {
+            return Err("Name at least one column".to_string());
+        }
+        Ok(columns)
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Title => "TITLE",
+            Column::Tags => "TAGS",
+            Column::Updated => "UPDATED",
+            Column::Words => "WORDS",
+        }
+    }
+
+    fn cell(self, note: &Note) -> String {
+        match self {
+            Column::Id => note.short_id(),
+            Column::Title => note.title.clone(),
+            Column::Tags => note.tags.join(", "),
+            Column::Updated => local_time(&note.updated_at),
+            Column::Words => note.word_count().to_string(),
+        }
+    }
+
+    /// Orders notes by the column, smallest first: alphabetically by text,
+    /// oldest first by time, fewest words first.
+    pub fn compare(self, a: &Note, b: &Note) -> Ordering {
+        match self {
+            Column::Id => a.id.cmp(&b.id),
+            Column::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            Column::Tags => a
+                .tags
+                .join(",")
+                .to_lowercase()
+                .cmp(&b.tags.join(",").to_lowercase()),
+            Column::Updated => a.updated_at.cmp(&b.updated_at),
+            Column::Words => a.word_count().cmp(&b.word_count()),
+        }
+    }
+}
+
+/// Notes as a numbered table of `columns`, or all their data as JSON or YAML.
+pub fn render_notes(
+    notes: &[Note],
+    format: OutputFormat,
+    columns: &[Column],
+) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(&notes, format);
+    }
+    let mut headers = vec!["#"];
+    headers.extend(columns.iter().map(|column| column.header()));
+    let rows: Vec<Vec<String>> = notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| note_row(i + 1, note, columns))
+        .collect();
+    Ok(render_table(&headers, &rows))
+}
+
+fn note_row(number: usize, note: &Note, columns: &[Column]) -> Vec<String> {
+    let mut row = vec![number.to_string()];
+    row.extend(columns.iter().map(|column| column.cell(note)));
+    row
+}
+
+pub fn render_note(note: &Note, format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(note, format);
+    }
+    let rows = vec![
+        vec!["ID".to_string(), note.id.clone()],
+        vec!["Slug".to_string(), note.slug.clone().unwrap_or_default()],
+        vec!["Title".to_string(), note.title.clone()],
+        vec!["Tags".to_string(), note.tags.join(", ")],
+        vec!["Created".to_string(), local_time(&note.created_at)],
+        vec!["Updated".to_string(), local_time(&note.updated_at)],
+        vec!["Views".to_string(), note.view_count.to_string()],
+        vec![
+            "Last viewed".to_string(),
+            note.last_viewed_at
+                .as_ref()
+                .map(local_time)
+                .unwrap_or_default(),
+        ],
+    ];
+    Ok(format!(
+        "{}\n\n{}",
+        render_table(&["FIELD", "VALUE"], &rows),
+        note.content
+    ))
+}
+
+/// A "Related" section to follow a note's table view; empty when no note
+/// is alike enough.
+pub fn render_related(related: &[RelatedNote]) -> String {
+    if related.is_empty() {
+        return String::new();
+    }
+    let rows: Vec<Vec<String>> = related
+        .iter()
+        .map(|note| {
+            vec![
+                note.id.chars().filter(|c| *c != '-').take(8).collect(),
+                note.title.clone(),
+                format!("{:.0}%", note.score * 100.0),
+                note.shared_tags.join(", "),
+            ]
+        })
+        .collect();
+    format!(
+        "\n\nRelated\n{}",
+        render_table(&["ID", "TITLE", "ALIKE", "SHARED TAGS"], &rows)
+    )
+}
+
+pub fn render_preferences(preferences: &Preferences, format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(preferences, format);
+    }
+    let fields = serde_json::to_value(preferences).map_err(io::Error::other)?;
+    let text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for (key, value) in fields.as_object().into_iter().flatten() {
+        match value.as_object() {
+            // e.g. `keymap.save`, the way `preferences set` names it
+            Some(section) => rows.extend(
+                section
+                    .iter()
+                    .map(|(name, value)| vec![format!("{}.{}", key, name), text(value)]),
+            ),
+            None => rows.push(vec![key.clone(), text(value)]),
+        }
+    }
+    Ok(render_table(&["PREFERENCE", "VALUE"], &rows))
+}
+
+// What `user list` shows of an account: not its token hash
+#[cfg(feature = "web")]
+#[derive(Serialize)]
+struct UserRow<'a> {
+    id: &'a str,
+    username: &'a str,
+    role: crate::users::Role,
+    created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disabled_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(feature = "web")]
+pub fn render_users(users: &[crate::users::User], format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        let rows: Vec<UserRow> = users
+            .iter()
+            .map(|user| UserRow {
+                id: &user.id,
+                username: &user.username,
+                role: user.role,
+                created_at: user.created_at,
+                disabled_at: user.disabled_at,
+            })
+            .collect();
+        return render_data(&rows, format);
+    }
+    let rows: Vec<Vec<String>> = users
+        .iter()
+        .map(|user| {
+            let status = match &user.disabled_at {
+                Some(at) => format!("disabled {}", local_time(at)),
+                None => "enabled".to_string(),
+            };
+            vec![
+                user.id.clone(),
+                user.username.clone(),
+                format!("{:?}", user.role).to_lowercase(),
+                local_time(&user.created_at),
+                status,
+            ]
+        })
+        .collect();
+    Ok(render_table(
+        &["ID", "USERNAME", "ROLE", "CREATED", "STATUS"],
+        &rows,
+    ))
+}
+
+pub fn render_hits(hits: &[SearchHit], format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(&hits, format);
+    }
+    let rows: Vec<Vec<String>> = hits
+        .iter()
+        .enumerate()
+        .map(|(i, hit)| {
+            let mut row = note_row(i + 1, &hit.note, DEFAULT_COLUMNS);
+            row.push(hit.score.to_string());
+            row.push(hit.scope.name().to_string());
+            row
+        })
+        .collect();
+    Ok(render_table(
+        &["#", "ID", "TITLE", "TAGS", "UPDATED", "SCORE", "FOUND IN"],
+        &rows,
+    ))
+}
+
+/// Latencies of each benchmarked operation.
+pub fn render_bench(report: &BenchReport, format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(report, format);
+    }
+    let rows: Vec<Vec<String>> = report
+        .timings
+        .iter()
+        .map(|timing| {
+            vec![
+                timing.operation.to_string(),
+                timing.runs.to_string(),
+                format!("{:.3}", timing.mean_ms),
+                format!("{:.3}", timing.p50_ms),
+                format!("{:.3}", timing.p95_ms),
+                format!("{:.3}", timing.max_ms),
+            ]
+        })
+        .collect();
+    Ok(format!(
+        "{} notes (seed {}) in {} storage, {} KiB\n{}",
+        report.notes,
+        report.seed,
+        report.storage,
+        report.store_bytes / 1024,
+        render_table(
+            &["OPERATION", "RUNS", "MEAN MS", "P50 MS", "P95 MS", "MAX MS"],
+            &rows
+        )
+    ))
+}
+
+/// Notes from this day in earlier years, with how long ago.
+pub fn render_anniversaries(found: &[Anniversary], format: OutputFormat) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(&found, format);
+    }
+    let rows: Vec<Vec<String>> = found
+        .iter()
+        .enumerate()
+        .map(|(i, found)| {
+            let mut row = note_row(i + 1, &found.note, DEFAULT_COLUMNS);
+            row.insert(1, found.years_ago.to_string());
+            row
+        })
+        .collect();
+    Ok(render_table(
+        &["#", "YEARS AGO", "ID", "TITLE", "TAGS", "UPDATED"],
+        &rows,
+    ))
+}
+
+/// Each note a replace changes, with its changed lines diff-style.
+pub fn render_replace_preview(
+    preview: &ReplacePreview,
+    format: OutputFormat,
+) -> io::Result<String> {
+    if format != OutputFormat::Table {
+        return render_data(preview, format);
+    }
+    let mut out = Vec::new();
+    for note in &preview.notes {
+        out.push(format!(
+            "{} ({}, {} replacements)",
+            note.title,
+            note.id
+                .chars()
+                .filter(|c| *c != '-')
+                .take(8)
+                .collect::<String>(),
+            note.replacements
+        ));
+        for line in &note.lines {
+            out.push(format!("  {}{:>5} | {}", line.op, line.line, line.text));
+        }
+        if note.more_lines > 0 {
+            out.push(format!("  ... {} more changed lines", note.more_lines));
+        }
+    }
+    out.push(format!(
+        "{} replacements in {} notes",
+        preview.replacements,
+        preview.notes.len()
+    ));
+    Ok(out.join("\n"))
+}
+
+// Tabs and line breaks would split a launcher's line or column
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Serialize)]
+struct AlfredItems<'a> {
+    items: Vec<AlfredItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct AlfredItem<'a> {
+    uid: &'a str,
+    title: &'a str,
+    subtitle: String,
+    /// Passed on to the next action, e.g. `notes-app open {query}`
+    arg: &'a str,
+    autocomplete: &'a str,
+    /// What Alfred filters on when it does the filtering itself
+    #[serde(rename = "match")]
+    match_text: String,
+}
+
+pub fn render_quicklist(notes: &[Note], format: QuicklistFormat) -> io::Result<String> {
+    match format {
+        QuicklistFormat::Tsv => Ok(notes
+            .iter()
+            .map(|note| {
+                format!(
+                    "{}\t{}\t{}",
+                    note.id,
+                    one_line(&note.title),
+                    one_line(&note.tags.join(","))
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        QuicklistFormat::Alfred => {
+            let items = notes
+                .iter()
+                .map(|note| AlfredItem {
+                    uid: &note.id,
+                    title: &note.title,
+                    subtitle: note
+                        .tags
+                        .iter()
+                        .map(|tag| format!("#{}", tag))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    arg: &note.id,
+                    autocomplete: &note.title,
+                    match_text: format!("{} {}", note.title, note.tags.join(" "))
+                        .trim_end()
+                        .to_string(),
+                })
+                .collect();
+            serde_json::to_string(&AlfredItems { items }).map_err(io::Error::other)
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::config::CliConfig;
+use crate::storage::SharedNotesManager;
+use colored::*;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::FuzzySelect;
+use std::io::{self, IsTerminal, Write};
+
+/// Whether the inline fuzzy finder can run: it needs an interactive,
+/// cursor-addressable terminal on both ends.
+fn fuzzy_supported() -> bool {
+    let dumb = std::env::var("TERM").map_or(true, |term| term.is_empty() || term == "dumb");
+    !dumb && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Asks which note to `action` and returns its 1-based list number. Uses the
+/// fuzzy finder (matching title and tags) when enabled and supported, and
+/// otherwise prompts for the number. `None` means nothing was chosen.
+pub fn choose_note(
+    manager: &SharedNotesManager,
+    config: &CliConfig,
+    action: &str,
+) -> Option<usize> {
+    if config.fuzzy_picker && fuzzy_supported() {
+        let items: Vec<String> = manager
+            .lock()
+            .unwrap()
+            .list_notes()
+            .iter()
+            .map(|note| {
+                if note.tags.is_empty() {
+                    note.title.clone()
+                } else {
+                    format!("{}  [{}]", note.title, note.tags.join(", "))
+                }
+            })
+            .collect();
+        if items.is_empty() {
+            println!("{}", "📭 No notes found.".bright_yellow());
+            return None;
+        }
+
+        return FuzzySelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Type to find the note to {} (Esc cancels)", action))
+            .items(&items)
+            .default(0)
+            .interact_opt()
+            .ok()
+            .flatten()
+            .map(|index| index + 1);
+    }
+
+    print!(
+        "{} ",
+        format!("Enter note number to {}:", action).bright_white()
+    );
+    io::stdout().flush().ok()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).ok()?;
+    input.trim().parse().ok()
+}
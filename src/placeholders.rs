@@ -0,0 +1,31 @@
+use crate::models::Note;
+use handlebars::Handlebars;
+use serde_json::json;
+
+/// The note with `{{metadata.project}}`-style Handlebars placeholders in its
+/// content filled in from the note itself: `metadata.*`, `title`, `tags`,
+/// `id`, `created_at`, `updated_at` and `due_at`. Unknown names render
+/// empty. Content that isn't a valid template is left as it is.
+pub fn resolve(note: &Note) -> Note {
+    let mut resolved = note.clone();
+    if !note.content.contains("{{") || note.is_encrypted() {
+        return resolved;
+    }
+
+    let mut handlebars = Handlebars::new();
+    // The output is Markdown; HTML is escaped (and sanitized) when it's rendered
+    handlebars.register_escape_fn(handlebars::no_escape);
+    let data = json!({
+        "id": note.id,
+        "title": note.title,
+        "tags": note.tags,
+        "created_at": note.created_at,
+        "updated_at": note.updated_at,
+        "due_at": note.due_at,
+        "metadata": note.metadata,
+    });
+    if let Ok(content) = handlebars.render_template(&note.content, &data) {
+        resolved.content = content;
+    }
+    resolved
+}
@@ -0,0 +1,216 @@
+use crate::backup::Backups;
+use crate::config::PolicyRule;
+use crate::events::EventKind;
+use crate::models::Note;
+use crate::reload::LiveConfig;
+use crate::storage::{NotesManager, SharedNotesManager};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::io;
+use std::thread;
+
+/// A change the retention rules make (or would make, in a dry run).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PolicyAction {
+    Purge {
+        note_id: String,
+        title: String,
+    },
+    Archive {
+        note_id: String,
+        title: String,
+    },
+    TrimRevisions {
+        note_id: String,
+        title: String,
+        removed: usize,
+    },
+    DeleteBackup {
+        target: String,
+        name: String,
+    },
+}
+
+fn older_than(time: Option<DateTime<Utc>>, days: i64, now: DateTime<Utc>) -> bool {
+    time.is_some_and(|time| time <= now - Duration::days(days))
+}
+
+/// Works out what the rules would do to the notes, without changing anything.
+pub fn plan(notes: &[Note], rules: &[PolicyRule], now: DateTime<Utc>) -> Vec<PolicyAction> {
+    let mut actions = Vec::new();
+
+    for note in notes {
+        let note_id = note.id.clone();
+        let title = note.title.clone();
+
+        // A purged note needs no other work
+        let purge = rules.iter().any(|rule| match rule {
+            PolicyRule::PurgeTrash { after_days } => older_than(note.trashed_at, *after_days, now),
+            _ => false,
+        });
+        if purge {
+            actions.push(PolicyAction::Purge { note_id, title });
+            continue;
+        }
+
+        for rule in rules {
+            match rule {
+                PolicyRule::ArchiveUntouched { after_days }
+                    if !note.is_trashed()
+                        && !note.is_archived()
+                        && older_than(Some(note.updated_at), *after_days, now) =>
+                {
+                    actions.push(PolicyAction::Archive {
+                        note_id: note_id.clone(),
+                        title: title.clone(),
+                    });
+                }
+                PolicyRule::CapRevisions { max } if note.revisions.len() > *max => {
+                    actions.push(PolicyAction::TrimRevisions {
+                        note_id: note_id.clone(),
+                        title: title.clone(),
+                        removed: note.revisions.len() - max,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+
+    actions
+}
+
+/// The backup snapshots the `prune_backups` rules would delete. Lists the
+/// backup targets, so this may need the network.
+pub fn plan_backups(backups: &Backups, rules: &[PolicyRule]) -> io::Result<Vec<PolicyAction>> {
+    let Some(keep) = rules
+        .iter()
+        .filter_map(|rule| match rule {
+            PolicyRule::PruneBackups { keep } => Some(*keep),
+            _ => None,
+        })
+        .min()
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut actions = Vec::new();
+    for store in backups.stores()? {
+        let snapshots = store.list(backups.vault())?;
+        let excess = snapshots.len().saturating_sub(keep);
+        actions.extend(snapshots.into_iter().take(excess).map(|snapshot| {
+            PolicyAction::DeleteBackup {
+                target: snapshot.target,
+                name: snapshot.name,
+            }
+        }));
+    }
+    Ok(actions)
+}
+
+/// Deletes the snapshots [`plan_backups`] picks and returns what was deleted.
+pub fn apply_to_backups(backups: &Backups, rules: &[PolicyRule]) -> io::Result<Vec<PolicyAction>> {
+    let actions = plan_backups(backups, rules)?;
+    let stores = backups.stores()?;
+    for action in &actions {
+        if let PolicyAction::DeleteBackup { target, name } = action {
+            if let Some(store) = stores.iter().find(|store| store.target() == *target) {
+                store.delete(name)?;
+            }
+        }
+    }
+    Ok(actions)
+}
+
+/// Runs the rules against the store and returns what was done.
+pub fn apply(
+    mgr: &mut NotesManager,
+    rules: &[PolicyRule],
+    now: DateTime<Utc>,
+) -> io::Result<Vec<PolicyAction>> {
+    let actions = plan(&mgr.notes, rules, now);
+    let mut changed = false;
+    let mut archived = Vec::new();
+
+    for action in &actions {
+        match action {
+            PolicyAction::Archive { note_id, .. } => {
+                if let Some(note) = mgr.notes.iter_mut().find(|note| note.id == *note_id) {
+                    note.archived_at = Some(now);
+                    archived.push(note.clone());
+                    changed = true;
+                }
+            }
+            PolicyAction::TrimRevisions {
+                note_id, removed, ..
+            } => {
+                if let Some(note) = mgr.notes.iter_mut().find(|note| note.id == *note_id) {
+                    note.revisions.drain(..*removed);
+                    changed = true;
+                }
+            }
+            PolicyAction::Purge { .. } | PolicyAction::DeleteBackup { .. } => {}
+        }
+    }
+    if changed {
+        mgr.save_notes()?;
+    }
+    for note in &archived {
+        mgr.emit(EventKind::Archived, note);
+    }
+
+    mgr.batch(|mgr| {
+        for action in &actions {
+            if let PolicyAction::Purge { note_id, .. } = action {
+                mgr.delete_note(note_id)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(actions)
+}
+
+/// Applies the configured rules at startup and then every `interval_minutes`.
+/// Both are looked up again each round, so edits to the config file apply
+/// from the next one.
+pub fn spawn_policy_task(manager: SharedNotesManager, config: LiveConfig, backups: Backups) {
+    thread::spawn(move || loop {
+        let policy = match config.for_vault(backups.vault()) {
+            Ok(settings) => settings.policy,
+            Err(e) => {
+                println!("⚠ Retention policy stopped: {}", e);
+                return;
+            }
+        };
+        if !policy.rules.is_empty() {
+            run_round(&manager, &policy.rules, &backups);
+        }
+        thread::sleep(std::time::Duration::from_secs(
+            policy.interval_minutes.max(1) * 60,
+        ));
+    });
+}
+
+fn run_round(manager: &SharedNotesManager, rules: &[PolicyRule], backups: &Backups) {
+    let result = {
+        let mut mgr = manager.lock().unwrap();
+        let now = mgr.now();
+        apply(&mut mgr, rules, now)
+    };
+    match result {
+        Ok(actions) if !actions.is_empty() => {
+            println!("🧹 Retention policy applied {} change(s)", actions.len())
+        }
+        Ok(_) => {}
+        Err(e) => println!("⚠ Retention policy failed: {}", e),
+    }
+    match apply_to_backups(backups, rules) {
+        Ok(actions) if !actions.is_empty() => {
+            println!("🧹 Retention policy deleted {} backup(s)", actions.len())
+        }
+        Ok(_) => {}
+        Err(e) => println!("⚠ Pruning backups failed: {}", e),
+    }
+}
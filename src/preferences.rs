@@ -0,0 +1,296 @@
+use crate::keymap::Keymap;
+use crate::models::Note;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_PREFERENCES_PATH: &str = "data/preferences.json";
+
+// Bounds for `page_size`
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Order notes are listed in when the request doesn't ask for one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteSort {
+    /// The order they were added in
+    #[default]
+    Added,
+    /// Most recently edited first
+    Updated,
+    /// Newest first
+    Created,
+    /// Alphabetical
+    Title,
+    /// Most recently viewed first, never viewed last
+    #[serde(rename = "last_viewed")]
+    LastViewed,
+}
+
+impl std::str::FromStr for NoteSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "added" => Ok(NoteSort::Added),
+            "updated" | "updated_at" => Ok(NoteSort::Updated),
+            "created" | "created_at" => Ok(NoteSort::Created),
+            "title" => Ok(NoteSort::Title),
+            "last_viewed" | "viewed" => Ok(NoteSort::LastViewed),
+            other => Err(format!(
+                "Unknown sort order: {} (expected added, updated, created, title or last_viewed)",
+                other
+            )),
+        }
+    }
+}
+
+impl NoteSort {
+    pub fn compare(self, a: &Note, b: &Note) -> Ordering {
+        match self {
+            NoteSort::Added => Ordering::Equal,
+            NoteSort::Updated => b.updated_at.cmp(&a.updated_at),
+            NoteSort::Created => b.created_at.cmp(&a.created_at),
+            NoteSort::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            NoteSort::LastViewed => b.last_viewed_at.cmp(&a.last_viewed_at),
+        }
+    }
+
+    /// Sorts `notes` in place; stable, so equal notes keep their order.
+    pub fn apply(self, notes: &mut [Note]) {
+        notes.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Follows the browser or OS setting
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "system" => Ok(Theme::System),
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            other => Err(format!(
+                "Unknown theme: {} (expected system, light or dark)",
+                other
+            )),
+        }
+    }
+}
+
+/// Zone times are shown in: the machine's own, or a fixed UTC offset.
+/// Written `local`, `UTC` or `+02:00`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Timezone {
+    #[default]
+    Local,
+    Offset(FixedOffset),
+}
+
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "" | "local" => return Ok(Timezone::Local),
+            "utc" | "z" => return Ok(Timezone::Offset(FixedOffset::east_opt(0).unwrap())),
+            _ => {}
+        }
+        let invalid = || format!("Invalid timezone: {} (expected local, UTC or +02:00)", s);
+        let (sign, rest) = match s.as_bytes().first() {
+            Some(b'+') => (1, &s[1..]),
+            Some(b'-') => (-1, &s[1..]),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 14 || minutes >= 60 {
+            return Err(invalid());
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+            .map(Timezone::Offset)
+            .ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timezone::Local => write!(f, "local"),
+            Timezone::Offset(offset) if offset.local_minus_utc() == 0 => write!(f, "UTC"),
+            Timezone::Offset(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl From<Timezone> for String {
+    fn from(timezone: Timezone) -> Self {
+        timezone.to_string()
+    }
+}
+
+/// How someone likes the notes shown, the same in the CLI and the web UI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub sort: NoteSort,
+    /// Notes shown at a time in lists
+    pub page_size: usize,
+    pub theme: Theme,
+    /// `strftime`-style, e.g. `%d %b %Y %H:%M`
+    pub date_format: String,
+    pub timezone: Timezone,
+    /// The web UI's keyboard shortcuts
+    pub keymap: Keymap,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            sort: NoteSort::default(),
+            page_size: 20,
+            theme: Theme::default(),
+            date_format: "%Y-%m-%d %H:%M:%S %Z".to_string(),
+            timezone: Timezone::default(),
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+impl Preferences {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_PAGE_SIZE).contains(&self.page_size) {
+            return Err(format!("page_size must be between 1 and {}", MAX_PAGE_SIZE));
+        }
+        if self.date_format.trim().is_empty()
+            || StrftimeItems::new(&self.date_format).any(|item| item == Item::Error)
+        {
+            return Err(format!("Invalid date_format: {}", self.date_format));
+        }
+        self.keymap.clone().normalize()
+    }
+
+    /// `time` in the preferred zone and format.
+    pub fn format_time(&self, time: &DateTime<Utc>) -> String {
+        match self.timezone {
+            Timezone::Local => time
+                .with_timezone(&Local)
+                .format(&self.date_format)
+                .to_string(),
+            Timezone::Offset(offset) => time
+                .with_timezone(&offset)
+                .format(&self.date_format)
+                .to_string(),
+        }
+    }
+
+    /// Sets one preference from text, as the CLI takes them.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "sort" => self.sort = value.parse()?,
+            "page_size" => {
+                self.page_size = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid page_size: {}", value))?
+            }
+            "theme" => self.theme = value.parse()?,
+            "date_format" => self.date_format = value.to_string(),
+            "timezone" => self.timezone = value.parse()?,
+            other => match other.strip_prefix("keymap.") {
+                Some(action) => self.keymap.set(action, value)?,
+                None => {
+                    return Err(format!(
+                "Unknown preference: {} (expected sort, page_size, theme, date_format, timezone or keymap.<action>)",
+                other
+            ))
+                }
+            },
+        }
+        self.validate()
+    }
+}
+
+/// Preferences of each user, keyed like the search history
+/// ([`crate::history::LOCAL_USER`] for the CLI and single-user web mode).
+pub struct PreferenceStore {
+    by_user: BTreeMap<String, Preferences>,
+    storage_path: String,
+}
+
+pub type SharedPreferenceStore = Arc<Mutex<PreferenceStore>>;
+
+impl PreferenceStore {
+    pub fn new(storage_path: &str) -> io::Result<Self> {
+        let path = Path::new(storage_path);
+        let by_user = if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            serde_json::from_reader(reader)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(PreferenceStore {
+            by_user,
+            storage_path: storage_path.to_string(),
+        })
+    }
+
+    fn save_preferences(&self) -> io::Result<()> {
+        let path = Path::new(&self.storage_path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.by_user)?;
+        Ok(())
+    }
+
+    /// The user's preferences, the defaults if they never set any.
+    pub fn get(&self, user: &str) -> Preferences {
+        self.by_user.get(user).cloned().unwrap_or_default()
+    }
+
+    /// Saves `preferences` for `user`, with their shortcuts written the
+    /// standard way.
+    pub fn set(&mut self, user: &str, mut preferences: Preferences) -> io::Result<()> {
+        preferences
+            .keymap
+            .normalize()
+            .and_then(|_| preferences.validate())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.by_user.insert(user.to_string(), preferences);
+        self.save_preferences()
+    }
+}
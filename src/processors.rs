@@ -0,0 +1,232 @@
+use crate::config::{ProcessorConfig, ProcessorStage, ProcessorsConfig};
+use crate::events::EventKind;
+use crate::models::Note;
+use crate::reload::LiveConfig;
+use crate::storage::SharedNotesManager;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+/// What a processor answers with; every field is optional and an empty
+/// body changes nothing.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Changes {
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// Merged into the note's metadata, e.g. `{"summary": "..."}`
+    pub metadata: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    stage: ProcessorStage,
+    event: &'static str,
+    note: &'a Note,
+}
+
+enum Failure {
+    /// The service answered 422: the note isn't to be saved
+    Refused(String),
+    Failed(String),
+}
+
+// POSTs the note and reads back the changes the service wants
+fn call(
+    processor: &ProcessorConfig,
+    config: &ProcessorsConfig,
+    event: EventKind,
+    note: &Note,
+) -> Result<Changes, Failure> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .build();
+    let mut request = agent.post(&processor.url);
+    if let Some(key) = &processor.api_key {
+        request = request.set("Authorization", &format!("Bearer {}", key));
+    }
+    let result = request.send_json(Request {
+        stage: processor.stage,
+        event: event.name(),
+        note,
+    });
+
+    let body = match result {
+        Ok(response) => response
+            .into_string()
+            .map_err(|e| Failure::Failed(e.to_string()))?,
+        Err(ureq::Error::Status(422, response)) => {
+            let body: Value = response.into_json().unwrap_or_default();
+            let message = ["message", "error", "detail"]
+                .iter()
+                .find_map(|key| body.get(key).and_then(Value::as_str))
+                .unwrap_or("refused the note");
+            return Err(Failure::Refused(message.to_string()));
+        }
+        Err(e) => return Err(Failure::Failed(e.to_string())),
+    };
+    if body.trim().is_empty() {
+        return Ok(Changes::default());
+    }
+    serde_json::from_str(&body).map_err(|e| Failure::Failed(format!("unreadable answer: {}", e)))
+}
+
+/// Runs the pre-save processors on a note about to be created or edited
+/// (`event`), applying their changes in turn. Returns whether its title,
+/// content or tags changed. A 422 refuses the save with `InvalidInput`; a
+/// processor that fails otherwise is skipped unless it's `required`.
+/// Encrypted notes aren't sent anywhere.
+pub fn pre_save(config: &ProcessorsConfig, event: EventKind, note: &mut Note) -> io::Result<bool> {
+    if note.is_encrypted() {
+        return Ok(false);
+    }
+    let mut changed = false;
+    for processor in stage(config, ProcessorStage::PreSave) {
+        match call(processor, config, event, note) {
+            Ok(changes) => changed |= apply(note, changes),
+            Err(Failure::Refused(message)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: {}", processor.name, message),
+                ))
+            }
+            Err(Failure::Failed(e)) if processor.required => {
+                return Err(io::Error::other(format!(
+                    "Processor {} failed: {}",
+                    processor.name, e
+                )))
+            }
+            Err(Failure::Failed(e)) => println!("⚠ Processor {} skipped: {}", processor.name, e),
+        }
+    }
+    Ok(changed)
+}
+
+/// A note about to be created or edited, waiting for the pre-save
+/// processors. `NotesManager::prepare_insert` and `prepare_update` start it
+/// and `insert_processed` and `update_processed` save it; `run` goes in
+/// between and needs no manager, so callers sharing one let go of it while
+/// the services answer.
+#[derive(Debug, Clone)]
+pub struct PendingSave {
+    pub note: Note,
+    pub(crate) event: EventKind,
+    /// When the edited note was last updated, to notice edits made meanwhile
+    pub(crate) base: Option<DateTime<Utc>>,
+    /// Whether scripts or processors changed the title, content or tags
+    pub(crate) changed: bool,
+    config: ProcessorsConfig,
+}
+
+impl PendingSave {
+    pub(crate) fn new(
+        note: Note,
+        event: EventKind,
+        base: Option<DateTime<Utc>>,
+        changed: bool,
+        config: &ProcessorsConfig,
+    ) -> Self {
+        Self {
+            note,
+            event,
+            base,
+            changed,
+            config: config.clone(),
+        }
+    }
+
+    /// Whether `run` has any service to call.
+    pub fn has_processors(&self) -> bool {
+        stage(&self.config, ProcessorStage::PreSave)
+            .next()
+            .is_some()
+    }
+
+    /// Calls the pre-save processors; see [`pre_save`].
+    pub fn run(mut self) -> io::Result<Self> {
+        self.changed |= pre_save(&self.config, self.event, &mut self.note)?;
+        Ok(self)
+    }
+}
+
+fn stage(
+    config: &ProcessorsConfig,
+    stage: ProcessorStage,
+) -> impl Iterator<Item = &ProcessorConfig> {
+    config
+        .pipeline
+        .iter()
+        .filter(move |processor| processor.stage == stage)
+}
+
+fn apply(note: &mut Note, changes: Changes) -> bool {
+    let mut changed = false;
+    if let Some(title) = changes.title.filter(|title| *title != note.title) {
+        note.title = title;
+        changed = true;
+    }
+    if let Some(content) = changes.content.filter(|content| *content != note.content) {
+        note.content = content;
+        changed = true;
+    }
+    if let Some(tags) = changes.tags.filter(|tags| *tags != note.tags) {
+        note.tags = tags;
+        changed = true;
+    }
+    note.metadata.extend(changes.metadata);
+    changed
+}
+
+/// Sends created and edited notes of `vault` to the post-save processors,
+/// looked up for every event like webhooks, and adds the metadata they
+/// answer with to the note. One worker calls them in order, so a slow
+/// service delays the annotations but never a save.
+pub fn spawn_processors(manager: &SharedNotesManager, config: LiveConfig, vault: &str) {
+    let vault = vault.to_string();
+    let manager = manager.clone();
+    let mut events = manager.lock().unwrap().subscribe();
+    thread::spawn(move || loop {
+        let event = match events.blocking_recv() {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                println!("⚠ Processors fell behind and skipped {} event(s)", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        let kind = event.event;
+        let Some(note) = event.note.filter(|note| {
+            matches!(kind, EventKind::Created | EventKind::Updated) && !note.is_encrypted()
+        }) else {
+            continue;
+        };
+        let Ok(settings) = config.for_vault(&vault) else {
+            continue;
+        };
+        let processors = settings.processors;
+        let mut metadata = BTreeMap::new();
+        for processor in stage(&processors, ProcessorStage::PostSave) {
+            match call(processor, &processors, kind, &note) {
+                Ok(changes) => metadata.extend(changes.metadata),
+                Err(Failure::Refused(e) | Failure::Failed(e)) => {
+                    println!(
+                        "✗ Processor {} failed for {}: {}",
+                        processor.name, note.id, e
+                    )
+                }
+            }
+        }
+        if metadata.is_empty() {
+            continue;
+        }
+        if let Err(e) = manager.lock().unwrap().annotate(&note.id, metadata) {
+            println!("✗ Could not save annotations for {}: {}", note.id, e);
+        }
+    });
+}
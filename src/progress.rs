@@ -0,0 +1,77 @@
+use crate::models::ReadingProgress;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// File next to the notes that holds where each reader left off in them.
+const PROGRESS_FILE: &str = "reading_progress.json";
+
+pub fn path_for(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(PROGRESS_FILE)
+}
+
+/// Where each reader left off in each note. Kept apart from the notes:
+/// every reader has a place of their own, and reading isn't editing.
+pub struct ProgressStore {
+    // Reader, then note id
+    by_reader: BTreeMap<String, BTreeMap<String, ReadingProgress>>,
+    storage_path: PathBuf,
+}
+
+pub type SharedProgressStore = Arc<Mutex<ProgressStore>>;
+
+impl ProgressStore {
+    pub fn new(storage_path: PathBuf) -> io::Result<Self> {
+        let by_reader = if storage_path.exists() {
+            serde_json::from_str(&fs::read_to_string(&storage_path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(ProgressStore {
+            by_reader,
+            storage_path,
+        })
+    }
+
+    pub fn get(&self, reader: &str, note_id: &str) -> Option<&ReadingProgress> {
+        self.by_reader.get(reader)?.get(note_id)
+    }
+
+    /// Remembers where `reader` left off in the note, or forgets it when
+    /// `None`. Progress out of range is `InvalidInput`.
+    pub fn set(
+        &mut self,
+        reader: &str,
+        note_id: &str,
+        progress: Option<ReadingProgress>,
+    ) -> io::Result<()> {
+        match progress {
+            Some(progress) => {
+                progress
+                    .validate()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                self.by_reader
+                    .entry(reader.to_string())
+                    .or_default()
+                    .insert(note_id.to_string(), progress);
+            }
+            None => {
+                if let Some(notes) = self.by_reader.get_mut(reader) {
+                    notes.remove(note_id);
+                    if notes.is_empty() {
+                        self.by_reader.remove(reader);
+                    }
+                }
+            }
+        }
+        fs::write(
+            &self.storage_path,
+            serde_json::to_string_pretty(&self.by_reader)?,
+        )
+    }
+}
@@ -0,0 +1,26 @@
+use image::{ImageFormat, Luma};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+use std::io::{self, Cursor};
+
+/// PNG image of a QR code encoding `data`.
+pub fn qr_png(data: &str) -> io::Result<Vec<u8>> {
+    let code = QrCode::new(data.as_bytes()).map_err(io::Error::other)?;
+    let image = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(png)
+}
+
+/// QR code drawn with half-block characters, two modules per terminal row.
+pub fn qr_terminal(data: &str) -> io::Result<String> {
+    let code = QrCode::new(data.as_bytes()).map_err(io::Error::other)?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
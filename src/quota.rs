@@ -0,0 +1,185 @@
+use crate::config::{QuotaConfig, QuotaLimits};
+use crate::models::Note;
+use serde::Serialize;
+use std::io;
+
+/// Share of a limit past which usage is reported as a warning.
+pub const WARN_PERCENT: f64 = 80.0;
+
+/// What a set of notes takes up, or how much a change adds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub notes: u64,
+    /// Titles and content
+    pub bytes: u64,
+    pub attachment_bytes: u64,
+}
+
+impl Usage {
+    pub fn of_note(note: &Note) -> Self {
+        Self {
+            notes: 1,
+            bytes: (note.title.len() + note.content.len()) as u64,
+            attachment_bytes: note.attachments.iter().map(|a| a.size).sum(),
+        }
+    }
+
+    pub fn of_notes<'a>(notes: impl IntoIterator<Item = &'a Note>) -> Self {
+        notes
+            .into_iter()
+            .map(Self::of_note)
+            .fold(Self::default(), |total, usage| total.plus(usage))
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Self {
+            notes: self.notes + other.notes,
+            bytes: self.bytes + other.bytes,
+            attachment_bytes: self.attachment_bytes + other.attachment_bytes,
+        }
+    }
+
+    /// What `after` takes up beyond `self`; shrinking counts as nothing.
+    pub fn growth_to(self, after: Self) -> Self {
+        Self {
+            notes: after.notes.saturating_sub(self.notes),
+            bytes: after.bytes.saturating_sub(self.bytes),
+            attachment_bytes: after.attachment_bytes.saturating_sub(self.attachment_bytes),
+        }
+    }
+
+    fn measures(self, limits: &QuotaLimits) -> [(&'static str, u64, Option<u64>); 3] {
+        [
+            ("notes", self.notes, limits.max_notes),
+            ("bytes", self.bytes, limits.max_bytes),
+            (
+                "attachment_bytes",
+                self.attachment_bytes,
+                limits.max_attachment_bytes,
+            ),
+        ]
+    }
+}
+
+/// Fails with `StorageFull` if adding `growth` to `used` goes past one of
+/// `limits`. `scope` says whose quota it is, "vault" or "user".
+pub fn check(limits: &QuotaLimits, used: Usage, growth: Usage, scope: &str) -> io::Result<()> {
+    let grown = used.plus(growth);
+    let measures = used
+        .measures(limits)
+        .into_iter()
+        .zip(grown.measures(limits));
+    for ((name, before, limit), (_, after, _)) in measures {
+        let Some(limit) = limit else {
+            continue;
+        };
+        if after > before && after > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "The {} quota for {} would be exceeded: {} of {} used, this needs {} more \
+                     (emptying the trash frees space)",
+                    scope,
+                    label(name),
+                    before,
+                    limit,
+                    after - before
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// One measure against its limit.
+#[derive(Debug, Clone, Serialize)]
+pub struct Meter {
+    pub used: u64,
+    pub limit: Option<u64>,
+    /// Of the limit, when there is one
+    pub percent: Option<f64>,
+}
+
+/// Usage of a vault or one user's notes, against the quota.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageReport {
+    /// `vault`, or `user` for the caller's own notes
+    pub scope: String,
+    pub notes: Meter,
+    pub bytes: Meter,
+    pub attachment_bytes: Meter,
+    /// Part of the usage above that's in the trash
+    pub trash: Usage,
+    /// Measures at or past [`WARN_PERCENT`] of their limit
+    pub warnings: Vec<String>,
+}
+
+impl StorageReport {
+    /// `notes` measured against `limits`.
+    pub fn new(scope: &str, limits: &QuotaLimits, notes: &[&Note]) -> Self {
+        let used = Usage::of_notes(notes.iter().copied());
+        let trash = Usage::of_notes(notes.iter().copied().filter(|note| note.is_trashed()));
+        let mut warnings = Vec::new();
+        let [notes, bytes, attachment_bytes] = used.measures(limits).map(|(name, used, limit)| {
+            let percent = limit.map(|limit| match limit {
+                0 => 100.0,
+                _ => used as f64 * 100.0 / limit as f64,
+            });
+            if let (Some(limit), Some(percent)) = (limit, percent) {
+                if percent >= WARN_PERCENT {
+                    warnings.push(format!(
+                        "{} at {:.0}% of the {} quota ({} of {})",
+                        capitalize(label(name)),
+                        percent,
+                        scope,
+                        used,
+                        limit
+                    ));
+                }
+            }
+            Meter {
+                used,
+                limit,
+                percent,
+            }
+        });
+        Self {
+            scope: scope.to_string(),
+            notes,
+            bytes,
+            attachment_bytes,
+            trash,
+            warnings,
+        }
+    }
+}
+
+/// The vault's report, and the report on `owner`'s notes when there's an owner.
+pub fn reports(quota: &QuotaConfig, notes: &[Note], owner: Option<&str>) -> Vec<StorageReport> {
+    let all: Vec<&Note> = notes.iter().collect();
+    let mut reports = vec![StorageReport::new("vault", &quota.vault, &all)];
+    if let Some(owner) = owner {
+        let owned: Vec<&Note> = notes
+            .iter()
+            .filter(|note| note.owner.as_deref() == Some(owner))
+            .collect();
+        reports.push(StorageReport::new("user", &quota.per_user, &owned));
+    }
+    reports
+}
+
+fn label(name: &str) -> &'static str {
+    match name {
+        "notes" => "notes",
+        "bytes" => "note storage",
+        _ => "attachment storage",
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
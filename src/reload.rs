@@ -0,0 +1,299 @@
+use crate::config::{Config, DEFAULT_VAULT};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Settings only read when the app starts (or a vault opens): changing them
+/// in the file has no effect until a restart. The same paths inside a vault
+/// section count too. Everything else is read as it's used.
+pub const RESTART_REQUIRED: &[&str] = &[
+    "storage",
+    "search",
+    "tags",
+    "server.port",
+    "server.cors",
+    "server.compression",
+    "server.cache",
+    "templates",
+    "backup",
+    "scripts",
+    "links.check_interval_hours",
+];
+
+// Shown instead of keys and passwords
+const REDACTED: &str = "********";
+const SECRETS: &[&str] = &["admin_key", "api_key", "password", "secret_access_key"];
+
+/// What reloading the file changed.
+#[derive(Debug, Clone, Default)]
+pub struct Reload {
+    /// Sections now in effect with new values
+    pub applied: Vec<String>,
+    /// Settings that differ in the file but wait for a restart
+    pub restart_required: Vec<String>,
+}
+
+/// Effective settings next to the file's, with secrets masked.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigReport {
+    pub path: String,
+    pub reloaded_at: Option<DateTime<Utc>>,
+    /// Why the file's latest version couldn't be used
+    pub error: Option<String>,
+    pub restart_required: Vec<String>,
+    pub effective: Value,
+    pub file: Value,
+}
+
+struct State {
+    /// In effect, all vaults
+    base: Config,
+    /// `base` for the app's own vault
+    current: Arc<Config>,
+    /// As last read from the file
+    file: Config,
+    restart_required: Vec<String>,
+    reloaded_at: Option<DateTime<Utc>>,
+    error: Option<String>,
+}
+
+/// The settings in effect, which [`LiveConfig::reload`] replaces with the
+/// config file's as far as they can change while running.
+#[derive(Clone)]
+pub struct LiveConfig {
+    path: Option<PathBuf>,
+    vault: String,
+    state: Arc<RwLock<State>>,
+}
+
+impl LiveConfig {
+    /// `base` as loaded from `path`, used for `vault`.
+    pub fn new(path: &str, base: Config, vault: &str) -> io::Result<Self> {
+        let current = Arc::new(base.for_vault(vault)?);
+        Ok(Self {
+            path: Some(PathBuf::from(path)),
+            vault: vault.to_string(),
+            state: Arc::new(RwLock::new(State {
+                file: base.clone(),
+                base,
+                current,
+                restart_required: Vec::new(),
+                reloaded_at: None,
+                error: None,
+            })),
+        })
+    }
+
+    /// Settings that never change, for apps that manage their own.
+    pub fn fixed(config: Config) -> Self {
+        Self {
+            path: None,
+            vault: DEFAULT_VAULT.to_string(),
+            state: Arc::new(RwLock::new(State {
+                file: config.clone(),
+                current: Arc::new(config.clone()),
+                base: config,
+                restart_required: Vec::new(),
+                reloaded_at: None,
+                error: None,
+            })),
+        }
+    }
+
+    /// The watched file, if there is one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The app's own vault's settings.
+    pub fn current(&self) -> Arc<Config> {
+        self.state.read().unwrap().current.clone()
+    }
+
+    /// The top-level settings, with every vault's section.
+    pub fn base(&self) -> Config {
+        self.state.read().unwrap().base.clone()
+    }
+
+    pub fn for_vault(&self, name: &str) -> io::Result<Config> {
+        self.state.read().unwrap().base.for_vault(name)
+    }
+
+    /// Reads the file again and puts its settings into effect, except the
+    /// ones in [`RESTART_REQUIRED`] and the list of vaults, which keep their
+    /// current values. A file that can't be read changes nothing.
+    pub fn reload(&self) -> io::Result<Reload> {
+        let Some(path) = &self.path else {
+            return Ok(Reload::default());
+        };
+        let loaded = Config::load(&path.to_string_lossy());
+        let mut state = self.state.write().unwrap();
+        let file = match loaded {
+            Ok(file) => file,
+            Err(e) => {
+                state.error = Some(e.to_string());
+                return Err(e);
+            }
+        };
+
+        let effective = serde_json::to_value(&state.base)?;
+        let mut next = serde_json::to_value(&file)?;
+        let restart_required = pin_restart_required(&effective, &mut next);
+        let base: Config = serde_json::from_value(next.clone())?;
+        let current = base.for_vault(&self.vault)?;
+
+        let reload = Reload {
+            applied: changed_sections(&effective, &next),
+            restart_required: restart_required.clone(),
+        };
+        *state = State {
+            base,
+            current: Arc::new(current),
+            file,
+            restart_required,
+            reloaded_at: Some(Utc::now()),
+            error: None,
+        };
+        Ok(reload)
+    }
+
+    pub fn report(&self) -> ConfigReport {
+        let state = self.state.read().unwrap();
+        let redacted = |config: &Config| {
+            let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+            redact(&mut value);
+            value
+        };
+        ConfigReport {
+            path: self
+                .path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            reloaded_at: state.reloaded_at,
+            error: state.error.clone(),
+            restart_required: state.restart_required.clone(),
+            effective: redacted(&state.base),
+            file: redacted(&state.file),
+        }
+    }
+}
+
+// Puts the effective values of restart-only settings back into `next`, the
+// file's settings, and names the ones that differed
+fn pin_restart_required(effective: &Value, next: &mut Value) -> Vec<String> {
+    let mut pinned = Vec::new();
+    for path in RESTART_REQUIRED {
+        let path: Vec<&str> = path.split('.').collect();
+        pin(effective, next, &path, &mut pinned);
+    }
+
+    let vaults = |config: &Value| -> Map<String, Value> {
+        config
+            .get("vaults")
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let (before, after) = (vaults(effective), vaults(next));
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for name in names {
+        if before.contains_key(name) != after.contains_key(name) {
+            pin(effective, next, &["vaults", name], &mut pinned);
+            continue;
+        }
+        for path in RESTART_REQUIRED {
+            let path: Vec<&str> = ["vaults", name]
+                .into_iter()
+                .chain(path.split('.'))
+                .collect();
+            pin(effective, next, &path, &mut pinned);
+        }
+    }
+    pinned
+}
+
+fn pin(effective: &Value, next: &mut Value, path: &[&str], pinned: &mut Vec<String>) {
+    let wanted = lookup(effective, path);
+    if lookup(next, path) == wanted {
+        return;
+    }
+    pinned.push(path.join("."));
+    let (last, parents) = path.split_last().expect("paths aren't empty");
+    let mut parent = next;
+    for key in parents {
+        if !parent.is_object() {
+            *parent = Value::Object(Map::new());
+        }
+        parent = parent
+            .as_object_mut()
+            .expect("just made an object")
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    let Some(parent) = parent.as_object_mut() else {
+        return;
+    };
+    match wanted {
+        Some(value) => {
+            parent.insert(last.to_string(), value.clone());
+        }
+        None => {
+            parent.remove(*last);
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+// Top-level sections (and vaults) whose values differ
+fn changed_sections(before: &Value, after: &Value) -> Vec<String> {
+    let keys = |value: &Value| -> Vec<String> {
+        value
+            .as_object()
+            .map(|object| object.keys().cloned().collect())
+            .unwrap_or_default()
+    };
+    let mut names: Vec<String> = keys(before);
+    names.extend(keys(after));
+    names.sort();
+    names.dedup();
+
+    let mut changed = Vec::new();
+    for name in names {
+        if name == "vaults" {
+            let before = before.get("vaults").cloned().unwrap_or(Value::Null);
+            let after = after.get("vaults").cloned().unwrap_or(Value::Null);
+            for vault in changed_sections(&before, &after) {
+                changed.push(format!("vaults.{}", vault));
+            }
+        } else if before.get(&name) != after.get(&name) {
+            changed.push(name);
+        }
+    }
+    changed
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                let secret = SECRETS.contains(&key.as_str())
+                    && value.as_str().is_some_and(|text| !text.is_empty());
+                if secret {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
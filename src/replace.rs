@@ -0,0 +1,235 @@
+use crate::diff::{self, Line};
+use crate::models::Note;
+use crate::storage::NotesManager;
+use crate::tags;
+use regex::{NoExpand, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+
+// Changed lines listed per note; the rest are only counted
+const MAX_PREVIEW_LINES: usize = 20;
+
+/// A find-and-replace over the content of many notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replace {
+    pub find: String,
+    pub replace: String,
+    /// `find` is a regular expression, and `replace` may use its groups
+    /// (`$1`, `${name}`); otherwise both are plain text
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    /// Only notes matching this search
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Only notes with this tag or one below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+/// A line taken out of (`-`) or put into (`+`) a note, numbered as in the
+/// note before or after the change.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedLine {
+    pub op: char,
+    pub line: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotePreview {
+    pub id: String,
+    pub title: String,
+    pub replacements: usize,
+    pub lines: Vec<ChangedLine>,
+    /// Changed lines left out of `lines`
+    pub more_lines: usize,
+}
+
+/// A note the replace would change but leaves alone, because another
+/// client holds an edit lease on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct LockedNote {
+    pub id: String,
+    pub title: String,
+}
+
+/// What a replace would do. Nothing changes until it's confirmed with
+/// `token`, which is only good while the notes (and their leases) stay as
+/// they are.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacePreview {
+    pub notes: Vec<NotePreview>,
+    pub replacements: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub locked: Vec<LockedNote>,
+    pub token: String,
+}
+
+/// A replace worked out against the notes as they are.
+pub struct Plan {
+    pub preview: ReplacePreview,
+    // New content by note id
+    changes: Vec<(String, String)>,
+}
+
+impl Replace {
+    fn matcher(&self) -> Result<Regex, String> {
+        if self.find.is_empty() {
+            return Err("Give the text to find".to_string());
+        }
+        let pattern = if self.regex {
+            self.find.clone()
+        } else {
+            regex::escape(&self.find)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|e| format!("Invalid regular expression: {}", e))
+    }
+
+    // The text with every match replaced, and how many there were
+    fn apply(&self, matcher: &Regex, text: &str) -> (String, usize) {
+        let count = matcher.find_iter(text).count();
+        if count == 0 {
+            return (text.to_string(), 0);
+        }
+        let replaced = if self.regex {
+            matcher.replace_all(text, self.replace.as_str())
+        } else {
+            matcher.replace_all(text, NoExpand(&self.replace))
+        };
+        (replaced.into_owned(), count)
+    }
+}
+
+/// Works out the replace on the notes outside the trash that `can_edit`
+/// allows and the filters select. Encrypted notes are left alone, and so
+/// are notes `is_locked` says someone else is editing: those are listed
+/// in the preview instead.
+pub fn plan(
+    mgr: &NotesManager,
+    replace: &Replace,
+    can_edit: &dyn Fn(&Note) -> bool,
+    is_locked: &dyn Fn(&Note) -> bool,
+) -> Result<Plan, String> {
+    let matcher = replace.matcher()?;
+    let matching: Option<HashSet<String>> = replace
+        .query
+        .as_deref()
+        .filter(|query| !query.trim().is_empty())
+        .map(|query| {
+            mgr.search_notes(query.trim())
+                .into_iter()
+                .map(|note| note.id)
+                .collect()
+        });
+    let tag = replace
+        .tag
+        .as_deref()
+        .map(|tag| mgr.tag_rules().canonical(tag));
+
+    let mut previews = Vec::new();
+    let mut locked = Vec::new();
+    let mut changes = Vec::new();
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_string(replace).unwrap_or_default());
+    for note in mgr.list_notes() {
+        let selected = can_edit(&note)
+            && !note.is_encrypted()
+            && matching.as_ref().is_none_or(|ids| ids.contains(&note.id))
+            && tag.as_deref().is_none_or(|tag| tags::has_tag(&note, tag));
+        if !selected {
+            continue;
+        }
+        let (content, replacements) = replace.apply(&matcher, &note.content);
+        if content == note.content {
+            continue;
+        }
+        if is_locked(&note) {
+            hasher.update(format!("\n{} locked", note.id));
+            locked.push(LockedNote {
+                id: note.id,
+                title: note.title,
+            });
+            continue;
+        }
+        hasher.update(format!("\n{} {}", note.id, note.updated_at.to_rfc3339()));
+        let lines = changed_lines(&note.content, &content);
+        previews.push(NotePreview {
+            id: note.id.clone(),
+            title: note.title.clone(),
+            replacements,
+            more_lines: lines.len().saturating_sub(MAX_PREVIEW_LINES),
+            lines: lines.into_iter().take(MAX_PREVIEW_LINES).collect(),
+        });
+        changes.push((note.id, content));
+    }
+
+    let token: String = hasher
+        .finalize()
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(Plan {
+        preview: ReplacePreview {
+            replacements: previews.iter().map(|note| note.replacements).sum(),
+            notes: previews,
+            locked,
+            token,
+        },
+        changes,
+    })
+}
+
+impl Plan {
+    /// Saves the new content of every note in one write. Each note keeps
+    /// its old content as a revision. Returns the notes changed.
+    pub fn apply(self, mgr: &mut NotesManager) -> io::Result<Vec<Note>> {
+        mgr.batch(|mgr| {
+            let mut changed = Vec::new();
+            for (id, content) in self.changes {
+                if let Some(note) = mgr.update_note(&id, None, Some(content), None)? {
+                    changed.push(note);
+                }
+            }
+            Ok(changed)
+        })
+    }
+}
+
+fn changed_lines(old: &str, new: &str) -> Vec<ChangedLine> {
+    let (mut old_line, mut new_line) = (0, 0);
+    let mut changed = Vec::new();
+    for line in diff::lines(old, new) {
+        match line {
+            Line::Same(_) => {
+                old_line += 1;
+                new_line += 1;
+            }
+            Line::Removed(text) => {
+                old_line += 1;
+                changed.push(ChangedLine {
+                    op: '-',
+                    line: old_line,
+                    text: text.to_string(),
+                });
+            }
+            Line::Added(text) => {
+                new_line += 1;
+                changed.push(ChangedLine {
+                    op: '+',
+                    line: new_line,
+                    text: text.to_string(),
+                });
+            }
+        }
+    }
+    changed
+}
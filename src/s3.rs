@@ -0,0 +1,273 @@
+use crate::config::S3Config;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::time::Duration;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// An object in the bucket.
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub key: String,
+    pub size: u64,
+}
+
+/// Just enough of the S3 API for backups: put, list and delete objects,
+/// with requests signed by AWS Signature Version 4. Works with AWS and
+/// S3-compatible servers such as MinIO.
+pub struct S3Client {
+    agent: ureq::Agent,
+    scheme: String,
+    /// `host[:port]` of the endpoint
+    host: String,
+    bucket: String,
+    region: String,
+    path_style: bool,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3Client {
+    /// Credentials left empty in the config come from `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`.
+    pub fn new(config: &S3Config) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let endpoint = Url::parse(&config.endpoint)
+            .map_err(|e| invalid(format!("Invalid S3 endpoint '{}': {}", config.endpoint, e)))?;
+        let host = endpoint
+            .host_str()
+            .ok_or_else(|| invalid(format!("S3 endpoint '{}' has no host", config.endpoint)))?;
+        let host = match endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+        let credential = |configured: &str, variable: &str| {
+            Some(configured.to_string())
+                .filter(|value| !value.is_empty())
+                .or_else(|| std::env::var(variable).ok())
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "No S3 credentials: set backup.s3 keys or {}",
+                        variable
+                    ))
+                })
+        };
+        if config.bucket.is_empty() {
+            return Err(invalid("backup.s3.bucket is not set".to_string()));
+        }
+
+        Ok(Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            scheme: endpoint.scheme().to_string(),
+            host,
+            bucket: config.bucket.clone(),
+            region: config.region.clone(),
+            path_style: config.path_style,
+            access_key_id: credential(&config.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential(&config.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+        })
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn put(&self, key: &str, body: &[u8]) -> io::Result<()> {
+        self.send("PUT", key, &[], body).map(|_| ())
+    }
+
+    pub fn delete(&self, key: &str) -> io::Result<()> {
+        self.send("DELETE", key, &[], &[]).map(|_| ())
+    }
+
+    /// Every object whose key starts with `prefix`, following continuation
+    /// tokens past the 1000 objects a single listing returns.
+    pub fn list(&self, prefix: &str) -> io::Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", prefix.to_string()),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+            let xml = self.send("GET", "", &query, &[])?.into_string()?;
+
+            for entry in elements(&xml, "Contents") {
+                let key = elements(entry, "Key").first().map(|key| unescape(key));
+                let size = elements(entry, "Size")
+                    .first()
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or(0);
+                if let Some(key) = key {
+                    objects.push(Object { key, size });
+                }
+            }
+            let truncated = elements(&xml, "IsTruncated").first() == Some(&"true");
+            token = elements(&xml, "NextContinuationToken")
+                .first()
+                .map(|token| unescape(token));
+            if !truncated || token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, String)],
+        body: &[u8],
+    ) -> io::Result<ureq::Response> {
+        let (host, path) = if self.path_style && key.is_empty() {
+            (
+                self.host.clone(),
+                format!("/{}", encode(&self.bucket, false)),
+            )
+        } else if self.path_style {
+            let path = format!("/{}/{}", encode(&self.bucket, false), encode(key, true));
+            (self.host.clone(), path)
+        } else {
+            (
+                format!("{}.{}", self.bucket, self.host),
+                format!("/{}", encode(key, true)),
+            )
+        };
+        let mut pairs: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (encode(name, false), encode(value, false)))
+            .collect();
+        pairs.sort();
+        let query: Vec<String> = pairs
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let query = query.join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(body));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_access_key).into_bytes(),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+        );
+
+        let url = if query.is_empty() {
+            format!("{}://{}{}", self.scheme, host, path)
+        } else {
+            format!("{}://{}{}?{}", self.scheme, host, path, query)
+        };
+        self.agent
+            .request(method, &url)
+            .set("x-amz-date", &amz_date)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("Authorization", &authorization)
+            .send_bytes(body)
+            .map_err(|e| describe(method, key, e))
+    }
+}
+
+// S3 explains refusals in an XML body; its <Code> says more than the status
+fn describe(method: &str, key: &str, error: ureq::Error) -> io::Error {
+    let target = if key.is_empty() { "bucket" } else { key };
+    match error {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let code = elements(&body, "Code")
+                .first()
+                .map(|code| format!(" ({})", code))
+                .unwrap_or_default();
+            let kind = match status {
+                401 | 403 => io::ErrorKind::PermissionDenied,
+                404 => io::ErrorKind::NotFound,
+                _ => io::ErrorKind::Other,
+            };
+            io::Error::new(
+                kind,
+                format!("S3 {} {} failed: HTTP {}{}", method, target, status, code),
+            )
+        }
+        ureq::Error::Transport(transport) => {
+            io::Error::other(format!("S3 {} {} failed: {}", method, target, transport))
+        }
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Percent-encoding as SigV4 wants it: everything but unreserved characters
+// (and `/` between path segments)
+fn encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// The text inside each `<tag>...</tag>` of `xml`, not nested
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let inner = &rest[start + open.len()..];
+        let Some(end) = inner.find(&close) else {
+            break;
+        };
+        found.push(&inner[..end]);
+        rest = &inner[end + close.len()..];
+    }
+    found
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,276 @@
+use crate::config::ScriptsConfig;
+use crate::models::Note;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A point in a note's life where scripts can step in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// `on_create(note)`: before a new note is saved
+    Create,
+    /// `on_update(note, before)`: before an edit is saved
+    Update,
+    /// `on_delete(note)`: before a note goes to the trash or is deleted for good
+    Delete,
+    /// `on_search(query)`: before a search runs
+    Search,
+}
+
+impl Hook {
+    const ALL: [Hook; 4] = [Hook::Create, Hook::Update, Hook::Delete, Hook::Search];
+
+    /// Name of the script function called for it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::Create => "on_create",
+            Hook::Update => "on_update",
+            Hook::Delete => "on_delete",
+            Hook::Search => "on_search",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Hook::Update => 2,
+            _ => 1,
+        }
+    }
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+    hooks: Vec<Hook>,
+}
+
+/// The Rhai scripts in the scripts directory, run in file name order at
+/// each hook. A hook gets the note as a map (`id`, `title`, `content`,
+/// `tags`, `owner`, `notebook`, `created_at`, `updated_at`) and may return
+/// it changed; changes to the title, content and tags are kept. `throw`
+/// refuses the change with the thrown message. `on_search` may return the
+/// query to run instead.
+pub struct Hooks {
+    engine: Engine,
+    scripts: Vec<Script>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Self {
+            engine: Engine::new(),
+            scripts: Vec::new(),
+        }
+    }
+}
+
+impl Hooks {
+    /// Compiles every `.rhai` file in the configured directory; a missing
+    /// directory means no scripts. A script that doesn't compile is
+    /// `InvalidData`.
+    pub fn load(config: &ScriptsConfig) -> io::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.max_operations);
+        engine.on_print(|text| eprintln!("📜 {}", text));
+        engine.on_debug(|text, source, position| {
+            eprintln!("📜 {} {}: {}", source.unwrap_or("script"), position, text)
+        });
+
+        let dir = Path::new(&config.dir);
+        if !dir.is_dir() {
+            return Ok(Self {
+                engine,
+                scripts: Vec::new(),
+            });
+        }
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+
+        let mut scripts = Vec::new();
+        for path in paths {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let source = fs::read_to_string(&path)?;
+            let mut ast = engine.compile(&source).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Script {} doesn't compile: {}", path.display(), e),
+                )
+            })?;
+            ast.set_source(name.as_str());
+            let hooks = Hook::ALL
+                .into_iter()
+                .filter(|hook| {
+                    ast.iter_functions()
+                        .any(|f| f.name == hook.name() && f.params.len() == hook.arity())
+                })
+                .collect();
+            scripts.push(Script { name, ast, hooks });
+        }
+        Ok(Self { engine, scripts })
+    }
+
+    /// Runs `on_create` on a note about to be added. Returns whether a
+    /// script changed it.
+    pub fn on_create(&self, note: &mut Note) -> io::Result<bool> {
+        let mut changed = false;
+        for script in self.scripts_for(Hook::Create) {
+            let result = self.call(script, Hook::Create, (note_map(note),))?;
+            changed |= apply_changes(note, result, script)?;
+        }
+        Ok(changed)
+    }
+
+    /// Runs `on_update` on the edited note, with the note as it was before.
+    /// Returns whether a script changed it.
+    pub fn on_update(&self, note: &mut Note, before: &Note) -> io::Result<bool> {
+        let mut changed = false;
+        for script in self.scripts_for(Hook::Update) {
+            let result = self.call(script, Hook::Update, (note_map(note), note_map(before)))?;
+            changed |= apply_changes(note, result, script)?;
+        }
+        Ok(changed)
+    }
+
+    /// Runs `on_delete` on a note about to be trashed or, with `permanent`,
+    /// deleted for good; the note map says which in its `permanent` field.
+    /// What the script returns is ignored.
+    pub fn on_delete(&self, note: &Note, permanent: bool) -> io::Result<()> {
+        for script in self.scripts_for(Hook::Delete) {
+            let mut map = note_map(note);
+            map.insert("permanent".into(), permanent.into());
+            let _ = self.call(script, Hook::Delete, (map,))?;
+        }
+        Ok(())
+    }
+
+    /// Runs `on_search` on a query, which becomes whatever string it
+    /// returns. A script that fails leaves the query as it was.
+    pub fn on_search(&self, query: &str) -> String {
+        let mut query = query.to_string();
+        for script in self.scripts_for(Hook::Search) {
+            match self.call(script, Hook::Search, (query.clone(),)) {
+                Ok(result) if result.is_string() => {
+                    query = result.into_string().unwrap_or_default();
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("⚠ {}", e),
+            }
+        }
+        query
+    }
+
+    fn scripts_for(&self, hook: Hook) -> impl Iterator<Item = &Script> {
+        self.scripts
+            .iter()
+            .filter(move |script| script.hooks.contains(&hook))
+    }
+
+    // A thrown value refuses the change; any other failure is the script's fault
+    fn call(&self, script: &Script, hook: Hook, args: impl rhai::FuncArgs) -> io::Result<Dynamic> {
+        let result =
+            self.engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &script.ast, hook.name(), args);
+        result.map_err(|e| match thrown(*e) {
+            Ok(value) => io::Error::new(io::ErrorKind::InvalidInput, value.to_string()),
+            Err(e) => io::Error::other(format!(
+                "Script {} failed in {}: {}",
+                script.name,
+                hook.name(),
+                e
+            )),
+        })
+    }
+}
+
+// The value passed to `throw`, looking through the calls it came out of
+fn thrown(error: EvalAltResult) -> Result<Dynamic, EvalAltResult> {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => Ok(value),
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => thrown(*inner),
+        other => Err(other),
+    }
+}
+
+// What scripts see of a note; encrypted content stays hidden
+fn note_map(note: &Note) -> Map {
+    let text = |value: Option<&String>| value.map_or(Dynamic::UNIT, |v| v.clone().into());
+    let content = if note.is_encrypted() {
+        String::new()
+    } else {
+        note.content.clone()
+    };
+    let tags: Array = note.tags.iter().map(|tag| tag.clone().into()).collect();
+    let mut map = Map::new();
+    map.insert("id".into(), note.id.clone().into());
+    map.insert("title".into(), note.title.clone().into());
+    map.insert("content".into(), content.into());
+    map.insert("tags".into(), tags.into());
+    map.insert("owner".into(), text(note.owner.as_ref()));
+    map.insert("notebook".into(), text(note.notebook.as_ref()));
+    map.insert("created_at".into(), note.created_at.to_rfc3339().into());
+    map.insert("updated_at".into(), note.updated_at.to_rfc3339().into());
+    map
+}
+
+// Takes the title, content and tags of a returned note map. Returns
+// whether any of them changed
+fn apply_changes(note: &mut Note, result: Dynamic, script: &Script) -> io::Result<bool> {
+    if result.is_unit() {
+        return Ok(false);
+    }
+    let wrong = |what: &str| {
+        io::Error::other(format!(
+            "Script {} returned {}; hooks return the note or nothing",
+            script.name, what
+        ))
+    };
+    let type_name = result.type_name();
+    let map = result.try_cast::<Map>().ok_or_else(|| wrong(type_name))?;
+    let field = |name: &str| -> io::Result<Option<String>> {
+        match map.get(name) {
+            None => Ok(None),
+            Some(value) => value
+                .clone()
+                .into_string()
+                .map(Some)
+                .map_err(|_| wrong(&format!("a note whose {} isn't a string", name))),
+        }
+    };
+
+    let mut changed = false;
+    if let Some(title) = field("title")?.filter(|title| *title != note.title) {
+        note.title = title;
+        changed = true;
+    }
+    if let Some(content) = field("content")? {
+        if !note.is_encrypted() && content != note.content {
+            note.content = content;
+            changed = true;
+        }
+    }
+    if let Some(tags) = map.get("tags") {
+        let tags = tags
+            .clone()
+            .into_array()
+            .ok()
+            .and_then(|tags| {
+                tags.into_iter()
+                    .map(|tag| tag.into_string().ok())
+                    .collect::<Option<Vec<String>>>()
+            })
+            .ok_or_else(|| wrong("a note whose tags aren't a list of strings"))?;
+        if tags != note.tags {
+            note.tags = tags;
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
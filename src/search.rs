@@ -0,0 +1,461 @@
+use crate::config::SearchConfig;
+use crate::language;
+use crate::models::Note;
+use crate::tags;
+use chrono::{DateTime, Utc};
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Turns note text and queries into a comparable form: NFKC, lowercase,
+/// optional diacritic folding and stemming.
+pub struct Normalizer {
+    fold_diacritics: bool,
+    stemming: bool,
+    /// Stemmer for notes whose language is unknown or has none
+    fallback: Option<Algorithm>,
+}
+
+impl Normalizer {
+    pub fn new(config: &SearchConfig) -> Self {
+        Normalizer {
+            fold_diacritics: config.fold_diacritics,
+            stemming: config.stemming,
+            fallback: stemmer_algorithm(&config.language),
+        }
+    }
+
+    // The stemmer for text in `language` (a note's detected language)
+    fn stemmer(&self, language: Option<&str>) -> Option<Stemmer> {
+        if !self.stemming {
+            return None;
+        }
+        language
+            .and_then(stemmer_algorithm)
+            .or(self.fallback)
+            .map(Stemmer::create)
+    }
+
+    /// Unicode-normalized, lowercased (and optionally accent-free) text.
+    pub fn fold(&self, text: &str) -> String {
+        let composed: String = text.nfkc().collect::<String>().to_lowercase();
+        if self.fold_diacritics {
+            composed
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .nfc()
+                .collect()
+        } else {
+            composed
+        }
+    }
+
+    /// Stems of every word in already-folded text.
+    fn stems(&self, stemmer: &Stemmer, folded: &str) -> HashSet<String> {
+        folded
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| stemmer.stem(word).into_owned())
+            .collect()
+    }
+
+    /// Substring match on folded text, or every query word sharing a stem
+    /// with some word in the text, stemmed as `language`.
+    pub fn matches(&self, text: &str, folded_query: &str, language: Option<&str>) -> bool {
+        let folded_text = self.fold(text);
+        if folded_text.contains(folded_query) {
+            return true;
+        }
+
+        match self.stemmer(language) {
+            Some(stemmer) => {
+                let query_stems = self.stems(&stemmer, folded_query);
+                !query_stems.is_empty()
+                    && query_stems.is_subset(&self.stems(&stemmer, &folded_text))
+            }
+            None => false,
+        }
+    }
+}
+
+impl Normalizer {
+    /// Byte ranges of the words in `text` that match a word of the query,
+    /// either by substring after folding or by sharing a stem.
+    pub fn match_spans(
+        &self,
+        text: &str,
+        folded_query: &str,
+        language: Option<&str>,
+    ) -> Vec<(usize, usize)> {
+        let stemmer = self.stemmer(language);
+        let query_words: Vec<&str> = folded_query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+        let query_stems: Vec<String> = match &stemmer {
+            Some(stemmer) => query_words
+                .iter()
+                .map(|word| stemmer.stem(word).into_owned())
+                .collect(),
+            None => Vec::new(),
+        };
+
+        words(text)
+            .filter(|&(start, end)| {
+                let word = self.fold(&text[start..end]);
+                query_words.iter().any(|q| word.contains(q))
+                    || stemmer.as_ref().is_some_and(|stemmer| {
+                        query_stems
+                            .iter()
+                            .any(|q| stemmer.stem(&word) == q.as_str())
+                    })
+            })
+            .collect()
+    }
+
+    /// Where the query matched in each field of a note, with HTML snippets.
+    pub fn highlights(&self, note: &Note, folded_query: &str) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        let mut add = |field: &'static str, text: &str, context: Option<usize>| {
+            let positions = self.match_spans(text, folded_query, note.language.as_deref());
+            if !positions.is_empty() {
+                highlights.push(Highlight {
+                    field,
+                    snippet: snippet(text, &positions, context, html_mark, html_escape),
+                    positions,
+                });
+            }
+        };
+
+        add("title", &note.title, None);
+        add("content", &note.content, Some(SNIPPET_CONTEXT));
+        for tag in &note.tags {
+            add("tags", tag, None);
+        }
+        for transcript in note
+            .attachments
+            .iter()
+            .filter_map(|a| a.transcript.as_deref())
+        {
+            add("transcript", transcript, Some(SNIPPET_CONTEXT));
+        }
+        highlights
+    }
+}
+
+/// A search query split into the words to look for and its `lang:` and
+/// `tag:` filters.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Language codes from `lang:de`, `lang:german`, ...; a note has to be in one of them
+    pub languages: Vec<String>,
+    /// Tags from `tag:recipes`; a note has to have all of them (or tags nested under them)
+    pub tags: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut words = Vec::new();
+        let mut languages = Vec::new();
+        let mut tags = Vec::new();
+        for word in query.split_whitespace() {
+            if let Some(lang) = word.strip_prefix("lang:").filter(|lang| !lang.is_empty()) {
+                languages.push(language::normalize(lang));
+            } else if let Some(tag) = word.strip_prefix("tag:").filter(|tag| !tag.is_empty()) {
+                tags.push(tag.to_string());
+            } else {
+                words.push(word);
+            }
+        }
+        SearchQuery {
+            text: words.join(" "),
+            languages,
+            tags,
+        }
+    }
+
+    /// Whether the filters let `note` through.
+    pub fn allows(&self, note: &Note) -> bool {
+        let language_ok = self.languages.is_empty()
+            || note
+                .language
+                .as_ref()
+                .is_some_and(|language| self.languages.contains(language));
+        language_ok && self.tags.iter().all(|tag| tags::has_tag(note, tag))
+    }
+}
+
+/// A search match inside one field of a note.
+#[derive(Debug, Clone, Serialize)]
+pub struct Highlight {
+    pub field: &'static str,
+    /// Text around the matches with each one wrapped in `<em>`, HTML-escaped.
+    pub snippet: String,
+    /// Byte ranges of the matched words in the original field.
+    pub positions: Vec<(usize, usize)>,
+}
+
+/// Where a search looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchScope {
+    /// Notes outside the trash
+    #[default]
+    Active,
+    /// Notes in the trash
+    Trash,
+    /// Past versions of every note
+    Revisions,
+    /// All of the above
+    All,
+}
+
+impl SearchScope {
+    pub fn includes(self, found_in: HitScope) -> bool {
+        match self {
+            SearchScope::Active => found_in == HitScope::Active,
+            SearchScope::Trash => found_in == HitScope::Trash,
+            SearchScope::Revisions => found_in == HitScope::Revisions,
+            SearchScope::All => true,
+        }
+    }
+}
+
+impl std::str::FromStr for SearchScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "active" => Ok(SearchScope::Active),
+            "trash" => Ok(SearchScope::Trash),
+            "revisions" | "history" => Ok(SearchScope::Revisions),
+            "all" => Ok(SearchScope::All),
+            other => Err(format!(
+                "Unknown search scope: {} (expected active, trash, revisions or all)",
+                other
+            )),
+        }
+    }
+}
+
+/// Which part of the vault a search hit came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HitScope {
+    #[default]
+    Active,
+    Trash,
+    /// A past version matched; the note itself may no longer
+    Revisions,
+}
+
+impl HitScope {
+    pub fn name(self) -> &'static str {
+        match self {
+            HitScope::Active => "active",
+            HitScope::Trash => "trash",
+            HitScope::Revisions => "revisions",
+        }
+    }
+}
+
+/// A search result: the note plus where it matched.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub note: Note,
+    pub highlights: Vec<Highlight>,
+    /// Relevance: matched words weighted by field (title 3, tags 2, body 1)
+    pub score: u32,
+    pub scope: HitScope,
+    /// For a hit in a past version, when that version was replaced; the
+    /// highlights are in its text rather than the note's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision_saved_at: Option<DateTime<Utc>>,
+}
+
+impl SearchHit {
+    pub fn new(note: Note, highlights: Vec<Highlight>) -> Self {
+        let score = highlights
+            .iter()
+            .map(|h| {
+                let weight = match h.field {
+                    "title" => 3,
+                    "tags" => 2,
+                    _ => 1,
+                };
+                weight * h.positions.len() as u32
+            })
+            .sum();
+        SearchHit {
+            note,
+            highlights,
+            score,
+            scope: HitScope::Active,
+            revision_saved_at: None,
+        }
+    }
+}
+
+/// Order of search results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSort {
+    /// Highest score first
+    #[default]
+    Relevance,
+    /// Most recently edited first
+    Updated,
+    /// Newest first
+    Created,
+    /// Alphabetical
+    Title,
+}
+
+impl std::str::FromStr for SearchSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "relevance" => Ok(SearchSort::Relevance),
+            "updated" | "updated_at" => Ok(SearchSort::Updated),
+            "created" | "created_at" => Ok(SearchSort::Created),
+            "title" => Ok(SearchSort::Title),
+            other => Err(format!("Unknown sort order: {}", other)),
+        }
+    }
+}
+
+/// Sorts hits by `sort`, breaking ties by note id so the order is stable
+/// across requests.
+pub fn sort_hits(hits: &mut [SearchHit], sort: SearchSort) {
+    hits.sort_by(|a, b| {
+        let order = match sort {
+            SearchSort::Relevance => b.score.cmp(&a.score),
+            SearchSort::Updated => b.note.updated_at.cmp(&a.note.updated_at),
+            SearchSort::Created => b.note.created_at.cmp(&a.note.created_at),
+            SearchSort::Title => a
+                .note
+                .title
+                .to_lowercase()
+                .cmp(&b.note.title.to_lowercase()),
+        };
+        order.then_with(|| a.note.id.cmp(&b.note.id))
+    });
+}
+
+// Characters of context shown on each side of the first content match
+pub const SNIPPET_CONTEXT: usize = 40;
+
+/// Byte ranges of the alphanumeric runs in `text`.
+fn words(text: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let start = loop {
+            let (i, c) = chars.next()?;
+            if c.is_alphanumeric() {
+                break i;
+            }
+        };
+        let mut end = text.len();
+        while let Some(&(i, c)) = chars.peek() {
+            if !c.is_alphanumeric() {
+                end = i;
+                break;
+            }
+            chars.next();
+        }
+        Some((start, end))
+    })
+}
+
+/// Renders `text` with the matched `spans` passed through `mark` and the rest
+/// through `plain`. With `context`, only that many characters around the first
+/// match are kept, with "..." marking the cuts.
+pub fn snippet(
+    text: &str,
+    spans: &[(usize, usize)],
+    context: Option<usize>,
+    mark: impl Fn(&str) -> String,
+    plain: impl Fn(&str) -> String,
+) -> String {
+    let (from, to) = match (context, spans.first()) {
+        (Some(context), Some(&(start, end))) => {
+            let from = text[..start]
+                .char_indices()
+                .rev()
+                .nth(context.saturating_sub(1))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            let to = text[end..]
+                .char_indices()
+                .nth(context)
+                .map(|(i, _)| end + i)
+                .unwrap_or(text.len());
+            (from, to)
+        }
+        _ => (0, text.len()),
+    };
+
+    let mut out = String::new();
+    if from > 0 {
+        out.push_str("...");
+    }
+    let mut cursor = from;
+    for &(start, end) in spans.iter().filter(|&&(s, e)| s >= from && e <= to) {
+        out.push_str(&plain(&text[cursor..start]));
+        out.push_str(&mark(&text[start..end]));
+        cursor = end;
+    }
+    out.push_str(&plain(&text[cursor..to]));
+    if to < text.len() {
+        out.push_str("...");
+    }
+    // Keep snippets on one line
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_mark(text: &str) -> String {
+    format!("<em>{}</em>", html_escape(text))
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new(&SearchConfig::default())
+    }
+}
+
+fn stemmer_algorithm(language: &str) -> Option<Algorithm> {
+    let algorithm = match language.to_lowercase().as_str() {
+        "arabic" | "ar" => Algorithm::Arabic,
+        "danish" | "da" => Algorithm::Danish,
+        "dutch" | "nl" => Algorithm::Dutch,
+        "english" | "en" => Algorithm::English,
+        "finnish" | "fi" => Algorithm::Finnish,
+        "french" | "fr" => Algorithm::French,
+        "german" | "de" => Algorithm::German,
+        "greek" | "el" => Algorithm::Greek,
+        "hungarian" | "hu" => Algorithm::Hungarian,
+        "italian" | "it" => Algorithm::Italian,
+        "norwegian" | "no" => Algorithm::Norwegian,
+        "portuguese" | "pt" => Algorithm::Portuguese,
+        "romanian" | "ro" => Algorithm::Romanian,
+        "russian" | "ru" => Algorithm::Russian,
+        "spanish" | "es" => Algorithm::Spanish,
+        "swedish" | "sv" => Algorithm::Swedish,
+        "tamil" | "ta" => Algorithm::Tamil,
+        "turkish" | "tr" => Algorithm::Turkish,
+        _ => return None,
+    };
+    Some(algorithm)
+}
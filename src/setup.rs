@@ -0,0 +1,142 @@
+use crate::config::{ColorChoice, Config, StorageConfig};
+use colored::*;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Input, Select};
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+/// Whether this is the very first launch: no config file and no notes yet.
+pub fn is_first_run(config_path: &str) -> bool {
+    !Path::new(config_path).exists() && !Path::new(&StorageConfig::default().uri).exists()
+}
+
+/// The wizard needs someone to answer it.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+fn prompt_error(e: dialoguer::Error) -> io::Error {
+    match e {
+        dialoguer::Error::IO(e) => e,
+    }
+}
+
+/// Asks for the basic settings, writes them to `config_path` and returns
+/// them. Answers start from `current`, so running it again edits the config.
+pub fn run_wizard(config_path: &str, current: Config) -> io::Result<Config> {
+    let theme = ColorfulTheme::default();
+    let mut config = current;
+
+    println!("\n{}", "🛠  Notes setup".bright_magenta().bold());
+    println!(
+        "{}",
+        "Press Enter to keep the suggested value. You can change everything later in the config file."
+            .dimmed()
+    );
+
+    let current_dir = Path::new(&config.storage.uri)
+        .parent()
+        .map(|dir| dir.display().to_string())
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| ".".to_string());
+    let data_dir: String = Input::with_theme(&theme)
+        .with_prompt("Data directory")
+        .default(current_dir)
+        .interact_text()
+        .map_err(prompt_error)?;
+    config.storage.uri = Path::new(data_dir.trim())
+        .join("notes.json")
+        .display()
+        .to_string();
+
+    let suggested_editor = config
+        .cli
+        .editor
+        .clone()
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_default();
+    let editor: String = Input::with_theme(&theme)
+        .with_prompt(
+            "Editor for note content (e.g. vim, nano, code --wait; empty to type in the terminal)",
+        )
+        .default(suggested_editor)
+        .allow_empty(true)
+        .interact_text()
+        .map_err(prompt_error)?;
+    config.cli.editor = Some(editor.trim().to_string()).filter(|editor| !editor.is_empty());
+
+    let port: u16 = Input::with_theme(&theme)
+        .with_prompt("Web server port")
+        .default(config.server.port.unwrap_or(8080))
+        .interact_text()
+        .map_err(prompt_error)?;
+    config.server.port = Some(port);
+
+    let choices = [ColorChoice::Auto, ColorChoice::Always, ColorChoice::Never];
+    let color = Select::with_theme(&theme)
+        .with_prompt("Colored output")
+        .items(&[
+            "Automatic (when the terminal supports it)",
+            "Always",
+            "Never",
+        ])
+        .default(
+            choices
+                .iter()
+                .position(|choice| *choice == config.cli.color)
+                .unwrap_or(0),
+        )
+        .interact()
+        .map_err(prompt_error)?;
+    config.cli.color = choices[color];
+
+    #[cfg(feature = "web")]
+    setup_auth(&theme)?;
+
+    config.save(config_path)?;
+    config.cli.color.apply();
+    println!(
+        "{} {}",
+        "✅ Settings saved to".bright_green(),
+        config_path.bright_cyan()
+    );
+    println!(
+        "{}",
+        "Run `notes-app setup` to go through these questions again.".dimmed()
+    );
+    Ok(config)
+}
+
+// Accounts turn authentication on; offer the first (admin) one
+#[cfg(feature = "web")]
+fn setup_auth(theme: &ColorfulTheme) -> io::Result<()> {
+    use crate::users::{self, Role, UserStore};
+    use dialoguer::Confirm;
+
+    let mut store = UserStore::new(users::DEFAULT_USERS_PATH)?;
+    if store.is_enabled() {
+        return Ok(());
+    }
+    let enable = Confirm::with_theme(theme)
+        .with_prompt("Require an API token for the web interface?")
+        .default(false)
+        .interact()
+        .map_err(prompt_error)?;
+    if !enable {
+        return Ok(());
+    }
+
+    let username: String = Input::with_theme(theme)
+        .with_prompt("Admin username")
+        .default("admin".to_string())
+        .interact_text()
+        .map_err(prompt_error)?;
+    let (_, token) = store.add_user(username.trim(), Role::Admin)?;
+    println!(
+        "{} {}",
+        "🔑 Admin token (shown once, keep it safe):".bright_yellow(),
+        token.bright_white().bold()
+    );
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+/// Parses when a snooze ends: a duration from `now` (`30m`, `12h`, `3d`,
+/// `2w`), an RFC 3339 timestamp, or a local `YYYY-MM-DD HH:MM` /
+/// `YYYY-MM-DD` (the start of that day). The time must be in the future.
+pub fn parse_until(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let until = parse_when(input, now).map_err(|_| {
+        format!(
+            "Invalid snooze time: {} (expected e.g. 3d, 12h, 2026-01-31 or 2026-01-31 09:00)",
+            input.trim()
+        )
+    })?;
+    if until <= now {
+        return Err("Snooze time must be in the future".to_string());
+    }
+    Ok(until)
+}
+
+/// Parses a time in any of the forms [`parse_until`] takes, past or future.
+pub fn parse_when(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    match parse_duration(input) {
+        Some(duration) => Ok(now + duration),
+        None => parse_time(input).ok_or_else(|| {
+            format!(
+                "Invalid time: {} (expected e.g. 3d, 12h, 2026-01-31 or 2026-01-31 09:00)",
+                input
+            )
+        }),
+    }
+}
+
+/// A span like `30m`, `12h`, `3d` or `2w`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let unit = input.chars().last()?;
+    let amount: i64 = input[..input.len() - unit.len_utf8()].trim().parse().ok()?;
+    match unit.to_ascii_lowercase() {
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+fn parse_time(input: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+}
@@ -1,106 +1,1521 @@
-use crate::models::Note;
-use serde_json;
-use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufReader, BufWriter};
-use std::path::Path;
+use crate::backend::{self, StorageBackend};
+use crate::blobs::{self, BlobStore};
+use crate::clock::{SharedClock, SystemClock};
+use crate::config::{ProcessorsConfig, QuotaConfig, SearchConfig, TagsConfig};
+use crate::crypto;
+use crate::events::{self, EventKind, EventSender, NoteEvent};
+use crate::merge::{self, MergeStrategy};
+use crate::models::{
+    Attachment, Comment, Conflict, GeoPoint, Note, NoteState, Reaction, Resolution, StateFilter,
+    ThreadNode, Transfer,
+};
+use crate::notebooks::{MemberAccess, Notebook, NotebookAccess, Visibility};
+use crate::processors::PendingSave;
+use crate::quota::{self, Usage};
+use crate::scripts::Hooks;
+use crate::search::{HitScope, Normalizer, SearchHit, SearchQuery, SearchScope};
+use crate::tags::{self, TagColors, TagRules};
+use crate::thumbnail::ThumbSize;
+use crate::views::{self, ViewLog};
+use chrono::{DateTime, Local, Utc};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex}; // ✅ Keep this in storage.rs
+use std::time::SystemTime;
+use tokio::sync::broadcast;
 
 pub struct NotesManager {
     pub notes: Vec<Note>,
-    storage_path: String,
+    backend: Box<dyn StorageBackend>,
+    blobs: BlobStore,
+    // Content over this many bytes is saved as a blob; 0 keeps it all inline
+    inline_limit: usize,
+    last_modified: SystemTime,
+    // Bumped on every change to the notes, so caches know they're stale
+    generation: u64,
+    // Store mtime as of our last load/save, to tell our writes from others'
+    synced_mtime: Option<SystemTime>,
+    // Each note as of the last load/save, to tell the notes changed here
+    // since from those changed by other processes
+    synced: HashMap<String, SyncedNote>,
+    normalizer: Normalizer,
+    tag_rules: TagRules,
+    tag_colors: TagColors,
+    notebooks: Vec<Notebook>,
+    views: ViewLog,
+    quota: QuotaConfig,
+    hooks: Hooks,
+    processors: ProcessorsConfig,
+    // Why the most recent write failed; cleared by the next successful one
+    last_save_error: Option<String>,
+    events: EventSender,
+    clock: SharedClock,
+    // Open batches; saves wait for the outermost one to be committed
+    batch_depth: usize,
+    // Whether a save was held back by the current batch
+    batch_unsaved: bool,
+}
+
+// What a note was like when last loaded or saved
+struct SyncedNote {
+    fingerprint: u64,
+    updated_at: DateTime<Utc>,
+}
+
+fn sync_points(notes: &[Note]) -> HashMap<String, SyncedNote> {
+    notes
+        .iter()
+        .map(|note| {
+            let at_sync = SyncedNote {
+                fingerprint: note.fingerprint(),
+                updated_at: note.updated_at,
+            };
+            (note.id.clone(), at_sync)
+        })
+        .collect()
+}
+
+/// File next to the notes that holds the tag colors.
+const TAG_COLORS_FILE: &str = "tag_colors.json";
+
+fn tag_colors_path(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(TAG_COLORS_FILE)
+}
+
+fn load_tag_colors(storage_path: &Path) -> io::Result<TagColors> {
+    let path = tag_colors_path(storage_path);
+    if !path.exists() {
+        return Ok(TagColors::default());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// File next to the notes that holds the notebooks.
+const NOTEBOOKS_FILE: &str = "notebooks.json";
+
+fn notebooks_path(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(NOTEBOOKS_FILE)
+}
+
+fn load_notebooks(storage_path: &Path) -> io::Result<Vec<Notebook>> {
+    let path = notebooks_path(storage_path);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Tag of copies made when an edit collides with a newer version.
+pub const CONFLICT_TAG: &str = "conflict";
+
+/// `title` without the " (conflict …)" a conflict copy's title ends in.
+pub fn without_conflict_suffix(title: &str) -> &str {
+    match title.rfind(" (conflict ") {
+        Some(start) if title.ends_with(')') => &title[..start],
+        _ => title,
+    }
+}
+
+/// Version of the notes file format written by this build.
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// Salvages every note that still deserializes from a damaged notes file.
+///
+/// Valid JSON with bad entries keeps the good entries; truncated or mangled
+/// JSON keeps each complete object of the notes array.
+pub fn recover_notes(raw: &str) -> Vec<Note> {
+    if let Ok(value) = serde_json::from_str::<Value>(raw) {
+        let items = match value {
+            Value::Array(items) => items,
+            Value::Object(mut map) => match map.remove("notes") {
+                Some(Value::Array(items)) => items,
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        return items
+            .into_iter()
+            .filter_map(|item| serde_json::from_value(item).ok())
+            .collect();
+    }
+
+    let start = raw
+        .find("\"notes\"")
+        .and_then(|key| raw[key..].find('[').map(|offset| key + offset))
+        .or_else(|| raw.find('['));
+    let Some(start) = start else {
+        return Vec::new();
+    };
+
+    let mut notes = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut object_start = 0;
+
+    for (i, c) in raw.char_indices().skip_while(|(i, _)| *i <= start) {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => {
+                if depth == 0 {
+                    object_start = i;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    if let Ok(note) = serde_json::from_str::<Note>(&raw[object_start..=i]) {
+                        notes.push(note);
+                    }
+                }
+            }
+            ']' if depth == 0 => break,
+            _ => {}
+        }
+    }
+    notes
+}
+
+/// Reads whatever notes can be salvaged from the file at `path`.
+pub fn recover_notes_file(path: &str) -> io::Result<Vec<Note>> {
+    let bytes = fs::read(path)?;
+    Ok(recover_notes(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Moves a damaged notes file out of the way as `<file>.corrupt-<timestamp>`
+/// and returns the new path.
+pub fn quarantine_file(path: &str) -> io::Result<String> {
+    let target = format!("{}.corrupt-{}", path, Utc::now().format("%Y%m%dT%H%M%SZ"));
+    fs::rename(path, &target)?;
+    Ok(target)
 }
 
 impl NotesManager {
-    pub fn new(storage_path: &str) -> io::Result<Self> {
-        let notes = Self::load_notes(storage_path)?;
-        Ok(NotesManager {
+    /// Opens the store named by `uri` (see [`backend::open`]).
+    pub fn new(uri: &str) -> io::Result<Self> {
+        let backend = backend::open(uri)?;
+        let blobs = BlobStore::new(blobs::dir_for(backend.path()));
+        let mut notes = backend.load()?;
+        blobs.internalize(&mut notes)?;
+        let tag_colors = load_tag_colors(backend.path())?;
+        for note in &mut notes {
+            note.tag_colors = tag_colors.for_tags(&note.tags);
+        }
+        let notebooks = load_notebooks(backend.path())?;
+        let views = ViewLog::load(views::path_for(backend.path()), &notes)?;
+        views.apply(&mut notes);
+        let synced_mtime = backend.modified();
+        let mut manager = NotesManager {
             notes,
-            storage_path: storage_path.to_string(),
+            backend,
+            blobs,
+            inline_limit: blobs::DEFAULT_INLINE_LIMIT,
+            // Start from the store's mtime so conditional requests survive restarts
+            last_modified: synced_mtime.unwrap_or_else(SystemTime::now),
+            generation: 0,
+            synced_mtime,
+            synced: HashMap::new(),
+            normalizer: Normalizer::default(),
+            tag_rules: TagRules::default(),
+            tag_colors,
+            notebooks,
+            views,
+            quota: QuotaConfig::default(),
+            hooks: Hooks::default(),
+            processors: ProcessorsConfig::default(),
+            last_save_error: None,
+            events: events::channel(),
+            clock: Arc::new(SystemClock),
+            batch_depth: 0,
+            batch_unsaved: false,
+        };
+        manager.share_notebooks();
+        manager.synced = sync_points(&manager.notes);
+        Ok(manager)
+    }
+
+    /// Starts a store with the given notes (e.g. recovered ones), replacing its contents.
+    pub fn with_notes(uri: &str, notes: Vec<Note>) -> io::Result<Self> {
+        backend::open(uri)?.save(&notes)?;
+        Self::new(uri)
+    }
+
+    /// Receives an event for every change made through this manager.
+    pub fn subscribe(&self) -> broadcast::Receiver<NoteEvent> {
+        self.events.subscribe()
+    }
+
+    // Having no listeners is fine, so send errors are ignored
+    pub(crate) fn emit(&self, kind: EventKind, note: &Note) {
+        let _ = self.events.send(NoteEvent::new(kind, note, self.now()));
+    }
+
+    fn emit_bulk(&self, kind: EventKind, notes: &[Note], summary: String) {
+        let _ = self
+            .events
+            .send(NoteEvent::bulk(kind, notes, summary, self.now()));
+    }
+
+    /// Makes the manager take note timestamps, and decide what's snoozed or
+    /// due, from `clock` instead of the system time.
+    #[allow(dead_code)] // for tests and apps embedding the crate
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = clock;
+    }
+
+    /// The current time by the manager's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// File the notes are stored in.
+    pub fn storage_path(&self) -> &Path {
+        self.backend.path()
+    }
+
+    pub fn set_search_config(&mut self, config: &SearchConfig) {
+        self.normalizer = Normalizer::new(config);
+    }
+
+    pub fn set_tag_config(&mut self, config: &TagsConfig) {
+        self.tag_rules = TagRules::new(config);
+    }
+
+    /// Scripts run when notes are created, edited, deleted or searched.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// HTTP services called before notes are saved.
+    pub fn set_processors(&mut self, processors: &ProcessorsConfig) {
+        self.processors = processors.clone();
+    }
+
+    /// Tag aliases and implications applied to saved notes.
+    pub fn tag_rules(&self) -> &TagRules {
+        &self.tag_rules
+    }
+
+    /// Colors given to tags, shown on every note with those tags.
+    pub fn tag_colors(&self) -> &TagColors {
+        &self.tag_colors
+    }
+
+    /// Gives `tag` (and the tags below it without their own) a color, or
+    /// takes it away with `None`. An invalid color is `InvalidInput`.
+    pub fn set_tag_color(&mut self, tag: &str, color: Option<&str>) -> io::Result<()> {
+        let mut colors = self.tag_colors.clone();
+        colors
+            .set(&self.tag_rules.canonical(tag), color)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(
+            tag_colors_path(self.storage_path()),
+            serde_json::to_string_pretty(&colors)?,
+        )?;
+        self.tag_colors = colors;
+        self.paint_tags();
+        self.save_notes()
+    }
+
+    // Brings every note's `tag_colors` up to date
+    fn paint_tags(&mut self) {
+        for note in &mut self.notes {
+            note.tag_colors = self.tag_colors.for_tags(&note.tags);
+        }
+    }
+
+    pub fn notebooks(&self) -> &[Notebook] {
+        &self.notebooks
+    }
+
+    pub fn get_notebook(&self, id: &str) -> Option<&Notebook> {
+        self.notebooks.iter().find(|notebook| notebook.id == id)
+    }
+
+    /// Notes outside the trash in the notebook.
+    pub fn notebook_notes(&self, id: &str) -> Vec<Note> {
+        self.list_notes()
+            .into_iter()
+            .filter(|note| note.notebook.as_deref() == Some(id))
+            .collect()
+    }
+
+    /// Starts an empty notebook. An empty or overlong name is `InvalidInput`.
+    pub fn create_notebook(
+        &mut self,
+        name: &str,
+        owner: Option<String>,
+        visibility: Visibility,
+    ) -> io::Result<Notebook> {
+        let notebook = Notebook::new(name, owner, visibility, self.now())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut notebooks = self.notebooks.clone();
+        notebooks.push(notebook.clone());
+        self.save_notebooks(notebooks)?;
+        Ok(notebook)
+    }
+
+    /// Renames the notebook or changes who can see it.
+    pub fn edit_notebook(
+        &mut self,
+        id: &str,
+        name: Option<&str>,
+        visibility: Option<Visibility>,
+    ) -> io::Result<Option<Notebook>> {
+        self.change_notebook(id, |notebook| {
+            if let Some(name) = name {
+                notebook.rename(name)?;
+            }
+            if let Some(visibility) = visibility {
+                notebook.visibility = visibility;
+            }
+            Ok(())
         })
     }
 
-    fn load_notes(path: &str) -> io::Result<Vec<Note>> {
-        let path = Path::new(path);
+    /// Invites `user` into the notebook; they join once they accept.
+    pub fn invite_member(
+        &mut self,
+        id: &str,
+        user: &str,
+        access: MemberAccess,
+        invited_by: Option<String>,
+    ) -> io::Result<Option<Notebook>> {
+        let now = self.now();
+        self.change_notebook(id, |notebook| {
+            notebook.invite(user, access, invited_by, now)
+        })
+    }
 
-        if !path.exists() {
-            return Ok(Vec::new());
+    pub fn accept_invitation(&mut self, id: &str, user: &str) -> io::Result<Option<Notebook>> {
+        let now = self.now();
+        self.change_notebook(id, |notebook| notebook.accept(user, now))
+    }
+
+    /// Takes a member (or an invitation) out of the notebook. Someone who
+    /// isn't there is `NotFound`.
+    pub fn remove_member(&mut self, id: &str, user: &str) -> io::Result<Option<Notebook>> {
+        let mut found = true;
+        let notebook = self.change_notebook(id, |notebook| {
+            found = notebook.remove(user);
+            Ok(())
+        })?;
+        if !found {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Not a member of this notebook",
+            ));
+        }
+        Ok(notebook)
+    }
+
+    /// Removes the notebook. Its notes stay, outside any notebook.
+    pub fn delete_notebook(&mut self, id: &str) -> io::Result<bool> {
+        if self.get_notebook(id).is_none() {
+            return Ok(false);
+        }
+        let mut notebooks = self.notebooks.clone();
+        notebooks.retain(|notebook| notebook.id != id);
+        self.save_notebooks(notebooks)?;
+        for note in &mut self.notes {
+            if note.notebook.as_deref() == Some(id) {
+                note.notebook = None;
+                note.notebook_access = None;
+            }
         }
+        self.save_notes()?;
+        Ok(true)
+    }
+
+    /// Puts the note into a notebook, or takes it out with `None`. Moving
+    /// isn't editing: `updated_at` stays as it is. An unknown notebook is
+    /// `InvalidInput`.
+    pub fn set_notebook(&mut self, id: &str, notebook: Option<&str>) -> io::Result<Option<Note>> {
+        if notebook.is_some_and(|notebook| self.get_notebook(notebook).is_none()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No such notebook",
+            ));
+        }
+        let access = self.notebook_access(notebook);
+        self.set_state(id, |note| {
+            note.notebook = notebook.map(str::to_string);
+            note.notebook_access = access;
+        })
+    }
+
+    // Applies `change` to a copy of the notebook and keeps it if it works out
+    fn change_notebook(
+        &mut self,
+        id: &str,
+        change: impl FnOnce(&mut Notebook) -> Result<(), String>,
+    ) -> io::Result<Option<Notebook>> {
+        let mut notebooks = self.notebooks.clone();
+        let Some(notebook) = notebooks.iter_mut().find(|notebook| notebook.id == id) else {
+            return Ok(None);
+        };
+        change(notebook).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let notebook = notebook.clone();
+        self.save_notebooks(notebooks)?;
+        Ok(Some(notebook))
+    }
+
+    fn save_notebooks(&mut self, notebooks: Vec<Notebook>) -> io::Result<()> {
+        fs::write(
+            notebooks_path(self.storage_path()),
+            serde_json::to_string_pretty(&notebooks)?,
+        )?;
+        self.notebooks = notebooks;
+        self.share_notebooks();
+        self.generation += 1;
+        Ok(())
+    }
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+    fn notebook_access(&self, notebook: Option<&str>) -> Option<NotebookAccess> {
+        notebook
+            .and_then(|id| self.get_notebook(id))
+            .map(Notebook::access)
+    }
 
-        match serde_json::from_reader(reader) {
-            Ok(notes) => Ok(notes),
-            Err(_) => Ok(Vec::new()),
+    // Brings every note's `notebook_access` up to date
+    fn share_notebooks(&mut self) {
+        for index in 0..self.notes.len() {
+            let access = self.notebook_access(self.notes[index].notebook.as_deref());
+            self.notes[index].notebook_access = access;
         }
     }
 
-    pub fn save_notes(&self) -> io::Result<()> {
-        let path = Path::new(&self.storage_path);
-        let parent = path.parent().unwrap_or(Path::new("."));
+    /// Limits checked before notes or attachments are added or grow.
+    pub fn set_quota(&mut self, quota: &QuotaConfig) {
+        self.quota = quota.clone();
+    }
+
+    /// Usage of the vault and of `owner`'s notes, trash included, against
+    /// the quota.
+    pub fn storage_reports(&self, owner: Option<&str>) -> Vec<quota::StorageReport> {
+        quota::reports(&self.quota, &self.notes, owner)
+    }
+
+    // `StorageFull` if `growth` doesn't fit in the vault's quota, or in the
+    // quota of `owner`'s notes
+    fn check_quota(&self, owner: Option<&str>, growth: Usage) -> io::Result<()> {
+        quota::check(
+            &self.quota.vault,
+            Usage::of_notes(&self.notes),
+            growth,
+            "vault",
+        )?;
+        if let Some(owner) = owner {
+            let owned = self
+                .notes
+                .iter()
+                .filter(|note| note.owner.as_deref() == Some(owner));
+            quota::check(&self.quota.per_user, Usage::of_notes(owned), growth, "user")?;
+        }
+        Ok(())
+    }
+
+    /// Content longer than `limit` bytes is saved outside the store from the
+    /// next save on; 0 keeps all content inline.
+    pub fn set_inline_limit(&mut self, limit: usize) {
+        self.inline_limit = limit;
+    }
+
+    // Notes as stored, with externalized content read back in
+    fn load_stored(&self) -> io::Result<Vec<Note>> {
+        let mut notes = self.backend.load()?;
+        self.blobs.internalize(&mut notes)?;
+        Ok(notes)
+    }
+
+    /// Picks up changes another process made to the store.
+    ///
+    /// Notes edited here since the last load/save keep the in-memory version
+    /// (and are written back); everything else follows the disk copy, including
+    /// external deletions. If the disk copy of such a note was edited too, it is
+    /// kept as a conflict copy. A file that doesn't parse (e.g. mid-write) is an error
+    /// and leaves the in-memory notes untouched. Returns whether anything was reloaded.
+    pub fn reload_if_changed(&mut self) -> io::Result<bool> {
+        let mtime = self.backend.modified();
+        if mtime.is_none() || mtime == self.synced_mtime {
+            return Ok(false);
+        }
+
+        let disk_notes = self.load_stored()?;
+        let synced = std::mem::take(&mut self.synced);
+        let is_unsaved = |note: &Note| {
+            synced
+                .get(&note.id)
+                .is_none_or(|at_sync| at_sync.fingerprint != note.fingerprint())
+        };
+
+        let mut local: HashMap<String, Note> = self
+            .notes
+            .drain(..)
+            .filter(|note| is_unsaved(note))
+            .map(|note| (note.id.clone(), note))
+            .collect();
+        let unsaved: Vec<String> = local.keys().cloned().collect();
+
+        let mut conflicts = Vec::new();
+        let mut merged: Vec<Note> = disk_notes
+            .into_iter()
+            .map(|theirs| match local.remove(&theirs.id) {
+                Some(mine) => {
+                    let diverged = theirs.title != mine.title
+                        || theirs.content != mine.content
+                        || theirs.tags != mine.tags;
+                    let edited_there = synced
+                        .get(&theirs.id)
+                        .is_none_or(|at_sync| at_sync.updated_at != theirs.updated_at);
+                    if diverged && edited_there && !theirs.is_encrypted() && !mine.is_encrypted() {
+                        conflicts.push(self.conflict_copy(
+                            &mine,
+                            theirs.title,
+                            theirs.content,
+                            theirs.tags,
+                        ));
+                    }
+                    mine
+                }
+                None => theirs,
+            })
+            .collect();
+        merged.extend(conflicts.iter().cloned());
+        // Unsaved notes the disk copy doesn't know about yet
+        merged.extend(local.into_values());
 
-        fs::create_dir_all(parent)?;
+        self.notes = merged;
+        self.views.apply(&mut self.notes);
+        self.paint_tags();
+        self.share_notebooks();
+        self.generation += 1;
+        self.synced_mtime = mtime;
+        self.synced = sync_points(&self.notes);
+        for id in unsaved.iter().chain(conflicts.iter().map(|copy| &copy.id)) {
+            self.synced.remove(id);
+        }
+        self.last_modified = SystemTime::now();
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)?;
+        if !unsaved.is_empty() {
+            self.save_notes()?;
+        }
+        for copy in &conflicts {
+            self.emit(EventKind::Created, copy);
+        }
+        Ok(true)
+    }
 
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, &self.notes)?;
+    /// Writes the notes to the store, or marks them for the commit of the
+    /// open batch (see [`begin_batch`](Self::begin_batch)).
+    pub fn save_notes(&mut self) -> io::Result<()> {
+        // The notes changed in memory whether or not the write works
+        self.generation += 1;
+        if self.batch_depth > 0 {
+            self.batch_unsaved = true;
+            return Ok(());
+        }
+        let stored = match self
+            .blobs
+            .externalize(&self.notes, self.inline_limit)
+            .and_then(|stored| self.backend.save(&stored).map(|_| stored))
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                self.last_save_error = Some(e.to_string());
+                return Err(e);
+            }
+        };
+        self.blobs.remove_unused(&stored);
+        self.last_save_error = None;
+        self.last_modified = SystemTime::now();
+        self.synced_mtime = self.backend.modified();
+        self.synced = sync_points(&self.notes);
 
         Ok(())
     }
 
+    /// Holds back writes until [`commit`](Self::commit), so a run of
+    /// changes (an import, a bulk request) reaches the store in one write
+    /// instead of one per note. Batches nest: only the outermost commit writes.
+    /// Events are still sent as each change is made.
+    pub fn begin_batch(&mut self) {
+        self.batch_depth += 1;
+    }
+
+    /// Ends the batch begun last; ending the outermost one writes whatever
+    /// changed during it.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.batch_depth = self.batch_depth.saturating_sub(1);
+        if self.batch_depth > 0 || !self.batch_unsaved {
+            return Ok(());
+        }
+        self.batch_unsaved = false;
+        self.save_notes()
+    }
+
+    /// Runs `changes` in a batch, committing it whether or not they succeed:
+    /// what was changed before an error is saved like any other change.
+    pub fn batch<T>(&mut self, changes: impl FnOnce(&mut Self) -> io::Result<T>) -> io::Result<T> {
+        self.begin_batch();
+        let result = changes(self);
+        let committed = self.commit();
+        let value = result?;
+        committed.map(|_| value)
+    }
+
+    pub fn last_save_error(&self) -> Option<&str> {
+        self.last_save_error.as_deref()
+    }
+
+    /// Creates and removes a probe file next to the data file.
+    pub fn check_writable(&self) -> io::Result<()> {
+        let dir = self.storage_path().parent().unwrap_or(Path::new("."));
+        let probe = dir.join(".write-check");
+        fs::write(&probe, b"ok")?;
+        fs::remove_file(probe)
+    }
+
+    /// Reads the store as it is on disk and returns how many notes it holds.
+    pub fn check_data_file(&self) -> io::Result<usize> {
+        self.load_stored().map(|notes| notes.len())
+    }
+
+    /// Changes whenever the notes do (saves and reloads).
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Time of the last successful write, used for `Last-Modified` on list responses.
+    pub fn last_modified(&self) -> SystemTime {
+        self.last_modified
+    }
+
     pub fn add_note(
         &mut self,
         title: String,
         content: String,
         tags: Vec<String>,
     ) -> io::Result<Note> {
-        let note = Note::new(title, content, tags);
+        self.add_note_for(None, title, content, tags)
+    }
+
+    pub fn add_note_for(
+        &mut self,
+        owner: Option<String>,
+        title: String,
+        content: String,
+        tags: Vec<String>,
+    ) -> io::Result<Note> {
+        let mut note = Note::new_at(title, content, tags, self.now());
+        note.owner = owner;
+        self.insert_note(note)
+    }
+
+    /// Adds a note built by the caller, e.g. one with a location or source set.
+    pub fn insert_note(&mut self, note: Note) -> io::Result<Note> {
+        let pending = self.prepare_insert(note)?.run()?;
+        self.insert_processed(pending)
+    }
+
+    /// Runs the create scripts on a new note; the pre-save processors are
+    /// left to [`PendingSave::run`], then [`Self::insert_processed`] adds it.
+    pub fn prepare_insert(&mut self, mut note: Note) -> io::Result<PendingSave> {
+        let changed = self.hooks.on_create(&mut note)?;
+        Ok(PendingSave::new(
+            note,
+            EventKind::Created,
+            None,
+            changed,
+            &self.processors,
+        ))
+    }
+
+    pub fn insert_processed(&mut self, pending: PendingSave) -> io::Result<Note> {
+        let mut note = pending.note;
+        let tags = self.tag_rules.sync_hashtags(note.tags, "", &note.content);
+        note.tags = self.tag_rules.apply(tags);
+        note.tag_colors = self.tag_colors.for_tags(&note.tags);
+        note.notebook_access = self.notebook_access(note.notebook.as_deref());
+        self.check_quota(note.owner.as_deref(), Usage::of_note(&note))?;
         self.notes.push(note.clone());
         self.save_notes()?;
+        self.emit(EventKind::Created, &note);
         Ok(note)
     }
 
+    /// Adds notes from a backup as they are. A note that already exists is
+    /// replaced only if the backup's copy was edited more recently. Returns
+    /// how many notes were added and how many replaced.
+    pub fn restore_notes(&mut self, notes: Vec<Note>) -> io::Result<(usize, usize)> {
+        let mut added = Vec::new();
+        let mut replaced = Vec::new();
+        for mut note in notes {
+            note.tag_colors = self.tag_colors.for_tags(&note.tags);
+            note.notebook_access = self.notebook_access(note.notebook.as_deref());
+            match self
+                .notes
+                .iter_mut()
+                .find(|existing| existing.id == note.id)
+            {
+                Some(existing) if existing.updated_at < note.updated_at => {
+                    *existing = note.clone();
+                    replaced.push(note);
+                }
+                Some(_) => {}
+                None => {
+                    self.notes.push(note.clone());
+                    added.push(note);
+                }
+            }
+        }
+        if added.is_empty() && replaced.is_empty() {
+            return Ok((0, 0));
+        }
+        self.save_notes()?;
+        for note in &added {
+            self.emit(EventKind::Created, note);
+        }
+        for note in &replaced {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok((added.len(), replaced.len()))
+    }
+
+    /// Notes that aren't in the trash.
     pub fn list_notes(&self) -> Vec<Note> {
-        self.notes.clone()
+        self.notes
+            .iter()
+            .filter(|note| !note.is_trashed())
+            .cloned()
+            .collect()
+    }
+
+    /// Notes outside the trash that aren't snoozed: what a plain listing shows.
+    pub fn awake_notes(&self) -> Vec<Note> {
+        let now = self.now();
+        self.notes
+            .iter()
+            .filter(|note| !note.is_trashed() && !note.is_snoozed(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Notes matching `filter`, including trashed ones if it asks for them.
+    pub fn notes_in_state(&self, filter: StateFilter) -> Vec<Note> {
+        self.notes
+            .iter()
+            .filter(|note| filter.matches(note, self.now()))
+            .cloned()
+            .collect()
+    }
+
+    /// How many of the notes `visible` allows are in each state.
+    pub fn state_counts(&self, visible: &dyn Fn(&Note) -> bool) -> HashMap<NoteState, usize> {
+        let mut counts: HashMap<NoteState, usize> =
+            [NoteState::Active, NoteState::Archived, NoteState::Trashed]
+                .into_iter()
+                .map(|state| (state, 0))
+                .collect();
+        for note in self.notes.iter().filter(|note| visible(note)) {
+            *counts.entry(note.state()).or_default() += 1;
+        }
+        counts
+    }
+
+    // Position in `self.notes` of the index-th note outside the trash
+    fn listed_position(&self, index: usize) -> Option<usize> {
+        self.notes
+            .iter()
+            .enumerate()
+            .filter(|(_, note)| !note.is_trashed())
+            .nth(index)
+            .map(|(position, _)| position)
     }
 
     pub fn get_note(&self, id: &str) -> Option<Note> {
         self.notes.iter().find(|note| note.id == id).cloned()
     }
 
+    pub fn get_note_by_slug(&self, slug: &str) -> Option<Note> {
+        let slug = slug.trim().to_lowercase();
+        self.notes
+            .iter()
+            .find(|note| note.slug.as_deref() == Some(slug.as_str()))
+            .cloned()
+    }
+
+    /// Gives the note a slug, or takes it away with `None`. A malformed slug
+    /// is `InvalidInput`, one another note has is `AlreadyExists`. The slug
+    /// isn't part of the content: `updated_at` stays as it is.
+    pub fn set_slug(&mut self, id: &str, slug: Option<&str>) -> io::Result<Option<Note>> {
+        let slug = slug
+            .map(Note::parse_slug)
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if let Some(slug) = &slug {
+            let taken = self
+                .notes
+                .iter()
+                .find(|note| note.id != id && note.slug.as_ref() == Some(slug));
+            if let Some(other) = taken {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("The slug '{}' is taken by '{}'", slug, other.title),
+                ));
+            }
+        }
+        self.set_state(id, |note| note.slug = slug)
+    }
+
+    /// Resolves a short link id, the 8 hex digits of [`Note::short_id`], if
+    /// it is unambiguous. Anything shorter is refused: short links are served
+    /// without login and mustn't let anyone walk the vault a digit at a time.
+    pub fn get_note_by_short_id(&self, short_id: &str) -> Option<Note> {
+        if short_id.len() != 8 || !short_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let short_id = short_id.to_lowercase();
+        let mut matches = self.notes.iter().filter(|note| note.short_id() == short_id);
+        match (matches.next(), matches.next()) {
+            (Some(note), None) => Some(note.clone()),
+            _ => None,
+        }
+    }
+
+    /// Index into [`list_notes`](Self::list_notes), as shown by the CLI.
     pub fn get_note_by_index(&self, index: usize) -> Option<&Note> {
-        self.notes.get(index)
+        self.listed_position(index)
+            .map(|position| &self.notes[position])
     }
 
+    /// Notes outside the trash matching `query`; snoozed notes stay hidden.
+    /// `lang:de` in the query keeps only notes in that language, `tag:work`
+    /// only notes tagged `work`, and a query of nothing but filters matches
+    /// every note they allow.
     pub fn search_notes(&self, query: &str) -> Vec<Note> {
-        let query_lower = query.to_lowercase();
+        let query = self.hooks.on_search(query);
+        let (query, text, canonical) = self.prepare_query(&query);
+        let now = self.now();
         self.notes
             .iter()
-            .filter(|note| {
-                note.title.to_lowercase().contains(&query_lower)
-                    || note.content.to_lowercase().contains(&query_lower)
-                    || note
-                        .tags
-                        .iter()
-                        .any(|tag| tag.to_lowercase().contains(&query_lower))
-            })
+            .filter(|note| !note.is_trashed() && !note.is_snoozed(now))
+            .filter(|note| query.allows(note))
+            .filter(|note| self.matches_text(note, &text, canonical.as_deref()))
             .cloned()
             .collect()
     }
 
+    /// Highlighted hits for `query` in `scope`. With more than one part of
+    /// the vault in scope a note turns up once: as it is now if that
+    /// matches, otherwise for its newest past version that does. The
+    /// `lang:` and `tag:` filters apply to the note as it is now.
+    pub fn search_scoped(&self, query: &str, scope: SearchScope) -> Vec<SearchHit> {
+        let query = &self.hooks.on_search(query);
+        let (parsed, text, canonical) = self.prepare_query(query);
+        let now = self.now();
+        let mut hits = Vec::new();
+        for note in self.notes.iter().filter(|note| parsed.allows(note)) {
+            let found_in = if note.is_trashed() {
+                HitScope::Trash
+            } else {
+                HitScope::Active
+            };
+            if scope.includes(found_in)
+                && !(found_in == HitScope::Active && note.is_snoozed(now))
+                && self.matches_text(note, &text, canonical.as_deref())
+            {
+                let mut hit = self.highlight_note(note.clone(), query);
+                hit.scope = found_in;
+                hits.push(hit);
+                continue;
+            }
+            if !scope.includes(HitScope::Revisions) {
+                continue;
+            }
+            let past = note.revisions.iter().rev().find_map(|revision| {
+                let mut past = note.clone();
+                past.title = revision.title.clone();
+                past.content = revision.content.clone();
+                past.tags = revision.tags.clone();
+                past.attachments.clear();
+                self.matches_text(&past, &text, canonical.as_deref())
+                    .then_some((past, revision.saved_at))
+            });
+            if let Some((past, saved_at)) = past {
+                let highlights = self.highlight_note(past, query).highlights;
+                let mut hit = SearchHit::new(note.clone(), highlights);
+                hit.scope = HitScope::Revisions;
+                hit.revision_saved_at = Some(saved_at);
+                hits.push(hit);
+            }
+        }
+        hits
+    }
+
+    // The query with its tag filters made canonical, its words folded, and
+    // the tag they name if they're an alias
+    fn prepare_query(&self, query: &str) -> (SearchQuery, String, Option<String>) {
+        let mut query = SearchQuery::parse(query);
+        for tag in query.tags.iter_mut() {
+            *tag = self.tag_rules.canonical(tag);
+        }
+        // A query naming an alias also finds the tag it stands for
+        let canonical = Some(self.tag_rules.canonical(&query.text))
+            .filter(|tag| !tag.eq_ignore_ascii_case(query.text.trim()));
+        let text = self.normalizer.fold(&query.text);
+        (query, text, canonical)
+    }
+
+    fn matches_text(&self, note: &Note, text: &str, canonical: Option<&str>) -> bool {
+        let language = note.language.as_deref();
+        let matches = |field: &str| self.normalizer.matches(field, text, language);
+        text.is_empty()
+            || matches(&note.title)
+            || matches(&note.content)
+            || note.tags.iter().any(|tag| matches(tag))
+            || note
+                .attachments
+                .iter()
+                .filter_map(|a| a.transcript.as_deref())
+                .any(matches)
+            || canonical.is_some_and(|canonical| tags::has_tag(note, canonical))
+    }
+
+    /// Attaches word-level match highlights for `query` to a note.
+    pub fn highlight_note(&self, note: Note, query: &str) -> SearchHit {
+        let query = SearchQuery::parse(query);
+        let highlights = if query.text.is_empty() {
+            Vec::new()
+        } else {
+            self.normalizer
+                .highlights(&note, &self.normalizer.fold(&query.text))
+        };
+        SearchHit::new(note, highlights)
+    }
+
     pub fn delete_note(&mut self, id: &str) -> io::Result<bool> {
-        let initial_len = self.notes.len();
-        self.notes.retain(|note| note.id != id);
-        let removed = self.notes.len() < initial_len;
-        if removed {
+        match self.notes.iter().position(|note| note.id == id) {
+            Some(index) => {
+                self.hooks.on_delete(&self.notes[index], true)?;
+                let note = self.notes.remove(index);
+                self.detach_children(&note);
+                self.save_notes()?;
+                self.remove_attachment_files(&note);
+                self.emit(EventKind::Deleted, &note);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Permanently deletes every note in the trash. Returns how many there were.
+    pub fn empty_trash(&mut self) -> io::Result<usize> {
+        let trashed: Vec<String> = self
+            .notes
+            .iter()
+            .filter(|note| note.is_trashed())
+            .map(|note| note.id.clone())
+            .collect();
+        self.delete_notes(&trashed)
+    }
+
+    /// Permanently deletes the notes `ids` with a single save. Returns how
+    /// many of them existed.
+    pub fn delete_notes(&mut self, ids: &[String]) -> io::Result<usize> {
+        for note in self.notes.iter().filter(|note| ids.contains(&note.id)) {
+            self.hooks.on_delete(note, true)?;
+        }
+        // One at a time, so sub-notes of deleted sub-notes end up with a parent that's kept
+        let mut deleted = Vec::new();
+        for id in ids {
+            if let Some(index) = self.notes.iter().position(|note| note.id == *id) {
+                let note = self.notes.remove(index);
+                self.detach_children(&note);
+                deleted.push(note);
+            }
+        }
+        if deleted.is_empty() {
+            return Ok(0);
+        }
+        self.save_notes()?;
+        for note in &deleted {
+            self.remove_attachment_files(note);
+            self.emit(EventKind::Deleted, note);
+        }
+        self.emit_bulk(
+            EventKind::BulkDeleted,
+            &deleted,
+            format!("Deleted {} notes", deleted.len()),
+        );
+        Ok(deleted.len())
+    }
+
+    /// Moves the notes `ids` to the trash with a single save. Returns how
+    /// many were moved; notes already there are left as they are.
+    pub fn trash_notes(&mut self, ids: &[String]) -> io::Result<usize> {
+        for note in self.notes.iter() {
+            if !note.is_trashed() && ids.contains(&note.id) {
+                self.hooks.on_delete(note, false)?;
+            }
+        }
+        let now = self.now();
+        let mut moved = Vec::new();
+        for note in self.notes.iter_mut() {
+            if !note.is_trashed() && ids.contains(&note.id) {
+                note.trashed_at = Some(now);
+                moved.push(note.clone());
+            }
+        }
+        if moved.is_empty() {
+            return Ok(0);
+        }
+        self.save_notes()?;
+        for note in &moved {
+            self.emit(EventKind::Trashed, note);
+        }
+        self.emit_bulk(
+            EventKind::BulkTrashed,
+            &moved,
+            format!("Moved {} notes to the trash", moved.len()),
+        );
+        Ok(moved.len())
+    }
+
+    /// Merges the notes `ids` (at least two) into the first; see
+    /// [`merge::merge`]. Sub-notes of the others move to the merged note.
+    /// Returns `None` if one of the notes doesn't exist.
+    pub fn merge_notes(
+        &mut self,
+        ids: &[String],
+        strategy: MergeStrategy,
+    ) -> io::Result<Option<Note>> {
+        let mut unique: Vec<&String> = Vec::new();
+        for id in ids {
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        if unique.len() < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Give at least two different notes to merge",
+            ));
+        }
+
+        let mut notes = Vec::new();
+        for id in &unique {
+            let Some(note) = self.get_note(id) else {
+                return Ok(None);
+            };
+            if note.is_encrypted() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Decrypt encrypted notes before merging them",
+                ));
+            }
+            notes.push(note);
+        }
+        let others: Vec<Note> = notes[1..].to_vec();
+
+        let mut merged = merge::merge(notes, strategy, self.now());
+        merged.tags = self.tag_rules.apply(merged.tags);
+        merged.tag_colors = self.tag_colors.for_tags(&merged.tags);
+        if merged
+            .parent_id
+            .as_ref()
+            .is_some_and(|parent| unique.contains(&parent))
+        {
+            merged.parent_id = None;
+        }
+        self.notes
+            .retain(|note| !others.iter().any(|other| other.id == note.id));
+        for note in self.notes.iter_mut() {
+            if note.id == merged.id {
+                *note = merged.clone();
+            } else if note
+                .parent_id
+                .as_ref()
+                .is_some_and(|parent| unique.contains(&parent))
+            {
+                note.parent_id = Some(merged.id.clone());
+            }
+        }
+        self.save_notes()?;
+
+        for other in &others {
+            self.emit(EventKind::Deleted, other);
+        }
+        self.emit(EventKind::Updated, &merged);
+        Ok(Some(merged))
+    }
+
+    /// The note that `id` was merged into, if any.
+    pub fn merged_into(&self, id: &str) -> Option<&Note> {
+        self.notes
+            .iter()
+            .find(|note| note.merged_from.iter().any(|merged| merged == id))
+    }
+
+    /// Makes `id` a sub-note of `parent_id`, or a top-level note when `None`.
+    /// Returns `None` if the note doesn't exist.
+    pub fn set_parent(&mut self, id: &str, parent_id: Option<String>) -> io::Result<Option<Note>> {
+        if let Some(parent_id) = &parent_id {
+            if self.get_note(parent_id).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Parent note not found",
+                ));
+            }
+            // Walk up from the new parent; meeting `id` means we'd create a cycle
+            let mut ancestor = Some(parent_id.clone());
+            let mut seen = HashSet::new();
+            while let Some(current) = ancestor {
+                if current == id {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "A note can't be nested under itself or its own sub-notes",
+                    ));
+                }
+                if !seen.insert(current.clone()) {
+                    break;
+                }
+                ancestor = self.get_note(&current).and_then(|note| note.parent_id);
+            }
+        }
+
+        self.set_state(id, |note| note.parent_id = parent_id)
+    }
+
+    /// Direct sub-notes outside the trash, oldest first.
+    pub fn children(&self, id: &str) -> Vec<Note> {
+        let mut children: Vec<Note> = self
+            .notes
+            .iter()
+            .filter(|note| !note.is_trashed() && note.parent_id.as_deref() == Some(id))
+            .cloned()
+            .collect();
+        children.sort_by_key(|note| note.created_at);
+        children
+    }
+
+    /// The note and all its sub-notes for which `visible` holds.
+    pub fn thread(&self, id: &str, visible: &dyn Fn(&Note) -> bool) -> Option<ThreadNode> {
+        let note = self.get_note(id).filter(|note| visible(note))?;
+        Some(self.thread_node(note, visible, &mut HashSet::new()))
+    }
+
+    fn thread_node(
+        &self,
+        note: Note,
+        visible: &dyn Fn(&Note) -> bool,
+        seen: &mut HashSet<String>,
+    ) -> ThreadNode {
+        seen.insert(note.id.clone());
+        let mut children = Vec::new();
+        for child in self.children(&note.id) {
+            if visible(&child) && !seen.contains(&child.id) {
+                children.push(self.thread_node(child, visible, seen));
+            }
+        }
+        ThreadNode { note, children }
+    }
+
+    // Sub-notes of a deleted note move up to its parent
+    fn detach_children(&mut self, deleted: &Note) {
+        for note in self.notes.iter_mut() {
+            if note.parent_id.as_deref() == Some(deleted.id.as_str()) {
+                note.parent_id = deleted.parent_id.clone();
+            }
+        }
+    }
+
+    /// Moves a note to the trash or restores it. Returns `None` if the note doesn't exist.
+    pub fn set_trashed(&mut self, id: &str, trashed: bool) -> io::Result<Option<Note>> {
+        let was_trashed = self.get_note(id).is_some_and(|note| note.is_trashed());
+        if let Some(note) = self.notes.iter().find(|note| note.id == id) {
+            if trashed && !was_trashed {
+                self.hooks.on_delete(note, false)?;
+            }
+        }
+        let now = self.now();
+        let note = self.set_state(id, |note| {
+            note.trashed_at = trashed.then_some(now);
+        })?;
+        if let Some(note) = note.as_ref().filter(|_| was_trashed != trashed) {
+            let kind = if trashed {
+                EventKind::Trashed
+            } else {
+                EventKind::Restored
+            };
+            self.emit(kind, note);
+        }
+        Ok(note)
+    }
+
+    pub fn set_archived(&mut self, id: &str, archived: bool) -> io::Result<Option<Note>> {
+        let was_archived = self.get_note(id).is_some_and(|note| note.is_archived());
+        let now = self.now();
+        let note = self.set_state(id, |note| {
+            note.archived_at = archived.then_some(now);
+        })?;
+        if let Some(note) = note.as_ref().filter(|_| was_archived != archived) {
+            let kind = if archived {
+                EventKind::Archived
+            } else {
+                EventKind::Unarchived
+            };
+            self.emit(kind, note);
+        }
+        Ok(note)
+    }
+
+    /// Snoozes a note until `until`, or wakes it up when `None`.
+    pub fn set_snoozed(
+        &mut self,
+        id: &str,
+        until: Option<DateTime<Utc>>,
+    ) -> io::Result<Option<Note>> {
+        self.set_state(id, |note| note.snoozed_until = until)
+    }
+
+    /// Pins a note to the top of the list filtered by `tag`, or unpins it.
+    /// Pinning needs the note to have the tag or one nested under it.
+    pub fn set_pinned_in(&mut self, id: &str, tag: &str, pinned: bool) -> io::Result<Option<Note>> {
+        let tag = self.tag_rules.canonical(tag);
+        let tag = tag.trim_matches(tags::SEPARATOR).to_string();
+        let Some(note) = self.get_note(id) else {
+            return Ok(None);
+        };
+        if pinned && !tags::has_tag(&note, &tag) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The note isn't tagged '{}'", tag),
+            ));
+        }
+        // Spelled like the note's own tag where there is one, as the tag tree is
+        let tag = note
+            .tags
+            .iter()
+            .find(|t| t.eq_ignore_ascii_case(&tag))
+            .cloned()
+            .unwrap_or(tag);
+        self.set_state(id, |note| {
+            note.pinned_in.retain(|pin| !pin.eq_ignore_ascii_case(&tag));
+            if pinned {
+                note.pinned_in.push(tag);
+            }
+        })
+    }
+
+    /// Lets the user read the note without owning it, or stops letting them.
+    pub fn set_shared_with(
+        &mut self,
+        id: &str,
+        user_id: &str,
+        shared: bool,
+    ) -> io::Result<Option<Note>> {
+        let Some(note) = self.get_note(id) else {
+            return Ok(None);
+        };
+        if shared && note.owner.as_deref() == Some(user_id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The note already belongs to that user",
+            ));
+        }
+        self.set_state(id, |note| {
+            note.shared_with.retain(|shared_id| shared_id != user_id);
+            if shared {
+                note.shared_with.push(user_id.to_string());
+            }
+        })
+    }
+
+    /// Offers the note to the user `to`, who takes it over by accepting
+    /// ([`NotesManager::transfer_note`]). A newer offer replaces an older one.
+    pub fn request_transfer(
+        &mut self,
+        id: &str,
+        to: &str,
+        requested_by: Option<String>,
+    ) -> io::Result<Option<Note>> {
+        let Some(note) = self.get_note(id) else {
+            return Ok(None);
+        };
+        if note.owner.as_deref() == Some(to) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The note already belongs to that user",
+            ));
+        }
+        let transfer = Transfer {
+            to: to.to_string(),
+            requested_by,
+            requested_at: self.now(),
+        };
+        self.set_state(id, |note| note.pending_transfer = Some(transfer))
+    }
+
+    /// Withdraws or declines the note's pending transfer.
+    pub fn cancel_transfer(&mut self, id: &str) -> io::Result<Option<Note>> {
+        self.set_state(id, |note| note.pending_transfer = None)
+    }
+
+    /// Makes `to` the note's owner. The previous owner keeps no access
+    /// unless the note is shared with them.
+    pub fn transfer_note(&mut self, id: &str, to: &str) -> io::Result<Option<Note>> {
+        let note = self.set_state(id, |note| {
+            note.owner = Some(to.to_string());
+            note.pending_transfer = None;
+            note.shared_with.retain(|shared_id| shared_id != to);
+        })?;
+        if let Some(note) = &note {
+            self.emit(EventKind::Transferred, note);
+        }
+        Ok(note)
+    }
+
+    // Lifecycle changes leave `updated_at` alone so "untouched for" rules keep working
+    fn set_state(&mut self, id: &str, change: impl FnOnce(&mut Note)) -> io::Result<Option<Note>> {
+        let Some(note) = self.notes.iter_mut().find(|note| note.id == id) else {
+            return Ok(None);
+        };
+        change(note);
+        let note = note.clone();
+        self.save_notes()?;
+        Ok(Some(note))
+    }
+
+    /// Directory holding attachment files, next to the data file.
+    pub fn attachments_dir(&self) -> PathBuf {
+        self.storage_path()
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("attachments")
+    }
+
+    /// File holding an attachment's bytes, by [`Attachment::file_key`].
+    pub fn attachment_path(&self, file_key: &str) -> PathBuf {
+        self.attachments_dir().join(file_key)
+    }
+
+    /// Where the cached preview of an image attachment lives.
+    pub fn thumbnail_path(&self, file_key: &str, size: ThumbSize) -> PathBuf {
+        self.attachments_dir()
+            .join("thumbs")
+            .join(format!("{}-{}.png", file_key, size.name()))
+    }
+
+    /// How many attachments, across all notes, share the file `file_key`.
+    pub fn attachment_references(&self, file_key: &str) -> usize {
+        self.notes
+            .iter()
+            .flat_map(|note| &note.attachments)
+            .filter(|attachment| attachment.file_key() == file_key)
+            .count()
+    }
+
+    /// Stores `bytes` as a new attachment of the note. Bytes already stored
+    /// for another attachment aren't written again; both share the file.
+    /// Returns `None` if the note doesn't exist.
+    pub fn add_attachment(
+        &mut self,
+        note_id: &str,
+        filename: String,
+        content_type: String,
+        bytes: &[u8],
+    ) -> io::Result<Option<Attachment>> {
+        let Some(index) = self.notes.iter().position(|note| note.id == note_id) else {
+            return Ok(None);
+        };
+        let growth = Usage {
+            attachment_bytes: bytes.len() as u64,
+            ..Usage::default()
+        };
+        self.check_quota(self.notes[index].owner.as_deref(), growth)?;
+
+        let mut attachment = Attachment::new(filename, content_type, bytes);
+        attachment.created_at = self.now();
+        let path = self.attachment_path(attachment.file_key());
+        if !path.exists() {
+            fs::create_dir_all(self.attachments_dir())?;
+            fs::write(path, bytes)?;
+        }
+
+        let now = self.now();
+        self.notes[index].attachments.push(attachment.clone());
+        self.notes[index].update_at(None, None, None, now);
+        self.save_notes()?;
+        Ok(Some(attachment))
+    }
+
+    /// Finds an attachment together with the note it belongs to.
+    pub fn find_attachment(&self, attachment_id: &str) -> Option<(Note, Attachment)> {
+        self.notes.iter().find_map(|note| {
+            note.attachments
+                .iter()
+                .find(|a| a.id == attachment_id)
+                .map(|a| (note.clone(), a.clone()))
+        })
+    }
+
+    pub fn set_transcript(
+        &mut self,
+        attachment_id: &str,
+        transcript: Option<String>,
+    ) -> io::Result<Option<Attachment>> {
+        let mut found = None;
+        let now = self.now();
+        for note in self.notes.iter_mut() {
+            if let Some(attachment) = note.attachments.iter_mut().find(|a| a.id == attachment_id) {
+                attachment.transcript = transcript;
+                found = Some(attachment.clone());
+                note.update_at(None, None, None, now);
+                break;
+            }
+        }
+
+        if found.is_some() {
             self.save_notes()?;
         }
-        Ok(removed)
+        Ok(found)
+    }
+
+    // Drops the files of a removed note's attachments unless another note
+    // still uses them. Best effort: a leftover blob is harmless, a failed
+    // delete shouldn't fail the request
+    fn remove_attachment_files(&self, note: &Note) {
+        for attachment in &note.attachments {
+            let key = attachment.file_key();
+            if self.attachment_references(key) > 0 {
+                continue;
+            }
+            let _ = fs::remove_file(self.attachment_path(key));
+            for size in ThumbSize::ALL {
+                let _ = fs::remove_file(self.thumbnail_path(key, size));
+            }
+        }
     }
 
     pub fn update_note(
@@ -110,30 +1525,735 @@ impl NotesManager {
         content: Option<String>,
         tags: Option<Vec<String>>,
     ) -> io::Result<Option<Note>> {
+        match self.prepare_update(id, title, content, tags)? {
+            Some(pending) => self.update_processed(pending.run()?),
+            None => Ok(None),
+        }
+    }
+
+    /// Applies an edit to a copy of the note and runs the update scripts on
+    /// it; the pre-save processors are left to [`PendingSave::run`], then
+    /// [`Self::update_processed`] saves it. `None` if there's no such note.
+    pub fn prepare_update(
+        &mut self,
+        id: &str,
+        title: Option<String>,
+        content: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> io::Result<Option<PendingSave>> {
         // Find index first
         if let Some(index) = self.notes.iter().position(|note| note.id == id) {
-            // Update the note
-            self.notes[index].update(title, content, tags);
-            let updated_note = self.notes[index].clone();
-            self.save_notes()?;
-            Ok(Some(updated_note))
+            if content.is_some() && self.notes[index].is_encrypted() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Note is encrypted; send its passphrase to change the content",
+                ));
+            }
+            // Update a copy, put in place once it fits the quota
+            let current = &self.notes[index];
+            let tags = match &content {
+                Some(content) if self.tag_rules.extracts_hashtags() => {
+                    Some(self.tag_rules.sync_hashtags(
+                        tags.unwrap_or_else(|| current.tags.clone()),
+                        &current.content,
+                        content,
+                    ))
+                }
+                _ => tags,
+            };
+            let tags = tags.map(|tags| self.tag_rules.apply(tags));
+            let now = self.now();
+            let mut updated_note = self.notes[index].clone();
+            updated_note.update_at(title, content, tags, now);
+            let scripted = self
+                .hooks
+                .on_update(&mut updated_note, &self.notes[index])?;
+            Ok(Some(PendingSave::new(
+                updated_note,
+                EventKind::Updated,
+                Some(self.notes[index].updated_at),
+                scripted,
+                &self.processors,
+            )))
         } else {
             Ok(None)
         }
     }
 
+    /// Whether the note a prepared edit is for changed, or went, since.
+    pub fn is_stale(&self, pending: &PendingSave) -> bool {
+        pending.base.is_some()
+            && self.get_note(&pending.note.id).map(|note| note.updated_at) != pending.base
+    }
+
+    /// Saves a prepared edit over the note as it is now; callers that let
+    /// go of the manager while the processors ran check [`Self::is_stale`]
+    /// first. `None` if the note is gone.
+    pub fn update_processed(&mut self, pending: PendingSave) -> io::Result<Option<Note>> {
+        let Some(index) = self
+            .notes
+            .iter()
+            .position(|note| note.id == pending.note.id)
+        else {
+            return Ok(None);
+        };
+        let mut updated_note = pending.note;
+        if pending.changed {
+            updated_note.tags = self.tag_rules.apply(std::mem::take(&mut updated_note.tags));
+        }
+        updated_note.tag_colors = self.tag_colors.for_tags(&updated_note.tags);
+        let growth = Usage::of_note(&self.notes[index]).growth_to(Usage::of_note(&updated_note));
+        self.check_quota(updated_note.owner.as_deref(), growth)?;
+        self.notes[index] = updated_note.clone();
+        self.save_notes()?;
+        self.emit(EventKind::Updated, &updated_note);
+        Ok(Some(updated_note))
+    }
+
+    /// Saves an edit made to an outdated version of a note as a separate
+    /// note tagged `conflict`, leaving the note itself as it is.
+    #[allow(dead_code)] // for tests and apps embedding the crate
+    pub fn add_conflict_copy(
+        &mut self,
+        id: &str,
+        title: Option<String>,
+        content: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> io::Result<Option<Note>> {
+        match self.prepare_conflict_copy(id, title, content, tags)? {
+            Some(pending) => self.insert_processed(pending.run()?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// [`Self::add_conflict_copy`] up to the pre-save processors, like
+    /// [`Self::prepare_insert`].
+    pub fn prepare_conflict_copy(
+        &mut self,
+        id: &str,
+        title: Option<String>,
+        content: Option<String>,
+        tags: Option<Vec<String>>,
+    ) -> io::Result<Option<PendingSave>> {
+        let Some(original) = self.get_note(id) else {
+            return Ok(None);
+        };
+        let copy = self.conflict_copy(
+            &original,
+            title.unwrap_or_else(|| original.title.clone()),
+            content.unwrap_or_else(|| original.content.clone()),
+            tags.unwrap_or_else(|| original.tags.clone()),
+        );
+        self.prepare_insert(copy).map(Some)
+    }
+
+    // "<title> (conflict 2024-06-01 14:02)", owned and placed like `original`
+    fn conflict_copy(
+        &self,
+        original: &Note,
+        title: String,
+        content: String,
+        mut tags: Vec<String>,
+    ) -> Note {
+        if !tags.iter().any(|tag| tag == CONFLICT_TAG) {
+            tags.push(CONFLICT_TAG.to_string());
+        }
+        let now = self.now();
+        let stamp = now.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        let title = format!("{} (conflict {})", without_conflict_suffix(&title), stamp);
+        let mut copy = Note::new_at(title, content, tags, now);
+        copy.owner = original.owner.clone();
+        copy.shared_with = original.shared_with.clone();
+        copy.parent_id = original.parent_id.clone();
+        copy.conflict_of = Some(original.id.clone());
+        copy
+    }
+
+    /// Conflict copies that haven't been resolved yet, newest first.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = self
+            .notes
+            .iter()
+            .filter(|copy| !copy.is_trashed())
+            .filter_map(|copy| {
+                let original = self.get_note(copy.conflict_of.as_deref()?);
+                Some(Conflict {
+                    copy: copy.clone(),
+                    original,
+                })
+            })
+            .collect();
+        conflicts.sort_by_key(|conflict| std::cmp::Reverse(conflict.copy.created_at));
+        conflicts
+    }
+
+    /// Settles the conflict copy `copy_id`. Unless both are kept, the copy
+    /// goes to the trash once the note has the chosen version. Returns the
+    /// note that remains (the copy itself with `KeepBoth`), or `None` if
+    /// `copy_id` isn't a conflict copy.
+    pub fn resolve_conflict(
+        &mut self,
+        copy_id: &str,
+        resolution: Resolution,
+    ) -> io::Result<Option<Note>> {
+        let Some(copy) = self.get_note(copy_id).filter(|n| n.conflict_of.is_some()) else {
+            return Ok(None);
+        };
+        let original_id = copy.conflict_of.clone().unwrap_or_default();
+        if resolution != Resolution::KeepBoth && self.get_note(&original_id).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "The note this copy conflicts with no longer exists; keep both instead",
+            ));
+        }
+        let title = without_conflict_suffix(&copy.title).to_string();
+        let tags: Vec<String> = copy
+            .tags
+            .iter()
+            .filter(|tag| *tag != CONFLICT_TAG)
+            .cloned()
+            .collect();
+
+        self.batch(|mgr| {
+            let kept = match resolution {
+                Resolution::KeepBoth => {
+                    mgr.update_note(copy_id, Some(title), None, Some(tags))?;
+                    return mgr.set_state(copy_id, |note| note.conflict_of = None);
+                }
+                Resolution::KeepOriginal => mgr.get_note(&original_id),
+                Resolution::KeepCopy => {
+                    mgr.update_note(&original_id, Some(title), Some(copy.content), Some(tags))?
+                }
+                Resolution::Merged(content) => {
+                    mgr.update_note(&original_id, None, Some(content), None)?
+                }
+            };
+            mgr.set_trashed(copy_id, true)?;
+            Ok(kept)
+        })
+    }
+
+    /// Like [`NotesManager::update_note`] for an encrypted note: new content
+    /// is encrypted again with `passphrase`, which must be the note's.
+    pub fn update_encrypted_note(
+        &mut self,
+        id: &str,
+        title: Option<String>,
+        content: Option<String>,
+        tags: Option<Vec<String>>,
+        passphrase: &str,
+    ) -> io::Result<Option<Note>> {
+        let Some(sealed) = self.get_note(id).map(|note| note.encrypted) else {
+            return Ok(None);
+        };
+        let Some(sealed) = sealed else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Note is not encrypted",
+            ));
+        };
+        crypto::open(&sealed, passphrase)?;
+        let resealed = content
+            .map(|content| crypto::seal(&content, passphrase))
+            .transpose()?;
+
+        let tags = tags.map(|tags| self.tag_rules.apply(tags));
+        let tag_colors = tags.as_ref().map(|tags| self.tag_colors.for_tags(tags));
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.update_at(title, None, tags, now);
+            if let Some(tag_colors) = tag_colors {
+                note.tag_colors = tag_colors;
+            }
+            if let Some(sealed) = resealed {
+                note.encrypted = Some(sealed);
+            }
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// Encrypts a note's content with `passphrase`. Its revision history,
+    /// which holds earlier plaintext, is dropped.
+    pub fn encrypt_note(&mut self, id: &str, passphrase: &str) -> io::Result<Option<Note>> {
+        let Some(note) = self.get_note(id) else {
+            return Ok(None);
+        };
+        if note.is_encrypted() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Note is already encrypted",
+            ));
+        }
+        let sealed = crypto::seal(&note.content, passphrase)?;
+
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.encrypted = Some(sealed);
+            note.content = crypto::LOCKED_PLACEHOLDER.to_string();
+            note.revisions.clear();
+            note.analyze();
+            note.updated_at = now;
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// An encrypted note with its content decrypted, for reading. The
+    /// stored note stays encrypted.
+    pub fn unlock_note(&self, id: &str, passphrase: &str) -> io::Result<Option<Note>> {
+        let Some(mut note) = self.get_note(id) else {
+            return Ok(None);
+        };
+        let Some(sealed) = &note.encrypted else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Note is not encrypted",
+            ));
+        };
+        note.content = crypto::open(sealed, passphrase)?;
+        Ok(Some(note))
+    }
+
+    /// Decrypts a note for good.
+    pub fn decrypt_note(&mut self, id: &str, passphrase: &str) -> io::Result<Option<Note>> {
+        let Some(unlocked) = self.unlock_note(id, passphrase)? else {
+            return Ok(None);
+        };
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.encrypted = None;
+            note.content = unlocked.content;
+            note.analyze();
+            note.updated_at = now;
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// Adds and removes tags on every note matching `query` that `visible`
+    /// allows. Returns how many notes matched and how many of them changed.
+    pub fn bulk_tag(
+        &mut self,
+        query: &str,
+        add: &[String],
+        remove: &[String],
+        visible: &dyn Fn(&Note) -> bool,
+    ) -> io::Result<(usize, usize)> {
+        let matched: HashSet<String> = self
+            .search_notes(query)
+            .into_iter()
+            .filter(|note| visible(note))
+            .map(|note| note.id)
+            .collect();
+
+        let remove: Vec<String> = remove
+            .iter()
+            .map(|tag| self.tag_rules.canonical(tag))
+            .collect();
+        let mut changed = Vec::new();
+        let now = self.now();
+        for note in self
+            .notes
+            .iter_mut()
+            .filter(|note| matched.contains(&note.id))
+        {
+            let mut tags: Vec<String> = note
+                .tags
+                .iter()
+                .filter(|tag| !remove.iter().any(|r| r.eq_ignore_ascii_case(tag)))
+                .cloned()
+                .collect();
+            for tag in add {
+                if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                    tags.push(tag.clone());
+                }
+            }
+            let tags = self.tag_rules.apply(tags);
+            if tags != note.tags {
+                note.update_at(None, None, Some(tags), now);
+                note.tag_colors = self.tag_colors.for_tags(&note.tags);
+                changed.push(note.clone());
+            }
+        }
+
+        if !changed.is_empty() {
+            self.save_notes()?;
+        }
+        for note in &changed {
+            self.emit(EventKind::Updated, note);
+        }
+        if !changed.is_empty() {
+            let mut summary = Vec::new();
+            if !add.is_empty() {
+                summary.push(format!("added {}", add.join(", ")));
+            }
+            if !remove.is_empty() {
+                summary.push(format!("removed {}", remove.join(", ")));
+            }
+            self.emit_bulk(
+                EventKind::Retagged,
+                &changed,
+                format!("Tags {} on {} notes", summary.join("; "), changed.len()),
+            );
+        }
+        Ok((matched.len(), changed.len()))
+    }
+
+    /// Renames a tag and every tag nested under it on the notes `visible`
+    /// allows, returning how many notes changed.
+    pub fn rename_tag(
+        &mut self,
+        from: &str,
+        to: &str,
+        visible: &dyn Fn(&Note) -> bool,
+    ) -> io::Result<usize> {
+        if to.trim_matches(tags::SEPARATOR).is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The new tag name can't be empty",
+            ));
+        }
+
+        let mut changed = Vec::new();
+        let now = self.now();
+        for note in self.notes.iter_mut().filter(|note| visible(note)) {
+            if !tags::has_tag(note, from) {
+                continue;
+            }
+            let mut renamed: Vec<String> = Vec::new();
+            for tag in &note.tags {
+                let tag = tags::renamed(tag, from, to).unwrap_or_else(|| tag.clone());
+                if !renamed.contains(&tag) {
+                    renamed.push(tag);
+                }
+            }
+            for pin in note.pinned_in.iter_mut() {
+                if let Some(moved) = tags::renamed(pin, from, to) {
+                    *pin = moved;
+                }
+            }
+            note.update_at(None, None, Some(self.tag_rules.apply(renamed)), now);
+            note.tag_colors = self.tag_colors.for_tags(&note.tags);
+            changed.push(note.clone());
+        }
+
+        if !changed.is_empty() {
+            self.save_notes()?;
+        }
+        for note in &changed {
+            self.emit(EventKind::Updated, note);
+        }
+        if !changed.is_empty() {
+            self.emit_bulk(
+                EventKind::TagRenamed,
+                &changed,
+                format!("Renamed tag '{}' to '{}'", from, to),
+            );
+        }
+        Ok(changed.len())
+    }
+
+    /// Sets or clears where a note was written. Returns `None` if the note
+    /// doesn't exist.
+    pub fn set_location(
+        &mut self,
+        id: &str,
+        location: Option<GeoPoint>,
+    ) -> io::Result<Option<Note>> {
+        if let Some(point) = &location {
+            point
+                .validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        }
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.location = location;
+            note.updated_at = now;
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// Sets or clears the note's due date.
+    pub fn set_due(&mut self, id: &str, due: Option<DateTime<Utc>>) -> io::Result<Option<Note>> {
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.due_at = due;
+            note.updated_at = now;
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// Replaces the note's metadata fields.
+    pub fn set_metadata(
+        &mut self,
+        id: &str,
+        metadata: BTreeMap<String, Value>,
+    ) -> io::Result<Option<Note>> {
+        let now = self.now();
+        let updated = self.set_state(id, |note| {
+            note.metadata = metadata;
+            note.updated_at = now;
+        })?;
+        if let Some(note) = &updated {
+            self.emit(EventKind::Updated, note);
+        }
+        Ok(updated)
+    }
+
+    /// Adds fields to the note's metadata, e.g. a summary a service made of
+    /// it. Annotating isn't editing: `updated_at` stays as it is and no
+    /// event goes out.
+    pub fn annotate(
+        &mut self,
+        id: &str,
+        metadata: BTreeMap<String, Value>,
+    ) -> io::Result<Option<Note>> {
+        self.set_state(id, |note| note.metadata.extend(metadata))
+    }
+
+    /// Counts a view of the note. Like reading, viewing leaves `updated_at`
+    /// alone and isn't announced as an event; nor is it a change to the
+    /// notes: the count is kept in memory until [`flush_views`](Self::flush_views).
+    pub fn record_view(&mut self, id: &str) -> Option<Note> {
+        let now = self.now();
+        let note = self.notes.iter_mut().find(|note| note.id == id)?;
+        self.views.record(note, now);
+        Some(note.clone())
+    }
+
+    /// Writes the views counted since the last flush to their own file.
+    pub fn flush_views(&mut self) -> io::Result<()> {
+        self.views.flush(&self.notes)
+    }
+
+    /// Leaves a comment on the note. Returns `None` if the note doesn't exist;
+    /// an empty or overlong comment is `InvalidInput`.
+    pub fn add_comment(
+        &mut self,
+        note_id: &str,
+        author: Option<String>,
+        author_name: Option<String>,
+        body: &str,
+    ) -> io::Result<Option<Comment>> {
+        let comment = Comment::new(author, author_name, body, self.now())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let Some(note) = self.set_state(note_id, |note| note.comments.push(comment.clone()))?
+        else {
+            return Ok(None);
+        };
+        self.emit(EventKind::Commented, &note);
+        Ok(Some(comment))
+    }
+
+    /// Takes a comment off the note and returns it, or `None` if there's no
+    /// such comment on it.
+    pub fn delete_comment(
+        &mut self,
+        note_id: &str,
+        comment_id: &str,
+    ) -> io::Result<Option<Comment>> {
+        let Some(note) = self.notes.iter_mut().find(|note| note.id == note_id) else {
+            return Ok(None);
+        };
+        let Some(position) = note.comments.iter().position(|c| c.id == comment_id) else {
+            return Ok(None);
+        };
+        let comment = note.comments.remove(position);
+        self.save_notes()?;
+        Ok(Some(comment))
+    }
+
+    /// Puts `emoji` on the note for `user`, unless they already did. Returns
+    /// the note and whether the reaction is new, or `None` if there's no such
+    /// note; something that isn't an emoji is `InvalidInput`.
+    pub fn add_reaction(
+        &mut self,
+        note_id: &str,
+        user: Option<String>,
+        emoji: &str,
+    ) -> io::Result<Option<(Note, bool)>> {
+        let emoji = Reaction::parse_emoji(emoji)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let Some(note) = self.get_note(note_id) else {
+            return Ok(None);
+        };
+        if note
+            .reactions
+            .iter()
+            .any(|r| r.emoji == emoji && r.user == user)
+        {
+            return Ok(Some((note, false)));
+        }
+        let reaction = Reaction {
+            emoji,
+            user,
+            created_at: self.now(),
+        };
+        let note = self.set_state(note_id, |note| note.reactions.push(reaction))?;
+        Ok(note.map(|note| {
+            self.emit(EventKind::Reacted, &note);
+            (note, true)
+        }))
+    }
+
+    /// Takes `user`'s `emoji` off the note. Returns the note and whether
+    /// there was such a reaction, or `None` if there's no such note.
+    pub fn remove_reaction(
+        &mut self,
+        note_id: &str,
+        user: Option<&str>,
+        emoji: &str,
+    ) -> io::Result<Option<(Note, bool)>> {
+        let emoji = emoji.trim();
+        let Some(note) = self.get_note(note_id) else {
+            return Ok(None);
+        };
+        let mine = |r: &Reaction| r.emoji == emoji && r.user.as_deref() == user;
+        if !note.reactions.iter().any(mine) {
+            return Ok(Some((note, false)));
+        }
+        let note = self.set_state(note_id, |note| note.reactions.retain(|r| !mine(r)))?;
+        Ok(note.map(|note| {
+            self.emit(EventKind::Reacted, &note);
+            (note, true)
+        }))
+    }
+
     pub fn delete_note_by_index(&mut self, index: usize) -> io::Result<()> {
-        if index < self.notes.len() {
-            self.notes.remove(index);
-            self.save_notes()
+        if let Some(position) = self.listed_position(index) {
+            self.hooks.on_delete(&self.notes[position], true)?;
+            let note = self.notes.remove(position);
+            self.detach_children(&note);
+            self.save_notes()?;
+            self.remove_attachment_files(&note);
+            self.emit(EventKind::Deleted, &note);
+            Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid index"))
         }
     }
 
     pub fn notes_count(&self) -> usize {
-        self.notes.len()
+        self.notes.iter().filter(|note| !note.is_trashed()).count()
     }
 }
 
 pub type SharedNotesManager = Arc<Mutex<NotesManager>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scratch_store;
+    use crate::clock::FixedClock;
+    use crate::fixtures::Fixtures;
+    use chrono::TimeZone;
+
+    #[test]
+    fn saved_notes_load_back_unchanged() {
+        let (dir, uri) = scratch_store();
+        let notes = Fixtures::new(7).notes(200);
+        NotesManager::with_notes(&uri, notes.clone()).unwrap();
+
+        let mgr = NotesManager::new(&uri).unwrap();
+        assert_eq!(mgr.list_notes().len(), notes.len());
+        for note in &notes {
+            let loaded = mgr.get_note(&note.id).unwrap();
+            assert_eq!(loaded.title, note.title);
+            assert_eq!(loaded.content, note.content);
+            assert_eq!(loaded.tags, note.tags);
+            assert_eq!(loaded.created_at, note.created_at);
+            assert_eq!(loaded.updated_at, note.updated_at);
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn short_ids_must_be_complete() {
+        let (dir, uri) = scratch_store();
+        let notes = Fixtures::new(11).notes(20);
+        let mgr = NotesManager::with_notes(&uri, notes.clone()).unwrap();
+
+        let short_id = notes[0].short_id();
+        assert_eq!(mgr.get_note_by_short_id(&short_id).unwrap().id, notes[0].id);
+        assert_eq!(
+            mgr.get_note_by_short_id(&short_id.to_uppercase())
+                .unwrap()
+                .id,
+            notes[0].id
+        );
+        assert!(mgr.get_note_by_short_id(&short_id[..1]).is_none());
+        assert!(mgr.get_note_by_short_id(&short_id[..7]).is_none());
+        assert!(mgr.get_note_by_short_id(&notes[0].id).is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn views_are_kept_out_of_the_store() {
+        let (dir, uri) = scratch_store();
+        let notes = Fixtures::new(3).notes(5);
+        let id = notes[0].id.clone();
+        let mut mgr = NotesManager::with_notes(&uri, notes).unwrap();
+        let generation = mgr.generation();
+        let modified = mgr.backend.modified();
+
+        assert_eq!(mgr.record_view(&id).unwrap().view_count, 1);
+        assert_eq!(mgr.record_view(&id).unwrap().view_count, 2);
+        assert_eq!(mgr.generation(), generation);
+        assert_eq!(mgr.backend.modified(), modified);
+
+        mgr.flush_views().unwrap();
+        let reopened = NotesManager::new(&uri).unwrap();
+        assert_eq!(reopened.get_note(&id).unwrap().view_count, 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unsaved_changes_survive_a_reload_whatever_the_clock() {
+        let (dir, uri) = scratch_store();
+        let notes = Fixtures::new(5).notes(3);
+        let (mine, theirs) = (notes[0].id.clone(), notes[1].id.clone());
+        let mut mgr = NotesManager::with_notes(&uri, notes).unwrap();
+        // Far behind the notes' own times
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        mgr.set_clock(Arc::new(FixedClock::new(start)));
+
+        mgr.begin_batch();
+        mgr.update_note(&mine, None, Some("edited here".to_string()), None)
+            .unwrap();
+        mgr.add_comment(&mine, None, None, "and commented").unwrap();
+
+        let mut other = NotesManager::new(&uri).unwrap();
+        other
+            .update_note(&theirs, None, Some("edited there".to_string()), None)
+            .unwrap();
+
+        assert!(mgr.reload_if_changed().unwrap());
+        let note = mgr.get_note(&mine).unwrap();
+        assert_eq!(note.content, "edited here");
+        assert_eq!(note.comments.len(), 1);
+        assert_eq!(mgr.get_note(&theirs).unwrap().content, "edited there");
+
+        mgr.commit().unwrap();
+        let reopened = NotesManager::new(&uri).unwrap();
+        assert_eq!(reopened.get_note(&mine).unwrap().content, "edited here");
+        assert_eq!(reopened.get_note(&theirs).unwrap().content, "edited there");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
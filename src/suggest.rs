@@ -0,0 +1,234 @@
+use crate::models::Note;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// Existing tags scoring below this aren't worth suggesting
+const MIN_TAG_SCORE: f64 = 0.05;
+
+// Notes less alike than this aren't worth pointing to
+const MIN_RELATED_SCORE: f64 = 0.1;
+
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was",
+    "one", "our", "out", "has", "have", "him", "his", "how", "its", "may", "new", "now", "old",
+    "see", "two", "who", "did", "get", "let", "say", "she", "too", "use", "that", "with", "this",
+    "from", "they", "will", "would", "there", "their", "what", "about", "which", "when", "make",
+    "like", "time", "just", "know", "take", "into", "your", "some", "could", "them", "than",
+    "then", "also", "been", "more", "only", "very", "should", "these", "those", "were", "here",
+    "where", "after", "before", "because", "while", "need", "needs",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionSource {
+    /// A tag already used on similar notes
+    Existing,
+    /// A distinctive word from the note itself
+    Keyword,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub score: f64,
+    pub source: SuggestionSource,
+}
+
+/// Another note on much the same topic.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelatedNote {
+    pub id: String,
+    pub title: String,
+    /// Cosine similarity, 0 to 1
+    pub score: f64,
+    pub shared_tags: Vec<String>,
+}
+
+fn terms(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .filter(|word| !word.chars().all(|c| c.is_numeric()))
+        .filter(|word| !STOP_WORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+type Vector = HashMap<String, f64>;
+
+fn normalize(mut vector: Vector) -> Vector {
+    let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vector.values_mut().for_each(|w| *w /= norm);
+    }
+    vector
+}
+
+fn dot(a: &Vector, b: &Vector) -> f64 {
+    a.iter()
+        .filter_map(|(term, weight)| b.get(term).map(|other| weight * other))
+        .sum()
+}
+
+/// Inverse document frequencies over the corpus.
+struct Idf {
+    documents: f64,
+    frequencies: HashMap<String, usize>,
+}
+
+impl Idf {
+    fn new<'a>(documents: impl Iterator<Item = &'a [String]>) -> Self {
+        let mut count = 0;
+        let mut frequencies = HashMap::new();
+        for terms in documents {
+            count += 1;
+            for term in terms.iter().collect::<HashSet<_>>() {
+                *frequencies.entry(term.clone()).or_insert(0) += 1;
+            }
+        }
+        Idf {
+            documents: count as f64,
+            frequencies,
+        }
+    }
+
+    fn weight(&self, term: &str) -> f64 {
+        let df = self.frequencies.get(term).copied().unwrap_or(0) as f64;
+        ((self.documents + 1.0) / (df + 1.0)).ln() + 1.0
+    }
+
+    /// Unit-length TF-IDF vector of a document's terms.
+    fn vector(&self, terms: &[String]) -> Vector {
+        let mut vector = Vector::new();
+        for term in terms {
+            *vector.entry(term.clone()).or_insert(0.0) += 1.0;
+        }
+        for (term, tf) in vector.iter_mut() {
+            *tf *= self.weight(term);
+        }
+        normalize(vector)
+    }
+}
+
+/// Suggests tags for a note with the given text, best first.
+///
+/// Tags already used in the corpus are ranked by how similar the text is to
+/// the notes carrying them (TF-IDF cosine similarity); any remaining slots go
+/// to the text's most distinctive words. `skip_id` leaves the note itself out
+/// of the corpus, and tags in `exclude` are never suggested.
+pub fn suggest_tags(
+    corpus: &[Note],
+    skip_id: Option<&str>,
+    text: &str,
+    exclude: &[String],
+    limit: usize,
+) -> Vec<TagSuggestion> {
+    let documents: Vec<(&Note, Vec<String>)> = corpus
+        .iter()
+        .filter(|note| Some(note.id.as_str()) != skip_id)
+        .map(|note| (note, terms(&format!("{}\n{}", note.title, note.content))))
+        .collect();
+    let target_terms = terms(text);
+    let idf = Idf::new(
+        documents
+            .iter()
+            .map(|(_, terms)| terms.as_slice())
+            .chain(std::iter::once(target_terms.as_slice())),
+    );
+    let target = idf.vector(&target_terms);
+    let excluded: HashSet<String> = exclude.iter().map(|tag| tag.to_lowercase()).collect();
+
+    // Each tag's profile is the sum of its notes' vectors
+    let mut profiles: HashMap<String, Vector> = HashMap::new();
+    for (note, terms) in &documents {
+        let vector = idf.vector(terms);
+        for tag in &note.tags {
+            let profile = profiles.entry(tag.clone()).or_default();
+            for (term, weight) in &vector {
+                *profile.entry(term.clone()).or_insert(0.0) += weight;
+            }
+        }
+    }
+
+    let mut suggestions: Vec<TagSuggestion> = profiles
+        .into_iter()
+        .filter(|(tag, _)| !excluded.contains(&tag.to_lowercase()))
+        .map(|(tag, profile)| {
+            let mentioned = target.contains_key(&tag.to_lowercase());
+            let score = dot(&target, &normalize(profile)) + if mentioned { 0.5 } else { 0.0 };
+            TagSuggestion {
+                tag,
+                score,
+                source: SuggestionSource::Existing,
+            }
+        })
+        .filter(|suggestion| suggestion.score >= MIN_TAG_SCORE)
+        .collect();
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.cmp(&b.tag)));
+    suggestions.truncate(limit);
+
+    let mut keywords: Vec<(&String, &f64)> = target.iter().collect();
+    keywords.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (term, weight) in keywords {
+        if suggestions.len() >= limit {
+            break;
+        }
+        let taken =
+            excluded.contains(term) || suggestions.iter().any(|s| s.tag.to_lowercase() == *term);
+        if !taken {
+            suggestions.push(TagSuggestion {
+                tag: term.clone(),
+                score: *weight,
+                source: SuggestionSource::Keyword,
+            });
+        }
+    }
+
+    suggestions
+}
+
+/// The notes in `corpus` most like `note`, best first, by TF-IDF cosine
+/// similarity of their titles and content. Encrypted notes have no readable
+/// text, so they are never related to anything.
+pub fn related_notes(corpus: &[Note], note: &Note, limit: usize) -> Vec<RelatedNote> {
+    if note.is_encrypted() {
+        return Vec::new();
+    }
+    let text = |note: &Note| terms(&format!("{}\n{}", note.title, note.content));
+    let documents: Vec<(&Note, Vec<String>)> = corpus
+        .iter()
+        .filter(|other| other.id != note.id && !other.is_encrypted())
+        .map(|other| (other, text(other)))
+        .collect();
+    let target_terms = text(note);
+    let idf = Idf::new(
+        documents
+            .iter()
+            .map(|(_, terms)| terms.as_slice())
+            .chain(std::iter::once(target_terms.as_slice())),
+    );
+    let target = idf.vector(&target_terms);
+
+    let mut related: Vec<RelatedNote> = documents
+        .iter()
+        .map(|(other, terms)| RelatedNote {
+            id: other.id.clone(),
+            title: other.title.clone(),
+            score: dot(&target, &idf.vector(terms)),
+            shared_tags: other
+                .tags
+                .iter()
+                .filter(|tag| note.tags.contains(tag))
+                .cloned()
+                .collect(),
+        })
+        .filter(|related| related.score >= MIN_RELATED_SCORE)
+        .collect();
+    related.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    related.truncate(limit);
+    related
+}
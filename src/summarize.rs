@@ -0,0 +1,97 @@
+use crate::config::SummaryConfig;
+use crate::models::Note;
+use crate::storage::NotesManager;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+/// Metadata field the summary is kept in, so the content can show it with
+/// `{{metadata.summary}}`.
+pub const SUMMARY_KEY: &str = "summary";
+
+/// Notes at least this many characters long are summarized by
+/// `notes-app summarize` unless `--min-length` says otherwise.
+pub const DEFAULT_MIN_LENGTH: usize = 4000;
+
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: String,
+}
+
+/// Asks the configured chat completions API for a summary of the note.
+/// `Unsupported` when no API is configured, `InvalidInput` for encrypted or
+/// empty notes, `Other` when the API can't be reached or answers with an
+/// error.
+pub fn summarize(config: &SummaryConfig, note: &Note) -> io::Result<String> {
+    let Some(url) = &config.url else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Summaries are off: set summary.url in the config",
+        ));
+    };
+    if note.is_encrypted() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Encrypted notes can't be summarized; decrypt the note first",
+        ));
+    }
+    if note.content.trim().is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The note has no content to summarize",
+        ));
+    }
+
+    // Cut at a character boundary; a summary of most of a long article is
+    // better than none
+    let text: String = note.content.chars().take(config.max_input_chars).collect();
+    let endpoint = format!("{}/chat/completions", url.trim_end_matches('/'));
+    let mut request = ureq::post(&endpoint).timeout(Duration::from_secs(config.timeout_secs));
+    if let Some(key) = &config.api_key {
+        request = request.set("Authorization", &format!("Bearer {}", key));
+    }
+    let response = request
+        .send_json(json!({
+            "model": config.model,
+            "messages": [
+                {"role": "system", "content": config.prompt},
+                {"role": "user", "content": format!("# {}\n\n{}", note.title, text)},
+            ],
+        }))
+        .map_err(|e| io::Error::other(format!("Summary request to {} failed: {}", endpoint, e)))?;
+
+    let completion: Completion = response
+        .into_json()
+        .map_err(|e| io::Error::other(format!("Unreadable answer from {}: {}", endpoint, e)))?;
+    completion
+        .choices
+        .into_iter()
+        .next()
+        .map(|choice| choice.message.content.trim().to_string())
+        .filter(|summary| !summary.is_empty())
+        .ok_or_else(|| io::Error::other(format!("{} answered without a summary", endpoint)))
+}
+
+/// The note's summary, if it has one.
+pub fn summary_of(note: &Note) -> Option<&str> {
+    note.metadata.get(SUMMARY_KEY).and_then(Value::as_str)
+}
+
+/// Keeps `summary` in the note's metadata, replacing an older one. Not an
+/// edit: `updated_at` stays as it is.
+pub fn save_summary(mgr: &mut NotesManager, id: &str, summary: String) -> io::Result<Option<Note>> {
+    let fields = BTreeMap::from([(SUMMARY_KEY.to_string(), Value::String(summary))]);
+    mgr.annotate(id, fields)
+}
@@ -0,0 +1,331 @@
+use crate::config::TagsConfig;
+use crate::models::Note;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Separates the levels of a nested tag, e.g. `work/projects/alpha`.
+pub const SEPARATOR: char = '/';
+
+/// Whether `tag` is `filter` itself or nested anywhere below it
+/// (case-insensitive).
+pub fn is_within(tag: &str, filter: &str) -> bool {
+    let tag = tag.to_lowercase();
+    let filter = filter.trim_matches(SEPARATOR).to_lowercase();
+    tag == filter
+        || tag
+            .strip_prefix(&filter)
+            .is_some_and(|rest| rest.starts_with(SEPARATOR))
+}
+
+pub fn has_tag(note: &Note, filter: &str) -> bool {
+    note.tags.iter().any(|tag| is_within(tag, filter))
+}
+
+/// `tag` with its `from` prefix replaced by `to`, or `None` if it isn't
+/// `from` or one of its descendants.
+pub fn renamed(tag: &str, from: &str, to: &str) -> Option<String> {
+    if !is_within(tag, from) {
+        return None;
+    }
+    // Matching ignores case, so measure the prefix in the tag itself
+    let depth = from.trim_matches(SEPARATOR).chars().count();
+    let rest = tag.char_indices().nth(depth).map_or("", |(i, _)| &tag[i..]);
+    Some(format!("{}{}", to.trim_matches(SEPARATOR), rest))
+}
+
+// Alias chains longer than this are treated as a loop and left alone
+const MAX_ALIAS_HOPS: usize = 16;
+
+/// Tag aliases (`js` → `javascript`) and implications (`rust` →
+/// `programming`), applied to tags as notes are saved and to tag searches.
+/// Both cover nested tags: `js/react` becomes `javascript/react`, and
+/// `rust/async` implies `programming` too.
+#[derive(Debug, Clone, Default)]
+pub struct TagRules {
+    aliases: Vec<(String, String)>,
+    implies: Vec<(String, Vec<String>)>,
+    hashtags: bool,
+}
+
+impl TagRules {
+    pub fn new(config: &TagsConfig) -> Self {
+        TagRules {
+            aliases: config
+                .aliases
+                .iter()
+                .map(|(alias, tag)| (alias.clone(), tag.clone()))
+                .collect(),
+            implies: config
+                .implies
+                .iter()
+                .map(|(tag, implied)| (tag.clone(), implied.clone()))
+                .collect(),
+            hashtags: config.hashtags,
+        }
+    }
+
+    /// Whether notes are tagged with the `#hashtags` in their content.
+    pub fn extracts_hashtags(&self) -> bool {
+        self.hashtags
+    }
+
+    /// `tags` kept in step with content changing from `before` to `after`:
+    /// hashtags written in it are added and those taken out of it removed.
+    /// Unchanged unless [`extracts_hashtags`](Self::extracts_hashtags).
+    pub fn sync_hashtags(&self, mut tags: Vec<String>, before: &str, after: &str) -> Vec<String> {
+        if !self.hashtags {
+            return tags;
+        }
+        let now: Vec<String> = hashtags(after)
+            .iter()
+            .map(|tag| self.canonical(tag))
+            .collect();
+        let gone: Vec<String> = hashtags(before)
+            .iter()
+            .map(|tag| self.canonical(tag))
+            .filter(|tag| !now.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .collect();
+        tags.retain(|tag| !gone.iter().any(|g| g.eq_ignore_ascii_case(tag)));
+        for tag in now {
+            if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
+    /// The tag `tag` stands for once aliases are resolved.
+    pub fn canonical(&self, tag: &str) -> String {
+        let mut tag = tag.trim().to_string();
+        for _ in 0..MAX_ALIAS_HOPS {
+            let resolved = self
+                .aliases
+                .iter()
+                .find_map(|(alias, target)| renamed(&tag, alias, target));
+            match resolved {
+                Some(resolved) if resolved != tag => tag = resolved,
+                _ => break,
+            }
+        }
+        tag
+    }
+
+    /// `tags` with aliases resolved and implied tags added, without
+    /// duplicates (ignoring case).
+    pub fn apply(&self, tags: Vec<String>) -> Vec<String> {
+        let mut result: Vec<String> = Vec::new();
+        let mut pending: Vec<String> = tags.iter().map(|tag| self.canonical(tag)).collect();
+        pending.reverse();
+        while let Some(tag) = pending.pop() {
+            if tag.is_empty() || result.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                continue;
+            }
+            for (trigger, implied) in self.implies.iter().rev() {
+                if is_within(&tag, trigger) {
+                    pending.extend(implied.iter().rev().map(|tag| self.canonical(tag)));
+                }
+            }
+            result.push(tag);
+        }
+        result
+    }
+}
+
+/// The `#hashtags` written in `content`, each once (ignoring case), in
+/// order. A hashtag starts a line or follows a space or an opening bracket
+/// and has a letter in it, so headings (`# Title`), `#12` and `page#anchor`
+/// aren't tags; nested ones are written `#work/alpha`. Code is skipped.
+pub fn hashtags(content: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut in_fence = false;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let mut in_code = false;
+        let mut prev = ' ';
+        for (i, c) in line.char_indices() {
+            if c == '`' {
+                in_code = !in_code;
+            } else if c == '#' && !in_code && (prev.is_whitespace() || "([{".contains(prev)) {
+                let rest = &line[i + 1..];
+                let end = rest
+                    .find(|c: char| {
+                        !(c.is_alphanumeric() || c == '_' || c == '-' || c == SEPARATOR)
+                    })
+                    .unwrap_or(rest.len());
+                let tag = rest[..end].trim_matches(['-', SEPARATOR]);
+                if tag.chars().any(char::is_alphabetic)
+                    && !found.iter().any(|t| t.eq_ignore_ascii_case(tag))
+                {
+                    found.push(tag.to_string());
+                }
+            }
+            prev = c;
+        }
+    }
+    found
+}
+
+/// Colors given to tags, as `#rrggbb`. A nested tag without a color of its
+/// own takes its parent's, so coloring `work` colors `work/alpha` too.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TagColors(BTreeMap<String, String>);
+
+// Tags are matched ignoring case, like everywhere else
+fn color_key(tag: &str) -> String {
+    tag.trim_matches(SEPARATOR).to_lowercase()
+}
+
+impl TagColors {
+    /// The color `tag` is shown in, if it or a tag above it has one.
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        let mut key = color_key(tag);
+        loop {
+            if let Some(color) = self.0.get(&key) {
+                return Some(color);
+            }
+            let (parent, _) = key.rsplit_once(SEPARATOR)?;
+            key = parent.to_string();
+        }
+    }
+
+    /// Gives `tag` a color (see [`parse_color`]), or takes it away with `None`.
+    pub fn set(&mut self, tag: &str, color: Option<&str>) -> Result<(), String> {
+        let key = color_key(tag);
+        if key.is_empty() {
+            return Err("Tag can't be empty".to_string());
+        }
+        match color {
+            Some(color) => {
+                self.0.insert(key, parse_color(color)?);
+            }
+            None => {
+                self.0.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    /// The colors of those of `tags` that have one, by tag.
+    pub fn for_tags(&self, tags: &[String]) -> BTreeMap<String, String> {
+        tags.iter()
+            .filter_map(|tag| Some((tag.clone(), self.get(tag)?.to_string())))
+            .collect()
+    }
+}
+
+/// `#rgb` or `#rrggbb` (the `#` is optional) as lowercase `#rrggbb`.
+pub fn parse_color(color: &str) -> Result<String, String> {
+    let hex = color.trim().trim_start_matches('#');
+    let invalid = || format!("Invalid color: {} (expected #rrggbb)", color.trim());
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    match hex.len() {
+        6 => Ok(format!("#{}", hex.to_lowercase())),
+        3 => Ok(hex
+            .to_lowercase()
+            .chars()
+            .fold("#".to_string(), |mut out, c| {
+                out.push(c);
+                out.push(c);
+                out
+            })),
+        _ => Err(invalid()),
+    }
+}
+
+/// Red, green and blue of a color from [`parse_color`].
+pub fn rgb(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// A level of the tag hierarchy.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagNode {
+    /// Last segment, e.g. `alpha`
+    pub name: String,
+    /// Full tag, e.g. `work/projects/alpha`
+    pub path: String,
+    /// Notes tagged with exactly this tag
+    pub count: usize,
+    /// Notes tagged with this tag or any tag below it
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Ids of the notes pinned in this tag, listed first when filtering by it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pinned: Vec<String>,
+    pub children: Vec<TagNode>,
+}
+
+#[derive(Default)]
+struct Level<'a> {
+    exact: usize,
+    within: HashSet<&'a str>,
+    pinned: Vec<&'a str>,
+}
+
+/// The tags used by `notes` as a forest, alphabetically at each level.
+/// Intermediate levels nobody tagged directly (`work/projects`) still appear.
+pub fn tag_tree(notes: &[Note], colors: &TagColors) -> Vec<TagNode> {
+    let mut levels: BTreeMap<String, Level> = BTreeMap::new();
+    for note in notes {
+        let unique: HashSet<&str> = note
+            .tags
+            .iter()
+            .map(|tag| tag.trim_matches(SEPARATOR))
+            .collect();
+        for tag in unique.into_iter().filter(|tag| !tag.is_empty()) {
+            levels.entry(tag.to_string()).or_default().exact += 1;
+            for (i, _) in tag
+                .match_indices(SEPARATOR)
+                .chain(std::iter::once((tag.len(), "")))
+            {
+                levels
+                    .entry(tag[..i].to_string())
+                    .or_default()
+                    .within
+                    .insert(&note.id);
+            }
+        }
+        for pin in &note.pinned_in {
+            if let Some(level) = levels.get_mut(pin.trim_matches(SEPARATOR)) {
+                level.pinned.push(&note.id);
+            }
+        }
+    }
+    children_of(None, &levels, colors)
+}
+
+fn children_of(
+    parent: Option<&str>,
+    levels: &BTreeMap<String, Level>,
+    colors: &TagColors,
+) -> Vec<TagNode> {
+    levels
+        .iter()
+        .filter(|(path, _)| {
+            let own_parent = path.rsplit_once(SEPARATOR).map(|(parent, _)| parent);
+            own_parent == parent
+        })
+        .map(|(path, level)| TagNode {
+            name: path.rsplit(SEPARATOR).next().unwrap_or(path).to_string(),
+            path: path.clone(),
+            count: level.exact,
+            total: level.within.len(),
+            color: colors.get(path).map(str::to_string),
+            pinned: level.pinned.iter().map(|id| id.to_string()).collect(),
+            children: children_of(Some(path), levels, colors),
+        })
+        .collect()
+}
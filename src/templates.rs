@@ -0,0 +1,171 @@
+use crate::config::TemplateConfig;
+use crate::models::Note;
+use crate::storage::{NotesManager, SharedNotesManager};
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// File next to the notes recording when each scheduled template last ran.
+const RUNS_FILE: &str = "template_runs.json";
+
+// Schedules have minute resolution
+const TICK: Duration = Duration::from_secs(60);
+
+/// Parses a cron expression. The usual five fields (minute hour day month
+/// weekday) are accepted as well as the cron crate's form with seconds.
+pub fn parse_schedule(expression: &str) -> Result<Schedule, String> {
+    let expression = expression.trim();
+    let full = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    Schedule::from_str(&full).map_err(|e| format!("Invalid schedule '{}': {}", expression, e))
+}
+
+/// Creates a note from the template, dated for `when`.
+pub fn instantiate(
+    mgr: &mut NotesManager,
+    template: &TemplateConfig,
+    owner: Option<String>,
+    when: DateTime<Local>,
+) -> io::Result<Note> {
+    let note = note_from(template, owner, when, mgr.now());
+    mgr.insert_note(note)
+}
+
+/// The note [`instantiate`] adds, created at `now`.
+pub fn note_from(
+    template: &TemplateConfig,
+    owner: Option<String>,
+    when: DateTime<Local>,
+    now: DateTime<Utc>,
+) -> Note {
+    let title = template
+        .title
+        .replace("{date}", &when.format("%Y-%m-%d").to_string());
+    let mut note = Note::new_at(title, template.content.clone(), template.tags.clone(), now);
+    note.owner = owner;
+    note
+}
+
+fn runs_path(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(RUNS_FILE)
+}
+
+/// When each scheduled template last ran, persisted so missed runs can be
+/// caught up after a restart.
+struct RunLog {
+    path: PathBuf,
+    last_runs: HashMap<String, DateTime<Utc>>,
+}
+
+impl RunLog {
+    fn load(path: PathBuf) -> io::Result<Self> {
+        let last_runs = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(RunLog { path, last_runs })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        fs::write(&self.path, serde_json::to_string_pretty(&self.last_runs)?)
+    }
+}
+
+/// Creates due notes for every scheduled template. A template seen for the
+/// first time starts counting from now rather than back-filling the past.
+fn run_due(
+    mgr: &mut NotesManager,
+    scheduled: &[(TemplateConfig, Schedule)],
+    log: &mut RunLog,
+    now: DateTime<Utc>,
+) -> io::Result<Vec<Note>> {
+    let mut created = Vec::new();
+
+    for (template, schedule) in scheduled {
+        let Some(last_run) = log.last_runs.get(&template.name).copied() else {
+            log.last_runs.insert(template.name.clone(), now);
+            continue;
+        };
+
+        // Only the latest missed occurrence gets a note, not one per missed run
+        let latest_due = schedule
+            .after(&last_run.with_timezone(&Local))
+            .take_while(|time| time.with_timezone(&Utc) <= now)
+            .last();
+        let Some(due) = latest_due else {
+            continue;
+        };
+
+        let missed_window =
+            now - due.with_timezone(&Utc) > chrono::Duration::from_std(TICK * 2).unwrap();
+        if template.catch_up || !missed_window {
+            created.push(instantiate(mgr, template, None, due)?);
+        }
+        log.last_runs.insert(template.name.clone(), now);
+    }
+
+    log.save()?;
+    Ok(created)
+}
+
+/// Checks the templates' schedules every minute, starting right away so runs
+/// missed while the app was closed are caught up at startup.
+pub fn spawn_template_scheduler(manager: SharedNotesManager, templates: Vec<TemplateConfig>) {
+    let scheduled: Vec<(TemplateConfig, Schedule)> = templates
+        .into_iter()
+        .filter_map(|template| {
+            let expression = template.schedule.clone()?;
+            match parse_schedule(&expression) {
+                Ok(schedule) => Some((template, schedule)),
+                Err(e) => {
+                    println!("⚠ Template '{}' not scheduled: {}", template.name, e);
+                    None
+                }
+            }
+        })
+        .collect();
+    if scheduled.is_empty() {
+        return;
+    }
+
+    thread::spawn(move || {
+        let path = runs_path(manager.lock().unwrap().storage_path());
+        let mut log = match RunLog::load(path) {
+            Ok(log) => log,
+            Err(e) => {
+                println!("⚠ Template scheduler disabled: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            let result = {
+                let mut mgr = manager.lock().unwrap();
+                let now = mgr.now();
+                run_due(&mut mgr, &scheduled, &mut log, now)
+            };
+            match result {
+                Ok(notes) => {
+                    for note in notes {
+                        println!("🗓 Created scheduled note '{}'", note.title);
+                    }
+                }
+                Err(e) => println!("⚠ Scheduled note creation failed: {}", e),
+            }
+            thread::sleep(TICK);
+        }
+    });
+}
@@ -0,0 +1,67 @@
+use image::ImageFormat;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+/// Preview sizes generated for image attachments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbSize {
+    #[default]
+    Small,
+    Medium,
+}
+
+impl ThumbSize {
+    pub const ALL: [ThumbSize; 2] = [ThumbSize::Small, ThumbSize::Medium];
+
+    /// Longest edge in pixels.
+    pub fn pixels(self) -> u32 {
+        match self {
+            ThumbSize::Small => 128,
+            ThumbSize::Medium => 512,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ThumbSize::Small => "small",
+            ThumbSize::Medium => "medium",
+        }
+    }
+}
+
+/// PNG scaled down to fit `size`, keeping the aspect ratio. Images already
+/// smaller than that are re-encoded but never enlarged.
+pub fn thumbnail_png(bytes: &[u8], size: ThumbSize) -> io::Result<Vec<u8>> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let max = size.pixels();
+    let image = if image.width() > max || image.height() > max {
+        image.thumbnail(max, max)
+    } else {
+        image
+    };
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(png)
+}
+
+/// Returns the cached thumbnail at `cached`, generating it from `original`
+/// first if needed.
+pub fn ensure_thumbnail(original: &Path, cached: &Path, size: ThumbSize) -> io::Result<Vec<u8>> {
+    if let Ok(png) = fs::read(cached) {
+        return Ok(png);
+    }
+
+    let png = thumbnail_png(&fs::read(original)?, size)?;
+    if let Some(dir) = cached.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(cached, &png)?;
+    Ok(png)
+}
@@ -0,0 +1,111 @@
+use crate::models::Note;
+use crate::snooze;
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How long `untouched` looks back when not told: six months.
+pub const DEFAULT_UNTOUCHED: &str = "6mo";
+
+/// A calendar period running up to now, in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    Today,
+    /// Since Monday
+    Week,
+    Month,
+    Year,
+}
+
+impl std::str::FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "today" | "day" => Ok(Period::Today),
+            "week" | "this_week" => Ok(Period::Week),
+            "month" | "this_month" => Ok(Period::Month),
+            "year" | "this_year" => Ok(Period::Year),
+            other => Err(format!(
+                "Unknown period: {} (expected today, week, month or year)",
+                other
+            )),
+        }
+    }
+}
+
+impl Period {
+    /// When the period holding `now` began.
+    pub fn start(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.with_timezone(&Local).date_naive();
+        let first = match self {
+            Period::Today => today,
+            Period::Week => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+            Period::Month => today.with_day(1).unwrap_or(today),
+            Period::Year => today.with_ordinal(1).unwrap_or(today),
+        };
+        local_midnight(first)
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        // Midnight skipped by a clock change
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+/// The time `input` reaches back to from `now`: a span like `6mo`, `26w`
+/// or `180d`, or a date.
+pub fn parse_cutoff(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    if let Some(months) = input
+        .strip_suffix("mo")
+        .and_then(|months| months.trim().parse::<u32>().ok())
+    {
+        return now
+            .checked_sub_months(Months::new(months))
+            .ok_or_else(|| format!("Too long ago: {}", input));
+    }
+    match snooze::parse_duration(input) {
+        Some(span) => Ok(now - span),
+        None => snooze::parse_when(input, now).map_err(|_| {
+            format!(
+                "Invalid time: {} (expected e.g. 6mo, 26w, 180d or 2026-01-31)",
+                input
+            )
+        }),
+    }
+}
+
+/// A note written on this date in an earlier year.
+#[derive(Debug, Clone, Serialize)]
+pub struct Anniversary {
+    pub years_ago: i32,
+    #[serde(flatten)]
+    pub note: Note,
+}
+
+/// The notes created on `today`'s day of the year in earlier years, the
+/// most recent year first. On 28 February outside leap years, notes from
+/// 29 February count too.
+pub fn on_this_day(notes: Vec<Note>, today: NaiveDate) -> Vec<Anniversary> {
+    let leap_day_today = today.month() == 2
+        && today.day() == 28
+        && NaiveDate::from_ymd_opt(today.year(), 2, 29).is_none();
+    let mut found: Vec<Anniversary> = notes
+        .into_iter()
+        .filter_map(|note| {
+            let created = note.created_at.with_timezone(&Local).date_naive();
+            let same_day = (created.month(), created.day()) == (today.month(), today.day())
+                || (leap_day_today && (created.month(), created.day()) == (2, 29));
+            let years_ago = today.year() - created.year();
+            (same_day && years_ago > 0).then_some(Anniversary { years_ago, note })
+        })
+        .collect();
+    found.sort_by_key(|found| (found.years_ago, found.note.created_at));
+    found
+}
@@ -0,0 +1,64 @@
+use crate::config::TitlesConfig;
+
+/// The title a new note is saved with: `title` when it has text, otherwise
+/// one made from `content` if `titles.auto` is on.
+pub fn resolve(title: &str, content: &str, config: &TitlesConfig) -> Result<String, String> {
+    if !title.trim().is_empty() {
+        return Ok(title.to_string());
+    }
+    if !config.auto {
+        return Err("Title cannot be empty".to_string());
+    }
+    from_content(content, config.max_length)
+        .ok_or_else(|| "A note needs a title or some text to make one from".to_string())
+}
+
+/// A title made from the first line of `content` with text in it: its first
+/// sentence if the line is long, clipped to `max_chars` at a word boundary.
+/// `None` when there's no text to take it from.
+pub fn from_content(content: &str, max_chars: usize) -> Option<String> {
+    let line = content
+        .lines()
+        .map(plain_text)
+        .find(|line| !line.is_empty())?;
+    Some(summarize(&line, max_chars.max(1)))
+}
+
+// The text of a Markdown line, without heading, quote and list marks.
+// Fences and rules have none
+fn plain_text(line: &str) -> String {
+    let line = line.trim();
+    if line.starts_with("```")
+        || line.starts_with("~~~")
+        || line
+            .chars()
+            .all(|c| matches!(c, '-' | '*' | '_' | '=' | ' '))
+    {
+        return String::new();
+    }
+    let line = line.trim_start_matches(['#', '>']).trim_start();
+    let line = ["- [ ] ", "- [x] ", "- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+        .unwrap_or(line);
+    line.replace(['*', '`'], "").trim().to_string()
+}
+
+fn summarize(line: &str, max_chars: usize) -> String {
+    if line.chars().count() <= max_chars {
+        return line.to_string();
+    }
+    // A first sentence that fits reads better than a clipped line
+    if let Some(end) = line.find(['.', '!', '?']) {
+        let sentence = &line[..end];
+        if !sentence.is_empty() && sentence.chars().count() <= max_chars {
+            return sentence.to_string();
+        }
+    }
+    let clipped: String = line.chars().take(max_chars - 1).collect();
+    let clipped = match clipped.rfind(char::is_whitespace) {
+        Some(space) if space > 0 => &clipped[..space],
+        _ => clipped.as_str(),
+    };
+    format!("{}…", clipped.trim_end())
+}
@@ -0,0 +1,128 @@
+use crate::users::hash_token;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+pub const DEFAULT_TOKENS_PATH: &str = "data/note_tokens.json";
+
+/// What a note token lets its holder do with the note.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenAccess {
+    #[default]
+    Read,
+    Write,
+}
+
+/// A token scoped to a single note, for embedding it elsewhere or sharing
+/// it with a collaborator who has no account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteToken {
+    pub id: String,
+    pub note_id: String,
+    /// Vault the note is in; `None` for tokens made before tokens recorded
+    /// their vault, which belong to the default vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<String>,
+    pub access: TokenAccess,
+    pub created_at: DateTime<Utc>,
+    /// User who generated the token; `None` in single-user mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    // SHA-256 of the token; the token itself is only shown once
+    pub token_hash: String,
+}
+
+pub struct NoteTokenStore {
+    tokens: Vec<NoteToken>,
+    storage_path: String,
+}
+
+impl NoteTokenStore {
+    pub fn new(storage_path: &str) -> io::Result<Self> {
+        let path = Path::new(storage_path);
+        let tokens = if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            serde_json::from_reader(reader)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(NoteTokenStore {
+            tokens,
+            storage_path: storage_path.to_string(),
+        })
+    }
+
+    fn save_tokens(&self) -> io::Result<()> {
+        let path = Path::new(&self.storage_path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.tokens)?;
+        Ok(())
+    }
+
+    /// Creates a token for the note in `vault`, made at `now`, and returns
+    /// it together with its secret.
+    pub fn create(
+        &mut self,
+        note_id: &str,
+        vault: &str,
+        access: TokenAccess,
+        created_by: Option<String>,
+        now: DateTime<Utc>,
+    ) -> io::Result<(NoteToken, String)> {
+        let secret = format!("nt_{}", Uuid::new_v4().simple());
+        let token = NoteToken {
+            id: Uuid::new_v4().to_string(),
+            note_id: note_id.to_string(),
+            vault: Some(vault.to_string()),
+            access,
+            created_at: now,
+            created_by,
+            token_hash: hash_token(&secret),
+        };
+        self.tokens.push(token.clone());
+        self.save_tokens()?;
+        Ok((token, secret))
+    }
+
+    pub fn list_for_note(&self, note_id: &str) -> Vec<NoteToken> {
+        self.tokens
+            .iter()
+            .filter(|token| token.note_id == note_id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn find_by_token(&self, secret: &str) -> Option<NoteToken> {
+        let hash = hash_token(secret);
+        self.tokens
+            .iter()
+            .find(|token| token.token_hash == hash)
+            .cloned()
+    }
+
+    /// Revokes a token of the note. Returns whether it existed.
+    pub fn revoke(&mut self, note_id: &str, token_id: &str) -> io::Result<bool> {
+        let before = self.tokens.len();
+        self.tokens
+            .retain(|token| !(token.note_id == note_id && token.id == token_id));
+        if self.tokens.len() == before {
+            return Ok(false);
+        }
+        self.save_tokens()?;
+        Ok(true)
+    }
+}
+
+pub type SharedNoteTokenStore = Arc<Mutex<NoteTokenStore>>;
@@ -0,0 +1,29 @@
+use crate::config::TranscriptionConfig;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs the configured speech-to-text command on an audio file and returns
+/// its standard output as the transcript. `Ok(None)` if no command is set.
+pub fn transcribe(config: &TranscriptionConfig, audio: &Path) -> io::Result<Option<String>> {
+    let Some(command) = &config.command else {
+        return Ok(None);
+    };
+
+    let file = audio.to_string_lossy();
+    let output = Command::new(command)
+        .args(config.args.iter().map(|arg| arg.replace("{file}", &file)))
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} exited with {}: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let transcript = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(transcript).filter(|t| !t.is_empty()))
+}
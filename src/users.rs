@@ -0,0 +1,226 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+pub const DEFAULT_USERS_PATH: &str = "data/users.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    // SHA-256 of the API token; the token itself is only shown once
+    pub token_hash: String,
+    /// Set while the account is disabled; its token is refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled_at: Option<DateTime<Utc>>,
+}
+
+impl User {
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_at.is_some()
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub struct UserStore {
+    pub users: Vec<User>,
+    storage_path: String,
+}
+
+impl UserStore {
+    pub fn new(storage_path: &str) -> io::Result<Self> {
+        let path = Path::new(storage_path);
+        let users = if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            serde_json::from_reader(reader)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(UserStore {
+            users,
+            storage_path: storage_path.to_string(),
+        })
+    }
+
+    fn save_users(&self) -> io::Result<()> {
+        let path = Path::new(&self.storage_path);
+        fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &self.users)?;
+        Ok(())
+    }
+
+    /// Multi-user mode (and with it authentication) is on once any account exists.
+    pub fn is_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Creates a user and returns it together with its freshly generated API
+    /// token. The first account turns authentication on, so it's always an
+    /// admin whatever `role` says; otherwise nobody could manage accounts.
+    pub fn add_user(&mut self, username: &str, role: Role) -> io::Result<(User, String)> {
+        if self.users.iter().any(|user| user.username == username) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("User '{}' already exists", username),
+            ));
+        }
+
+        let token = Uuid::new_v4().simple().to_string();
+        let user = User {
+            id: Uuid::new_v4().to_string(),
+            username: username.to_string(),
+            role: if self.is_enabled() { role } else { Role::Admin },
+            created_at: Utc::now(),
+            token_hash: hash_token(&token),
+            disabled_at: None,
+        };
+        self.users.push(user.clone());
+        self.save_users()?;
+        Ok((user, token))
+    }
+
+    pub fn list_users(&self) -> Vec<User> {
+        self.users.clone()
+    }
+
+    /// The user with this id or username.
+    pub fn find(&self, key: &str) -> Option<User> {
+        self.users
+            .iter()
+            .find(|user| user.id == key || user.username == key)
+            .cloned()
+    }
+
+    /// The enabled user this token belongs to.
+    pub fn find_by_token(&self, token: &str) -> Option<User> {
+        let hash = hash_token(token);
+        self.users
+            .iter()
+            .find(|user| user.token_hash == hash && !user.is_disabled())
+            .cloned()
+    }
+
+    /// Changes the user's role. The last enabled admin stays an admin, so
+    /// someone can still manage accounts.
+    pub fn set_role(&mut self, id: &str, role: Role) -> io::Result<Option<User>> {
+        let Some(index) = self.users.iter().position(|user| user.id == id) else {
+            return Ok(None);
+        };
+        if role < Role::Admin && self.is_last_admin(index) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "'{}' is the only enabled admin; make someone else admin first",
+                    self.users[index].username
+                ),
+            ));
+        }
+
+        let user = &mut self.users[index];
+        user.role = role;
+        let updated = user.clone();
+        self.save_users()?;
+        Ok(Some(updated))
+    }
+
+    // Whether the user at `index` is an admin and no other enabled user is
+    fn is_last_admin(&self, index: usize) -> bool {
+        self.users[index].role == Role::Admin
+            && !self
+                .users
+                .iter()
+                .enumerate()
+                .any(|(i, user)| i != index && user.role == Role::Admin && !user.is_disabled())
+    }
+
+    /// Disables or enables the user with this id or username. The last
+    /// enabled admin stays enabled, so someone can still manage accounts.
+    pub fn set_disabled(&mut self, key: &str, disabled: bool) -> io::Result<Option<User>> {
+        let Some(index) = self
+            .users
+            .iter()
+            .position(|user| user.id == key || user.username == key)
+        else {
+            return Ok(None);
+        };
+        if disabled && self.is_last_admin(index) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "'{}' is the only enabled admin; make someone else admin first",
+                    self.users[index].username
+                ),
+            ));
+        }
+
+        let user = &mut self.users[index];
+        match (disabled, user.disabled_at) {
+            (true, None) => user.disabled_at = Some(Utc::now()),
+            (false, Some(_)) => user.disabled_at = None,
+            _ => {}
+        }
+        let updated = user.clone();
+        self.save_users()?;
+        Ok(Some(updated))
+    }
+
+    /// Gives the user with this id or username a new API token, which
+    /// replaces the old one. Returns the user and the token, shown once.
+    pub fn reset_token(&mut self, key: &str) -> io::Result<Option<(User, String)>> {
+        let Some(user) = self
+            .users
+            .iter_mut()
+            .find(|user| user.id == key || user.username == key)
+        else {
+            return Ok(None);
+        };
+        let token = Uuid::new_v4().simple().to_string();
+        user.token_hash = hash_token(&token);
+        let updated = user.clone();
+        self.save_users()?;
+        Ok(Some((updated, token)))
+    }
+}
+
+pub type SharedUserStore = Arc<Mutex<UserStore>>;
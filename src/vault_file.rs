@@ -0,0 +1,189 @@
+use crate::crypto;
+use crate::models::Note;
+use crate::storage::NotesManager;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// File name extension of vault files.
+pub const EXTENSION: &str = "nvault";
+
+// Identifies the file type and its layout version
+const MAGIC: &[u8] = b"NVAULT\x01";
+
+const FORMAT_VERSION: u32 = 1;
+
+/// What a vault file holds besides the attachment bytes that follow it.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    app_version: String,
+    exported_at: DateTime<Utc>,
+    /// Name of the vault it was exported from
+    vault: String,
+    notes: Vec<Note>,
+    /// Attachment files, in the order their bytes follow the manifest
+    files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+    key: String,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportReport {
+    pub notes: usize,
+    pub files: usize,
+    /// Size of the vault file
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub vault: String,
+    pub exported_at: DateTime<Utc>,
+    pub added: usize,
+    /// Notes that existed but were older than the vault file's copy
+    pub replaced: usize,
+    /// Notes already there and at least as recent, left alone
+    pub unchanged: usize,
+    /// Attachment files that weren't there yet
+    pub files: usize,
+}
+
+/// Writes every note of `mgr`, trashed ones included, and the attachment
+/// files they use to a single gzip-compressed file encrypted with
+/// `password` (AES-256-GCM, Argon2id key).
+pub fn export(
+    mgr: &NotesManager,
+    vault: &str,
+    path: &Path,
+    password: &str,
+) -> io::Result<ExportReport> {
+    let keys: BTreeSet<&str> = mgr
+        .notes
+        .iter()
+        .flat_map(|note| &note.attachments)
+        .map(|attachment| attachment.file_key())
+        .collect();
+    let mut files = Vec::new();
+    let mut data = Vec::new();
+    for key in keys {
+        // A missing file can't be carried along; `admin verify` reports it
+        let Ok(bytes) = fs::read(mgr.attachment_path(key)) else {
+            continue;
+        };
+        files.push(FileEntry {
+            key: key.to_string(),
+            size: bytes.len() as u64,
+        });
+        data.extend(bytes);
+    }
+
+    let manifest = Manifest {
+        format: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: Utc::now(),
+        vault: vault.to_string(),
+        notes: mgr.notes.clone(),
+        files,
+    };
+    let manifest_json = serde_json::to_vec(&manifest)?;
+
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(&(manifest_json.len() as u64).to_le_bytes())?;
+    gzip.write_all(&manifest_json)?;
+    gzip.write_all(&data)?;
+    let sealed = crypto::seal_bytes(&gzip.finish()?, password)?;
+
+    let mut file = fs::File::create(path)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&sealed)?;
+    Ok(ExportReport {
+        notes: manifest.notes.len(),
+        files: manifest.files.len(),
+        bytes: (MAGIC.len() + sealed.len()) as u64,
+    })
+}
+
+/// Restores a vault file into `mgr`: notes it doesn't have are added,
+/// older copies are replaced, and missing attachment files are written.
+/// A wrong password is `PermissionDenied`.
+pub fn import(mgr: &mut NotesManager, path: &Path, password: &str) -> io::Result<ImportReport> {
+    let raw = fs::read(path)?;
+    let Some(sealed) = raw.strip_prefix(MAGIC) else {
+        return Err(damaged("not a vault file"));
+    };
+    let mut body = Vec::new();
+    GzDecoder::new(crypto::open_bytes(sealed, password)?.as_slice()).read_to_end(&mut body)?;
+
+    let (length, rest) = body
+        .split_first_chunk::<8>()
+        .ok_or_else(|| damaged("missing manifest"))?;
+    let length = usize::try_from(u64::from_le_bytes(*length))
+        .ok()
+        .filter(|length| *length <= rest.len())
+        .ok_or_else(|| damaged("manifest is cut off"))?;
+    let (manifest_json, mut data) = rest.split_at(length);
+    let manifest: Manifest = serde_json::from_slice(manifest_json)?;
+    if manifest.format > FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Vault file was made by a newer version ({}); update to import it",
+                manifest.app_version
+            ),
+        ));
+    }
+
+    fs::create_dir_all(mgr.attachments_dir())?;
+    let mut files = 0;
+    for entry in &manifest.files {
+        // Keys are file names in the attachments directory, never paths
+        if entry.key.is_empty()
+            || !entry
+                .key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(damaged("bad attachment name"));
+        }
+        let size = usize::try_from(entry.size)
+            .ok()
+            .filter(|size| *size <= data.len())
+            .ok_or_else(|| damaged("attachment data is cut off"))?;
+        let (bytes, rest) = data.split_at(size);
+        data = rest;
+        let target = mgr.attachment_path(&entry.key);
+        if !target.exists() {
+            fs::write(target, bytes)?;
+            files += 1;
+        }
+    }
+
+    let total = manifest.notes.len();
+    let (added, replaced) = mgr.restore_notes(manifest.notes)?;
+    Ok(ImportReport {
+        vault: manifest.vault,
+        exported_at: manifest.exported_at,
+        added,
+        replaced,
+        unchanged: total - added - replaced,
+        files,
+    })
+}
+
+fn damaged(detail: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Vault file is damaged: {}", detail),
+    )
+}
@@ -0,0 +1,111 @@
+use crate::models::Note;
+use crate::storage::SharedNotesManager;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// File next to the notes that holds how often each note was opened.
+const VIEWS_FILE: &str = "views.json";
+
+// How long counted views may wait in memory before being written
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn path_for(storage_path: &Path) -> PathBuf {
+    storage_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(VIEWS_FILE)
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct NoteViews {
+    pub count: u64,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+}
+
+/// View counts, kept apart from the notes: opening a note mustn't rewrite
+/// the store, count as a change for caches or wake other processes up.
+/// Views are counted in memory and written by [`ViewLog::flush`].
+pub struct ViewLog {
+    by_note: BTreeMap<String, NoteViews>,
+    path: PathBuf,
+    unsaved: bool,
+}
+
+impl ViewLog {
+    /// Reads the views file; without one, starts from the counts the notes
+    /// carry from before views had a file of their own.
+    pub fn load(path: PathBuf, notes: &[Note]) -> io::Result<Self> {
+        let by_note = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)?
+        } else {
+            notes
+                .iter()
+                .filter(|note| note.view_count > 0)
+                .map(|note| {
+                    let views = NoteViews {
+                        count: note.view_count,
+                        last_viewed_at: note.last_viewed_at,
+                    };
+                    (note.id.clone(), views)
+                })
+                .collect()
+        };
+        Ok(ViewLog {
+            by_note,
+            path,
+            unsaved: false,
+        })
+    }
+
+    /// Counts a view of `note`.
+    pub fn record(&mut self, note: &mut Note, now: DateTime<Utc>) {
+        let views = self.by_note.entry(note.id.clone()).or_default();
+        views.count += 1;
+        views.last_viewed_at = Some(now);
+        self.unsaved = true;
+        Self::show(note, views);
+    }
+
+    /// Puts the counts on the notes, e.g. after they were (re)loaded.
+    pub fn apply(&self, notes: &mut [Note]) {
+        for note in notes {
+            if let Some(views) = self.by_note.get(&note.id) {
+                Self::show(note, views);
+            }
+        }
+    }
+
+    fn show(note: &mut Note, views: &NoteViews) {
+        note.view_count = views.count;
+        note.last_viewed_at = views.last_viewed_at;
+    }
+
+    /// Writes views counted since the last flush, forgetting notes that no
+    /// longer exist.
+    pub fn flush(&mut self, notes: &[Note]) -> io::Result<()> {
+        if !self.unsaved {
+            return Ok(());
+        }
+        self.by_note
+            .retain(|id, _| notes.iter().any(|note| &note.id == id));
+        fs::write(&self.path, serde_json::to_string_pretty(&self.by_note)?)?;
+        self.unsaved = false;
+        Ok(())
+    }
+}
+
+/// Writes counted views every minute.
+pub fn spawn_view_flusher(manager: SharedNotesManager) {
+    thread::spawn(move || loop {
+        thread::sleep(FLUSH_INTERVAL);
+        if let Err(e) = manager.lock().unwrap().flush_views() {
+            println!("⚠ Views not saved: {}", e);
+        }
+    });
+}
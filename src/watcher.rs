@@ -0,0 +1,98 @@
+use crate::reload::LiveConfig;
+use crate::storage::SharedNotesManager;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Give external writers a moment to finish and coalesce bursts of events
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Reloads the notes file whenever another process (editor, sync tool)
+/// changes it. Watching stops when the returned watcher is dropped.
+pub fn watch_notes_file(manager: SharedNotesManager) -> notify::Result<RecommendedWatcher> {
+    let path = manager.lock().unwrap().storage_path().to_path_buf();
+    watch_file(path, move || {
+        match manager.lock().unwrap().reload_if_changed() {
+            Ok(true) => println!("🔄 Reloaded notes changed on disk"),
+            Ok(false) => {}
+            Err(e) => println!("⚠ Could not reload notes from disk: {}", e),
+        }
+    })
+}
+
+/// Puts edits to the config file into effect as far as they can be without
+/// a restart, and says which can't. The open `vaults` get their new quotas
+/// and processors.
+pub fn watch_config_file(
+    config: LiveConfig,
+    vaults: Vec<(String, SharedNotesManager)>,
+) -> notify::Result<RecommendedWatcher> {
+    let Some(path) = config.path().map(Path::to_path_buf) else {
+        return Err(notify::Error::generic("the config has no file"));
+    };
+    watch_file(path, move || match config.reload() {
+        Ok(reload) => {
+            for (name, manager) in &vaults {
+                if let Ok(settings) = config.for_vault(name) {
+                    let mut manager = manager.lock().unwrap();
+                    manager.set_quota(&settings.quota);
+                    manager.set_processors(&settings.processors);
+                }
+            }
+            if !reload.applied.is_empty() {
+                println!("🔄 Reloaded config: {}", reload.applied.join(", "));
+            }
+            if !reload.restart_required.is_empty() {
+                println!(
+                    "⚠ Changes to {} take effect after a restart",
+                    reload.restart_required.join(", ")
+                );
+            }
+        }
+        Err(e) => println!("⚠ Could not reload config: {}", e),
+    })
+}
+
+// Calls `on_change` after `path` changes, once per burst of changes
+fn watch_file(
+    path: PathBuf,
+    on_change: impl Fn() + Send + 'static,
+) -> notify::Result<RecommendedWatcher> {
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    // Watch the directory: many tools replace the file via rename instead of writing in place
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    std::fs::create_dir_all(&dir).map_err(notify::Error::io)?;
+
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    thread::spawn(move || {
+        while let Ok(event) = rx.recv() {
+            let touches_file = match event {
+                // Reading the file (as reloading does) isn't a change
+                Ok(event) if event.kind.is_access() => false,
+                Ok(event) => event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name().map(|n| n.to_os_string()) == file_name),
+                Err(_) => false,
+            };
+            if !touches_file {
+                continue;
+            }
+
+            thread::sleep(DEBOUNCE);
+            while rx.try_recv().is_ok() {}
+
+            on_change();
+        }
+    });
+
+    Ok(watcher)
+}
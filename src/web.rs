@@ -1,13 +1,132 @@
-use crate::storage::SharedNotesManager;
+use crate::activity::{self, Granularity};
+use crate::archive::Archive;
+use crate::auth::{self, CurrentUser};
+use crate::backup::Backups;
+use crate::cache::{CachedResponse, ResponseCache, SharedResponseCache};
+use crate::capture;
+use crate::cleanup::{self, CleanupFilter};
+use crate::commands;
+use crate::config::{
+    Config, CorsConfig, ProblemDetails, ServerConfig, TemplateConfig, TranscriptionConfig,
+};
+use crate::crdt::{self, CrdtStore};
+use crate::dashboard::Dashboard;
+use crate::duplicates::{self, DuplicateHint};
+use crate::export::{self, ExportFilter, ExportFormat};
+use crate::history::{self, SearchHistory, SharedSearchHistory};
+use crate::html;
+use crate::ics;
+use crate::keymap::Keymap;
+use crate::links::{self, LinkCheckReport};
+use crate::locks::{self, Lease, SharedEditLocks};
+use crate::maintenance;
+use crate::merge::MergeStrategy;
+use crate::models::{
+    AttachmentKind, Conflict, GeoPoint, Note, ReactionCount, ReadingProgress, Resolution,
+    StateFilter,
+};
+use crate::notebooks::{MemberAccess, Notebook, Visibility};
+use crate::placeholders;
+use crate::policy;
+use crate::preferences::{self, NoteSort, PreferenceStore, Preferences, SharedPreferenceStore};
+use crate::processors::PendingSave;
+use crate::progress::{self, ProgressStore, SharedProgressStore};
+use crate::qr;
+use crate::reload::LiveConfig;
+use crate::replace::{self, Replace};
+use crate::search::{self, SearchHit, SearchScope, SearchSort};
+use crate::snooze;
+use crate::storage::{NotesManager, SharedNotesManager};
+use crate::suggest;
+use crate::summarize;
+use crate::tags;
+use crate::templates;
+use crate::thumbnail::{self, ThumbSize};
+use crate::timeline::{self, Period};
+use crate::titles;
+use crate::tokens::{NoteToken, SharedNoteTokenStore, TokenAccess};
+use crate::transcribe;
+use crate::users::{Role, SharedUserStore, User};
 use actix_cors::Cors;
-use actix_web::{delete, get, post, put, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{HttpServiceFactory, Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::guard::GuardContext;
+use actix_web::http::header::HeaderValue;
+use actix_web::http::header::{
+    self, ContentDisposition, DispositionParam, DispositionType, ETag, EntityTag, HttpDate,
+    IfMatch, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::middleware::Next;
+use actix_web::{
+    delete, get, middleware, patch, post, put, web, App, FromRequest, HttpMessage, HttpRequest,
+    HttpResponse, HttpServer, Responder,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use futures_util::stream;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::future::{ready, Ready};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CreateNoteRequest {
+    /// Made from the content when empty, if `titles.auto` is on
+    #[serde(default)]
     title: String,
     content: String,
     tags: Vec<String>,
+    #[serde(default)]
+    parent_id: Option<String>,
+    #[serde(default)]
+    location: Option<GeoPoint>,
+    /// Fields the content can show with `{{metadata.<name>}}`
+    #[serde(default)]
+    metadata: BTreeMap<String, Value>,
+    /// Id of a notebook the caller may write to
+    #[serde(default)]
+    notebook: Option<String>,
+    #[serde(default)]
+    slug: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateQuery {
+    /// Create the note even if it's nearly the same as an existing one
+    #[serde(default)]
+    allow_duplicate: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DuplicateWarning {
+    duplicate_of: DuplicateHint,
+}
+
+// Among the notes the caller can see
+fn near_duplicate(mgr: &NotesManager, user: &CurrentUser, content: &str) -> Option<DuplicateHint> {
+    let notes = mgr.list_notes();
+    duplicates::find_near_duplicate(notes.iter().filter(|note| user.can_read(note)), content)
+}
+
+// 200 instead of 201: nothing was created, the caller can open the
+// existing note or send the note again with `?allow_duplicate=true`
+fn duplicate_warning(hint: DuplicateHint) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse {
+        success: false,
+        message: format!(
+            "Nearly the same as \"{}\"; send ?allow_duplicate=true to create it anyway",
+            hint.title
+        ),
+        data: Some(DuplicateWarning { duplicate_of: hint }),
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,10 +134,18 @@ struct UpdateNoteRequest {
     title: Option<String>,
     content: Option<String>,
     tags: Option<Vec<String>>,
+    /// New parent note; an empty string makes the note top-level again
+    parent_id: Option<String>,
+    /// Required to change the content of an encrypted note
+    passphrase: Option<String>,
+    /// `updated_at` of the version the edit was made to. If the note has
+    /// changed since (or no longer has the `If-Match` ETag), the edit is saved
+    /// as a conflict copy instead
+    base_updated_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ApiResponse<T> {
+pub(crate) struct ApiResponse<T> {
     success: bool,
     message: String,
     data: Option<T>,
@@ -36,7 +163,7 @@ impl<T> ApiResponse<T> {
 
 // Special implementation for error case (returns ApiResponse<()>)
 impl ApiResponse<()> {
-    fn error(message: &str) -> Self {
+    pub(crate) fn error(message: &str) -> Self {
         Self {
             success: false,
             message: message.to_string(),
@@ -45,182 +172,5925 @@ impl ApiResponse<()> {
     }
 }
 
+impl<T: Serialize> ApiResponse<T> {
+    fn to_cached(&self) -> CachedResponse {
+        CachedResponse {
+            content_type: "application/json",
+            body: serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+// Cached responses differ by request and by who's asking
+fn cache_key(req: &HttpRequest, user: &CurrentUser) -> String {
+    let caller = match user {
+        CurrentUser::Local => "local".to_string(),
+        CurrentUser::User(user) => format!("user:{}:{:?}", user.id, user.role),
+        CurrentUser::NoteToken(token) => format!("token:{}:{:?}", token.note_id, token.access),
+    };
+    format!("{} {}?{}", caller, req.path(), req.query_string())
+}
+
+/// Sends the cached response for `key` if the notes haven't changed since
+/// it was rendered (`X-Cache: HIT`), otherwise renders and caches it.
+fn cached(
+    cache: &SharedResponseCache,
+    key: String,
+    generation: u64,
+    render: impl FnOnce() -> CachedResponse,
+) -> HttpResponse {
+    let hit = cache.lock().unwrap().get(&key, generation);
+    let (response, status) = match hit {
+        Some(response) => (response, "HIT"),
+        None => {
+            let response = render();
+            cache
+                .lock()
+                .unwrap()
+                .insert(key, generation, response.clone());
+            (response, "MISS")
+        }
+    };
+    HttpResponse::Ok()
+        .content_type(response.content_type)
+        .insert_header(("X-Cache", status))
+        .body(response.body)
+}
+
+/// Header naming the vault a request works on; the server's first vault
+/// when absent.
+pub const VAULT_HEADER: &str = "X-Vault";
+
+// What the server keeps for each vault
+struct VaultState {
+    name: String,
+    manager: SharedNotesManager,
+    documents: CrdtStore,
+    cache: SharedResponseCache,
+    history: SharedSearchHistory,
+    progress: SharedProgressStore,
+}
+
+struct Vaults {
+    default: String,
+    by_name: HashMap<String, Arc<VaultState>>,
+}
+
+/// The vault picked by the request's `X-Vault` header (or `?vault=`). Derefs to its notes;
+/// nothing in one vault is reachable through another.
+pub(crate) struct Vault(Arc<VaultState>);
+
+impl Vault {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn notes(&self) -> &SharedNotesManager {
+        &self.0.manager
+    }
+
+    fn documents(&self) -> &CrdtStore {
+        &self.0.documents
+    }
+
+    fn cache(&self) -> &SharedResponseCache {
+        &self.0.cache
+    }
+
+    fn history(&self) -> &SharedSearchHistory {
+        &self.0.history
+    }
+
+    fn progress(&self) -> &SharedProgressStore {
+        &self.0.progress
+    }
+}
+
+impl std::ops::Deref for Vault {
+    type Target = SharedNotesManager;
+
+    fn deref(&self) -> &SharedNotesManager {
+        self.notes()
+    }
+}
+
+fn query_vault(req: &HttpRequest) -> Option<&str> {
+    req.query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("vault="))
+}
+
+impl FromRequest for Vault {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let vaults = req
+            .app_data::<web::Data<Vaults>>()
+            .expect("vaults are registered with the app");
+        // A note token's holder needn't know which vault its note is in
+        let token_vault = match req.extensions().get::<CurrentUser>() {
+            Some(CurrentUser::NoteToken(token)) => token.vault.clone(),
+            _ => None,
+        };
+        let name = match req.headers().get(VAULT_HEADER).map(|value| value.to_str()) {
+            Some(Ok(name)) => name.trim(),
+            Some(Err(_)) => "",
+            // Calendar apps and the like can't set headers
+            None => query_vault(req)
+                .or(token_vault.as_deref())
+                .unwrap_or(vaults.default.as_str()),
+        };
+        ready(match vaults.by_name.get(name) {
+            Some(vault) => Ok(Vault(vault.clone())),
+            None => Err(InternalError::from_response(
+                "unknown vault",
+                HttpResponse::NotFound()
+                    .json(ApiResponse::error(&format!("Unknown vault: {}", name))),
+            )
+            .into()),
+        })
+    }
+}
+
+/// The settings in effect when the request arrived. They follow the config
+/// file while the server runs (see [`LiveConfig`]), but each request sees
+/// one consistent version.
+#[derive(Clone)]
+struct Settings(Arc<Config>);
+
+impl std::ops::Deref for Settings {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        &self.0
+    }
+}
+
+impl FromRequest for Settings {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let config = req
+            .app_data::<web::Data<LiveConfig>>()
+            .expect("the config is registered with the app");
+        ready(Ok(Settings(config.current())))
+    }
+}
+
+// Cache validators (ETag / Last-Modified) for conditional GETs
+struct Validators {
+    etag: EntityTag,
+    last_modified: SystemTime,
+}
+
+impl Validators {
+    fn for_note(note: &Note) -> Self {
+        Self::from_notes(std::slice::from_ref(note), note_timestamp(note))
+    }
+
+    fn from_notes(notes: &[Note], last_modified: SystemTime) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for note in notes {
+            // Everything a client gets, so changes that leave `updated_at`
+            // alone (comments, pins, shares, ...) are seen too. Views aren't
+            // changes: looking at a note mustn't make copies of it stale.
+            note.fingerprint().hash(&mut hasher);
+        }
+        Self {
+            etag: EntityTag::new_strong(format!("{:016x}", hasher.finish())),
+            // HTTP dates only carry whole seconds
+            last_modified: truncate_to_secs(last_modified),
+        }
+    }
+
+    /// Whether an `If-Match` header, if any, names this version.
+    fn is_matched_by(&self, req: &HttpRequest) -> bool {
+        match req.get_header::<IfMatch>() {
+            Some(IfMatch::Items(tags)) => tags.iter().any(|tag| tag.strong_eq(&self.etag)),
+            Some(IfMatch::Any) | None => true,
+        }
+    }
+
+    /// If-None-Match takes precedence over If-Modified-Since (RFC 7232 §6).
+    fn is_fresh(&self, req: &HttpRequest) -> bool {
+        if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+            return match if_none_match {
+                IfNoneMatch::Any => true,
+                IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+            };
+        }
+        match req.get_header::<IfModifiedSince>() {
+            Some(IfModifiedSince(since)) => HttpDate::from(self.last_modified) <= since,
+            None => false,
+        }
+    }
+
+    fn respond<T: Serialize>(&self, req: &HttpRequest, body: &ApiResponse<T>) -> HttpResponse {
+        let fresh = self.is_fresh(req);
+        let mut builder = if fresh {
+            HttpResponse::NotModified()
+        } else {
+            HttpResponse::Ok()
+        };
+        builder
+            .insert_header(ETag(self.etag.clone()))
+            .insert_header(LastModified(self.last_modified.into()));
+
+        if fresh {
+            builder.finish()
+        } else {
+            builder.json(body)
+        }
+    }
+}
+
+fn note_timestamp(note: &Note) -> SystemTime {
+    SystemTime::from(note.updated_at)
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+// Hides notes the user can't see (404) and rejects read-only access (403)
+fn check_write_access(
+    mgr: &NotesManager,
+    user: &CurrentUser,
+    id: &str,
+) -> Result<(), HttpResponse> {
+    match mgr.get_note(id) {
+        Some(note) if user.can_write(&note) => Ok(()),
+        Some(note) if user.can_read(&note) => {
+            Err(HttpResponse::Forbidden().json(ApiResponse::error("Insufficient permissions")))
+        }
+        _ => Err(HttpResponse::NotFound().json(ApiResponse::error("Note not found"))),
+    }
+}
+
+/// Header carrying the holder id of the client's edit lease.
+const LOCK_HOLDER_HEADER: &str = "X-Lock-Holder";
+
+fn lock_holder(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(LOCK_HOLDER_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[derive(Debug, Serialize)]
+struct LockResponse {
+    note_id: String,
+    /// Only shown to the client holding the lease
+    #[serde(skip_serializing_if = "Option::is_none")]
+    holder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locked_by: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+impl LockResponse {
+    fn new(lease: Lease, show_holder: bool) -> Self {
+        Self {
+            note_id: lease.note_id,
+            holder: Some(lease.holder).filter(|_| show_holder),
+            locked_by: lease.user,
+            expires_at: lease.expires_at,
+        }
+    }
+}
+
+fn locked(lease: Lease) -> HttpResponse {
+    HttpResponse::Locked().json(ApiResponse {
+        success: false,
+        message: format!(
+            "Note is being edited elsewhere until {}",
+            lease.expires_at.to_rfc3339()
+        ),
+        data: Some(LockResponse::new(lease, false)),
+    })
+}
+
+/// Turns edits away while another client holds the note's edit lease
+/// (leases expire by the vault's clock, `now`).
+fn check_lock(
+    req: &HttpRequest,
+    locks: &SharedEditLocks,
+    id: &str,
+    now: DateTime<Utc>,
+) -> Result<(), HttpResponse> {
+    match locks.lock().unwrap().blocking(id, lock_holder(req), now) {
+        Some(lease) => Err(locked(lease)),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NotesQuery {
+    /// `lat,lon` to only list notes located around that point
+    near: Option<String>,
+    radius_km: Option<f64>,
+    /// Only notes with this tag or one nested under it (`work` covers
+    /// `work/alpha`), those pinned in it first
+    tag: Option<String>,
+    /// `active`, `archived`, `trashed`, `snoozed` or `all`; everything
+    /// outside the trash that isn't snoozed if absent
+    state: Option<StateFilter>,
+    /// `added`, `updated`, `created`, `title` or `last_viewed`; the caller's
+    /// preferred order if absent
+    sort: Option<NoteSort>,
+    /// Only notes in this notebook
+    notebook: Option<String>,
+    /// `today`, `week`, `month` or `year`: only notes created since it began
+    created: Option<Period>,
+    /// `6mo`, `26w`, `180d`, a date, ...: only notes not edited since then
+    untouched: Option<String>,
+}
+
+// Radius used by `?near=` when no `radius_km` is given
+const DEFAULT_RADIUS_KM: f64 = 10.0;
+
 #[get("/api/notes")]
-async fn get_notes(manager: web::Data<SharedNotesManager>) -> impl Responder {
+async fn get_notes(
+    req: HttpRequest,
+    user: CurrentUser,
+    query: web::Query<NotesQuery>,
+    manager: Vault,
+    preferences: web::Data<SharedPreferenceStore>,
+) -> impl Responder {
+    let sort = query.sort.unwrap_or_else(|| {
+        user_key(&user)
+            .map(|key| preferences.lock().unwrap().get(key).sort)
+            .unwrap_or_default()
+    });
+    let near = match query.near.as_deref().map(str::parse::<GeoPoint>) {
+        Some(Err(e)) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+        Some(Ok(point)) => Some(point),
+        None => None,
+    };
+    let radius_km = query.radius_km.unwrap_or(DEFAULT_RADIUS_KM);
+    if !(radius_km.is_finite() && radius_km >= 0.0) {
+        return HttpResponse::BadRequest()
+            .json(ApiResponse::error("radius_km must be a positive number"));
+    }
+
     let mgr = manager.lock().unwrap();
-    let notes = mgr.list_notes();
-    HttpResponse::Ok().json(ApiResponse::success(notes, "Notes retrieved successfully"))
+    let untouched_since = match query
+        .untouched
+        .as_deref()
+        .map(|untouched| timeline::parse_cutoff(untouched, mgr.now()))
+    {
+        Some(Err(e)) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+        Some(Ok(cutoff)) => Some(cutoff),
+        None => None,
+    };
+    let created_since = query.created.map(|period| period.start(mgr.now()));
+    let listed = match query.state {
+        Some(state) => mgr.notes_in_state(state),
+        None => mgr.awake_notes(),
+    };
+    let tag = query
+        .tag
+        .as_deref()
+        .map(|tag| mgr.tag_rules().canonical(tag));
+    let mut notes: Vec<Note> = listed
+        .into_iter()
+        .filter(|note| user.can_read(note))
+        .filter(|note| tag.as_deref().is_none_or(|tag| tags::has_tag(note, tag)))
+        .filter(|note| {
+            query
+                .notebook
+                .as_ref()
+                .is_none_or(|notebook| note.notebook.as_ref() == Some(notebook))
+        })
+        .filter(|note| created_since.is_none_or(|since| note.created_at >= since))
+        .filter(|note| untouched_since.is_none_or(|cutoff| note.updated_at < cutoff))
+        .collect();
+    sort.apply(&mut notes);
+    if let Some(tag) = &tag {
+        // Stable, so pinned and unpinned notes each keep their order
+        notes.sort_by_key(|note| !note.is_pinned_in(tag));
+    }
+    if let Some(center) = &near {
+        // Closest first; notes without a location never match
+        let mut located: Vec<(f64, Note)> = notes
+            .into_iter()
+            .filter_map(|note| {
+                let distance = center.distance_km(note.location.as_ref()?);
+                (distance <= radius_km).then_some((distance, note))
+            })
+            .collect();
+        located.sort_by(|a, b| a.0.total_cmp(&b.0));
+        notes = located.into_iter().map(|(_, note)| note).collect();
+    }
+    let validators = Validators::from_notes(&notes, mgr.last_modified());
+    validators.respond(
+        &req,
+        &ApiResponse::success(notes, "Notes retrieved successfully"),
+    )
+}
+
+/// Plain-text representations of a note, for curl-friendly workflows, and
+/// a rendered page for browsers.
+#[derive(Debug, Clone, Copy)]
+enum TextFormat {
+    Plain,
+    Markdown,
+    Html,
+}
+
+impl TextFormat {
+    fn from_essence(essence: &str) -> Option<Self> {
+        match essence {
+            "text/plain" => Some(TextFormat::Plain),
+            "text/markdown" => Some(TextFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    /// The client's most preferred type, when it is one of ours.
+    fn accepted(req: &HttpRequest) -> Option<Self> {
+        let accept = req.get_header::<header::Accept>()?;
+        match accept.preference().essence_str() {
+            "text/html" => Some(TextFormat::Html),
+            essence => Self::from_essence(essence),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            TextFormat::Plain => "text/plain; charset=utf-8",
+            TextFormat::Markdown => "text/markdown; charset=utf-8",
+            TextFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    fn body(self, note: &Note, server: &ServerConfig) -> String {
+        match self {
+            TextFormat::Plain | TextFormat::Markdown => placeholders::resolve(note).content,
+            TextFormat::Html => html::render_page(note, &note.comments, &server.sanitizer),
+        }
+    }
+
+    /// The note in this format. Rendered pages come from the response cache
+    /// when the notes haven't changed.
+    fn respond(
+        self,
+        key: String,
+        note: &Note,
+        mgr: &NotesManager,
+        server: &ServerConfig,
+        cache: &SharedResponseCache,
+    ) -> HttpResponse {
+        let mut response = match self {
+            TextFormat::Plain | TextFormat::Markdown => HttpResponse::Ok()
+                .content_type(self.content_type())
+                .body(self.body(note, server)),
+            TextFormat::Html => cached(cache, key, mgr.generation(), || CachedResponse {
+                content_type: self.content_type(),
+                body: self.body(note, server),
+            }),
+        };
+        let headers = response.headers_mut();
+        headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+        if let TextFormat::Html = self {
+            // Second line of defense should anything get past the sanitizer
+            headers.insert(
+                header::CONTENT_SECURITY_POLICY,
+                HeaderValue::from_static(HTML_CSP),
+            );
+        }
+        response
+    }
+}
+
+const HTML_CSP: &str = "default-src 'none'; img-src * data:; style-src 'unsafe-inline'";
+
+fn is_text_body(ctx: &GuardContext) -> bool {
+    ctx.header::<header::ContentType>()
+        .and_then(|content_type| TextFormat::from_essence(content_type.essence_str()))
+        .is_some()
+}
+
+/// Splits a text body into title (first line, minus any Markdown heading
+/// marks) and content (the rest).
+fn parse_text_note(body: &str) -> (String, String) {
+    let (first_line, rest) = body.split_once('\n').unwrap_or((body, ""));
+    let title = first_line.trim().trim_start_matches('#').trim().to_string();
+    (title, rest.trim().to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct HighlightQuery {
+    highlight: Option<String>,
 }
 
 #[get("/api/notes/{id}")]
-async fn get_note(id: web::Path<String>, manager: web::Data<SharedNotesManager>) -> impl Responder {
+async fn get_note(
+    req: HttpRequest,
+    user: CurrentUser,
+    id: web::Path<String>,
+    query: web::Query<HighlightQuery>,
+    config: Settings,
+    manager: Vault,
+) -> impl Responder {
+    note_response(&req, &user, &id, &query, &config, &manager)
+}
+
+#[derive(Debug, Deserialize)]
+struct OnThisDayQuery {
+    /// The day to look back from, `YYYY-MM-DD`; today if absent
+    date: Option<NaiveDate>,
+}
+
+/// Readable notes written on this day (or `?date=`) in earlier years, each
+/// with how many years ago, the most recent first.
+#[get("/api/notes/on-this-day")]
+async fn notes_on_this_day(
+    user: CurrentUser,
+    query: web::Query<OnThisDayQuery>,
+    manager: Vault,
+) -> impl Responder {
     let mgr = manager.lock().unwrap();
+    let today = query
+        .date
+        .unwrap_or_else(|| mgr.now().with_timezone(&Local).date_naive());
+    let notes: Vec<Note> = mgr
+        .awake_notes()
+        .into_iter()
+        .filter(|note| user.can_read(note))
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(
+        timeline::on_this_day(notes, today),
+        "Notes from this day in earlier years",
+    ))
+}
 
-    match mgr.get_note(&id) {
-        Some(note) => {
-            HttpResponse::Ok().json(ApiResponse::success(note, "Note retrieved successfully"))
-        }
+/// The note with the given slug, as `GET /api/notes/{id}` serves it.
+#[get("/api/notes/by-slug/{slug}")]
+async fn get_note_by_slug(
+    req: HttpRequest,
+    user: CurrentUser,
+    slug: web::Path<String>,
+    query: web::Query<HighlightQuery>,
+    config: Settings,
+    manager: Vault,
+) -> impl Responder {
+    let id = manager
+        .lock()
+        .unwrap()
+        .get_note_by_slug(&slug)
+        .filter(|note| user.can_read(note))
+        .map(|note| note.id);
+    match id {
+        Some(id) => note_response(&req, &user, &id, &query, &config, &manager),
         None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
     }
 }
 
+fn note_response(
+    req: &HttpRequest,
+    user: &CurrentUser,
+    id: &str,
+    query: &HighlightQuery,
+    config: &Config,
+    manager: &Vault,
+) -> HttpResponse {
+    let mut mgr = manager.lock().unwrap();
+
+    let note = mgr.get_note(id).filter(|note| user.can_read(note));
+    if let (Some(note), Some(format)) = (&note, TextFormat::accepted(req)) {
+        let key = format!("{} {:?}", cache_key(req, user), format);
+        let note = mgr.record_view(&note.id).unwrap_or_else(|| note.clone());
+        return format.respond(key, &note, &mgr, &config.server, manager.cache());
+    }
+
+    match note {
+        // Highlighted responses vary by query, so they skip the cache validators
+        Some(note) => match query.highlight.as_deref() {
+            Some(terms) if !terms.trim().is_empty() => {
+                let note = mgr.record_view(&note.id).unwrap_or(note);
+                HttpResponse::Ok().json(ApiResponse::success(
+                    mgr.highlight_note(note, terms),
+                    "Note retrieved successfully",
+                ))
+            }
+            _ => {
+                // A client revalidating its copy hasn't viewed the note again
+                let validators = Validators::for_note(&note);
+                let note = if validators.is_fresh(req) {
+                    note
+                } else {
+                    mgr.record_view(&note.id).unwrap_or(note)
+                };
+                validators.respond(
+                    req,
+                    &ApiResponse::success(note, "Note retrieved successfully"),
+                )
+            }
+        },
+        // Links to a note merged into another lead to that one
+        None => match mgr.merged_into(id).filter(|note| user.can_read(note)) {
+            Some(merged) => HttpResponse::PermanentRedirect()
+                .insert_header((
+                    header::LOCATION,
+                    format!("{}/api/notes/{}", mount_prefix(req), merged.id),
+                ))
+                .finish(),
+            None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        },
+    }
+}
+
 #[post("/api/notes")]
 async fn create_note(
     req: web::Json<CreateNoteRequest>,
-    manager: web::Data<SharedNotesManager>,
+    query: web::Query<CreateQuery>,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
 ) -> impl Responder {
+    if let Some(Err(e)) = req.location.as_ref().map(GeoPoint::validate) {
+        return HttpResponse::BadRequest().json(ApiResponse::error(&e));
+    }
+    let title = match titles::resolve(&req.title, &req.content, &config.titles) {
+        Ok(title) => title,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+
+    let pending = {
+        let mut mgr = manager.lock().unwrap();
+        if let Some(parent_id) = &req.parent_id {
+            if !mgr.get_note(parent_id).is_some_and(|p| user.can_read(&p)) {
+                return HttpResponse::BadRequest()
+                    .json(ApiResponse::error("Parent note not found"));
+            }
+        }
+        if let Some(notebook) = &req.notebook {
+            let writable = mgr.get_notebook(notebook).is_some_and(|notebook| {
+                user.notebook_access(notebook) == Some(MemberAccess::Write)
+            });
+            if !writable {
+                return HttpResponse::BadRequest().json(ApiResponse::error(
+                    "Notebook not found or not open to your notes",
+                ));
+            }
+        }
+        if let Some(slug) = &req.slug {
+            match Note::parse_slug(slug) {
+                Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+                Ok(slug) if mgr.get_note_by_slug(&slug).is_some() => {
+                    return HttpResponse::Conflict()
+                        .json(ApiResponse::error(&format!("The slug '{}' is taken", slug)))
+                }
+                Ok(_) => {}
+            }
+        }
+        if !query.allow_duplicate {
+            if let Some(hint) = near_duplicate(&mgr, &user, &req.content) {
+                return duplicate_warning(hint);
+            }
+        }
+        let mut note = Note::new_at(title, req.content.clone(), req.tags.clone(), mgr.now());
+        note.owner = user.owner_id();
+        mgr.prepare_insert(note)
+    };
+    let pending = match pending {
+        Ok(pending) => run_processors(pending).await,
+        Err(e) => Err(e),
+    };
+    let pending = match pending {
+        Ok(pending) => pending,
+        Err(e) => return create_error(e),
+    };
+
+    // Parent, location, metadata, notebook and slug are set with the note in a single write
     let mut mgr = manager.lock().unwrap();
+    let created = mgr.batch(|mgr| {
+        mgr.insert_processed(pending)
+            .and_then(|note| match &req.parent_id {
+                Some(parent_id) => mgr
+                    .set_parent(&note.id, Some(parent_id.clone()))
+                    .map(|updated| updated.unwrap_or(note)),
+                None => Ok(note),
+            })
+            .and_then(|note| match &req.location {
+                Some(location) => mgr
+                    .set_location(&note.id, Some(location.clone()))
+                    .map(|updated| updated.unwrap_or(note)),
+                None => Ok(note),
+            })
+            .and_then(|note| match req.metadata.is_empty() {
+                false => mgr
+                    .set_metadata(&note.id, req.metadata.clone())
+                    .map(|updated| updated.unwrap_or(note)),
+                true => Ok(note),
+            })
+            .and_then(|note| match &req.notebook {
+                Some(notebook) => mgr
+                    .set_notebook(&note.id, Some(notebook))
+                    .map(|updated| updated.unwrap_or(note)),
+                None => Ok(note),
+            })
+            .and_then(|note| match &req.slug {
+                Some(slug) => mgr
+                    .set_slug(&note.id, Some(slug))
+                    .map(|updated| updated.unwrap_or(note)),
+                None => Ok(note),
+            })
+    });
 
-    match mgr.add_note(req.title.clone(), req.content.clone(), req.tags.clone()) {
+    match created {
         Ok(note) => {
             HttpResponse::Created().json(ApiResponse::success(note, "Note created successfully"))
         }
-        Err(e) => HttpResponse::InternalServerError()
-            .json(ApiResponse::error(&format!("Failed to create note: {}", e))),
+        Err(e) => create_error(e),
     }
 }
 
-#[put("/api/notes/{id}")]
-async fn update_note(
-    id: web::Path<String>,
-    req: web::Json<UpdateNoteRequest>,
-    manager: web::Data<SharedNotesManager>,
+// Registered ahead of `create_note`, whose JSON extractor would reject text bodies
+#[post("/api/notes", guard = "is_text_body")]
+async fn create_text_note(
+    body: String,
+    query: web::Query<CreateQuery>,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
 ) -> impl Responder {
-    let mut mgr = manager.lock().unwrap();
+    let (title, content) = parse_text_note(&body);
+    let title = match titles::resolve(&title, &content, &config.titles) {
+        Ok(title) => title,
+        Err(_) if !config.titles.auto => {
+            return HttpResponse::BadRequest()
+                .json(ApiResponse::error("The first line must contain a title"))
+        }
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
 
-    match mgr.update_note(
-        &id,
-        req.title.clone(),
-        req.content.clone(),
-        req.tags.clone(),
-    ) {
-        Ok(Some(note)) => {
-            HttpResponse::Ok().json(ApiResponse::success(note, "Note updated successfully"))
+    let pending = {
+        let mut mgr = manager.lock().unwrap();
+        if !query.allow_duplicate {
+            if let Some(hint) = near_duplicate(&mgr, &user, &content) {
+                return duplicate_warning(hint);
+            }
         }
-        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
-        Err(e) => HttpResponse::InternalServerError()
-            .json(ApiResponse::error(&format!("Failed to update note: {}", e))),
+        let mut note = Note::new_at(title, content, Vec::new(), mgr.now());
+        note.owner = user.owner_id();
+        mgr.prepare_insert(note)
+    };
+    match insert_note(&manager, pending).await {
+        Ok(note) => {
+            HttpResponse::Created().json(ApiResponse::success(note, "Note created successfully"))
+        }
+        Err(e) => create_error(e),
     }
 }
 
-#[delete("/api/notes/{id}")]
-async fn delete_note(
+#[derive(Debug, Deserialize)]
+struct LockRequest {
+    #[serde(default = "default_lease_secs")]
+    seconds: u64,
+}
+
+fn default_lease_secs() -> u64 {
+    locks::DEFAULT_LEASE_SECS
+}
+
+/// Takes a short edit lease on the note, or renews the caller's own lease
+/// (identified by `X-Lock-Holder`). Other clients' edits get 423 until it
+/// expires or is released.
+#[post("/api/notes/{id}/lock")]
+async fn lock_note(
+    http: HttpRequest,
     id: web::Path<String>,
-    manager: web::Data<SharedNotesManager>,
+    req: Option<web::Json<LockRequest>>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
 ) -> impl Responder {
-    let mut mgr = manager.lock().unwrap();
+    let now = {
+        let mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        mgr.now()
+    };
 
-    match mgr.delete_note(&id) {
-        Ok(true) => HttpResponse::Ok().json(ApiResponse::success((), "Note deleted successfully")),
-        Ok(false) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
-        Err(e) => HttpResponse::InternalServerError()
-            .json(ApiResponse::error(&format!("Failed to delete note: {}", e))),
+    let seconds = req.map_or(locks::DEFAULT_LEASE_SECS, |req| req.seconds);
+    let acquired =
+        locks
+            .lock()
+            .unwrap()
+            .acquire(&id, lock_holder(&http), user.owner_id(), seconds, now);
+    match acquired {
+        Ok(lease) => HttpResponse::Ok().json(ApiResponse::success(
+            LockResponse::new(lease, true),
+            "Lock acquired",
+        )),
+        Err(lease) => locked(lease),
     }
 }
 
-#[get("/api/notes/search/{query}")]
-async fn search_notes(
-    query: web::Path<String>,
-    manager: web::Data<SharedNotesManager>,
+#[get("/api/notes/{id}/lock")]
+async fn get_note_lock(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
 ) -> impl Responder {
-    let mgr = manager.lock().unwrap();
-    let notes = mgr.search_notes(&query);
+    let now = {
+        let mgr = manager.lock().unwrap();
+        if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+            return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+        }
+        mgr.now()
+    };
 
-    HttpResponse::Ok().json(ApiResponse::success(notes, "Search results"))
+    let lease = locks.lock().unwrap().active(&id, now).cloned();
+    HttpResponse::Ok().json(ApiResponse::success(
+        lease.map(|lease| LockResponse::new(lease, false)),
+        "Lock status retrieved",
+    ))
 }
 
-#[get("/")]
-async fn index() -> impl Responder {
-    // Try to load from file first
-    match std::fs::read_to_string("static/index.html") {
-        Ok(html) => {
-            println!("✓ Loaded HTML from static/index.html");
-            HttpResponse::Ok().content_type("text/html").body(html)
-        }
-        Err(err) => {
-            println!("✗ Could not load static/index.html: {}", err);
-            // Fallback HTML
-            let fallback = r#"<!DOCTYPE html>
-<html>
-<head>
-    <title>Rust Notes App</title>
-    <style>
-        body { font-family: Arial; padding: 20px; }
-        .container { max-width: 800px; margin: 0 auto; }
-    </style>
-</head>
-<body>
-    <div class="container">
-        <h1>📝 Rust Notes App</h1>
-        <p>Note: Could not load static/index.html file</p>
-        <p>API is running. Try these endpoints:</p>
-        <ul>
-            <li><a href="/api/notes">/api/notes</a> - List all notes</li>
-            <li><a href="/health">/health</a> - Health check</li>
-        </ul>
-    </div>
-</body>
-</html>"#;
-
-            HttpResponse::Ok().content_type("text/html").body(fallback)
+#[delete("/api/notes/{id}/lock")]
+async fn unlock_note(
+    http: HttpRequest,
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    let now = {
+        let mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
         }
+        mgr.now()
+    };
+
+    let released = locks.lock().unwrap().release(&id, lock_holder(&http), now);
+    match released {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse::success((), "Lock released")),
+        Ok(false) => HttpResponse::NotFound().json(ApiResponse::error("Note is not locked")),
+        Err(lease) => locked(lease),
     }
 }
 
-#[get("/health")]
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(ApiResponse::success("OK", "Server is running"))
+const AUTOMERGE_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Merging would need the plaintext on the server
+const ENCRYPTED_NO_MERGE: &str = "Encrypted notes can't be merged";
+
+/// The note's content as an Automerge document, for clients that edit
+/// offline and merge their changes back with `POST`.
+#[get("/api/notes/{id}/crdt")]
+async fn get_note_crdt(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    let note = match manager.lock().unwrap().get_note(&id) {
+        Some(note) if user.can_read(&note) => note,
+        _ => return HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    };
+    if note.is_encrypted() {
+        return HttpResponse::Conflict().json(ApiResponse::error(ENCRYPTED_NO_MERGE));
+    }
+
+    // Stored right away: later updates must share this document's history
+    match manager.documents().document(&note).and_then(|mut doc| {
+        manager.documents().save(&note.id, &mut doc)?;
+        Ok(doc.save())
+    }) {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type(AUTOMERGE_CONTENT_TYPE)
+            .body(bytes),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to load document: {}",
+            e
+        ))),
+    }
 }
 
-// endpoint to get stats
-#[get("/api/stats")]
-async fn get_stats(manager: web::Data<SharedNotesManager>) -> impl Responder {
-    let mgr = manager.lock().unwrap();
-    let notes = mgr.list_notes();
+/// Merges Automerge changes into the note's content and returns the merged
+/// document.
+#[post("/api/notes/{id}/crdt")]
+async fn merge_note_crdt(
+    id: web::Path<String>,
+    body: web::Bytes,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let (mut doc, pending) = {
+        let mut mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        let Some(note) = mgr.get_note(&id) else {
+            return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+        };
+        if note.is_encrypted() {
+            return HttpResponse::Conflict().json(ApiResponse::error(ENCRYPTED_NO_MERGE));
+        }
 
-    let total_notes = notes.len();
-    let all_tags: Vec<String> = notes.into_iter().flat_map(|note| note.tags).collect();
-    let unique_tags: std::collections::HashSet<String> = all_tags.into_iter().collect();
+        let (doc, text) = match manager.documents().merge(&note, &body) {
+            Ok(merged) => merged,
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+                return HttpResponse::Conflict().json(ApiResponse::error(&e.to_string()))
+            }
+            Err(e) => {
+                return HttpResponse::BadRequest().json(ApiResponse::error(&format!(
+                    "Invalid Automerge data: {}",
+                    e
+                )))
+            }
+        };
+        let pending = if text == note.content {
+            Ok(None)
+        } else {
+            mgr.prepare_update(&id, None, Some(text), None)
+        };
+        (doc, pending)
+    };
 
-    let stats = serde_json::json!({
-        "total_notes": total_notes,
-        "total_tags": unique_tags.len(),
-        "last_updated": chrono::Local::now().to_rfc3339()
-    });
+    let saved = match pending {
+        Ok(Some(pending)) => match run_processors(pending).await {
+            Ok(pending) => {
+                let mut mgr = manager.lock().unwrap();
+                if mgr.is_stale(&pending) {
+                    return HttpResponse::Conflict().json(ApiResponse::error(
+                        "Note changed while the merge was being saved; merge again",
+                    ));
+                }
+                mgr.update_processed(pending).map(|_| ())
+            }
+            Err(e) => Err(e),
+        },
+        other => other.map(|_| ()),
+    };
+    match saved.and_then(|_| manager.documents().save(&id, &mut doc)) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(AUTOMERGE_CONTENT_TYPE)
+            .body(doc.save()),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to merge changes: {}",
+            e
+        ))),
+    }
+}
 
-    HttpResponse::Ok().json(ApiResponse::success(stats, "Stats retrieved"))
-}
-
-pub async fn start_web_server(manager: SharedNotesManager) {
-    println!("🌐 Web server starting on http://localhost:8080");
-    println!("📱 Access at http://localhost:8080");
-    println!("📚 API at http://localhost:8080/api/*");
-    println!("{}", "─".repeat(60));
-
-    let manager_data = web::Data::new(manager);
-
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
-
-        App::new()
-            .wrap(cors)
-            .app_data(manager_data.clone())
-            .service(index)
-            .service(get_notes)
-            .service(get_note)
-            .service(create_note)
-            .service(update_note)
-            .service(delete_note)
-            .service(search_notes)
-            .service(health_check)
-    })
-    .bind("127.0.0.1:8080")
-    .expect("Failed to bind to address")
-    .run()
-    .await
-    .expect("Failed to run server");
+#[derive(Debug, Deserialize)]
+struct CaptureRequest {
+    url: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Fetches a web page and saves its article as a Markdown note.
+#[post("/api/notes/from-url")]
+async fn create_note_from_url(
+    req: web::Json<CaptureRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let CaptureRequest { url, tags } = req.into_inner();
+    if let Err(e) = capture::parse_url(&url) {
+        return HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()));
+    }
+
+    let captured = match web::block(move || capture::capture(&url)).await {
+        Ok(Ok(captured)) => captured,
+        Ok(Err(e)) => {
+            return HttpResponse::BadGateway().json(ApiResponse::error(&e.to_string()));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to capture page: {}",
+                e
+            )))
+        }
+    };
+
+    let pending = {
+        let mut mgr = manager.lock().unwrap();
+        let mut note = captured.into_note(&tags, mgr.now());
+        note.owner = user.owner_id();
+        mgr.prepare_insert(note)
+    };
+    match insert_note(&manager, pending).await {
+        Ok(note) => {
+            HttpResponse::Created().json(ApiResponse::success(note, "Page captured successfully"))
+        }
+        Err(e) => create_error(e),
+    }
+}
+
+#[put("/api/notes/{id}")]
+async fn update_note(
+    http: HttpRequest,
+    id: web::Path<String>,
+    req: web::Json<UpdateNoteRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    // None when the edit was made to an outdated version
+    let pending = {
+        let mut mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        if let Err(response) = check_lock(&http, &locks, &id, mgr.now()) {
+            return response;
+        }
+
+        let stale = mgr.get_note(&id).is_some_and(|note| {
+            req.base_updated_at
+                .is_some_and(|base| note.updated_at > base)
+                || !Validators::for_note(&note).is_matched_by(&http)
+        });
+        if stale {
+            None
+        } else {
+            if let Some(parent_id) = req.parent_id.as_deref().filter(|p| !p.is_empty()) {
+                if !mgr.get_note(parent_id).is_some_and(|p| user.can_read(&p)) {
+                    return HttpResponse::BadRequest()
+                        .json(ApiResponse::error("Parent note not found"));
+                }
+            }
+
+            // Re-parent first so a rejected move leaves the note untouched
+            if let Some(parent_id) = &req.parent_id {
+                let parent_id = Some(parent_id.clone()).filter(|p| !p.is_empty());
+                if let Err(e) = mgr.set_parent(&id, parent_id) {
+                    return update_error(e);
+                }
+            }
+            // Encrypted notes aren't sent to processors
+            if let Some(passphrase) = &req.passphrase {
+                return changed(
+                    mgr.update_encrypted_note(
+                        &id,
+                        req.title.clone(),
+                        req.content.clone(),
+                        req.tags.clone(),
+                        passphrase,
+                    ),
+                    "Note updated successfully",
+                );
+            }
+            Some(mgr.prepare_update(
+                &id,
+                req.title.clone(),
+                req.content.clone(),
+                req.tags.clone(),
+            ))
+        }
+    };
+    let Some(pending) = pending else {
+        return save_conflict_copy(&manager, &id, &req).await;
+    };
+
+    let processed = match pending {
+        Ok(Some(pending)) => run_processors(pending).await,
+        other => return changed(other.map(|_| None), "Note updated successfully"),
+    };
+    let updated = match processed {
+        // Edited by someone else while the processors ran
+        Ok(pending) => {
+            let mut mgr = manager.lock().unwrap();
+            (!mgr.is_stale(&pending)).then(|| mgr.update_processed(pending))
+        }
+        Err(e) => Some(Err(e)),
+    };
+    match updated {
+        Some(updated) => changed(updated, "Note updated successfully"),
+        None => save_conflict_copy(&manager, &id, &req).await,
+    }
+}
+
+// Keeps an edit made to an outdated version of the note as a separate note
+async fn save_conflict_copy(
+    manager: &SharedNotesManager,
+    id: &str,
+    req: &UpdateNoteRequest,
+) -> HttpResponse {
+    let pending = manager.lock().unwrap().prepare_conflict_copy(
+        id,
+        req.title.clone(),
+        req.content.clone(),
+        req.tags.clone(),
+    );
+    let copy = match pending {
+        Ok(Some(pending)) => insert_note(manager, Ok(pending)).await.map(Some),
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    match copy {
+        Ok(copy) => HttpResponse::Conflict().json(ApiResponse {
+            success: false,
+            message: "Note changed since the version you edited; yours was saved as a copy"
+                .to_string(),
+            data: copy,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to save conflict copy: {}",
+            e
+        ))),
+    }
+}
+
+/// Header a permanent delete has to carry, set to the id of the note.
+pub const CONFIRM_DELETE_HEADER: &str = "X-Confirm-Delete";
+
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Deletion {
+    /// Moved to the trash, where it can be restored
+    Soft,
+    Permanent,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteResult {
+    id: String,
+    deletion: Deletion,
+}
+
+/// Moves the note to the trash. With `?force=true` and the note's id in
+/// `X-Confirm-Delete`, it's deleted for good instead.
+#[delete("/api/notes/{id}")]
+async fn delete_note(
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<DeleteQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = check_write_access(&mgr, &user, &id) {
+        return response;
+    }
+
+    if !query.force {
+        // Deleting a note that's already in the trash leaves it there as it is
+        let trashed = match mgr.get_note(&id) {
+            Some(note) if note.is_trashed() => Ok(Some(note)),
+            _ => mgr.set_trashed(&id, true),
+        };
+        return match trashed {
+            Ok(Some(_)) => HttpResponse::Ok().json(ApiResponse::success(
+                DeleteResult {
+                    id: id.into_inner(),
+                    deletion: Deletion::Soft,
+                },
+                "Note moved to trash",
+            )),
+            Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+            Err(e) => update_error(e),
+        };
+    }
+
+    let confirmed = req
+        .headers()
+        .get(CONFIRM_DELETE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == id.as_str());
+    if !confirmed {
+        return HttpResponse::PreconditionRequired().json(ApiResponse::error(&format!(
+            "Deleting a note for good needs its id in the {} header",
+            CONFIRM_DELETE_HEADER
+        )));
+    }
+    match mgr.delete_note(&id) {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse::success(
+            DeleteResult {
+                id: id.into_inner(),
+                deletion: Deletion::Permanent,
+            },
+            "Note deleted permanently",
+        )),
+        Ok(false) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to delete note: {}", e))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkDeleteQuery {
+    tag: Option<String>,
+    /// Untouched for at least this long, e.g. `90d`
+    older_than: Option<String>,
+    query: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct BulkDeleteResult {
+    deletion: Deletion,
+    dry_run: bool,
+    count: usize,
+    ids: Vec<String>,
+}
+
+/// Moves every note matching `tag`, `older_than` and `query` that the
+/// caller may edit to the trash. `dry_run=true` only reports them; with
+/// `force=true` and their count in `X-Confirm-Delete`, they're deleted for
+/// good instead (trashed ones included).
+#[delete("/api/notes")]
+async fn bulk_delete_notes(
+    req: HttpRequest,
+    query: web::Query<BulkDeleteQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let query = query.into_inner();
+    let filter = CleanupFilter {
+        tag: query.tag,
+        older_than: query.older_than,
+        query: query.query,
+    };
+    let mut mgr = manager.lock().unwrap();
+    let notes = match cleanup::matching(&mgr, &filter, query.force, &|note| user.can_write(note)) {
+        Ok(notes) => notes,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+    let ids: Vec<String> = notes.into_iter().map(|note| note.id).collect();
+    let (deletion, verb) = if query.force {
+        (Deletion::Permanent, "deleted permanently")
+    } else {
+        (Deletion::Soft, "moved to trash")
+    };
+    let result = |ids: Vec<String>| BulkDeleteResult {
+        deletion,
+        dry_run: query.dry_run,
+        count: ids.len(),
+        ids,
+    };
+
+    if query.dry_run {
+        let message = format!("Dry run: {} notes would be {}", ids.len(), verb);
+        return HttpResponse::Ok().json(ApiResponse::success(result(ids), &message));
+    }
+    if query.force {
+        let confirmed = req
+            .headers()
+            .get(CONFIRM_DELETE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim() == ids.len().to_string());
+        if !confirmed {
+            return HttpResponse::PreconditionRequired().json(ApiResponse::error(&format!(
+                "Deleting {} notes for good needs that count in the {} header",
+                ids.len(),
+                CONFIRM_DELETE_HEADER
+            )));
+        }
+    }
+
+    let done = if query.force {
+        mgr.delete_notes(&ids)
+    } else {
+        mgr.trash_notes(&ids)
+    };
+    match done {
+        Ok(count) => HttpResponse::Ok().json(ApiResponse::success(
+            result(ids),
+            &format!("{} notes {}", count, verb),
+        )),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to delete notes: {}",
+            e
+        ))),
+    }
+}
+
+/// The note's section headings with their anchors; the rendered page (and
+/// `/share/{token}`) links to `#<slug>`.
+#[get("/api/notes/{id}/outline")]
+async fn note_outline(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    match manager
+        .lock()
+        .unwrap()
+        .get_note(&id)
+        .filter(|note| user.can_read(note))
+    {
+        Some(note) => HttpResponse::Ok().json(ApiResponse::success(
+            note.outline,
+            "Outline retrieved successfully",
+        )),
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+/// The note's broken links: wiki-links naming no note you can see, and web
+/// links that fail or don't answer in time.
+#[get("/api/notes/{id}/link-check")]
+async fn check_note_links(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
+) -> impl Responder {
+    let pending = {
+        let mgr = manager.lock().unwrap();
+        let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+            return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+        };
+        let visible = mgr
+            .notes
+            .iter()
+            .filter(|note| !note.is_trashed() && user.can_read(note));
+        links::prepare(&note, visible)
+    };
+
+    let links_config = config.links.clone();
+    let checked = web::block(move || {
+        pending.finish(&links::Checker::new(&links_config), &mut HashMap::new())
+    });
+    match checked.await {
+        Ok(report) => {
+            let message = if report.broken.is_empty() {
+                "No broken links".to_string()
+            } else {
+                format!("{} broken link(s)", report.broken.len())
+            };
+            HttpResponse::Ok().json(ApiResponse::success(report, &message))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to check links: {}", e))),
+    }
+}
+
+#[get("/api/notes/{id}/children")]
+async fn note_children(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    }
+
+    let children: Vec<Note> = mgr
+        .children(&id)
+        .into_iter()
+        .filter(|note| user.can_read(note))
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(
+        children,
+        "Children retrieved successfully",
+    ))
+}
+
+/// The note with all its sub-notes nested under `children`.
+#[get("/api/notes/{id}/thread")]
+async fn note_thread(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    match mgr.thread(&id, &|note| user.can_read(note)) {
+        Some(thread) => HttpResponse::Ok().json(ApiResponse::success(
+            thread,
+            "Thread retrieved successfully",
+        )),
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestQuery {
+    limit: Option<usize>,
+}
+
+/// Tags worth adding to a note, learned from how existing notes are tagged.
+#[get("/api/notes/{id}/suggested-tags")]
+async fn suggested_tags(
+    id: web::Path<String>,
+    query: web::Query<SuggestQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+
+    // Only notes the caller can read, or others' tags and words would leak
+    let readable: Vec<Note> = mgr
+        .list_notes()
+        .into_iter()
+        .filter(|note| user.can_read(note))
+        .collect();
+    let suggestions = suggest::suggest_tags(
+        &readable,
+        Some(&note.id),
+        &format!("{}\n{}", note.title, note.content),
+        &note.tags,
+        query.limit.unwrap_or(5).min(20),
+    );
+    HttpResponse::Ok().json(ApiResponse::success(suggestions, "Suggested tags"))
+}
+
+/// Other notes on the same topic, most alike first, to resurface old ones.
+#[get("/api/notes/{id}/related")]
+async fn related_notes(
+    id: web::Path<String>,
+    query: web::Query<SuggestQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+
+    let readable: Vec<Note> = mgr
+        .list_notes()
+        .into_iter()
+        .filter(|note| user.can_read(note))
+        .collect();
+    let related = suggest::related_notes(&readable, &note, query.limit.unwrap_or(5).min(20));
+    HttpResponse::Ok().json(ApiResponse::success(related, "Related notes"))
+}
+
+#[get("/api/notes/{id}/comments")]
+async fn list_comments(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    match manager.lock().unwrap().get_note(&id) {
+        Some(note) if user.can_read(&note) => {
+            HttpResponse::Ok().json(ApiResponse::success(note.comments, "Comments retrieved"))
+        }
+        _ => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentRequest {
+    body: String,
+}
+
+// Comments and reactions are signed by an account (or the local user); a
+// note token only grants a look at the note
+fn check_participant(user: &CurrentUser) -> Result<(), HttpResponse> {
+    match user {
+        CurrentUser::NoteToken(_) => Err(HttpResponse::Forbidden()
+            .json(ApiResponse::error("Note tokens can't comment or react"))),
+        _ => Ok(()),
+    }
+}
+
+/// Leaves a comment on a note the caller can read.
+#[post("/api/notes/{id}/comments")]
+async fn add_comment(
+    id: web::Path<String>,
+    req: web::Json<CommentRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_participant(&user) {
+        return response;
+    }
+    let mut mgr = manager.lock().unwrap();
+    if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    }
+    let author_name = match &user {
+        CurrentUser::User(account) => Some(account.username.clone()),
+        _ => None,
+    };
+    match mgr.add_comment(&id, user.owner_id(), author_name, &req.body) {
+        Ok(Some(comment)) => {
+            HttpResponse::Created().json(ApiResponse::success(comment, "Comment added"))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Removes one of the caller's own comments.
+#[delete("/api/notes/{id}/comments/{comment_id}")]
+async fn delete_comment(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_participant(&user) {
+        return response;
+    }
+    let (id, comment_id) = path.into_inner();
+    let mut mgr = manager.lock().unwrap();
+    let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+    let Some(comment) = note.comments.iter().find(|c| c.id == comment_id) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Comment not found"));
+    };
+    if comment.author != user.owner_id() {
+        return HttpResponse::Forbidden()
+            .json(ApiResponse::error("Only its author can delete a comment"));
+    }
+    match mgr.delete_comment(&id, &comment_id) {
+        Ok(Some(comment)) => {
+            HttpResponse::Ok().json(ApiResponse::success(comment, "Comment deleted"))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Comment not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReactionRequest {
+    emoji: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReactionsResponse {
+    note_id: String,
+    reactions: Vec<ReactionCount>,
+    /// The caller's own emoji on the note
+    mine: Vec<String>,
+}
+
+impl ReactionsResponse {
+    fn new(note: &Note, user: &CurrentUser) -> Self {
+        let me = user.owner_id();
+        Self {
+            note_id: note.id.clone(),
+            reactions: note.reaction_counts(),
+            mine: note
+                .reactions
+                .iter()
+                .filter(|r| r.user == me)
+                .map(|r| r.emoji.clone())
+                .collect(),
+        }
+    }
+}
+
+#[get("/api/notes/{id}/reactions")]
+async fn list_reactions(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    match manager.lock().unwrap().get_note(&id) {
+        Some(note) if user.can_read(&note) => HttpResponse::Ok().json(ApiResponse::success(
+            ReactionsResponse::new(&note, &user),
+            "Reactions retrieved",
+        )),
+        _ => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+/// Puts an emoji on a note the caller can read: `201` when it's new, `200`
+/// when they had already reacted with it.
+#[post("/api/notes/{id}/reactions")]
+async fn add_reaction(
+    id: web::Path<String>,
+    req: web::Json<ReactionRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_participant(&user) {
+        return response;
+    }
+    let mut mgr = manager.lock().unwrap();
+    if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    }
+    match mgr.add_reaction(&id, user.owner_id(), &req.emoji) {
+        Ok(Some((note, true))) => HttpResponse::Created().json(ApiResponse::success(
+            ReactionsResponse::new(&note, &user),
+            "Reaction added",
+        )),
+        Ok(Some((note, false))) => HttpResponse::Ok().json(ApiResponse::success(
+            ReactionsResponse::new(&note, &user),
+            "Already reacted with this emoji",
+        )),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Takes the caller's emoji off a note.
+#[delete("/api/notes/{id}/reactions/{emoji}")]
+async fn remove_reaction(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_participant(&user) {
+        return response;
+    }
+    let (id, emoji) = path.into_inner();
+    let mut mgr = manager.lock().unwrap();
+    if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    }
+    match mgr.remove_reaction(&id, user.owner_id().as_deref(), &emoji) {
+        Ok(Some((note, true))) => HttpResponse::Ok().json(ApiResponse::success(
+            ReactionsResponse::new(&note, &user),
+            "Reaction removed",
+        )),
+        Ok(Some(_)) => {
+            HttpResponse::NotFound().json(ApiResponse::error("You haven't reacted with this emoji"))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+// Shared by the trash/restore/archive/unarchive endpoints
+fn update_error(e: std::io::Error) -> HttpResponse {
+    match e.kind() {
+        std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        std::io::ErrorKind::PermissionDenied => {
+            HttpResponse::Forbidden().json(ApiResponse::error(&e.to_string()))
+        }
+        std::io::ErrorKind::AlreadyExists => {
+            HttpResponse::Conflict().json(ApiResponse::error(&e.to_string()))
+        }
+        std::io::ErrorKind::StorageFull => quota_exceeded(e),
+        _ => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to update note: {}", e))),
+    }
+}
+
+// 507 for a change that doesn't fit the vault's or the user's quota
+fn quota_exceeded(e: std::io::Error) -> HttpResponse {
+    HttpResponse::InsufficientStorage().json(ApiResponse::error(&e.to_string()))
+}
+
+// Pre-save processors can take seconds to answer: they run on the blocking
+// pool with the vault unlocked
+async fn run_processors(pending: PendingSave) -> std::io::Result<PendingSave> {
+    if !pending.has_processors() {
+        return Ok(pending);
+    }
+    web::block(move || pending.run())
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+// Finishes a `prepare_insert` made with the vault locked
+async fn insert_note(
+    manager: &SharedNotesManager,
+    pending: std::io::Result<PendingSave>,
+) -> std::io::Result<Note> {
+    let pending = run_processors(pending?).await?;
+    manager.lock().unwrap().insert_processed(pending)
+}
+
+fn create_error(e: std::io::Error) -> HttpResponse {
+    match e.kind() {
+        // Refused by a script or processor, or an invalid slug
+        std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        std::io::ErrorKind::StorageFull => quota_exceeded(e),
+        _ => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to create note: {}", e))),
+    }
+}
+
+fn change_state(
+    manager: &SharedNotesManager,
+    user: &CurrentUser,
+    id: &str,
+    change: impl FnOnce(&mut NotesManager) -> std::io::Result<Option<Note>>,
+    message: &str,
+) -> HttpResponse {
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = check_write_access(&mgr, user, id) {
+        return response;
+    }
+    changed(change(&mut mgr), message)
+}
+
+/// Like [`change_state`], for edits another client's lease holds off
+/// (`423`). Access is checked first, so the lease of a note the caller
+/// can't edit isn't given away.
+fn change_unleased(
+    http: &HttpRequest,
+    locks: &SharedEditLocks,
+    manager: &SharedNotesManager,
+    user: &CurrentUser,
+    id: &str,
+    change: impl FnOnce(&mut NotesManager) -> std::io::Result<Option<Note>>,
+    message: &str,
+) -> HttpResponse {
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) =
+        check_write_access(&mgr, user, id).and_then(|_| check_lock(http, locks, id, mgr.now()))
+    {
+        return response;
+    }
+    changed(change(&mut mgr), message)
+}
+
+/// Like [`change_state`], for changes only the note's owner (or an admin)
+/// may make.
+fn change_managed(
+    manager: &SharedNotesManager,
+    user: &CurrentUser,
+    id: &str,
+    change: impl FnOnce(&mut NotesManager) -> std::io::Result<Option<Note>>,
+    message: &str,
+) -> HttpResponse {
+    let mut mgr = manager.lock().unwrap();
+    match mgr.get_note(id) {
+        Some(note) if user.can_manage(&note) => {}
+        Some(note) if user.can_read(&note) => {
+            return HttpResponse::Forbidden()
+                .json(ApiResponse::error("Only the note's owner can do that"))
+        }
+        _ => return HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+    changed(change(&mut mgr), message)
+}
+
+fn changed(result: std::io::Result<Option<Note>>, message: &str) -> HttpResponse {
+    match result {
+        Ok(Some(note)) => HttpResponse::Ok().json(ApiResponse::success(note, message)),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PassphraseRequest {
+    passphrase: String,
+}
+
+/// Encrypts the note's content; from then on it reads as a placeholder
+/// until unlocked.
+#[post("/api/notes/{id}/encrypt")]
+async fn encrypt_note(
+    id: web::Path<String>,
+    req: web::Json<PassphraseRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = check_write_access(&mgr, &user, &id) {
+        return response;
+    }
+    match mgr.encrypt_note(&id, &req.passphrase) {
+        Ok(Some(note)) => {
+            // The merge document holds the plaintext
+            if let Err(e) = manager.documents().remove(&id) {
+                return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                    "Note encrypted, but its merge document couldn't be removed: {}",
+                    e
+                )));
+            }
+            HttpResponse::Ok().json(ApiResponse::success(note, "Note encrypted"))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// The note with its content decrypted. Nothing is stored: the note stays
+/// encrypted.
+#[post("/api/notes/{id}/unlock")]
+async fn unlock_encrypted_note(
+    id: web::Path<String>,
+    req: web::Json<PassphraseRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    if !mgr.get_note(&id).is_some_and(|note| user.can_read(&note)) {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    }
+    match mgr.unlock_note(&id, &req.passphrase) {
+        Ok(Some(note)) => HttpResponse::Ok().json(ApiResponse::success(note, "Note unlocked")),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Removes the note's encryption for good.
+#[post("/api/notes/{id}/decrypt")]
+async fn decrypt_note(
+    id: web::Path<String>,
+    req: web::Json<PassphraseRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.decrypt_note(&id, &req.passphrase),
+        "Note decrypted",
+    )
+}
+
+#[put("/api/notes/{id}/location")]
+async fn set_note_location(
+    http: HttpRequest,
+    id: web::Path<String>,
+    location: web::Json<GeoPoint>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    change_unleased(
+        &http,
+        &locks,
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_location(&id, Some(location.into_inner())),
+        "Location set",
+    )
+}
+
+#[delete("/api/notes/{id}/location")]
+async fn clear_note_location(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_location(&id, None),
+        "Location cleared",
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct DueRequest {
+    due: String,
+}
+
+/// Sets when the note is due (`3d`, `2026-01-31 09:00`, RFC 3339, ...);
+/// due notes show up in the calendar feed.
+#[put("/api/notes/{id}/due")]
+async fn set_note_due(
+    http: HttpRequest,
+    id: web::Path<String>,
+    req: web::Json<DueRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    let due = match snooze::parse_when(&req.due, manager.lock().unwrap().now()) {
+        Ok(due) => due,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+    change_unleased(
+        &http,
+        &locks,
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_due(&id, Some(due)),
+        "Due date set",
+    )
+}
+
+#[delete("/api/notes/{id}/due")]
+async fn clear_note_due(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_due(&id, None),
+        "Due date cleared",
+    )
+}
+
+/// Replaces the note's metadata fields with the JSON object sent, e.g.
+/// `{"project": "Apollo"}`; the content shows them with `{{metadata.project}}`.
+#[put("/api/notes/{id}/metadata")]
+async fn set_note_metadata(
+    http: HttpRequest,
+    id: web::Path<String>,
+    req: web::Json<BTreeMap<String, Value>>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    let metadata = req.into_inner();
+    change_unleased(
+        &http,
+        &locks,
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_metadata(&id, metadata),
+        "Metadata updated",
+    )
+}
+
+#[delete("/api/notes/{id}/metadata")]
+async fn clear_note_metadata(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_metadata(&id, BTreeMap::new()),
+        "Metadata cleared",
+    )
+}
+
+/// Asks the configured language model for a summary of the note and keeps
+/// it in the note's metadata as `summary`. The vault isn't held while the
+/// model writes.
+#[post("/api/notes/{id}/summarize")]
+async fn summarize_note(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
+) -> impl Responder {
+    let note = {
+        let mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        mgr.get_note(&id)
+    };
+    let Some(note) = note else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+
+    let summary_config = config.summary.clone();
+    let summary = match web::block(move || summarize::summarize(&summary_config, &note)).await {
+        Ok(Ok(summary)) => summary,
+        Ok(Err(e)) => {
+            let mut response = match e.kind() {
+                std::io::ErrorKind::Unsupported => HttpResponse::ServiceUnavailable(),
+                std::io::ErrorKind::InvalidInput => HttpResponse::Conflict(),
+                _ => HttpResponse::BadGateway(),
+            };
+            return response.json(ApiResponse::error(&e.to_string()));
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to summarize note: {}",
+                e
+            )))
+        }
+    };
+    changed(
+        summarize::save_summary(&mut manager.lock().unwrap(), &id, summary),
+        "Note summarized",
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct SlugRequest {
+    slug: String,
+}
+
+/// Gives the note a memorable address, `/api/notes/by-slug/{slug}`.
+#[put("/api/notes/{id}/slug")]
+async fn set_note_slug(
+    http: HttpRequest,
+    id: web::Path<String>,
+    req: web::Json<SlugRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    change_unleased(
+        &http,
+        &locks,
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_slug(&id, Some(&req.slug)),
+        "Slug updated",
+    )
+}
+
+#[delete("/api/notes/{id}/slug")]
+async fn clear_note_slug(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_slug(&id, None),
+        "Slug removed",
+    )
+}
+
+/// Due dates and snooze reminders of the notes the caller can read, for
+/// subscribing from a calendar app. With accounts, pass a token as
+/// `?access_token=`.
+#[get("/calendar.ics")]
+async fn calendar_feed(
+    req: HttpRequest,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
+) -> impl Responder {
+    let (notes, now) = {
+        let mgr = manager.lock().unwrap();
+        let notes: Vec<Note> = mgr
+            .notes
+            .iter()
+            .filter(|note| user.can_read(note))
+            .cloned()
+            .collect();
+        (notes, mgr.now())
+    };
+    let name = match query_vault(&req) {
+        Some(vault) => format!("Notes ({})", vault),
+        None => "Notes".to_string(),
+    };
+    let body = ics::calendar(&notes, &name, &public_url(&req, &config.server), now);
+    HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(body)
+}
+
+#[derive(Debug, Deserialize)]
+struct ProgressRequest {
+    percent: Option<f64>,
+    anchor: Option<String>,
+}
+
+// Whose place in the notes progress is kept for; a note token has its own
+fn reader_key(user: &CurrentUser) -> String {
+    match user {
+        CurrentUser::NoteToken(token) => format!("token:{}", token.id),
+        _ => user_key(user).unwrap_or_default().to_string(),
+    }
+}
+
+// The time now if the user can read the note, 404 otherwise
+fn check_read_access(
+    manager: &SharedNotesManager,
+    user: &CurrentUser,
+    id: &str,
+) -> Result<DateTime<Utc>, HttpResponse> {
+    let mgr = manager.lock().unwrap();
+    match mgr.get_note(id) {
+        Some(note) if user.can_read(&note) => Ok(mgr.now()),
+        _ => Err(HttpResponse::NotFound().json(ApiResponse::error("Note not found"))),
+    }
+}
+
+/// Where the caller left off reading the note; `null` if they haven't
+/// saved a place yet.
+#[get("/api/notes/{id}/progress")]
+async fn get_reading_progress(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_read_access(&manager, &user, &id) {
+        return response;
+    }
+    let store = manager.progress().lock().unwrap();
+    let progress = store.get(&reader_key(&user), &id).cloned();
+    HttpResponse::Ok().json(ApiResponse::success(progress, "Reading progress retrieved"))
+}
+
+/// Saves where the caller left off reading the note. Fields left out keep
+/// their previous value. Reading isn't editing: anyone who can read the
+/// note may save their place, and the note itself is left alone.
+#[patch("/api/notes/{id}/progress")]
+async fn set_reading_progress(
+    id: web::Path<String>,
+    req: web::Json<ProgressRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let req = req.into_inner();
+    if req.percent.is_none() && req.anchor.is_none() {
+        return HttpResponse::BadRequest()
+            .json(ApiResponse::error("Send a percent, an anchor or both"));
+    }
+    let now = match check_read_access(&manager, &user, &id) {
+        Ok(now) => now,
+        Err(response) => return response,
+    };
+    let reader = reader_key(&user);
+    let mut store = manager.progress().lock().unwrap();
+    let previous = store.get(&reader, &id).cloned();
+    let progress = ReadingProgress {
+        percent: req
+            .percent
+            .or(previous.as_ref().map(|progress| progress.percent))
+            .unwrap_or(0.0),
+        anchor: req.anchor.or(previous.and_then(|progress| progress.anchor)),
+        updated_at: now,
+    };
+    match store.set(&reader, &id, Some(progress.clone())) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse::success(progress, "Reading progress saved")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[delete("/api/notes/{id}/progress")]
+async fn clear_reading_progress(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let Err(response) = check_read_access(&manager, &user, &id) {
+        return response;
+    }
+    let mut store = manager.progress().lock().unwrap();
+    match store.set(&reader_key(&user), &id, None) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse::success((), "Reading progress cleared")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[post("/api/notes/{id}/trash")]
+async fn trash_note(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_trashed(&id, true),
+        "Note moved to trash",
+    )
+}
+
+#[post("/api/notes/{id}/restore")]
+async fn restore_note(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_trashed(&id, false),
+        "Note restored from trash",
+    )
+}
+
+#[post("/api/notes/{id}/archive")]
+async fn archive_note(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_archived(&id, true),
+        "Note archived",
+    )
+}
+
+#[post("/api/notes/{id}/unarchive")]
+async fn unarchive_note(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_archived(&id, false),
+        "Note unarchived",
+    )
+}
+
+/// Lists the note first whenever notes are filtered by the tag (nested
+/// tags like `work/alpha` included); the unfiltered list is unaffected.
+#[put("/api/notes/{id}/pins/{tag:.*}")]
+async fn pin_note(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let (id, tag) = path.into_inner();
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_pinned_in(&id, &tag, true),
+        &format!("Note pinned in '{}'", tag),
+    )
+}
+
+#[delete("/api/notes/{id}/pins/{tag:.*}")]
+async fn unpin_note(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let (id, tag) = path.into_inner();
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_pinned_in(&id, &tag, false),
+        &format!("Note unpinned from '{}'", tag),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TransferRequest {
+    /// Username or id of the new owner
+    to: String,
+    /// Admins only: hand the note over without waiting for acceptance
+    #[serde(default)]
+    force: bool,
+}
+
+// The account a note is handed to or shared with, by username or id.
+// Only account holders hand notes around, not note tokens
+fn find_account(
+    users: &SharedUserStore,
+    user: &CurrentUser,
+    key: &str,
+) -> Result<User, HttpResponse> {
+    if let CurrentUser::NoteToken(_) = user {
+        return Err(HttpResponse::Forbidden().json(ApiResponse::error(
+            "Note tokens can't transfer or share notes",
+        )));
+    }
+    let users = users.lock().unwrap();
+    if !users.is_enabled() {
+        return Err(HttpResponse::BadRequest().json(ApiResponse::error(
+            "Transfers and sharing need user accounts",
+        )));
+    }
+    users.find(key).ok_or_else(|| {
+        HttpResponse::NotFound().json(ApiResponse::error(&format!("No user '{}'", key)))
+    })
+}
+
+/// Offers the note to another user, who owns it once they accept; admins
+/// can `force` the handover instead.
+#[post("/api/notes/{id}/transfer")]
+async fn transfer_note(
+    id: web::Path<String>,
+    body: web::Json<TransferRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let recipient = match find_account(&users, &user, &body.to) {
+        Ok(recipient) => recipient,
+        Err(response) => return response,
+    };
+    if recipient.role == Role::Viewer {
+        return HttpResponse::BadRequest().json(ApiResponse::error(&format!(
+            "{} is a viewer and can't own notes",
+            recipient.username
+        )));
+    }
+    if recipient.is_disabled() {
+        return HttpResponse::BadRequest().json(ApiResponse::error(&format!(
+            "{}'s account is disabled",
+            recipient.username
+        )));
+    }
+    if !body.force {
+        return change_managed(
+            &manager,
+            &user,
+            &id,
+            |mgr| mgr.request_transfer(&id, &recipient.id, user.owner_id()),
+            &format!("Waiting for {} to accept the note", recipient.username),
+        );
+    }
+    if user.role() != Role::Admin {
+        return HttpResponse::Forbidden().json(ApiResponse::error(
+            "Only admins can transfer a note without acceptance",
+        ));
+    }
+    change_managed(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.transfer_note(&id, &recipient.id),
+        &format!("Note transferred to {}", recipient.username),
+    )
+}
+
+// Whether the note waits for `user` to accept it
+fn offered_to(note: &Note, user: &CurrentUser) -> bool {
+    let (Some(transfer), Some(me)) = (&note.pending_transfer, user.owner_id()) else {
+        return false;
+    };
+    transfer.to == me
+}
+
+#[post("/api/notes/{id}/transfer/accept")]
+async fn accept_transfer(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+    if !offered_to(&note, &user) {
+        return HttpResponse::NotFound().json(ApiResponse::error(
+            "No transfer of this note is waiting for you",
+        ));
+    }
+    let me = user.owner_id().unwrap_or_default();
+    match mgr.transfer_note(&id, &me) {
+        Ok(Some(note)) => {
+            HttpResponse::Ok().json(ApiResponse::success(note, "Note transferred to you"))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Withdraws the pending transfer (owner or admin) or declines it (the
+/// user it's offered to).
+#[delete("/api/notes/{id}/transfer")]
+async fn cancel_transfer(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    let Some(note) = mgr.get_note(&id).filter(|note| user.can_read(note)) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+    let declining = offered_to(&note, &user);
+    if !declining && !user.can_manage(&note) {
+        return HttpResponse::Forbidden().json(ApiResponse::error("Insufficient permissions"));
+    }
+    if note.pending_transfer.is_none() {
+        return HttpResponse::NotFound().json(ApiResponse::error("No transfer is pending"));
+    }
+    let message = if declining {
+        "Transfer declined"
+    } else {
+        "Transfer cancelled"
+    };
+    match mgr.cancel_transfer(&id) {
+        Ok(Some(note)) => HttpResponse::Ok().json(ApiResponse::success(note, message)),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Notes other users offered the caller, waiting for an answer.
+#[get("/api/transfers")]
+async fn incoming_transfers(user: CurrentUser, manager: Vault) -> impl Responder {
+    let notes: Vec<Note> = manager
+        .lock()
+        .unwrap()
+        .notes
+        .iter()
+        .filter(|note| offered_to(note, &user))
+        .cloned()
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(notes, "Pending transfers retrieved"))
+}
+
+/// Lets another user read (not edit) the note.
+#[put("/api/notes/{id}/shares/{user}")]
+async fn share_note(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let (id, key) = path.into_inner();
+    let reader = match find_account(&users, &user, &key) {
+        Ok(reader) => reader,
+        Err(response) => return response,
+    };
+    change_managed(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_shared_with(&id, &reader.id, true),
+        &format!("Note shared with {}", reader.username),
+    )
+}
+
+#[delete("/api/notes/{id}/shares/{user}")]
+async fn unshare_note(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let (id, key) = path.into_inner();
+    let reader = match find_account(&users, &user, &key) {
+        Ok(reader) => reader,
+        Err(response) => return response,
+    };
+    change_managed(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_shared_with(&id, &reader.id, false),
+        &format!("Note no longer shared with {}", reader.username),
+    )
+}
+
+#[derive(Debug, Serialize)]
+struct NotebookResponse {
+    #[serde(flatten)]
+    notebook: Notebook,
+    /// Notes in it the caller can read
+    notes: usize,
+    /// What the caller may do with its notes; `None` while only invited
+    access: Option<MemberAccess>,
+    /// The caller is invited and hasn't answered yet
+    invited: bool,
+}
+
+impl NotebookResponse {
+    fn new(notebook: Notebook, mgr: &NotesManager, user: &CurrentUser) -> Self {
+        let notes = mgr
+            .notebook_notes(&notebook.id)
+            .iter()
+            .filter(|note| user.can_read(note))
+            .count();
+        let invited = user
+            .owner_id()
+            .is_some_and(|me| notebook.invitation_for(&me).is_some());
+        Self {
+            access: user.notebook_access(&notebook),
+            notes,
+            invited,
+            notebook,
+        }
+    }
+}
+
+// The notebook if the caller may see it
+fn find_notebook(
+    mgr: &NotesManager,
+    user: &CurrentUser,
+    id: &str,
+) -> Result<Notebook, HttpResponse> {
+    mgr.get_notebook(id)
+        .filter(|notebook| user.can_see_notebook(notebook))
+        .cloned()
+        .ok_or_else(|| HttpResponse::NotFound().json(ApiResponse::error("Notebook not found")))
+}
+
+// The notebook if the caller may rename it and choose its members
+fn managed_notebook(
+    mgr: &NotesManager,
+    user: &CurrentUser,
+    id: &str,
+) -> Result<Notebook, HttpResponse> {
+    let notebook = find_notebook(mgr, user, id)?;
+    if !user.can_manage_notebook(&notebook) {
+        return Err(HttpResponse::Forbidden()
+            .json(ApiResponse::error("Only the notebook's owner can do that")));
+    }
+    Ok(notebook)
+}
+
+fn notebook_changed(
+    result: std::io::Result<Option<Notebook>>,
+    mgr: &NotesManager,
+    user: &CurrentUser,
+    message: &str,
+) -> HttpResponse {
+    match result {
+        Ok(Some(notebook)) => HttpResponse::Ok().json(ApiResponse::success(
+            NotebookResponse::new(notebook, mgr, user),
+            message,
+        )),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Notebook not found")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            HttpResponse::NotFound().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => update_error(e),
+    }
+}
+
+/// Notebooks the caller owns, belongs to, is invited to or can see as
+/// part of the team.
+#[get("/api/notebooks")]
+async fn list_notebooks(user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    let notebooks: Vec<NotebookResponse> = mgr
+        .notebooks()
+        .iter()
+        .filter(|notebook| user.can_see_notebook(notebook))
+        .map(|notebook| NotebookResponse::new(notebook.clone(), &mgr, &user))
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(notebooks, "Notebooks retrieved"))
+}
+
+#[derive(Debug, Deserialize)]
+struct NotebookRequest {
+    name: String,
+    #[serde(default)]
+    visibility: Visibility,
+}
+
+#[post("/api/notebooks")]
+async fn create_notebook(
+    body: web::Json<NotebookRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    if let CurrentUser::NoteToken(_) = user {
+        return HttpResponse::Forbidden()
+            .json(ApiResponse::error("Note tokens can't create notebooks"));
+    }
+    let mut mgr = manager.lock().unwrap();
+    match mgr.create_notebook(&body.name, user.owner_id(), body.visibility) {
+        Ok(notebook) => HttpResponse::Created().json(ApiResponse::success(
+            NotebookResponse::new(notebook, &mgr, &user),
+            "Notebook created",
+        )),
+        Err(e) => update_error(e),
+    }
+}
+
+#[get("/api/notebooks/{id}")]
+async fn get_notebook(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    match find_notebook(&mgr, &user, &id) {
+        Ok(notebook) => HttpResponse::Ok().json(ApiResponse::success(
+            NotebookResponse::new(notebook, &mgr, &user),
+            "Notebook retrieved",
+        )),
+        Err(response) => response,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EditNotebookRequest {
+    name: Option<String>,
+    visibility: Option<Visibility>,
+}
+
+#[patch("/api/notebooks/{id}")]
+async fn edit_notebook(
+    id: web::Path<String>,
+    body: web::Json<EditNotebookRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = managed_notebook(&mgr, &user, &id) {
+        return response;
+    }
+    let result = mgr.edit_notebook(&id, body.name.as_deref(), body.visibility);
+    notebook_changed(result, &mgr, &user, "Notebook updated")
+}
+
+/// Removes the notebook; its notes stay with their owners.
+#[delete("/api/notebooks/{id}")]
+async fn delete_notebook(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    let notebook = match managed_notebook(&mgr, &user, &id) {
+        Ok(notebook) => notebook,
+        Err(response) => return response,
+    };
+    match mgr.delete_notebook(&id) {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse::success(notebook, "Notebook deleted")),
+        Ok(false) => HttpResponse::NotFound().json(ApiResponse::error("Notebook not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[get("/api/notebooks/{id}/members")]
+async fn list_members(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    match find_notebook(&mgr, &user, &id) {
+        Ok(notebook) => {
+            HttpResponse::Ok().json(ApiResponse::success(notebook.members, "Members retrieved"))
+        }
+        Err(response) => response,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteRequest {
+    /// Username or id of the user to invite
+    user: String,
+    #[serde(default)]
+    access: MemberAccess,
+}
+
+/// Invites a user into the notebook; they join once they accept. Inviting
+/// a member again changes their access.
+#[post("/api/notebooks/{id}/members")]
+async fn invite_member(
+    id: web::Path<String>,
+    body: web::Json<InviteRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let invitee = match find_account(&users, &user, &body.user) {
+        Ok(invitee) => invitee,
+        Err(response) => return response,
+    };
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = managed_notebook(&mgr, &user, &id) {
+        return response;
+    }
+    let result = mgr.invite_member(&id, &invitee.id, body.access, user.owner_id());
+    notebook_changed(
+        result,
+        &mgr,
+        &user,
+        &format!("Waiting for {} to accept", invitee.username),
+    )
+}
+
+#[post("/api/notebooks/{id}/members/accept")]
+async fn accept_invitation(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let Some(me) = user.owner_id() else {
+        return HttpResponse::BadRequest().json(ApiResponse::error(
+            "Only account holders can join notebooks",
+        ));
+    };
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = find_notebook(&mgr, &user, &id) {
+        return response;
+    }
+    let result = mgr.accept_invitation(&id, &me);
+    notebook_changed(result, &mgr, &user, "You joined the notebook")
+}
+
+/// Takes a member or an invitation out of the notebook: the owner removes
+/// anyone, members leave (or decline) by removing themselves.
+#[delete("/api/notebooks/{id}/members/{user}")]
+async fn remove_member(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let (id, key) = path.into_inner();
+    let member = match find_account(&users, &user, &key) {
+        Ok(member) => member,
+        Err(response) => return response,
+    };
+    let mut mgr = manager.lock().unwrap();
+    let notebook = match find_notebook(&mgr, &user, &id) {
+        Ok(notebook) => notebook,
+        Err(response) => return response,
+    };
+    let leaving = user.owner_id().as_deref() == Some(member.id.as_str());
+    if !leaving && !user.can_manage_notebook(&notebook) {
+        return HttpResponse::Forbidden()
+            .json(ApiResponse::error("Only the notebook's owner can do that"));
+    }
+    let message = if leaving {
+        "You left the notebook".to_string()
+    } else {
+        format!("{} removed from the notebook", member.username)
+    };
+    let result = mgr.remove_member(&id, &member.id);
+    notebook_changed(result, &mgr, &user, &message)
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveToNotebookRequest {
+    notebook: String,
+}
+
+/// Puts the note into a notebook the caller may write to.
+#[put("/api/notes/{id}/notebook")]
+async fn move_to_notebook(
+    id: web::Path<String>,
+    body: web::Json<MoveToNotebookRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    {
+        let mgr = manager.lock().unwrap();
+        let notebook = match find_notebook(&mgr, &user, &body.notebook) {
+            Ok(notebook) => notebook,
+            Err(response) => return response,
+        };
+        if user.notebook_access(&notebook) != Some(MemberAccess::Write) {
+            return HttpResponse::Forbidden()
+                .json(ApiResponse::error("You can't add notes to this notebook"));
+        }
+    }
+    change_managed(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_notebook(&id, Some(&body.notebook)),
+        "Note moved to the notebook",
+    )
+}
+
+#[delete("/api/notes/{id}/notebook")]
+async fn remove_from_notebook(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    change_managed(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_notebook(&id, None),
+        "Note taken out of its notebook",
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct SnoozeRequest {
+    /// A duration (`3d`, `12h`) or a time; see [`snooze::parse_until`]
+    until: String,
+}
+
+/// Hides the note from the default list and search until the given time.
+#[post("/api/notes/{id}/snooze")]
+async fn snooze_note(
+    id: web::Path<String>,
+    req: web::Json<SnoozeRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let until = match snooze::parse_until(&req.until, manager.lock().unwrap().now()) {
+        Ok(until) => until,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_snoozed(&id, Some(until)),
+        "Note snoozed",
+    )
+}
+
+#[delete("/api/notes/{id}/snooze")]
+async fn wake_note(id: web::Path<String>, user: CurrentUser, manager: Vault) -> impl Responder {
+    change_state(
+        &manager,
+        &user,
+        &id,
+        |mgr| mgr.set_snoozed(&id, None),
+        "Note woken up",
+    )
+}
+
+#[get("/api/notes/{id}/pdf")]
+async fn export_note_pdf(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let note = manager
+        .lock()
+        .unwrap()
+        .get_note(&id)
+        .filter(|note| user.can_read(note));
+
+    match note {
+        Some(note) => match export::note_to_pdf(&note) {
+            Ok(pdf) => HttpResponse::Ok()
+                .content_type("application/pdf")
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename(export::file_name(&note, "pdf"))],
+                })
+                .body(pdf),
+            Err(e) => HttpResponse::InternalServerError()
+                .json(ApiResponse::error(&format!("Failed to export note: {}", e))),
+        },
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    format: Option<String>,
+    /// Names the file and titles the PDF
+    name: Option<String>,
+    tag: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    query: Option<String>,
+    /// A zip of the notes (Markdown or JSON) with their attachments and a
+    /// manifest instead of one file
+    #[serde(default)]
+    zip: bool,
+}
+
+/// The readable notes matching `tag`, `from`/`to` (creation time) and
+/// `query` as one Markdown, JSON or PDF download, or with `zip=true` as a
+/// zip archive streamed as it's written.
+#[get("/api/export")]
+async fn export_notes(
+    query: web::Query<ExportQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let query = query.into_inner();
+    let format = match query.format.as_deref().map(str::parse::<ExportFormat>) {
+        Some(Err(e)) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+        Some(Ok(format)) => format,
+        None => ExportFormat::default(),
+    };
+    let filter = ExportFilter {
+        tag: query.tag,
+        from: query.from,
+        to: query.to,
+        query: query.query,
+    };
+    let notes = match export::select(&manager.lock().unwrap(), &filter, &|note| {
+        user.can_read(note)
+    }) {
+        Ok(notes) => notes,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+
+    let name = query.name.unwrap_or_else(|| "Notes".to_string());
+    if query.zip {
+        let attachments_dir = manager.lock().unwrap().attachments_dir();
+        return match Archive::new(notes, format, &name, &attachments_dir) {
+            Ok(archive) => stream_archive(archive, &name),
+            Err(e) => HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+        };
+    }
+    match export::export_notes(&notes, format, &name) {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(export::export_file_name(
+                    &name, format,
+                ))],
+            })
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to export notes: {}",
+            e
+        ))),
+    }
+}
+
+// Size of the pieces a streamed download is sent in
+const STREAM_CHUNK: usize = 64 * 1024;
+
+/// Passes what's written to it on to a streamed response, a chunk at a time.
+/// Only a few chunks wait to be sent, so a slow client slows the writer
+/// down instead of the download piling up in memory.
+struct ChunkSender {
+    sender: tokio::sync::mpsc::Sender<io::Result<web::Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl ChunkSender {
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = web::Bytes::from(std::mem::take(&mut self.buffer));
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The client went away"))
+    }
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= STREAM_CHUNK {
+            self.send()?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.send()
+    }
+}
+
+// Writes the archive on a blocking thread while the response streams it.
+// An error partway through aborts the response, so the client can tell
+// the download broke off
+fn stream_archive(archive: Archive, name: &str) -> HttpResponse {
+    let (sender, receiver) = tokio::sync::mpsc::channel(4);
+    let failed = sender.clone();
+    tokio::task::spawn_blocking(move || {
+        let out = ChunkSender {
+            sender,
+            buffer: Vec::with_capacity(STREAM_CHUNK),
+        };
+        if let Err(e) = archive.write_to(out) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                eprintln!("⚠ Zip export failed: {}", e);
+                let _ = failed.blocking_send(Err(e));
+            }
+        }
+    });
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((
+            chunk.map_err(actix_web::error::ErrorInternalServerError),
+            receiver,
+        ))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(crate::archive::file_name(name))],
+        })
+        .streaming(stream)
+}
+
+/// Base URL for links handed to other devices: the configured public URL
+/// (including any mount prefix), otherwise whatever host and prefix the
+/// client used to reach us.
+fn public_url(req: &HttpRequest, server: &ServerConfig) -> String {
+    match &server.public_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => {
+            let info = req.connection_info();
+            format!("{}://{}{}", info.scheme(), info.host(), mount_prefix(req))
+        }
+    }
+}
+
+/// The note a note token shares, as a page anyone with the link can open.
+/// Sections can be linked directly: `/share/{token}#<slug>`. The note is
+/// looked up in the vault the token was made in, whatever the request asks
+/// for, and stops being shared while it's in the trash.
+#[get("/share/{token}")]
+async fn shared_note(
+    token: web::Path<String>,
+    config: Settings,
+    tokens: web::Data<SharedNoteTokenStore>,
+    vaults: web::Data<Vaults>,
+) -> impl Responder {
+    let note = tokens
+        .lock()
+        .unwrap()
+        .find_by_token(&token)
+        .and_then(|token| {
+            let vault = vaults
+                .by_name
+                .get(token.vault.as_deref().unwrap_or(&vaults.default))?;
+            vault.manager.lock().unwrap().get_note(&token.note_id)
+        })
+        .filter(|note| !note.is_trashed());
+    match note {
+        Some(note) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CONTENT_SECURITY_POLICY, HTML_CSP))
+            // Comments stay with the account holders; the link shows the note alone
+            .body(html::render_page(&note, &[], &config.server.sanitizer)),
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+/// The note as a page made for printing from the browser: no navigation,
+/// print-friendly page breaks and its details in a footer, with times in
+/// the caller's preferred format.
+#[get("/api/notes/{id}/print")]
+async fn print_note(
+    id: web::Path<String>,
+    user: CurrentUser,
+    config: Settings,
+    preferences: web::Data<SharedPreferenceStore>,
+    manager: Vault,
+) -> impl Responder {
+    let (note, now) = {
+        let mgr = manager.lock().unwrap();
+        (
+            mgr.get_note(&id).filter(|note| user.can_read(note)),
+            mgr.now(),
+        )
+    };
+    let Some(note) = note else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Note not found"));
+    };
+    if note.is_encrypted() {
+        return HttpResponse::Conflict().json(ApiResponse::error(
+            "Encrypted notes can't be printed; decrypt the note first",
+        ));
+    }
+    let preferences = user_key(&user)
+        .map(|key| preferences.lock().unwrap().get(key))
+        .unwrap_or_default();
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, HTML_CSP))
+        .body(html::render_print_page(
+            &note,
+            &config.server.sanitizer,
+            &preferences,
+            now,
+        ))
+}
+
+#[get("/n/{short_id}")]
+async fn short_link(
+    req: HttpRequest,
+    short_id: web::Path<String>,
+    manager: Vault,
+) -> impl Responder {
+    match manager.lock().unwrap().get_note_by_short_id(&short_id) {
+        Some(note) => HttpResponse::Found()
+            .insert_header((
+                header::LOCATION,
+                format!("{}/#note-{}", mount_prefix(&req), note.id),
+            ))
+            .finish(),
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+#[get("/api/notes/{id}/qr")]
+async fn note_qr(
+    req: HttpRequest,
+    id: web::Path<String>,
+    user: CurrentUser,
+    config: Settings,
+    manager: Vault,
+) -> impl Responder {
+    let note = manager
+        .lock()
+        .unwrap()
+        .get_note(&id)
+        .filter(|note| user.can_read(note));
+
+    match note {
+        Some(note) => {
+            let url = format!("{}/n/{}", public_url(&req, &config.server), note.short_id());
+            match qr::qr_png(&url) {
+                Ok(png) => HttpResponse::Ok().content_type("image/png").body(png),
+                Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                    "Failed to render QR code: {}",
+                    e
+                ))),
+            }
+        }
+        None => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+    }
+}
+
+// Largest request body accepted, sized for attachment uploads
+const MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct UploadQuery {
+    filename: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptRequest {
+    transcript: Option<String>,
+}
+
+/// Upload the raw request body as an attachment; `?filename=` names it and
+/// the Content-Type header decides its kind (audio, image or file).
+#[post("/api/notes/{id}/attachments")]
+async fn upload_attachment(
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<UploadQuery>,
+    body: web::Bytes,
+    user: CurrentUser,
+    config: Settings,
+    manager: Vault,
+) -> impl Responder {
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let filename = query
+        .filename
+        .clone()
+        .unwrap_or_else(|| "attachment".to_string());
+
+    let result = {
+        let mut mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        mgr.add_attachment(&id, filename, content_type, &body)
+            .map(|added| added.map(|a| (a.clone(), mgr.attachment_path(a.file_key()))))
+    };
+
+    match result {
+        Ok(Some((attachment, path))) => {
+            if attachment.kind == AttachmentKind::Image {
+                spawn_thumbnails(manager.notes(), attachment.file_key(), path.clone());
+            }
+            if attachment.kind == AttachmentKind::Audio && config.transcription.command.is_some() {
+                spawn_transcription(
+                    manager.notes().clone(),
+                    config.transcription.clone(),
+                    attachment.id.clone(),
+                    path,
+                );
+            }
+            HttpResponse::Created().json(ApiResponse::success(
+                attachment,
+                "Attachment uploaded successfully",
+            ))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) if e.kind() == std::io::ErrorKind::StorageFull => quota_exceeded(e),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to save attachment: {}",
+            e
+        ))),
+    }
+}
+
+// Transcription can take a while, so it runs after the upload has been answered
+fn spawn_transcription(
+    manager: SharedNotesManager,
+    config: TranscriptionConfig,
+    attachment_id: String,
+    path: std::path::PathBuf,
+) {
+    tokio::task::spawn_blocking(move || match transcribe::transcribe(&config, &path) {
+        Ok(Some(transcript)) => {
+            if let Err(e) = manager
+                .lock()
+                .unwrap()
+                .set_transcript(&attachment_id, Some(transcript))
+            {
+                println!("✗ Could not save transcript for {}: {}", attachment_id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => println!("✗ Transcription failed for {}: {}", attachment_id, e),
+    });
+}
+
+// Pre-generates every preview size so the first list render is fast
+fn spawn_thumbnails(manager: &SharedNotesManager, file_key: &str, original: PathBuf) {
+    let targets: Vec<(ThumbSize, PathBuf)> = {
+        let mgr = manager.lock().unwrap();
+        ThumbSize::ALL
+            .iter()
+            .map(|size| (*size, mgr.thumbnail_path(file_key, *size)))
+            .collect()
+    };
+    let file_key = file_key.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        for (size, cached) in targets {
+            if let Err(e) = thumbnail::ensure_thumbnail(&original, &cached, size) {
+                println!("✗ Thumbnail failed for {}: {}", file_key, e);
+                break;
+            }
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+struct ThumbQuery {
+    #[serde(default)]
+    size: ThumbSize,
+}
+
+/// PNG preview of an image attachment, `?size=small` (default) or `medium`.
+#[get("/api/attachments/{id}/thumb")]
+async fn get_attachment_thumb(
+    id: web::Path<String>,
+    query: web::Query<ThumbQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let (original, cached) = {
+        let mgr = manager.lock().unwrap();
+        match mgr
+            .find_attachment(&id)
+            .filter(|(note, _)| user.can_read(note))
+        {
+            Some((_, attachment)) if attachment.kind == AttachmentKind::Image => (
+                mgr.attachment_path(attachment.file_key()),
+                mgr.thumbnail_path(attachment.file_key(), query.size),
+            ),
+            Some(_) => {
+                return HttpResponse::UnsupportedMediaType()
+                    .json(ApiResponse::error("Attachment is not an image"))
+            }
+            None => {
+                return HttpResponse::NotFound().json(ApiResponse::error("Attachment not found"))
+            }
+        }
+    };
+
+    let size = query.size;
+    match web::block(move || thumbnail::ensure_thumbnail(&original, &cached, size)).await {
+        Ok(Ok(png)) => HttpResponse::Ok()
+            .content_type("image/png")
+            // Attachments never change, so neither do their previews
+            .insert_header((header::CACHE_CONTROL, "private, max-age=86400"))
+            .body(png),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::InvalidData => {
+            HttpResponse::UnprocessableEntity().json(ApiResponse::error(&format!(
+                "Could not decode image: {}",
+                e
+            )))
+        }
+        Ok(Err(e)) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to create thumbnail: {}",
+            e
+        ))),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to create thumbnail: {}",
+            e
+        ))),
+    }
+}
+
+#[get("/api/attachments/{id}")]
+async fn get_attachment(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+
+    match mgr
+        .find_attachment(&id)
+        .filter(|(note, _)| user.can_read(note))
+    {
+        Some((_, attachment)) => match std::fs::read(mgr.attachment_path(attachment.file_key())) {
+            Ok(bytes) => HttpResponse::Ok()
+                .content_type(attachment.content_type.as_str())
+                .insert_header(ContentDisposition {
+                    disposition: if shows_inline(&attachment.content_type) {
+                        DispositionType::Inline
+                    } else {
+                        DispositionType::Attachment
+                    },
+                    parameters: vec![DispositionParam::Filename(attachment.filename)],
+                })
+                // The uploader picks the content type: never let the browser
+                // guess another, and never let the file run script here
+                .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+                .insert_header((header::CONTENT_SECURITY_POLICY, ATTACHMENT_CSP))
+                .body(bytes),
+            Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to read attachment: {}",
+                e
+            ))),
+        },
+        None => HttpResponse::NotFound().json(ApiResponse::error("Attachment not found")),
+    }
+}
+
+const ATTACHMENT_CSP: &str = "sandbox; default-src 'none'";
+
+// Raster images and audio are shown in the browser; anything else, SVG and
+// HTML included, is downloaded
+fn shows_inline(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    matches!(
+        essence.as_str(),
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "image/avif" | "image/bmp"
+    ) || essence.starts_with("audio/")
+}
+
+#[put("/api/attachments/{id}/transcript")]
+async fn set_attachment_transcript(
+    id: web::Path<String>,
+    req: web::Json<TranscriptRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    let note_id = match mgr.find_attachment(&id) {
+        Some((note, _)) => note.id,
+        None => return HttpResponse::NotFound().json(ApiResponse::error("Attachment not found")),
+    };
+    if let Err(response) = check_write_access(&mgr, &user, &note_id) {
+        return response;
+    }
+
+    match mgr.set_transcript(&id, req.transcript.clone()) {
+        Ok(Some(attachment)) => HttpResponse::Ok().json(ApiResponse::success(
+            attachment,
+            "Transcript updated successfully",
+        )),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Attachment not found")),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to update transcript: {}",
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    #[serde(default)]
+    sort: SearchSort,
+    tag: Option<String>,
+    /// `active` (default), `trash`, `revisions` or `all`
+    #[serde(default)]
+    scope: SearchScope,
+}
+
+// Whose search history and preferences a caller has; note tokens have none
+fn user_key(user: &CurrentUser) -> Option<&str> {
+    match user {
+        CurrentUser::Local => Some(history::LOCAL_USER),
+        CurrentUser::User(user) => Some(&user.id),
+        CurrentUser::NoteToken(_) => None,
+    }
+}
+
+#[get("/api/notes/search/{query}")]
+async fn search_notes(
+    query: web::Path<String>,
+    params: web::Query<SearchParams>,
+    user: CurrentUser,
+    manager: Vault,
+    config: Settings,
+) -> impl Responder {
+    if let Some(key) = user_key(&user) {
+        let now = manager.lock().unwrap().now();
+        let recorded =
+            manager
+                .history()
+                .lock()
+                .unwrap()
+                .record(key, &query, config.search.history_size, now);
+        if let Err(e) = recorded {
+            eprintln!("⚠ Couldn't save search history: {}", e);
+        }
+    }
+    let mgr = manager.lock().unwrap();
+    let mut hits: Vec<SearchHit> = mgr
+        .search_scoped(&query, params.scope)
+        .into_iter()
+        .filter(|hit| user.can_read(&hit.note))
+        .filter(|hit| {
+            params
+                .tag
+                .as_deref()
+                .is_none_or(|tag| tags::has_tag(&hit.note, &mgr.tag_rules().canonical(tag)))
+        })
+        .collect();
+    search::sort_hits(&mut hits, params.sort);
+
+    HttpResponse::Ok().json(ApiResponse::success(hits, "Search results"))
+}
+
+#[get("/api/search/history")]
+async fn get_search_history(user: CurrentUser, manager: Vault) -> impl Responder {
+    let history = manager.history().lock().unwrap();
+    let entries = user_key(&user)
+        .map(|key| history.recent(key).to_vec())
+        .unwrap_or_default();
+    HttpResponse::Ok().json(ApiResponse::success(entries, "Search history retrieved"))
+}
+
+#[delete("/api/search/history")]
+async fn clear_search_history(user: CurrentUser, manager: Vault) -> impl Responder {
+    let Some(key) = user_key(&user) else {
+        return HttpResponse::Ok().json(ApiResponse::success((), "Search history cleared"));
+    };
+    match manager.history().lock().unwrap().clear(key) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse::success((), "Search history cleared")),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to clear search history: {}",
+            e
+        ))),
+    }
+}
+
+#[get("/api/preferences")]
+async fn get_preferences(
+    user: CurrentUser,
+    preferences: web::Data<SharedPreferenceStore>,
+) -> impl Responder {
+    let preferences = user_key(&user)
+        .map(|key| preferences.lock().unwrap().get(key))
+        .unwrap_or_default();
+    HttpResponse::Ok().json(ApiResponse::success(preferences, "Preferences retrieved"))
+}
+
+/// Replaces the caller's preferences; fields left out take their defaults.
+#[put("/api/preferences")]
+async fn update_preferences(
+    user: CurrentUser,
+    preferences: web::Data<SharedPreferenceStore>,
+    body: web::Json<Preferences>,
+) -> impl Responder {
+    let Some(key) = user_key(&user) else {
+        return HttpResponse::Forbidden()
+            .json(ApiResponse::error("Note tokens have no preferences"));
+    };
+    let mut store = preferences.lock().unwrap();
+    match store.set(key, body.into_inner()) {
+        Ok(()) => {
+            HttpResponse::Ok().json(ApiResponse::success(store.get(key), "Preferences saved"))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to save preferences: {}",
+            e
+        ))),
+    }
+}
+
+/// The web UI's keyboard shortcuts, part of the caller's preferences.
+#[get("/api/ui/keymap")]
+async fn get_keymap(
+    user: CurrentUser,
+    preferences: web::Data<SharedPreferenceStore>,
+) -> impl Responder {
+    let keymap = user_key(&user)
+        .map(|key| preferences.lock().unwrap().get(key).keymap)
+        .unwrap_or_default();
+    HttpResponse::Ok().json(ApiResponse::success(keymap, "Keymap retrieved"))
+}
+
+/// Replaces the caller's shortcuts; actions left out get their defaults.
+#[put("/api/ui/keymap")]
+async fn update_keymap(
+    user: CurrentUser,
+    preferences: web::Data<SharedPreferenceStore>,
+    body: web::Json<Keymap>,
+) -> impl Responder {
+    let Some(key) = user_key(&user) else {
+        return HttpResponse::Forbidden().json(ApiResponse::error("Note tokens have no keymap"));
+    };
+    let mut store = preferences.lock().unwrap();
+    let mut updated = store.get(key);
+    updated.keymap = body.into_inner();
+    match store.set(key, updated) {
+        Ok(()) => {
+            HttpResponse::Ok().json(ApiResponse::success(store.get(key).keymap, "Keymap saved"))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to save keymap: {}", e))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestParams {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+// Most suggestions returned when the request doesn't say
+const DEFAULT_SUGGESTIONS: usize = 10;
+
+/// Type-ahead for the search box: the caller's past queries, then tags.
+#[get("/api/search/suggest")]
+async fn search_suggestions(
+    params: web::Query<SuggestParams>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let tags: BTreeSet<String> = manager
+        .lock()
+        .unwrap()
+        .notes
+        .iter()
+        .filter(|note| user.can_read(note))
+        .flat_map(|note| note.tags.iter().cloned())
+        .collect();
+    let history = manager.history().lock().unwrap();
+    let recent = user_key(&user)
+        .map(|key| history.recent(key))
+        .unwrap_or_default();
+    let suggestions = history::suggest(
+        recent,
+        &tags,
+        &params.q,
+        params.limit.unwrap_or(DEFAULT_SUGGESTIONS),
+    );
+    HttpResponse::Ok().json(ApiResponse::success(suggestions, "Suggestions"))
+}
+
+/// Conflict copies waiting to be resolved, each with the note it collided with.
+#[get("/api/conflicts")]
+async fn list_conflicts(user: CurrentUser, manager: Vault) -> impl Responder {
+    let conflicts: Vec<Conflict> = manager
+        .lock()
+        .unwrap()
+        .conflicts()
+        .into_iter()
+        .filter(|conflict| user.can_read(&conflict.copy))
+        .map(|mut conflict| {
+            conflict.original = conflict.original.filter(|note| user.can_read(note));
+            conflict
+        })
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(conflicts, "Conflicts retrieved"))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum KeepVersion {
+    Original,
+    Copy,
+    Both,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolveConflictRequest {
+    keep: Option<KeepVersion>,
+    /// The two versions merged by hand; takes the place of `keep`
+    content: Option<String>,
+}
+
+/// Settles a conflict copy: the note keeps its version, takes the copy's or
+/// the merged `content`, or both are kept as separate notes.
+#[post("/api/conflicts/{id}/resolve")]
+async fn resolve_conflict(
+    id: web::Path<String>,
+    req: web::Json<ResolveConflictRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let req = req.into_inner();
+    let resolution = match (req.content, req.keep) {
+        (Some(content), _) => Resolution::Merged(content),
+        (None, Some(KeepVersion::Original)) => Resolution::KeepOriginal,
+        (None, Some(KeepVersion::Copy)) => Resolution::KeepCopy,
+        (None, Some(KeepVersion::Both)) => Resolution::KeepBoth,
+        (None, None) => {
+            return HttpResponse::BadRequest().json(ApiResponse::error(
+                "Send \"keep\" (original, copy or both) or the merged \"content\"",
+            ))
+        }
+    };
+
+    let mut mgr = manager.lock().unwrap();
+    if let Err(response) = check_write_access(&mgr, &user, &id) {
+        return response;
+    }
+    // Resolving writes to the original as well
+    let original = mgr
+        .get_note(&id)
+        .and_then(|copy| copy.conflict_of)
+        .and_then(|original_id| mgr.get_note(&original_id));
+    if original.is_some_and(|note| !user.can_write(&note)) {
+        return HttpResponse::Forbidden().json(ApiResponse::error("Insufficient permissions"));
+    }
+    match mgr.resolve_conflict(&id, resolution) {
+        Ok(Some(note)) => HttpResponse::Ok().json(ApiResponse::success(note, "Conflict resolved")),
+        Ok(None) => {
+            HttpResponse::NotFound().json(ApiResponse::error("Note is not a conflict copy"))
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::InvalidInput
+            ) =>
+        {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to resolve conflict: {}",
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    ids: Vec<String>,
+    #[serde(default)]
+    strategy: MergeStrategy,
+}
+
+/// Merges notes into the first of `ids`; the others are removed and their
+/// ids redirect to it.
+#[post("/api/notes/merge")]
+async fn merge_notes(
+    http: HttpRequest,
+    req: web::Json<MergeRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    for id in &req.ids {
+        if let Err(response) = check_write_access(&mgr, &user, id)
+            .and_then(|_| check_lock(&http, &locks, id, mgr.now()))
+        {
+            return response;
+        }
+    }
+
+    match mgr.merge_notes(&req.ids, req.strategy) {
+        Ok(Some(note)) => {
+            // Merge documents hold the old content
+            for id in &req.ids {
+                if let Err(e) = manager.documents().remove(id) {
+                    eprintln!("⚠ Couldn't remove merge document of {}: {}", id, e);
+                }
+            }
+            let merged: HashSet<&String> = req.ids.iter().collect();
+            let message = format!("Merged {} notes", merged.len());
+            HttpResponse::Ok().json(ApiResponse::success(note, &message))
+        }
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("Note not found")),
+        Err(e) => update_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkTagRequest {
+    query: String,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+/// Adds/removes tags on every note matching a search the caller may edit.
+#[post("/api/notes/bulk-tag")]
+async fn bulk_tag(
+    req: web::Json<BulkTagRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let clean = |tags: &[String]| -> Vec<String> {
+        tags.iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    };
+    let (add, remove) = (clean(&req.add), clean(&req.remove));
+    if req.query.trim().is_empty() || (add.is_empty() && remove.is_empty()) {
+        return HttpResponse::BadRequest().json(ApiResponse::error(
+            "Give a query and at least one tag to add or remove",
+        ));
+    }
+
+    let mut mgr = manager.lock().unwrap();
+    match mgr.bulk_tag(req.query.trim(), &add, &remove, &|note| {
+        user.can_write(note)
+    }) {
+        Ok((matched, modified)) => HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({ "matched": matched, "modified": modified }),
+            &format!("Updated tags on {} of {} matching notes", modified, matched),
+        )),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to update tags: {}", e))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplaceRequest {
+    #[serde(flatten)]
+    replace: Replace,
+    /// Token of the preview being applied; without one it's a dry run
+    confirm: Option<String>,
+}
+
+/// Find-and-replace across the notes the caller may edit. A dry run first
+/// previews the lines that would change; sending back its `token` as
+/// `confirm` applies it, unless the notes changed since (`409` with a new
+/// preview).
+#[post("/api/replace")]
+async fn replace_notes(
+    http: HttpRequest,
+    req: web::Json<ReplaceRequest>,
+    user: CurrentUser,
+    manager: Vault,
+    locks: web::Data<SharedEditLocks>,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    let now = mgr.now();
+    let plan = replace::plan(&mgr, &req.replace, &|note| user.can_write(note), &|note| {
+        check_lock(&http, &locks, &note.id, now).is_err()
+    });
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(e) => return HttpResponse::BadRequest().json(ApiResponse::error(&e)),
+    };
+    let mut summary = format!(
+        "{} replacements in {} notes",
+        plan.preview.replacements,
+        plan.preview.notes.len()
+    );
+    if !plan.preview.locked.is_empty() {
+        summary.push_str(&format!(
+            ", skipping {} being edited elsewhere",
+            plan.preview.locked.len()
+        ));
+    }
+    match &req.confirm {
+        None => {
+            return HttpResponse::Ok().json(ApiResponse::success(
+                plan.preview,
+                &format!("Dry run: {}; send its token as confirm to apply", summary),
+            ))
+        }
+        Some(token) if *token != plan.preview.token => {
+            return HttpResponse::Conflict().json(ApiResponse {
+                success: false,
+                message: "The notes changed since the preview; check this one and confirm again"
+                    .to_string(),
+                data: Some(plan.preview),
+            })
+        }
+        Some(_) => {}
+    }
+
+    match plan.apply(&mut mgr) {
+        Ok(changed) => HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({
+                "modified": changed.len(),
+                "ids": changed.iter().map(|note| &note.id).collect::<Vec<_>>(),
+            }),
+            &format!("Made {}", summary),
+        )),
+        Err(e) => update_error(e),
+    }
+}
+
+/// Tags in use as a hierarchy (`work/projects/alpha` sits under `work`).
+#[get("/api/tags/tree")]
+async fn tag_tree(req: HttpRequest, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    cached(
+        manager.cache(),
+        cache_key(&req, &user),
+        mgr.generation(),
+        || {
+            let notes: Vec<Note> = mgr
+                .list_notes()
+                .into_iter()
+                .filter(|note| user.can_read(note))
+                .collect();
+            ApiResponse::success(
+                tags::tag_tree(&notes, mgr.tag_colors()),
+                "Tag tree retrieved successfully",
+            )
+            .to_cached()
+        },
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TagColorRequest {
+    /// `#rrggbb` or `#rgb`
+    color: String,
+}
+
+/// Shows the tag, and the tags below it without a color of their own, in
+/// `color`: in the CLI, the tag tree and every note's `tag_colors`. Colors
+/// are the same for everyone in the vault, so only admins pick them.
+// Outside `/api/tags`, where a nested tag ending in `color` would be ambiguous
+#[put("/api/tag-colors/{tag:.*}")]
+async fn set_tag_color(
+    tag: web::Path<String>,
+    req: web::Json<TagColorRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    tag_color_response(&manager, &user, &tag, Some(&req.color))
+}
+
+#[delete("/api/tag-colors/{tag:.*}")]
+async fn clear_tag_color(
+    tag: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    tag_color_response(&manager, &user, &tag, None)
+}
+
+fn tag_color_response(
+    manager: &Vault,
+    user: &CurrentUser,
+    tag: &str,
+    color: Option<&str>,
+) -> HttpResponse {
+    if user.role() != Role::Admin {
+        return HttpResponse::Forbidden().json(ApiResponse::error("Only admins can color tags"));
+    }
+    let mut mgr = manager.lock().unwrap();
+    match mgr.set_tag_color(tag, color) {
+        Ok(()) => HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({
+                "tag": tag,
+                "color": mgr.tag_colors().get(&mgr.tag_rules().canonical(tag)),
+            }),
+            "Tag color saved",
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to save tag color: {}",
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameTagRequest {
+    name: String,
+}
+
+/// Renames a tag on every note the caller may edit, along with the tags
+/// nested under it.
+#[put("/api/tags/{tag:.*}")]
+async fn rename_tag(
+    tag: web::Path<String>,
+    req: web::Json<RenameTagRequest>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    match mgr.rename_tag(&tag, req.name.trim(), &|note| user.can_write(note)) {
+        Ok(changed) => HttpResponse::Ok().json(ApiResponse::success(
+            serde_json::json!({ "notes_updated": changed }),
+            &format!("Renamed '{}' on {} notes", tag, changed),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to rename tag: {}", e))),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreateUserRequest {
+    username: String,
+    role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SetRoleRequest {
+    role: Role,
+}
+
+// Public view of an account (never includes the token hash)
+#[derive(Debug, Serialize)]
+struct UserResponse {
+    id: String,
+    username: String,
+    role: Role,
+    created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disabled_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl UserResponse {
+    fn new(user: User, token: Option<String>) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            role: user.role,
+            created_at: user.created_at,
+            disabled_at: user.disabled_at,
+            token,
+        }
+    }
+}
+
+#[get("/api/admin/users")]
+async fn list_users(users: web::Data<SharedUserStore>) -> impl Responder {
+    let users: Vec<UserResponse> = users
+        .lock()
+        .unwrap()
+        .list_users()
+        .into_iter()
+        .map(|user| UserResponse::new(user, None))
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(users, "Users retrieved successfully"))
+}
+
+#[post("/api/admin/users")]
+async fn create_user(
+    req: web::Json<CreateUserRequest>,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let mut store = users.lock().unwrap();
+
+    match store.add_user(req.username.trim(), req.role) {
+        Ok((user, token)) => HttpResponse::Created().json(ApiResponse::success(
+            UserResponse::new(user, Some(token)),
+            "User created successfully; store the token, it won't be shown again",
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            HttpResponse::Conflict().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to create user: {}", e))),
+    }
+}
+
+#[put("/api/admin/users/{id}/role")]
+async fn set_user_role(
+    id: web::Path<String>,
+    req: web::Json<SetRoleRequest>,
+    users: web::Data<SharedUserStore>,
+) -> impl Responder {
+    let mut store = users.lock().unwrap();
+
+    match store.set_role(&id, req.role) {
+        Ok(Some(user)) => HttpResponse::Ok().json(ApiResponse::success(
+            UserResponse::new(user, None),
+            "Role updated successfully",
+        )),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error("User not found")),
+        // The last admin
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to update role: {}", e))),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CreateTokenRequest {
+    #[serde(default)]
+    access: TokenAccess,
+}
+
+// Public view of a note token (never includes the hash)
+#[derive(Debug, Serialize)]
+struct NoteTokenResponse {
+    id: String,
+    note_id: String,
+    access: TokenAccess,
+    created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl NoteTokenResponse {
+    fn new(token: NoteToken, secret: Option<String>) -> Self {
+        Self {
+            id: token.id,
+            note_id: token.note_id,
+            access: token.access,
+            created_at: token.created_at,
+            token: secret,
+        }
+    }
+}
+
+/// Generates a token that reads (or edits) only this note.
+#[post("/api/notes/{id}/tokens")]
+async fn create_note_token(
+    id: web::Path<String>,
+    req: Option<web::Json<CreateTokenRequest>>,
+    user: CurrentUser,
+    manager: Vault,
+    tokens: web::Data<SharedNoteTokenStore>,
+) -> impl Responder {
+    let now = {
+        let mgr = manager.lock().unwrap();
+        if let Err(response) = check_write_access(&mgr, &user, &id) {
+            return response;
+        }
+        mgr.now()
+    };
+
+    let access = req.map(|req| req.access).unwrap_or_default();
+    match tokens
+        .lock()
+        .unwrap()
+        .create(&id, manager.name(), access, user.owner_id(), now)
+    {
+        Ok((token, secret)) => HttpResponse::Created().json(ApiResponse::success(
+            NoteTokenResponse::new(token, Some(secret)),
+            "Token created; store it, it won't be shown again",
+        )),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to create token: {}",
+            e
+        ))),
+    }
+}
+
+#[get("/api/notes/{id}/tokens")]
+async fn list_note_tokens(
+    id: web::Path<String>,
+    user: CurrentUser,
+    manager: Vault,
+    tokens: web::Data<SharedNoteTokenStore>,
+) -> impl Responder {
+    if let Err(response) = check_write_access(&manager.lock().unwrap(), &user, &id) {
+        return response;
+    }
+
+    let tokens: Vec<NoteTokenResponse> = tokens
+        .lock()
+        .unwrap()
+        .list_for_note(&id)
+        .into_iter()
+        .map(|token| NoteTokenResponse::new(token, None))
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(
+        tokens,
+        "Tokens retrieved successfully",
+    ))
+}
+
+#[delete("/api/notes/{id}/tokens/{token_id}")]
+async fn revoke_note_token(
+    path: web::Path<(String, String)>,
+    user: CurrentUser,
+    manager: Vault,
+    tokens: web::Data<SharedNoteTokenStore>,
+) -> impl Responder {
+    let (id, token_id) = path.into_inner();
+    if let Err(response) = check_write_access(&manager.lock().unwrap(), &user, &id) {
+        return response;
+    }
+
+    match tokens.lock().unwrap().revoke(&id, &token_id) {
+        Ok(true) => HttpResponse::Ok().json(ApiResponse::success((), "Token revoked")),
+        Ok(false) => HttpResponse::NotFound().json(ApiResponse::error("Token not found")),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to revoke token: {}",
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateResponse {
+    #[serde(flatten)]
+    template: TemplateConfig,
+    /// Next scheduled creation, if the template has a valid schedule
+    next_run: Option<DateTime<Utc>>,
+}
+
+#[get("/api/templates")]
+async fn list_templates(config: Settings) -> impl Responder {
+    let templates: Vec<TemplateResponse> = config
+        .templates
+        .iter()
+        .map(|template| TemplateResponse {
+            next_run: template
+                .schedule
+                .as_deref()
+                .and_then(|expression| templates::parse_schedule(expression).ok())
+                .and_then(|schedule| schedule.upcoming(Local).next())
+                .map(|time| time.with_timezone(&Utc)),
+            template: template.clone(),
+        })
+        .collect();
+    HttpResponse::Ok().json(ApiResponse::success(
+        templates,
+        "Templates retrieved successfully",
+    ))
+}
+
+/// Creates a note from a template right now, regardless of its schedule.
+#[post("/api/templates/{name}/notes")]
+async fn create_from_template(
+    name: web::Path<String>,
+    user: CurrentUser,
+    config: Settings,
+    manager: Vault,
+) -> impl Responder {
+    let Some(template) = config.templates.iter().find(|t| t.name == *name) else {
+        return HttpResponse::NotFound().json(ApiResponse::error("Template not found"));
+    };
+
+    let pending = {
+        let mut mgr = manager.lock().unwrap();
+        let now = mgr.now();
+        let note = templates::note_from(template, user.owner_id(), now.with_timezone(&Local), now);
+        mgr.prepare_insert(note)
+    };
+    match insert_note(&manager, pending).await {
+        Ok(note) => {
+            HttpResponse::Created().json(ApiResponse::success(note, "Note created from template"))
+        }
+        Err(e) => create_error(e),
+    }
+}
+
+#[get("/api/admin/policy/report")]
+async fn policy_report(config: Settings, manager: Vault) -> impl Responder {
+    let mut actions = {
+        let mgr = manager.lock().unwrap();
+        policy::plan(&mgr.notes, &config.policy.rules, mgr.now())
+    };
+    let backups = Backups::new(manager.name(), &config.backup);
+    let rules = config.policy.rules.clone();
+    match web::block(move || policy::plan_backups(&backups, &rules)).await {
+        Ok(Ok(pruned)) => actions.extend(pruned),
+        Ok(Err(e)) => {
+            return HttpResponse::BadGateway().json(ApiResponse::error(&format!(
+                "Failed to list backups: {}",
+                e
+            )))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to list backups: {}",
+                e
+            )))
+        }
+    }
+    HttpResponse::Ok().json(ApiResponse::success(
+        actions,
+        "Dry run: these changes would be made by the retention policy",
+    ))
+}
+
+#[post("/api/admin/policy/run")]
+async fn run_policy(config: Settings, manager: Vault) -> impl Responder {
+    let applied = {
+        let mut mgr = manager.lock().unwrap();
+        let now = mgr.now();
+        policy::apply(&mut mgr, &config.policy.rules, now)
+    };
+    let mut actions = match applied {
+        Ok(actions) => actions,
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to apply retention policy: {}",
+                e
+            )))
+        }
+    };
+    let backups = Backups::new(manager.name(), &config.backup);
+    let rules = config.policy.rules.clone();
+    match web::block(move || policy::apply_to_backups(&backups, &rules)).await {
+        Ok(Ok(pruned)) => actions.extend(pruned),
+        Ok(Err(e)) => {
+            return HttpResponse::BadGateway().json(ApiResponse::error(&format!(
+                "Notes done, but pruning backups failed: {}",
+                e
+            )))
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+                "Failed to prune backups: {}",
+                e
+            )))
+        }
+    }
+    HttpResponse::Ok().json(ApiResponse::success(actions, "Retention policy applied"))
+}
+
+/// Takes a backup now, the same as the scheduled ones (see `backup.schedule`).
+#[post("/api/admin/backup")]
+async fn run_backup(config: Settings, manager: Vault) -> impl Responder {
+    let backups = Backups::new(manager.name(), &config.backup);
+    let notes = manager.notes().clone();
+    match web::block(move || backups.run(&notes)).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(ApiResponse::success(report, "Backup taken")),
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::InvalidInput => {
+            HttpResponse::BadRequest().json(ApiResponse::error(&e.to_string()))
+        }
+        Ok(Err(e)) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Backup failed: {}", e))),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Backup failed: {}", e))),
+    }
+}
+
+/// The vault's backups, locally and in the bucket, oldest first.
+#[get("/api/admin/backups")]
+async fn list_backups(config: Settings, manager: Vault) -> impl Responder {
+    let backups = Backups::new(manager.name(), &config.backup);
+    match web::block(move || backups.list()).await {
+        Ok(Ok(snapshots)) => {
+            HttpResponse::Ok().json(ApiResponse::success(snapshots, "Backups retrieved"))
+        }
+        Ok(Err(e)) => HttpResponse::BadGateway().json(ApiResponse::error(&format!(
+            "Failed to list backups: {}",
+            e
+        ))),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to list backups: {}",
+            e
+        ))),
+    }
+}
+
+/// The settings in effect next to the config file's. Settings that differ
+/// only apply after a restart; they're listed in `restart_required`.
+#[get("/api/admin/config")]
+async fn get_config(config: web::Data<LiveConfig>) -> impl Responder {
+    HttpResponse::Ok().json(ApiResponse::success(config.report(), "Config retrieved"))
+}
+
+#[get("/api/admin/verify")]
+async fn verify_store(manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    let report = maintenance::verify(&mgr, mgr.now());
+    let message = if report.is_ok() {
+        "No problems found".to_string()
+    } else {
+        format!("{} problem(s) found", report.problems.len())
+    };
+    HttpResponse::Ok().json(ApiResponse::success(report, &message))
+}
+
+// The parts of a link check report about notes `user` can read
+fn readable_report(
+    mut report: LinkCheckReport,
+    mgr: &NotesManager,
+    user: &CurrentUser,
+) -> LinkCheckReport {
+    report.notes.retain(|entry| {
+        mgr.get_note(&entry.note_id)
+            .is_some_and(|note| user.can_read(&note))
+    });
+    report
+}
+
+/// Broken links across all notes, as found by the last background check
+/// (see `links.check_interval_hours`).
+#[get("/api/link-check")]
+async fn link_check_report(user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    match links::load_report(&links::report_path(mgr.storage_path())) {
+        Ok(Some(report)) => HttpResponse::Ok().json(ApiResponse::success(
+            readable_report(report, &mgr, &user),
+            "Link check report retrieved",
+        )),
+        Ok(None) => HttpResponse::NotFound().json(ApiResponse::error(
+            "No link check has run yet; POST /api/admin/link-check runs one",
+        )),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to read link check report: {}",
+            e
+        ))),
+    }
+}
+
+/// Checks every note's links now instead of waiting for the background check.
+#[post("/api/admin/link-check")]
+async fn run_link_check(user: CurrentUser, manager: Vault, config: Settings) -> impl Responder {
+    let notes = manager.notes().clone();
+    let links_config = config.links.clone();
+    let checked = web::block(move || {
+        let report = links::check_all(&notes, &links_config);
+        let path = links::report_path(notes.lock().unwrap().storage_path());
+        links::save_report(&path, &report).map(|_| report)
+    });
+    match checked.await {
+        Ok(Ok(report)) => {
+            let mgr = manager.lock().unwrap();
+            HttpResponse::Ok().json(ApiResponse::success(
+                readable_report(report, &mgr, &user),
+                "Links checked",
+            ))
+        }
+        Ok(Err(e)) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to save link check report: {}",
+            e
+        ))),
+        Err(e) => HttpResponse::InternalServerError()
+            .json(ApiResponse::error(&format!("Failed to check links: {}", e))),
+    }
+}
+
+#[post("/api/admin/compact")]
+async fn compact_store(manager: Vault) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    match maintenance::compact(&mut mgr) {
+        Ok(report) => {
+            manager
+                .documents()
+                .remove_unused(|id| mgr.get_note(id).is_some());
+            HttpResponse::Ok().json(ApiResponse::success(report, "Store compacted"))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to compact store: {}",
+            e
+        ))),
+    }
+}
+
+#[post("/api/admin/reindex")]
+async fn reindex_store(manager: Vault) -> impl Responder {
+    let mut mgr = manager.lock().unwrap();
+    match maintenance::reindex(&mut mgr) {
+        Ok(report) => HttpResponse::Ok().json(ApiResponse::success(report, "Store reindexed")),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse::error(&format!(
+            "Failed to reindex store: {}",
+            e
+        ))),
+    }
+}
+
+// The page refers to the API by absolute path; point it into the mount prefix
+fn with_prefix(html: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        return html.to_string();
+    }
+    let mut html = html.to_string();
+    for quote in ['\'', '"', '`'] {
+        for root in ["/api", "/health"] {
+            html = html.replace(
+                &format!("{}{}", quote, root),
+                &format!("{}{}{}", quote, prefix, root),
+            );
+        }
+    }
+    html
+}
+
+// The web UI is built into the binary; a static/index.html next to it
+// takes precedence, for working on the UI without rebuilding
+const INDEX_HTML: &str = include_str!("../static/index.html");
+const SERVICE_WORKER_JS: &str = include_str!("../static/sw.js");
+const MANIFEST_JSON: &str = include_str!("../static/manifest.webmanifest");
+const ICON_SVG: &str = include_str!("../static/icon.svg");
+
+#[get("/")]
+async fn index(req: HttpRequest) -> impl Responder {
+    let html = match std::fs::read_to_string("static/index.html") {
+        Ok(html) => {
+            println!("✓ Loaded HTML from static/index.html");
+            html
+        }
+        Err(_) => INDEX_HTML.to_string(),
+    };
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .body(with_prefix(&html, mount_prefix(&req)))
+}
+
+/// The service worker that makes the web UI work offline. Its cache is
+/// named after the app version, so upgrading the server refreshes it.
+#[get("/sw.js")]
+async fn service_worker() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/javascript")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .body(SERVICE_WORKER_JS.replace("{{VERSION}}", env!("CARGO_PKG_VERSION")))
+}
+
+#[get("/manifest.webmanifest")]
+async fn web_manifest() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/manifest+json")
+        .body(MANIFEST_JSON)
+}
+
+#[get("/icon.svg")]
+async fn app_icon() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .body(ICON_SVG)
+}
+
+// A comment line is sent when nothing happened for this long, so proxies
+// and clients don't drop an idle stream
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+fn sse_frame(event: &str, data: &impl Serialize) -> web::Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Server-sent events for every change to a note the caller can read,
+/// named after the event (`note.created`, ...) with the JSON event as data.
+#[get("/api/events")]
+async fn note_events(user: CurrentUser, manager: Vault) -> impl Responder {
+    let receiver = manager.lock().unwrap().subscribe();
+    let stream = stream::unfold((receiver, user), |(mut receiver, user)| async move {
+        let frame = loop {
+            match tokio::time::timeout(SSE_KEEP_ALIVE, receiver.recv()).await {
+                Err(_) => break web::Bytes::from_static(b": keep-alive\n\n"),
+                Ok(Ok(event)) => match event.visible_to(|note| user.can_read(note)) {
+                    Some(event) => break sse_frame(event.event.name(), &event),
+                    None => continue,
+                },
+                Ok(Err(RecvError::Lagged(missed))) => {
+                    break sse_frame("lagged", &serde_json::json!({ "missed": missed }))
+                }
+                Ok(Err(RecvError::Closed)) => return None,
+            }
+        };
+        Some((Ok::<_, actix_web::Error>(frame), (receiver, user)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(stream)
+}
+
+/// When the server started, for uptime in health reports.
+struct StartedAt(Instant);
+
+#[derive(Debug, Serialize)]
+struct HealthCheck {
+    name: &'static str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl HealthCheck {
+    fn new<T, E: ToString>(name: &'static str, result: Result<T, E>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            detail: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    version: &'static str,
+    uptime_secs: u64,
+    checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    fn new(started: &StartedAt, checks: Vec<HealthCheck>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: started.0.elapsed().as_secs(),
+            checks,
+        }
+    }
+}
+
+fn live_response(started: &StartedAt) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse::success(
+        HealthReport::new(started, Vec::new()),
+        "Server is running",
+    ))
+}
+
+/// Liveness: the process is up and serving requests.
+#[get("/health/live")]
+async fn health_live(started: web::Data<StartedAt>) -> impl Responder {
+    live_response(&started)
+}
+
+#[get("/health")]
+async fn health_check(started: web::Data<StartedAt>) -> impl Responder {
+    live_response(&started)
+}
+
+/// Readiness: storage is writable, the data file parses and the last write worked.
+#[get("/health/ready")]
+async fn health_ready(started: web::Data<StartedAt>, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    let checks = vec![
+        HealthCheck::new("storage_writable", mgr.check_writable()),
+        HealthCheck::new("data_file", mgr.check_data_file()),
+        HealthCheck::new(
+            "last_flush",
+            match mgr.last_save_error() {
+                Some(error) => Err(error.to_string()),
+                None => Ok(()),
+            },
+        ),
+    ];
+
+    let ready = checks.iter().all(|check| check.ok);
+    let report = HealthReport::new(&started, checks);
+    if ready {
+        HttpResponse::Ok().json(ApiResponse::success(report, "Server is ready"))
+    } else {
+        HttpResponse::ServiceUnavailable().json(ApiResponse {
+            success: false,
+            message: "Server is not ready".to_string(),
+            data: Some(report),
+        })
+    }
+}
+
+/// What the caller may do, with the request behind each action, so clients
+/// can build a command palette (see [`commands::COMMANDS`]).
+#[get("/api/commands")]
+async fn list_commands(user: CurrentUser) -> impl Responder {
+    HttpResponse::Ok().json(ApiResponse::success(
+        commands::available(user.role()),
+        "Commands retrieved",
+    ))
+}
+
+/// Counts per state, the week's activity, recent and due notes and open
+/// tasks: the same summary the CLI dashboard shows.
+#[get("/api/stats")]
+async fn get_stats(req: HttpRequest, user: CurrentUser, manager: Vault) -> impl Responder {
+    let mgr = manager.lock().unwrap();
+    cached(
+        manager.cache(),
+        cache_key(&req, &user),
+        mgr.generation(),
+        || {
+            let stats = Dashboard::build(&mgr, &|note| user.can_read(note));
+            ApiResponse::success(stats, "Stats retrieved").to_cached()
+        },
+    )
+}
+
+/// How much the vault, and the caller's own notes, take up against their
+/// quotas, with a warning for each at 80% or more.
+#[get("/api/stats/storage")]
+async fn get_storage_stats(user: CurrentUser, manager: Vault) -> impl Responder {
+    let reports = manager
+        .lock()
+        .unwrap()
+        .storage_reports(user.owner_id().as_deref());
+    HttpResponse::Ok().json(ApiResponse::success(reports, "Storage usage retrieved"))
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityQuery {
+    #[serde(default)]
+    granularity: Granularity,
+    periods: Option<usize>,
+}
+
+// Ten years of days is plenty for any heatmap
+const MAX_ACTIVITY_PERIODS: usize = 3660;
+
+/// Notes created and edited per day or week, oldest first, for heatmaps.
+/// Edits come from each note's revision history.
+#[get("/api/stats/activity")]
+async fn get_activity(
+    req: HttpRequest,
+    query: web::Query<ActivityQuery>,
+    user: CurrentUser,
+    manager: Vault,
+) -> impl Responder {
+    let periods = query
+        .periods
+        .unwrap_or(query.granularity.default_periods())
+        .clamp(1, MAX_ACTIVITY_PERIODS);
+
+    let mgr = manager.lock().unwrap();
+    // Buckets end today, so yesterday's cached heatmap is stale
+    let today = mgr.now().with_timezone(&Local).date_naive();
+    let key = format!("{} {}", cache_key(&req, &user), today);
+    cached(manager.cache(), key, mgr.generation(), || {
+        let notes: Vec<Note> = mgr
+            .notes
+            .iter()
+            .filter(|note| user.can_read(note))
+            .cloned()
+            .collect();
+        let buckets = activity::activity(&notes, query.granularity, periods, today);
+        ApiResponse::success(buckets, "Activity retrieved").to_cached()
+    })
+}
+
+/// Marks responses that shouldn't be compressed (small bodies, event
+/// streams) with `Content-Encoding: identity`, which `Compress` leaves alone.
+async fn skip_compression<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let compression = req
+        .app_data::<web::Data<LiveConfig>>()
+        .map(|config| config.current().server.compression.clone())
+        .unwrap_or_default();
+    if !compression.enabled {
+        return next.call(req).await;
+    }
+    let mut res = next.call(req).await?;
+
+    let is_stream = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    let is_small = matches!(res.response().body().size(), BodySize::Sized(n) if n < compression.min_size as u64);
+    if is_stream || is_small {
+        res.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static("identity"),
+        );
+    }
+    Ok(res)
+}
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// An error as RFC 7807 problem details.
+#[derive(Debug, Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: String,
+    status: u16,
+    detail: String,
+    /// The path that failed
+    instance: String,
+    /// What the error came with besides its message, e.g. a conflict copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+fn wants_problem_details(req: &ServiceRequest, mode: ProblemDetails) -> bool {
+    match mode {
+        ProblemDetails::Always => true,
+        ProblemDetails::Never => false,
+        ProblemDetails::Negotiate => req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains(PROBLEM_CONTENT_TYPE)),
+    }
+}
+
+/// Rewrites error responses as `application/problem+json` for clients that
+/// want them (see `server.problem_details`). The `ApiResponse` message
+/// becomes the `detail`, and plain-text errors such as a rejected JSON body
+/// are converted as well; other bodies are left as they are.
+async fn problem_details<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let mode = req
+        .app_data::<web::Data<LiveConfig>>()
+        .map(|config| config.current().server.problem_details)
+        .unwrap_or_default();
+    let wanted = wants_problem_details(&req, mode);
+    let instance = req.path().to_string();
+    let mut res = next.call(req).await?;
+    let varies = res.headers().get_all(header::VARY).any(|value| {
+        value.to_str().is_ok_and(|value| {
+            value
+                .split(',')
+                .any(|name| name.trim().eq_ignore_ascii_case("accept"))
+        })
+    });
+    if mode == ProblemDetails::Negotiate && !varies {
+        res.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept"));
+    }
+
+    let status = res.status();
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("text/plain")
+        .to_string();
+    let is_json = content_type.starts_with("application/json");
+    if !wanted
+        || !(status.is_client_error() || status.is_server_error())
+        || !(is_json || content_type.starts_with("text/plain"))
+    {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (request, response) = res.into_parts();
+    let (response, body) = response.into_parts();
+    let body = actix_web::body::to_bytes(body)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to read the error"))?;
+    let (detail, data) = if is_json {
+        match serde_json::from_slice::<ApiResponse<Value>>(&body) {
+            Ok(error) if !error.success => (error.message, error.data),
+            _ => {
+                let response = response.set_body(BoxBody::new(body));
+                return Ok(ServiceResponse::new(request, response));
+            }
+        }
+    } else {
+        (String::from_utf8_lossy(&body).into_owned(), None)
+    };
+
+    let title = status.canonical_reason().unwrap_or("Error").to_string();
+    let problem = Problem {
+        kind: "about:blank",
+        detail: if detail.is_empty() {
+            title.clone()
+        } else {
+            detail
+        },
+        title,
+        status: status.as_u16(),
+        instance,
+        data,
+    };
+    let mut response = response.set_body(BoxBody::new(
+        serde_json::to_vec(&problem).unwrap_or_default(),
+    ));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+    );
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// Where the web server listens.
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub bind: String,
+    pub port: u16,
+    /// Try the following ports when `port` is already taken.
+    pub auto_port: bool,
+    /// Allow cross-origin requests from anywhere, ignoring the CORS config.
+    pub insecure_cors: bool,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 8080,
+            auto_port: false,
+            insecure_cors: false,
+        }
+    }
+}
+
+// How many ports past the requested one to try with auto_port
+const PORT_ATTEMPTS: u16 = 20;
+
+fn bind_listener(options: &ServerOptions) -> std::io::Result<TcpListener> {
+    let attempts = if options.auto_port { PORT_ATTEMPTS } else { 1 };
+    let mut last_err = None;
+
+    for offset in 0..attempts {
+        let Some(port) = options.port.checked_add(offset) else {
+            break;
+        };
+        match TcpListener::bind((options.bind.as_str(), port)) {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && options.auto_port => {
+                println!("⚠ Port {} is in use, trying next...", port);
+                last_err = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::AddrInUse, "No free port found")
+    }))
+}
+
+// Rejects entries actix-cors would otherwise only fail on at request time
+fn validate_cors(config: &CorsConfig) -> std::io::Result<()> {
+    let invalid = |what: &str, value: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid CORS {} in config: {}", what, value),
+        )
+    };
+
+    for origin in config.allowed_origins.iter().filter(|o| *o != "*") {
+        let valid = origin
+            .parse::<actix_web::http::Uri>()
+            .is_ok_and(|uri| uri.scheme().is_some() && uri.host().is_some());
+        if !valid {
+            return Err(invalid("origin", origin));
+        }
+    }
+    for method in &config.allowed_methods {
+        method
+            .parse::<actix_web::http::Method>()
+            .map_err(|_| invalid("method", method))?;
+    }
+    for name in &config.allowed_headers {
+        name.parse::<header::HeaderName>()
+            .map_err(|_| invalid("header", name))?;
+    }
+    Ok(())
+}
+
+fn build_cors(config: &CorsConfig, insecure: bool) -> Cors {
+    if insecure {
+        return Cors::permissive();
+    }
+
+    let mut cors = Cors::default()
+        .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(config.allowed_headers.iter().map(String::as_str))
+        .max_age(config.max_age);
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+/// Extra routes registered next to the notes API, inside its prefix.
+type RouteConfig = Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>;
+
+/// Path the routes are mounted under; empty at the root.
+struct MountPrefix(String);
+
+fn mount_prefix(req: &HttpRequest) -> &str {
+    req.app_data::<web::Data<MountPrefix>>()
+        .map(|prefix| prefix.0.as_str())
+        .unwrap_or("")
+}
+
+/// Sets up the notes web server: where it listens, the path prefix its
+/// routes live under, which middleware runs and any extra routes.
+///
+/// [`WebServerBuilder::run`] serves it on its own; [`WebServerBuilder::build`]
+/// gives a [`NotesService`] to mount in an existing actix app.
+pub struct WebServerBuilder {
+    vaults: Vec<(String, SharedNotesManager)>,
+    users: SharedUserStore,
+    note_tokens: SharedNoteTokenStore,
+    config: Config,
+    live: Option<LiveConfig>,
+    options: ServerOptions,
+    prefix: String,
+    auth: bool,
+    cors: bool,
+    compression: bool,
+    preferences: Option<SharedPreferenceStore>,
+    routes: Vec<RouteConfig>,
+}
+
+// The binary only uses part of this; the rest is for apps embedding the crate
+#[allow(dead_code)]
+impl WebServerBuilder {
+    /// Serves `vaults` (name and notes). The first one answers requests that
+    /// don't name a vault with `X-Vault`.
+    pub fn new(
+        vaults: Vec<(String, SharedNotesManager)>,
+        users: SharedUserStore,
+        note_tokens: SharedNoteTokenStore,
+        config: Config,
+    ) -> Self {
+        let mut options = ServerOptions::default();
+        if let Some(port) = config.server.port {
+            options.port = port;
+        }
+        let compression = config.server.compression.enabled;
+        Self {
+            vaults,
+            users,
+            note_tokens,
+            config,
+            live: None,
+            options,
+            prefix: String::new(),
+            auth: true,
+            cors: true,
+            compression,
+            preferences: None,
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn options(mut self, options: ServerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.options.port = port;
+        self
+    }
+
+    pub fn bind(mut self, bind: impl Into<String>) -> Self {
+        self.options.bind = bind.into();
+        self
+    }
+
+    /// Mounts every route under `prefix` (e.g. `/notes` serves
+    /// `/notes/api/notes` and the web UI at `/notes/`).
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+        self.prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", prefix)
+        };
+        self
+    }
+
+    /// Token authentication and role checks on `/api`. Turning it off
+    /// leaves the API, admin routes included, open to anyone who can reach it.
+    pub fn auth(mut self, enabled: bool) -> Self {
+        self.auth = enabled;
+        self
+    }
+
+    /// CORS headers from `server.cors`; off when the embedding app handles CORS.
+    pub fn cors(mut self, enabled: bool) -> Self {
+        self.cors = enabled;
+        self
+    }
+
+    /// Follows `live` instead of the config given to [`WebServerBuilder::new`],
+    /// so requests see settings reloaded from the config file.
+    pub fn live_config(mut self, live: LiveConfig) -> Self {
+        self.config = (*live.current()).clone();
+        self.live = Some(live);
+        self
+    }
+
+    /// Response compression; defaults to `server.compression.enabled`.
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Where each user's preferences are kept; defaults to
+    /// [`preferences::DEFAULT_PREFERENCES_PATH`].
+    pub fn preferences(mut self, preferences: SharedPreferenceStore) -> Self {
+        self.preferences = Some(preferences);
+        self
+    }
+
+    /// Registers more routes inside the prefix, behind the same middleware.
+    pub fn route(
+        mut self,
+        configure: impl Fn(&mut web::ServiceConfig) + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.push(Arc::new(configure));
+        self
+    }
+
+    /// Checks the config and opens the vaults' shared state.
+    pub fn build(self) -> std::io::Result<NotesService> {
+        validate_cors(&self.config.server.cors)?;
+        let preferences = match self.preferences {
+            Some(preferences) => preferences,
+            None => Arc::new(Mutex::new(PreferenceStore::new(
+                preferences::DEFAULT_PREFERENCES_PATH,
+            )?)),
+        };
+        let capacity = self.config.server.cache.capacity;
+        let vaults = Vaults {
+            default: self
+                .vaults
+                .first()
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default(),
+            by_name: self
+                .vaults
+                .into_iter()
+                .map(|(name, manager)| {
+                    let (documents, history, progress) = {
+                        let mgr = manager.lock().unwrap();
+                        let documents = CrdtStore::new(crdt::dir_for(mgr.storage_path()));
+                        documents.remove_unused(|id| mgr.get_note(id).is_some());
+                        let history = history::path_for(mgr.storage_path());
+                        let progress = progress::path_for(mgr.storage_path());
+                        (
+                            documents,
+                            SearchHistory::new(&history.to_string_lossy())?,
+                            ProgressStore::new(progress)?,
+                        )
+                    };
+                    let cache = Arc::new(Mutex::new(ResponseCache::new(capacity)));
+                    let vault = VaultState {
+                        name: name.clone(),
+                        manager,
+                        documents,
+                        cache,
+                        history: Arc::new(Mutex::new(history)),
+                        progress: Arc::new(Mutex::new(progress)),
+                    };
+                    Ok((name, Arc::new(vault)))
+                })
+                .collect::<std::io::Result<_>>()?,
+        };
+
+        Ok(NotesService {
+            cors: self.cors.then(|| self.config.server.cors.clone()),
+            insecure_cors: self.options.insecure_cors,
+            prefix: web::Data::new(MountPrefix(self.prefix)),
+            vaults: web::Data::new(vaults),
+            users: web::Data::new(self.users),
+            note_tokens: web::Data::new(self.note_tokens),
+            preferences: web::Data::new(preferences),
+            locks: web::Data::new(SharedEditLocks::default()),
+            config: web::Data::new(self.live.unwrap_or_else(|| LiveConfig::fixed(self.config))),
+            started: web::Data::new(StartedAt(Instant::now())),
+            auth: self.auth,
+            compression: self.compression,
+            routes: self.routes,
+        })
+    }
+
+    /// Binds the listener and serves until the server stops.
+    pub async fn run(self) -> std::io::Result<()> {
+        let options = self.options.clone();
+        let vault_names: Vec<String> = self.vaults.iter().map(|(name, _)| name.clone()).collect();
+        let service = self.build()?;
+        let listener = bind_listener(&options)?;
+        let port = listener.local_addr()?.port();
+        let prefix = service.prefix();
+        // Unspecified addresses aren't browsable, point at localhost instead
+        let host = match options.bind.as_str() {
+            "0.0.0.0" | "::" => "localhost",
+            other => other,
+        };
+
+        println!("🌐 Web server starting on http://{}:{}", options.bind, port);
+        println!("📱 Access at http://{}:{}{}/", host, port, prefix);
+        println!("📚 API at http://{}:{}{}/api/*", host, port, prefix);
+        if host != options.bind {
+            println!("📡 Listening on all interfaces (LAN access enabled)");
+        }
+        if vault_names.len() > 1 {
+            println!(
+                "🗄 Vaults: {} (pick one with {})",
+                vault_names.join(", "),
+                VAULT_HEADER
+            );
+        }
+        if options.insecure_cors && service.cors.is_some() {
+            println!("⚠ CORS is wide open (--insecure-cors): any website can call this API");
+        }
+        if !service.auth {
+            println!("⚠ Authentication is off: anyone who can reach this server has full access");
+        }
+        println!("{}", "─".repeat(60));
+
+        HttpServer::new(move || App::new().service(service.scope()))
+            .listen(listener)?
+            .run()
+            .await
+    }
+}
+
+/// The notes routes with their state and middleware, ready to be mounted
+/// (once per worker) in an actix app:
+///
+/// ```ignore
+/// let notes = WebServerBuilder::new(vaults, users, tokens, config)
+///     .prefix("/notes")
+///     .build()?;
+/// HttpServer::new(move || App::new().service(notes.scope()).service(my_routes))
+/// ```
+#[derive(Clone)]
+pub struct NotesService {
+    prefix: web::Data<MountPrefix>,
+    vaults: web::Data<Vaults>,
+    users: web::Data<SharedUserStore>,
+    note_tokens: web::Data<SharedNoteTokenStore>,
+    preferences: web::Data<SharedPreferenceStore>,
+    locks: web::Data<SharedEditLocks>,
+    config: web::Data<LiveConfig>,
+    started: web::Data<StartedAt>,
+    // `None` when the embedding app handles CORS
+    cors: Option<CorsConfig>,
+    insecure_cors: bool,
+    auth: bool,
+    compression: bool,
+    routes: Vec<RouteConfig>,
+}
+
+impl NotesService {
+    pub fn prefix(&self) -> &str {
+        &self.prefix.0
+    }
+
+    pub fn scope(&self) -> impl HttpServiceFactory {
+        let cors = self
+            .cors
+            .as_ref()
+            .map(|config| build_cors(config, self.insecure_cors));
+        let mut scope = web::scope(self.prefix())
+            .wrap(middleware::Condition::new(
+                self.auth,
+                middleware::from_fn(auth::require_role),
+            ))
+            .wrap(middleware::from_fn(problem_details))
+            .wrap(middleware::from_fn(skip_compression))
+            .wrap(middleware::Condition::new(
+                self.compression,
+                middleware::Compress::default(),
+            ))
+            .wrap(middleware::Condition::new(
+                cors.is_some(),
+                cors.unwrap_or_default(),
+            ))
+            .app_data(self.prefix.clone())
+            .app_data(self.vaults.clone())
+            .app_data(self.users.clone())
+            .app_data(self.note_tokens.clone())
+            .app_data(self.preferences.clone())
+            .app_data(self.locks.clone())
+            .app_data(self.config.clone())
+            .app_data(self.started.clone())
+            .app_data(web::PayloadConfig::new(MAX_UPLOAD_BYTES))
+            .configure(notes_routes);
+        for route in &self.routes {
+            scope = scope.configure(|cfg| route(cfg));
+        }
+        scope
+    }
+}
+
+fn notes_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(index)
+        .service(service_worker)
+        .service(web_manifest)
+        .service(app_icon)
+        .service(get_notes)
+        .service(bulk_delete_notes)
+        .service(bulk_tag)
+        .service(replace_notes)
+        .service(merge_notes)
+        .service(list_conflicts)
+        .service(resolve_conflict)
+        .service(get_note_by_slug)
+        .service(notes_on_this_day)
+        .service(get_note)
+        .service(create_text_note)
+        .service(create_note)
+        .service(create_note_from_url)
+        .service(update_note)
+        .service(lock_note)
+        .service(get_note_lock)
+        .service(unlock_note)
+        .service(get_note_crdt)
+        .service(merge_note_crdt)
+        .service(encrypt_note)
+        .service(unlock_encrypted_note)
+        .service(decrypt_note)
+        .service(delete_note)
+        .service(note_children)
+        .service(check_note_links)
+        .service(link_check_report)
+        .service(note_thread)
+        .service(suggested_tags)
+        .service(related_notes)
+        .service(list_comments)
+        .service(add_comment)
+        .service(delete_comment)
+        .service(list_reactions)
+        .service(add_reaction)
+        .service(remove_reaction)
+        .service(set_note_location)
+        .service(clear_note_location)
+        .service(set_note_due)
+        .service(clear_note_due)
+        .service(set_note_metadata)
+        .service(clear_note_metadata)
+        .service(summarize_note)
+        .service(set_note_slug)
+        .service(clear_note_slug)
+        .service(calendar_feed)
+        .service(get_reading_progress)
+        .service(set_reading_progress)
+        .service(clear_reading_progress)
+        .service(trash_note)
+        .service(restore_note)
+        .service(archive_note)
+        .service(unarchive_note)
+        .service(pin_note)
+        .service(unpin_note)
+        .service(transfer_note)
+        .service(accept_transfer)
+        .service(cancel_transfer)
+        .service(incoming_transfers)
+        .service(share_note)
+        .service(unshare_note)
+        .service(list_notebooks)
+        .service(create_notebook)
+        .service(get_notebook)
+        .service(edit_notebook)
+        .service(delete_notebook)
+        .service(list_members)
+        .service(invite_member)
+        .service(accept_invitation)
+        .service(remove_member)
+        .service(move_to_notebook)
+        .service(remove_from_notebook)
+        .service(snooze_note)
+        .service(wake_note)
+        .service(search_notes)
+        .service(get_search_history)
+        .service(clear_search_history)
+        .service(get_preferences)
+        .service(update_preferences)
+        .service(get_keymap)
+        .service(update_keymap)
+        .service(search_suggestions)
+        .service(tag_tree)
+        .service(set_tag_color)
+        .service(clear_tag_color)
+        .service(rename_tag)
+        .service(export_note_pdf)
+        .service(export_notes)
+        .service(note_qr)
+        .service(short_link)
+        .service(shared_note)
+        .service(print_note)
+        .service(note_outline)
+        .service(upload_attachment)
+        .service(get_attachment)
+        .service(get_attachment_thumb)
+        .service(set_attachment_transcript)
+        .service(list_users)
+        .service(create_user)
+        .service(set_user_role)
+        .service(create_note_token)
+        .service(list_note_tokens)
+        .service(revoke_note_token)
+        .service(list_templates)
+        .service(create_from_template)
+        .service(policy_report)
+        .service(run_policy)
+        .service(run_backup)
+        .service(list_backups)
+        .service(get_config)
+        .service(verify_store)
+        .service(compact_store)
+        .service(run_link_check)
+        .service(reindex_store)
+        .service(list_commands)
+        .service(get_stats)
+        .service(get_storage_stats)
+        .service(get_activity)
+        .service(note_events)
+        .service(health_check)
+        .service(health_live)
+        .service(health_ready);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scratch_store;
+    use crate::models::{Comment, Reaction, Transfer};
+    use crate::tokens::NoteTokenStore;
+    use crate::users::UserStore;
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn etag(note: &Note) -> EntityTag {
+        Validators::for_note(note).etag
+    }
+
+    #[test]
+    fn comments_change_the_etag() {
+        let mut note = Note::new("Plan".to_string(), "draft".to_string(), Vec::new());
+        let before = etag(&note);
+        let comment = Comment::new(None, None, "looks good", Utc::now()).unwrap();
+        note.comments.push(comment);
+        assert_ne!(etag(&note), before);
+    }
+
+    #[test]
+    fn reactions_change_the_etag() {
+        let mut note = Note::new("Plan".to_string(), "draft".to_string(), Vec::new());
+        let before = etag(&note);
+        note.reactions.push(Reaction {
+            emoji: "👍".to_string(),
+            user: Some("sam".to_string()),
+            created_at: Utc::now(),
+        });
+        let reacted = etag(&note);
+        assert_ne!(reacted, before);
+
+        note.reactions.clear();
+        assert_ne!(etag(&note), reacted);
+    }
+
+    #[test]
+    fn state_changes_that_keep_updated_at_change_the_etag() {
+        let changes: Vec<fn(&mut Note)> = vec![
+            |note| note.pinned_in.push("work".to_string()),
+            |note| note.shared_with.push("alex".to_string()),
+            |note| {
+                note.pending_transfer = Some(Transfer {
+                    to: "sam".to_string(),
+                    requested_by: None,
+                    requested_at: Utc::now(),
+                })
+            },
+            |note| note.owner = Some("sam".to_string()),
+            |note| note.slug = Some("plan".to_string()),
+            |note| {
+                note.metadata
+                    .insert("summary".to_string(), Value::from("A plan"));
+            },
+        ];
+        let note = Note::new(
+            "Plan".to_string(),
+            "draft".to_string(),
+            vec!["work".to_string()],
+        );
+        for change in changes {
+            let mut changed = note.clone();
+            change(&mut changed);
+            assert_eq!(changed.updated_at, note.updated_at);
+            assert_ne!(etag(&changed), etag(&note));
+        }
+    }
+
+    #[test]
+    fn views_leave_the_etag_alone() {
+        let mut note = Note::new("Plan".to_string(), "draft".to_string(), Vec::new());
+        let before = etag(&note);
+        note.view_count += 1;
+        note.last_viewed_at = Some(Utc::now());
+        assert_eq!(etag(&note), before);
+    }
+
+    fn note(title: &str) -> Note {
+        Note::new(title.to_string(), "draft".to_string(), Vec::new())
+    }
+
+    // A vault over a fresh store holding `notes`, and its directory
+    fn scratch_vault(notes: Vec<Note>) -> (PathBuf, SharedNotesManager) {
+        let (dir, uri) = scratch_store();
+        let manager = NotesManager::with_notes(&uri, notes).unwrap();
+        (dir, Arc::new(Mutex::new(manager)))
+    }
+
+    // Accounts and note tokens kept in `dir`; without users the server
+    // runs in single-user mode
+    fn scratch_accounts(dir: &Path) -> (SharedUserStore, SharedNoteTokenStore) {
+        let users = UserStore::new(&dir.join("users.json").to_string_lossy()).unwrap();
+        let tokens = NoteTokenStore::new(&dir.join("note_tokens.json").to_string_lossy()).unwrap();
+        (Arc::new(Mutex::new(users)), Arc::new(Mutex::new(tokens)))
+    }
+
+    fn scratch_service(
+        vaults: Vec<(String, SharedNotesManager)>,
+        accounts: (SharedUserStore, SharedNoteTokenStore),
+        dir: &Path,
+    ) -> NotesService {
+        let preferences = PreferenceStore::new(&dir.join("preferences.json").to_string_lossy());
+        WebServerBuilder::new(vaults, accounts.0, accounts.1, Config::default())
+            .preferences(Arc::new(Mutex::new(preferences.unwrap())))
+            .build()
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn shared_notes_open_in_their_tokens_vault_until_trashed() {
+        let (main_dir, main) = scratch_vault(vec![note("Groceries")]);
+        let shared = note("Roadmap");
+        let (work_dir, work) = scratch_vault(vec![shared.clone()]);
+        let accounts = scratch_accounts(&main_dir);
+        let (_, secret) = accounts
+            .1
+            .lock()
+            .unwrap()
+            .create(&shared.id, "work", TokenAccess::Read, None, Utc::now())
+            .unwrap();
+        let vaults = vec![
+            ("main".to_string(), main),
+            ("work".to_string(), work.clone()),
+        ];
+        let service = scratch_service(vaults, accounts, &main_dir);
+        let app = test::init_service(App::new().service(service.scope())).await;
+        let uri = format!("/share/{}", secret);
+
+        for req in [
+            TestRequest::get().uri(&uri),
+            TestRequest::get()
+                .uri(&uri)
+                .insert_header((VAULT_HEADER, "main")),
+        ] {
+            let response = test::call_service(&app, req.to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains("Roadmap"));
+        }
+
+        work.lock().unwrap().set_trashed(&shared.id, true).unwrap();
+        let response = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        fs::remove_dir_all(main_dir).unwrap();
+        fs::remove_dir_all(work_dir).unwrap();
+    }
+
+    // A vault holding one note of ana's, with accounts for everyone: root
+    // (the first account, so an admin), cam the viewer and the editors
+    struct Team {
+        dir: PathBuf,
+        notes: SharedNotesManager,
+        note: String,
+        users: HashMap<&'static str, (User, String)>,
+        service: NotesService,
+    }
+
+    impl Team {
+        fn new() -> Self {
+            let (dir, notes) = scratch_vault(Vec::new());
+            let accounts = scratch_accounts(&dir);
+            let users: HashMap<_, _> = [
+                ("root", Role::Admin),
+                ("ana", Role::Editor),
+                ("ben", Role::Editor),
+                ("cam", Role::Viewer),
+                ("dee", Role::Editor),
+                ("eli", Role::Editor),
+            ]
+            .into_iter()
+            .map(|(name, role)| {
+                (
+                    name,
+                    accounts.0.lock().unwrap().add_user(name, role).unwrap(),
+                )
+            })
+            .collect();
+            let mut note = note("Plan");
+            note.owner = Some(users["ana"].0.id.clone());
+            let note = notes.lock().unwrap().insert_note(note).unwrap().id;
+            let vaults = vec![("main".to_string(), notes.clone())];
+            let service = scratch_service(vaults, accounts, &dir);
+            Team {
+                dir,
+                notes,
+                note,
+                users,
+                service,
+            }
+        }
+
+        fn id(&self, name: &str) -> &str {
+            &self.users[name].0.id
+        }
+
+        // The request signed with `name`'s API token
+        fn signed(&self, name: &str, req: TestRequest) -> TestRequest {
+            let token = &self.users[name].1;
+            req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+        }
+
+        fn uri(&self, rest: &str) -> String {
+            format!("/api/notes/{}{}", self.note, rest)
+        }
+
+        fn get(&self) -> Note {
+            self.notes.lock().unwrap().get_note(&self.note).unwrap()
+        }
+    }
+
+    impl Drop for Team {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // The response's status and JSON body (`null` if it has none)
+    async fn send<S, R, B>(app: &S, req: R) -> (StatusCode, Value)
+    where
+        S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        let response = test::call_service(app, req).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn somewhere() -> Value {
+        json!({"lat": 52.52, "lon": 13.40})
+    }
+
+    #[actix_web::test]
+    async fn roles_decide_which_requests_reach_the_notes() {
+        let team = Team::new();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let create = || {
+            TestRequest::post().uri("/api/notes").set_json(json!({
+                "title": "Minutes",
+                "content": "agreed",
+                "tags": []
+            }))
+        };
+
+        let anonymous = send(&app, create().to_request()).await;
+        assert_eq!(anonymous.0, StatusCode::UNAUTHORIZED);
+        let viewer = send(&app, team.signed("cam", create()).to_request()).await;
+        assert_eq!(viewer.0, StatusCode::FORBIDDEN);
+        let (status, body) = send(&app, team.signed("ben", create()).to_request()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["data"]["owner"], team.id("ben"));
+
+        // Editors only see their own notes and those shared with them,
+        // admins see them all
+        let note = || TestRequest::get().uri(&team.uri(""));
+        assert_eq!(
+            send(&app, team.signed("ben", note()).to_request()).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&app, team.signed("root", note()).to_request()).await.0,
+            StatusCode::OK
+        );
+        let admin = TestRequest::get().uri("/api/admin/stats");
+        assert_eq!(
+            send(&app, team.signed("ana", admin).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[actix_web::test]
+    async fn note_tokens_reach_only_their_note() {
+        let team = Team::new();
+        let other = {
+            let mut other = note("Budget");
+            other.owner = Some(team.id("ana").to_string());
+            team.notes.lock().unwrap().insert_note(other).unwrap().id
+        };
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let mut tokens = HashMap::new();
+        for access in ["read", "write"] {
+            let req = TestRequest::post()
+                .uri(&team.uri("/tokens"))
+                .set_json(json!({ "access": access }));
+            let (status, body) = send(&app, team.signed("ana", req).to_request()).await;
+            assert_eq!(status, StatusCode::CREATED);
+            tokens.insert(access, body["data"]["token"].as_str().unwrap().to_string());
+        }
+        let with = |access: &str, req: TestRequest| {
+            req.insert_header((header::AUTHORIZATION, format!("Bearer {}", tokens[access])))
+        };
+
+        let own = TestRequest::get().uri(&team.uri(""));
+        assert_eq!(
+            send(&app, with("read", own).to_request()).await.0,
+            StatusCode::OK
+        );
+        let elsewhere = TestRequest::get().uri(&format!("/api/notes/{}", other));
+        assert_eq!(
+            send(&app, with("read", elsewhere).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+        let listing = TestRequest::get().uri(&team.uri("/tokens"));
+        assert_eq!(
+            send(&app, with("write", listing).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+
+        let locate = || {
+            TestRequest::put()
+                .uri(&team.uri("/location"))
+                .set_json(somewhere())
+        };
+        assert_eq!(
+            send(&app, with("read", locate()).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, with("write", locate()).to_request()).await.0,
+            StatusCode::OK
+        );
+
+        // Writing the note doesn't make the token its owner
+        let transfer = TestRequest::post()
+            .uri(&team.uri("/transfer"))
+            .set_json(json!({ "to": "ben" }));
+        assert_eq!(
+            send(&app, with("write", transfer).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(team.get().owner.as_deref(), Some(team.id("ana")));
+    }
+
+    #[actix_web::test]
+    async fn readers_keep_their_own_place_in_a_note() {
+        let team = Team::new();
+        team.notes
+            .lock()
+            .unwrap()
+            .set_shared_with(&team.note, team.id("cam"), true)
+            .unwrap();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let save = |percent: f64| {
+            TestRequest::patch()
+                .uri(&team.uri("/progress"))
+                .set_json(json!({ "percent": percent }))
+        };
+
+        assert_eq!(
+            send(&app, team.signed("cam", save(40.0)).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, team.signed("ana", save(90.0)).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, team.signed("ben", save(10.0)).to_request())
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+
+        for (reader, percent) in [("cam", 40.0), ("ana", 90.0)] {
+            let req = TestRequest::get().uri(&team.uri("/progress"));
+            let (_, body) = send(&app, team.signed(reader, req).to_request()).await;
+            assert_eq!(body["data"]["percent"], percent);
+        }
+        // Reading isn't editing
+        assert_eq!(team.get().updated_at, team.get().created_at);
+    }
+
+    #[actix_web::test]
+    async fn leased_notes_refuse_other_clients_after_checking_access() {
+        let team = Team::new();
+        team.notes
+            .lock()
+            .unwrap()
+            .set_shared_with(&team.note, team.id("ben"), true)
+            .unwrap();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let lease = TestRequest::post().uri(&team.uri("/lock"));
+        let (status, body) = send(&app, team.signed("ana", lease).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        let holder = body["data"]["holder"].as_str().unwrap().to_string();
+
+        let locate = |holder: &str| {
+            TestRequest::put()
+                .uri(&team.uri("/location"))
+                .insert_header((LOCK_HOLDER_HEADER, holder))
+                .set_json(somewhere())
+        };
+        // Those who can't edit the note learn nothing about the lease
+        assert_eq!(
+            send(&app, team.signed("eli", locate("lk_other")).to_request())
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&app, team.signed("ben", locate("lk_other")).to_request())
+                .await
+                .0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, team.signed("ana", locate("lk_other")).to_request())
+                .await
+                .0,
+            StatusCode::LOCKED
+        );
+        assert!(team.get().location.is_none());
+        assert_eq!(
+            send(&app, team.signed("ana", locate(&holder)).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+        assert!(team.get().location.is_some());
+    }
+
+    #[actix_web::test]
+    async fn only_a_successful_encryption_drops_the_merge_document() {
+        let team = Team::new();
+        team.notes
+            .lock()
+            .unwrap()
+            .set_shared_with(&team.note, team.id("ben"), true)
+            .unwrap();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let document = team
+            .dir
+            .join("crdt")
+            .join(format!("{}.automerge", team.note));
+        let fetch = TestRequest::get().uri(&team.uri("/crdt"));
+        assert_eq!(
+            send(&app, team.signed("ana", fetch).to_request()).await.0,
+            StatusCode::OK
+        );
+        assert!(document.exists());
+
+        let encrypt = |passphrase: &str| {
+            TestRequest::post()
+                .uri(&team.uri("/encrypt"))
+                .set_json(json!({ "passphrase": passphrase }))
+        };
+        for (name, passphrase, status) in [
+            ("eli", "secret", StatusCode::NOT_FOUND),
+            ("ben", "secret", StatusCode::FORBIDDEN),
+            ("cam", "secret", StatusCode::FORBIDDEN),
+            ("ana", "", StatusCode::BAD_REQUEST),
+        ] {
+            let req = team.signed(name, encrypt(passphrase));
+            assert_eq!(send(&app, req.to_request()).await.0, status, "{}", name);
+            assert!(document.exists(), "{}", name);
+            assert!(!team.get().is_encrypted(), "{}", name);
+        }
+
+        let req = team.signed("ana", encrypt("secret"));
+        assert_eq!(send(&app, req.to_request()).await.0, StatusCode::OK);
+        assert!(!document.exists());
+        assert!(team.get().is_encrypted());
+    }
+
+    #[actix_web::test]
+    async fn transfers_hand_the_note_over_once_accepted() {
+        let team = Team::new();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let offer = |to: &str| {
+            TestRequest::post()
+                .uri(&team.uri("/transfer"))
+                .set_json(json!({ "to": to }))
+        };
+
+        assert_eq!(
+            send(&app, team.signed("eli", offer("eli")).to_request())
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&app, team.signed("ana", offer("cam")).to_request())
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        let forced = TestRequest::post()
+            .uri(&team.uri("/transfer"))
+            .set_json(json!({ "to": "ben", "force": true }));
+        assert_eq!(
+            send(&app, team.signed("ana", forced).to_request()).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, team.signed("ana", offer("ben")).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+
+        // Offered, not yet given: ben may look but not edit
+        let look = TestRequest::get().uri(&team.uri(""));
+        assert_eq!(
+            send(&app, team.signed("ben", look).to_request()).await.0,
+            StatusCode::OK
+        );
+        let locate = || {
+            TestRequest::put()
+                .uri(&team.uri("/location"))
+                .set_json(somewhere())
+        };
+        assert_eq!(
+            send(&app, team.signed("ben", locate()).to_request())
+                .await
+                .0,
+            StatusCode::FORBIDDEN
+        );
+        let accept = || TestRequest::post().uri(&team.uri("/transfer/accept"));
+        assert_eq!(
+            send(&app, team.signed("dee", accept()).to_request())
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(&app, team.signed("ben", accept()).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+
+        assert_eq!(team.get().owner.as_deref(), Some(team.id("ben")));
+        assert_eq!(
+            send(&app, team.signed("ben", locate()).to_request())
+                .await
+                .0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, team.signed("ana", locate()).to_request())
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[actix_web::test]
+    async fn notebook_members_get_the_access_they_were_invited_with() {
+        let team = Team::new();
+        let app = test::init_service(App::new().service(team.service.scope())).await;
+        let create = TestRequest::post()
+            .uri("/api/notebooks")
+            .set_json(json!({ "name": "Launch" }));
+        let (status, body) = send(&app, team.signed("ana", create).to_request()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let notebook = body["data"]["id"].as_str().unwrap().to_string();
+        let file = TestRequest::put()
+            .uri(&team.uri("/notebook"))
+            .set_json(json!({ "notebook": notebook }));
+        assert_eq!(
+            send(&app, team.signed("ana", file).to_request()).await.0,
+            StatusCode::OK
+        );
+
+        for (name, access) in [("ben", "write"), ("cam", "write"), ("dee", "read")] {
+            let invite = TestRequest::post()
+                .uri(&format!("/api/notebooks/{}/members", notebook))
+                .set_json(json!({ "user": name, "access": access }));
+            assert_eq!(
+                send(&app, team.signed("ana", invite).to_request()).await.0,
+                StatusCode::OK
+            );
+        }
+        // Invited members are only in once they accept
+        let look = || TestRequest::get().uri(&team.uri(""));
+        assert_eq!(
+            send(&app, team.signed("ben", look()).to_request()).await.0,
+            StatusCode::NOT_FOUND
+        );
+        for name in ["ben", "cam", "dee"] {
+            let accept =
+                TestRequest::post().uri(&format!("/api/notebooks/{}/members/accept", notebook));
+            assert_eq!(
+                send(&app, team.signed(name, accept).to_request()).await.0,
+                StatusCode::OK,
+                "{}",
+                name
+            );
+        }
+
+        let locate = || {
+            TestRequest::put()
+                .uri(&team.uri("/location"))
+                .set_json(somewhere())
+        };
+        for (name, read, write) in [
+            ("ben", StatusCode::OK, StatusCode::OK),
+            // A viewer's role caps the access they were invited with
+            ("cam", StatusCode::OK, StatusCode::FORBIDDEN),
+            ("dee", StatusCode::OK, StatusCode::FORBIDDEN),
+            ("eli", StatusCode::NOT_FOUND, StatusCode::NOT_FOUND),
+        ] {
+            assert_eq!(
+                send(&app, team.signed(name, look()).to_request()).await.0,
+                read,
+                "{}",
+                name
+            );
+            assert_eq!(
+                send(&app, team.signed(name, locate()).to_request()).await.0,
+                write,
+                "{}",
+                name
+            );
+        }
+
+        // Editing the notes doesn't make members their owners
+        let take_out = TestRequest::delete().uri(&team.uri("/notebook"));
+        assert_eq!(
+            send(&app, team.signed("ben", take_out).to_request())
+                .await
+                .0,
+            StatusCode::FORBIDDEN
+        );
+    }
 }
@@ -0,0 +1,141 @@
+use crate::config::{WebhookTarget, WebhooksConfig};
+use crate::events::NoteEvent;
+use crate::reload::LiveConfig;
+use crate::storage::SharedNotesManager;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+// Retries stop doubling here, however many are configured
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// How long to wait before retry number `attempt + 1`.
+fn backoff(attempt: u32) -> Duration {
+    2u32.checked_pow(attempt)
+        .map_or(MAX_BACKOFF, |factor| BASE_BACKOFF.saturating_mul(factor))
+        .min(MAX_BACKOFF)
+}
+
+/// POSTs the event, retrying with exponential backoff. Server errors,
+/// timeouts and 429s are retried; other client errors are not.
+fn deliver(
+    agent: &ureq::Agent,
+    target: &WebhookTarget,
+    event: &NoteEvent,
+    config: &WebhooksConfig,
+) -> Result<(), String> {
+    let mut attempt = 0;
+
+    loop {
+        let result = agent
+            .post(&target.url)
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .set("X-Notes-Event", event.event.name())
+            .send_json(event);
+
+        let error = match result {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(code, _)) if code < 500 && code != 429 => {
+                return Err(format!("rejected with status {}", code))
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if attempt >= config.max_retries {
+            return Err(format!("{} (after {} attempts)", error, attempt + 1));
+        }
+        thread::sleep(backoff(attempt));
+        attempt += 1;
+    }
+}
+
+fn wants(target: &WebhookTarget, event: &NoteEvent) -> bool {
+    target.events.is_empty() || target.events.iter().any(|name| name == event.event.name())
+}
+
+/// Forwards note events to the webhook targets configured for `vault`,
+/// looked up for every event so targets added to or removed from the config
+/// file apply right away. Each target has its own worker, so a slow or
+/// failing endpoint doesn't hold up the others and its events still arrive
+/// in order.
+pub fn spawn_webhooks(manager: &SharedNotesManager, config: LiveConfig, vault: &str) {
+    let vault = vault.to_string();
+    let mut events = manager.lock().unwrap().subscribe();
+    thread::spawn(move || {
+        let mut workers: HashMap<String, mpsc::Sender<Delivery>> = HashMap::new();
+        loop {
+            let event = match events.blocking_recv() {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    println!("⚠ Webhooks fell behind and skipped {} event(s)", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let Ok(settings) = config.for_vault(&vault) else {
+                continue;
+            };
+            let webhooks = settings.webhooks;
+            // Dropping a removed target's sender lets its worker finish the
+            // events it has and stop
+            workers.retain(|url, _| webhooks.targets.iter().any(|target| &target.url == url));
+            for target in webhooks
+                .targets
+                .iter()
+                .filter(|target| wants(target, &event))
+            {
+                let tx = workers
+                    .entry(target.url.clone())
+                    .or_insert_with(spawn_worker);
+                let _ = tx.send(Delivery {
+                    target: target.clone(),
+                    event: event.clone(),
+                    config: webhooks.clone(),
+                });
+            }
+        }
+    });
+}
+
+struct Delivery {
+    target: WebhookTarget,
+    event: NoteEvent,
+    config: WebhooksConfig,
+}
+
+fn spawn_worker() -> mpsc::Sender<Delivery> {
+    let (tx, rx) = mpsc::channel::<Delivery>();
+    thread::spawn(move || {
+        // One agent per target, so deliveries reuse its connections
+        let agent = ureq::AgentBuilder::new().build();
+        for delivery in rx {
+            let result = deliver(&agent, &delivery.target, &delivery.event, &delivery.config);
+            if let Err(e) = result {
+                println!(
+                    "✗ Webhook {} for {} failed: {}",
+                    delivery.target.url,
+                    delivery.event.event.name(),
+                    e
+                );
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(0), BASE_BACKOFF);
+        assert_eq!(backoff(3), BASE_BACKOFF * 8);
+        assert_eq!(backoff(20), MAX_BACKOFF);
+        assert_eq!(backoff(32), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,192 @@
+// Service worker for the notes web UI: keeps the app shell and the last
+// note listings available offline, and queues changes made while offline
+// until the server can be reached again.
+const VERSION = '{{VERSION}}';
+const SHELL_CACHE = `notes-shell-${VERSION}`;
+const DATA_CACHE = 'notes-data';
+const SHELL = ['./', 'manifest.webmanifest', 'icon.svg'];
+const QUEUE_DB = 'notes-offline';
+const QUEUE_STORE = 'mutations';
+
+const scopePath = new URL(self.registration.scope).pathname;
+const isApi = url => url.pathname.startsWith(`${scopePath}api/`);
+
+self.addEventListener('install', event => {
+    event.waitUntil(
+        caches.open(SHELL_CACHE)
+            .then(cache => cache.addAll(SHELL))
+            .then(() => self.skipWaiting())
+    );
+});
+
+self.addEventListener('activate', event => {
+    event.waitUntil(
+        caches.keys()
+            .then(keys => Promise.all(keys
+                .filter(key => key.startsWith('notes-shell-') && key !== SHELL_CACHE)
+                .map(key => caches.delete(key))))
+            .then(() => self.clients.claim())
+            .then(replay)
+    );
+});
+
+self.addEventListener('fetch', event => {
+    const request = event.request;
+    const url = new URL(request.url);
+
+    if (request.method !== 'GET') {
+        if (isApi(url)) {
+            event.respondWith(sendOrQueue(request));
+        }
+        return;
+    }
+    // Live event streams have nothing to fall back to
+    if (request.headers.get('Accept') === 'text/event-stream') {
+        return;
+    }
+    if (request.mode === 'navigate') {
+        event.respondWith(
+            fetch(request).catch(() => caches.match('./', { ignoreSearch: true }))
+        );
+        return;
+    }
+    event.respondWith(networkFirst(request, isApi(url) ? DATA_CACHE : SHELL_CACHE));
+});
+
+self.addEventListener('sync', event => {
+    if (event.tag === 'replay-notes') {
+        event.waitUntil(replay());
+    }
+});
+
+self.addEventListener('message', event => {
+    if (event.data && event.data.type === 'replay') {
+        event.waitUntil(replay());
+    }
+});
+
+// The network's answer, remembered for next time; the remembered one when offline
+async function networkFirst(request, cacheName) {
+    const cache = await caches.open(cacheName);
+    try {
+        const response = await fetch(request);
+        if (response.ok || response.type === 'opaque') {
+            cache.put(request, response.clone());
+        }
+        return response;
+    } catch (error) {
+        const cached = await cache.match(request);
+        if (cached) return cached;
+        throw error;
+    }
+}
+
+async function sendOrQueue(request) {
+    const saved = await serialize(request);
+    try {
+        // Queued changes go first so the server sees them in order
+        if ((await pending()) > 0) {
+            await replay();
+        }
+        if ((await pending()) === 0) {
+            return await fetch(request);
+        }
+    } catch (error) {
+        // Offline: queue it below
+    }
+    await enqueue(saved);
+    if (self.registration.sync) {
+        self.registration.sync.register('replay-notes').catch(() => {});
+    }
+    const count = await pending();
+    notify({ type: 'queued', pending: count });
+    return new Response(JSON.stringify({
+        success: true,
+        message: 'Saved offline; it will be sent when you are back online',
+        data: null
+    }), {
+        status: 202,
+        headers: { 'Content-Type': 'application/json', 'X-Queued-Offline': 'true' }
+    });
+}
+
+async function serialize(request) {
+    return {
+        url: request.url,
+        method: request.method,
+        headers: [...request.headers.entries()],
+        body: request.method === 'GET' || request.method === 'HEAD'
+            ? null
+            : await request.clone().text(),
+        queued_at: new Date().toISOString()
+    };
+}
+
+let replaying = null;
+
+// Sends queued changes oldest first, stopping at the first network failure
+function replay() {
+    if (!replaying) {
+        replaying = replayQueue().finally(() => { replaying = null; });
+    }
+    return replaying;
+}
+
+async function replayQueue() {
+    let sent = 0;
+    let rejected = 0;
+    for (const entry of await queued()) {
+        let response;
+        try {
+            response = await fetch(entry.url, {
+                method: entry.method,
+                headers: entry.headers,
+                body: entry.body
+            });
+        } catch (error) {
+            break;
+        }
+        // A change the server refuses won't succeed later either
+        if (response.ok) sent++; else rejected++;
+        await dequeue(entry.id);
+    }
+    if (sent + rejected > 0) {
+        const cache = await caches.open(DATA_CACHE);
+        for (const key of await cache.keys()) {
+            await cache.delete(key);
+        }
+        notify({ type: 'replayed', sent, rejected, pending: await pending() });
+    }
+}
+
+async function notify(message) {
+    for (const client of await self.clients.matchAll()) {
+        client.postMessage(message);
+    }
+}
+
+function openQueue() {
+    return new Promise((resolve, reject) => {
+        const open = indexedDB.open(QUEUE_DB, 1);
+        open.onupgradeneeded = () => {
+            open.result.createObjectStore(QUEUE_STORE, { keyPath: 'id', autoIncrement: true });
+        };
+        open.onsuccess = () => resolve(open.result);
+        open.onerror = () => reject(open.error);
+    });
+}
+
+async function withStore(mode, action) {
+    const db = await openQueue();
+    return new Promise((resolve, reject) => {
+        const tx = db.transaction(QUEUE_STORE, mode);
+        const request = action(tx.objectStore(QUEUE_STORE));
+        tx.oncomplete = () => resolve(request.result);
+        tx.onerror = () => reject(tx.error);
+    });
+}
+
+const enqueue = entry => withStore('readwrite', store => store.add(entry));
+const dequeue = id => withStore('readwrite', store => store.delete(id));
+const queued = () => withStore('readonly', store => store.getAll());
+const pending = () => withStore('readonly', store => store.count());